@@ -0,0 +1,185 @@
+// hyrule-node/src/alerts.rs
+use crate::proxy::ProxyConfig;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Minimum time between two alerts of the same kind
+const DEBOUNCE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    StorageFull,
+    Corruption,
+}
+
+#[derive(Debug, Serialize)]
+struct AlertEvent<'a> {
+    #[serde(rename = "type")]
+    kind: AlertKind,
+    node_id: &'a str,
+    detail: &'a str,
+    timestamp: String,
+}
+
+/// Posts operator alerts to a webhook, at most once per kind per debounce window
+pub struct Alerter {
+    webhook: Option<String>,
+    node_id: String,
+    last_sent: Mutex<HashMap<AlertKind, Instant>>,
+}
+
+impl Alerter {
+    pub fn new(webhook: Option<String>, node_id: String) -> Self {
+        Self {
+            webhook,
+            node_id,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send an alert unless one of the same kind was delivered recently.
+    /// Returns whether the webhook accepted it; an alert that failed to go
+    /// out is sent again next time rather than debounced.
+    pub async fn notify(&self, proxy: &ProxyConfig, kind: AlertKind, detail: &str) -> bool {
+        let Some(webhook) = &self.webhook else {
+            return false;
+        };
+
+        // Held while posting so concurrent alerts of a kind don't both go out
+        let mut last_sent = self.last_sent.lock().await;
+        if last_sent.get(&kind).is_some_and(|sent| sent.elapsed() < DEBOUNCE) {
+            return false;
+        }
+
+        let event = AlertEvent {
+            kind,
+            node_id: &self.node_id,
+            detail,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+
+        match post_event(proxy, webhook, &event).await {
+            Ok(()) => {
+                last_sent.insert(kind, Instant::now());
+                true
+            }
+            Err(e) => {
+                tracing::warn!("Failed to deliver {:?} alert: {}", kind, e);
+                false
+            }
+        }
+    }
+}
+
+async fn post_event(proxy: &ProxyConfig, webhook: &str, event: &AlertEvent<'_>) -> anyhow::Result<()> {
+    let timeout = Duration::from_secs(30);
+
+    // Route through Tor when it's enabled, same as all other outbound traffic
+    let status = if proxy.enabled {
+        let client = proxy.build_client()?;
+        client.post(webhook).json(event).timeout(timeout).send().await?.status().as_u16()
+    } else {
+        let client = reqwest::Client::new();
+        client.post(webhook)
+            .headers(proxy.identity.headers(webhook))
+            .json(event)
+            .timeout(timeout)
+            .send().await?.status().as_u16()
+    };
+
+    if !(200..300).contains(&status) {
+        anyhow::bail!("Webhook returned {}", status);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_webhook_receives_debounced_alerts() {
+        let received: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+
+        let sink = received.clone();
+        let app = Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<serde_json::Value>| {
+                let sink = sink.clone();
+                async move {
+                    sink.lock().await.push(body);
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = crate::config::NodeConfig::generate();
+        config.enable_proxy = false;
+        let proxy = ProxyConfig::from_config(&config);
+
+        let alerter = Alerter::new(Some(format!("http://{}/hook", addr)), "node-1".to_string());
+
+        assert!(alerter.notify(&proxy, AlertKind::StorageFull, "92.0% used").await);
+        // Same kind inside the debounce window is suppressed
+        assert!(!alerter.notify(&proxy, AlertKind::StorageFull, "93.0% used").await);
+        // A different kind still goes out
+        assert!(alerter.notify(&proxy, AlertKind::Corruption, "3 corrupted objects").await);
+
+        let received = received.lock().await;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0]["type"], "storage_full");
+        assert_eq!(received[0]["node_id"], "node-1");
+        assert_eq!(received[0]["detail"], "92.0% used");
+        assert!(received[0]["timestamp"].is_string());
+        assert_eq!(received[1]["type"], "corruption");
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_not_debounced() {
+        let failures = Arc::new(std::sync::atomic::AtomicU32::new(1));
+        let remaining = failures.clone();
+        let app = Router::new().route(
+            "/hook",
+            post(move || {
+                let remaining = remaining.clone();
+                async move {
+                    if remaining.fetch_sub(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                        axum::http::StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        axum::http::StatusCode::OK
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = crate::config::NodeConfig::generate();
+        config.enable_proxy = false;
+        let proxy = ProxyConfig::from_config(&config);
+        let alerter = Alerter::new(Some(format!("http://{}/hook", addr)), "node-1".to_string());
+
+        assert!(!alerter.notify(&proxy, AlertKind::StorageFull, "92.0% used").await);
+        assert!(alerter.notify(&proxy, AlertKind::StorageFull, "92.0% used").await);
+        assert!(!alerter.notify(&proxy, AlertKind::StorageFull, "92.0% used").await);
+    }
+
+    #[tokio::test]
+    async fn test_no_webhook_configured() {
+        let mut config = crate::config::NodeConfig::generate();
+        config.enable_proxy = false;
+        let proxy = ProxyConfig::from_config(&config);
+
+        let alerter = Alerter::new(None, "node-1".to_string());
+        assert!(!alerter.notify(&proxy, AlertKind::Corruption, "detail").await);
+    }
+}
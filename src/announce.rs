@@ -0,0 +1,158 @@
+// hyrule-node/src/announce.rs
+//
+// `announce_on_store`: a repo the node starts hosting because of an upload
+// is announced to the DHT and the coordinator within seconds, rather than
+// at the next announce tick. Each announcement waits for stores to the
+// repo to pause, so a large push is announced once.
+
+use crate::dht::{Announcement, ContentRecord};
+use crate::{replication, NodeState};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Quiet time after the last store before a new repo is announced
+const DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Longest a repo waits while stores keep arriving
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Newly hosted repos waiting to be announced
+#[derive(Debug, Default)]
+pub struct StoreAnnouncements {
+    enabled: bool,
+    /// First and last store to each waiting repo
+    pending: Mutex<HashMap<String, (Instant, Instant)>>,
+    wake: Notify,
+}
+
+impl StoreAnnouncements {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, ..Self::default() }
+    }
+
+    /// Note a store to a repo. Repos the node just started hosting are
+    /// queued; further stores to a queued repo push its announcement back.
+    pub fn stored(&self, repo_hash: &str, newly_hosted: bool) {
+        if self.enabled && self.stored_at(repo_hash, newly_hosted, Instant::now()) {
+            self.wake.notify_one();
+        }
+    }
+
+    fn stored_at(&self, repo_hash: &str, newly_hosted: bool, now: Instant) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(repo_hash) {
+            Some((_, last)) => *last = now,
+            None if newly_hosted => {
+                pending.insert(repo_hash.to_string(), (now, now));
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Remove and return the repos due by `now`
+    fn take_due(&self, now: Instant) -> Vec<String> {
+        let mut pending = self.pending.lock().unwrap();
+        let due: Vec<String> = pending
+            .iter()
+            .filter(|(_, &(first, last))| due_at(first, last) <= now)
+            .map(|(repo_hash, _)| repo_hash.clone())
+            .collect();
+        for repo_hash in &due {
+            pending.remove(repo_hash);
+        }
+        due
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        let pending = self.pending.lock().unwrap();
+        pending.values().map(|&(first, last)| due_at(first, last)).min()
+    }
+}
+
+fn due_at(first: Instant, last: Instant) -> Instant {
+    (last + DEBOUNCE).min(first + MAX_DELAY)
+}
+
+/// Announce repos queued by [`StoreAnnouncements::stored`] once they are due
+pub async fn announce_loop(state: NodeState) {
+    let queue = state.store_announcements.clone();
+    loop {
+        for repo_hash in queue.take_due(Instant::now()) {
+            crate::tasks::record_run();
+            announce(&state, &repo_hash).await;
+        }
+
+        match queue.next_due() {
+            Some(due) => tokio::time::sleep_until(due.into()).await,
+            None => queue.wake.notified().await,
+        }
+    }
+}
+
+async fn announce(state: &NodeState, repo_hash: &str) {
+    if state.dht.read().await.is_some() {
+        let storage = state.storage.clone();
+        let (hash, node_id) = (repo_hash.to_string(), state.config.node_id.clone());
+        let record = tokio::task::spawn_blocking(move || ContentRecord::from_storage(&storage, &hash, &node_id, None))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r)
+            .and_then(|record| Announcement::sign(repo_hash, record, &state.config));
+
+        let result = match (record, state.dht.write().await.as_mut()) {
+            (Ok(announcement), Some(dht)) => dht.announce_content(repo_hash, announcement).and_then(|()| dht.save()),
+            (Ok(_), None) => Ok(()),
+            (Err(e), _) => Err(e),
+        };
+        match result {
+            Ok(()) => tracing::debug!(repo = %repo_hash, "Announced new repo to DHT"),
+            Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to announce new repo to DHT"),
+        }
+    }
+
+    let result = match state.proxy.build_client() {
+        Ok(client) => replication::announce_replica(&state.config.hyrule_server, &state.config.network_namespace, &state.config.node_id, repo_hash, &client).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => tracing::info!(repo = %repo_hash, "Registered as a host of new repo"),
+        Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to register as a host of new repo"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounced_until_stores_pause() {
+        let queue = StoreAnnouncements::new(true);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // Repos already hosted aren't announced again
+        assert!(!queue.stored_at("old", false, at(0)));
+        assert!(queue.stored_at("new", true, at(0)));
+        assert!(queue.stored_at("new", false, at(3)));
+        assert_eq!(queue.next_due(), Some(at(8)));
+        assert!(queue.take_due(at(7)).is_empty());
+        assert_eq!(queue.take_due(at(8)), vec!["new".to_string()]);
+        assert_eq!(queue.next_due(), None);
+
+        // A push that never pauses is still announced after MAX_DELAY
+        for secs in (0..=40).step_by(2) {
+            queue.stored_at("busy", secs == 0, at(secs));
+        }
+        assert_eq!(queue.next_due(), Some(at(30)));
+    }
+
+    #[test]
+    fn test_disabled_queues_nothing() {
+        let queue = StoreAnnouncements::new(false);
+        queue.stored("new", true);
+        assert_eq!(queue.next_due(), None);
+    }
+}
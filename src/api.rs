@@ -3,27 +3,81 @@
 // ============================================================================
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{get, post},
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Router, Json,
 };
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use crate::NodeState;
+use tower_http::compression::{
+    predicate::{DefaultPredicate, NotForContentType, Predicate},
+    CompressionLayer,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::capabilities::{self, Capabilities};
+use crate::load_shed::{self, LoadShedStatus};
+use crate::object_cache::ObjectCacheStats;
+use crate::proxy::TorState;
+use crate::rate_limit::{self, RateLimiter};
+use crate::{auth, batch, git_http, maintenance, request_log, tasks, NodeState, NodeStats, RepoStats};
+use crate::error::HyruleError;
+use crate::storage::{self, RefUpdate};
+
+/// Body chunks of one upload queued for compression at once
+const UPLOAD_CHUNKS_IN_FLIGHT: usize = 4;
 
 #[derive(Debug, Serialize)]
 struct StatusResponse {
     node_id: String,
+    network_namespace: String,
+    started_at: String,
     uptime_seconds: u64,
     storage_used: u64,
+    /// Bytes not on disk thanks to the shared object pool
+    dedup_saved_bytes: u64,
     storage_capacity: u64,
+    /// Disk the capacity was sized from, with `capacity_auto`
+    capacity_detection: Option<CapacityDetection>,
     repos_hosted: usize,
     total_requests: u64,
     bytes_served: u64,
     is_anchor: bool,
     replication_count: u64,
     failed_requests: u64,
+    onion_address: Option<String>,
+    tor: TorState,
+    maintenance_mode: bool,
     features: NodeFeatures,
+    /// Same as `GET /capabilities`
+    capabilities: Capabilities,
+    object_cache: ObjectCacheStats,
+    load_shed: LoadShedStatus,
+    replication: ReplicationLag,
+}
+
+/// How far behind the node is on the repos assigned to it
+#[derive(Debug, Serialize)]
+struct ReplicationLag {
+    /// Repos the coordinator has this node down as hosting that aren't
+    /// here yet; null before the first coordinator sync
+    repos_missing: Option<usize>,
+    /// Seconds since the replication loop last completed a pass; null if
+    /// it hasn't yet
+    seconds_since_last_pass: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CapacityDetection {
+    disk_total: u64,
+    disk_available: u64,
+    fraction: f64,
 }
 
 #[derive(Debug, Serialize)]
@@ -33,6 +87,42 @@ struct NodeFeatures {
     auto_replicate: bool,
 }
 
+/// `/admin/storage`: where the disk space went
+#[derive(Debug, Serialize)]
+struct StorageBreakdownResponse {
+    /// Same figure as `storage_used` in `/status`
+    total_bytes: u64,
+    /// Largest first
+    repos: Vec<RepoUsageEntry>,
+    pool: PoolUsage,
+    spool_bytes: u64,
+    object_cache: ObjectCacheStats,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoUsageEntry {
+    repo_hash: String,
+    size_bytes: u64,
+    objects: u64,
+    pack_cache_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct PoolUsage {
+    /// Also counted in each repo that links the objects
+    bytes: u64,
+    objects: u64,
+    saved_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadyResponse {
+    ready: bool,
+    reasons: Vec<String>,
+    tor: TorState,
+    maintenance_mode: bool,
+}
+
 #[derive(Debug, Deserialize)]
 struct StoreObjectRequest {
     object_id: String,
@@ -50,16 +140,112 @@ struct BatchStoreRequest {
     objects: Vec<StoreObjectRequest>,
 }
 
+/// Outcome of a whole batch, so clients can tell at a glance whether to retry
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BatchStatus {
+    Complete,
+    Partial,
+    Failed,
+}
+
+/// Why one object in a batch wasn't stored
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum StoreFailure {
+    /// The data wasn't valid base64; retrying won't help
+    InvalidBase64,
+    /// No room left on any tier
+    StorageFull,
+    /// The repo holds `max_objects_per_repo` objects already
+    TooManyObjects,
+    /// Any other write error, possibly transient
+    StorageError,
+}
+
+#[derive(Debug, Serialize)]
+struct FailedObject {
+    object_id: String,
+    reason: StoreFailure,
+}
+
+/// What happened to an object that didn't fail
+#[derive(Debug, PartialEq)]
+enum StoreOutcome {
+    Stored,
+    /// Already present; objects are content-addressed so it's identical
+    Skipped,
+}
+
 #[derive(Debug, Serialize)]
 struct BatchStoreResponse {
+    status: BatchStatus,
     uploaded: usize,
-    failed: Vec<String>,
+    skipped: usize,
+    failed: Vec<FailedObject>,
 }
 
 #[derive(Debug, Deserialize)]
 struct UpdateRefRequest {
     ref_name: String,
     commit_id: String,
+    /// Only update if the ref currently has this value (force-with-lease);
+    /// the all-zero id means the ref must not exist yet
+    #[serde(default)]
+    expected_old: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRefRequest {
+    updates: Vec<UpdateRefRequest>,
+}
+
+#[derive(Debug, Serialize)]
+struct RefUpdateResult {
+    ref_name: String,
+    /// "updated", "conflict", or "skipped" when another ref conflicted
+    status: &'static str,
+    /// Current value of a conflicting ref; null if it doesn't exist
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchRefResponse {
+    applied: bool,
+    results: Vec<RefUpdateResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteRefQuery {
+    /// Allow deleting the branch HEAD points at
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RefEntry {
+    name: String,
+    commit_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ListRefsResponse {
+    refs: Vec<RefEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct HeadResponse {
+    /// Branch HEAD follows; null when detached
+    target: Option<String>,
+    /// null while the branch has no commits
+    commit_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetHeadRequest {
+    /// A ref such as `refs/heads/main`, or a commit id to detach HEAD
+    target: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -68,27 +254,194 @@ struct ListObjectsResponse {
     count: usize,
 }
 
+/// Public API. Admin routes are included unless `admin_socket` is set, in
+/// which case they are only served by [`create_admin_router`]. Serve it
+/// with `ClientKey` connect info so clients can be rate limited.
 pub fn create_router(state: NodeState) -> Router {
-    Router::new()
+    let writes = guard_writes(
+        Router::new()
+            .route("/repos/{hash}/objects", post(store_object))
+            .route("/repos/{hash}/objects/{id}", put(put_object))
+            .route("/repos/{hash}/refs", post(update_ref))
+            .route("/repos/{hash}/refs/batch", post(batch_update_refs))
+            .route("/repos/{hash}/init", post(init_repo))
+            .route("/repos/{hash}/head", put(set_head)),
+        &state,
+    );
+    
+    // Held to the batch limits while its body is read, not the general one
+    let batch_uploads = guard_writes(
+        Router::new().route("/repos/{hash}/objects/batch", post(batch_store_objects)),
+        &state,
+    );
+    
+    let downloads = limit_downloads(
+        Router::new()
+            .route("/repos/{hash}/objects/{id}", get(get_object))
+            .route("/repos/{hash}/pack", get(get_packfile))
+            .route("/repos/{hash}/objects/since", post(objects_since))
+            .merge(git_http::router()),
+        &state,
+    );
+    
+    let mut router = Router::new()
         .route("/status", get(get_status))
+        .route("/capabilities", get(capabilities::get_capabilities))
         .route("/health", get(health_check))
+        .route("/ready", get(ready_check))
         .route("/repos", get(list_repos))
-        .route("/repos/{hash}/objects/{id}", get(get_object))
-        .route("/repos/{hash}/objects", post(store_object))
         .route("/repos/{hash}/objects", get(list_objects))
-        .route("/repos/{hash}/objects/batch", post(batch_store_objects))
-        .route("/repos/{hash}/refs", post(update_ref))
+        .route("/repos/{hash}/refs", get(list_refs))
         .route("/repos/{hash}/refs/{ref_name}", get(get_ref))
-        .route("/repos/{hash}/init", post(init_repo))
-        .route("/repos/{hash}/pack", get(get_packfile))
+        .route("/repos/{hash}/head", get(get_head))
+        .route("/repos/{hash}/stats", get(get_repo_stats))
+        .merge(downloads)
+        .merge(writes);
+    
+    if state.config.admin_socket.is_none() {
+        router = router.merge(admin_routes(&state));
+    }
+    
+    let router = limit_body(router, &state).merge(batch_uploads);
+    let mut router = router.layer(axum::middleware::from_fn_with_state(
+        auth::AuthConfig::from_config(&state.config),
+        auth::require_admin_token,
+    ));
+    
+    // Sub-requests are dispatched through the routes above, auth and rate
+    // limit included, so each one costs a token
+    let limiter = RateLimiter::from_config(&state.config).map(Arc::new);
+    let rate_limited = |routes: Router<NodeState>| match &limiter {
+        Some(limiter) => routes.layer(axum::middleware::from_fn_with_state(limiter.clone(), rate_limit::limit)),
+        None => routes,
+    };
+    let dispatch = rate_limited(router.clone()).with_state(state.clone());
+    router = router.route(
+        "/batch",
+        post(batch::run_batch)
+            .with_state(dispatch)
+            .layer(RequestBodyLimitLayer::new(state.config.max_request_body_bytes)),
+    );
+    router = rate_limited(router);
+    
+    with_common_layers(router, &state)
+        .layer(compression_layer())
+        .layer(cors_layer(&state.config.cors_allowed_origins))
         .with_state(state)
 }
+
+/// Admin routes alone, for the local `admin_socket`. Filesystem permissions
+/// on the socket control access, so no token is checked.
+pub fn create_admin_router(state: NodeState) -> Router {
+    with_common_layers(limit_body(admin_routes(&state), &state), &state).with_state(state)
+}
+
+/// Status for a failed storage call. Failures the client can't fix are
+/// logged, since a bare 500 says nothing about them.
+impl From<HyruleError> for StatusCode {
+    fn from(e: HyruleError) -> Self {
+        match e {
+            HyruleError::NotFound(_) => StatusCode::NOT_FOUND,
+            HyruleError::Invalid(_) => StatusCode::BAD_REQUEST,
+            HyruleError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            HyruleError::Conflict(_) | HyruleError::RefConflict(_) | HyruleError::RefIsHead(_) => StatusCode::CONFLICT,
+            HyruleError::Auth(_) => StatusCode::UNAUTHORIZED,
+            HyruleError::Network(_) => StatusCode::BAD_GATEWAY,
+            HyruleError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            e if e.is_disk_full() => StatusCode::INSUFFICIENT_STORAGE,
+            e => {
+                tracing::warn!(error = %e, "Storage request failed");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+/// Everything under `/admin/` plus the DELETE routes
+fn admin_routes(state: &NodeState) -> Router<NodeState> {
+    Router::new()
+        .route("/admin/requests", get(request_log::recent_requests))
+        .route("/admin/tasks", get(tasks::task_status))
+        .route("/admin/storage", get(get_storage_breakdown))
+        .route("/admin/maintenance", post(maintenance::set_maintenance))
+        .merge(guard_writes(
+            Router::new()
+                .route("/repos/{hash}/refs/{ref_name}", delete(delete_ref))
+                .route("/repos/{hash}/objects/{id}", delete(delete_object)),
+            state,
+        ))
+}
+
+/// Writes are refused while the node is in maintenance mode, and cut off
+/// with 408 once they exceed `request_timeout_secs`
+fn guard_writes(routes: Router<NodeState>, state: &NodeState) -> Router<NodeState> {
+    routes
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.maintenance.clone(),
+            maintenance::reject_writes,
+        ))
+        .route_layer(TimeoutLayer::with_status_code(
+            StatusCode::REQUEST_TIMEOUT,
+            Duration::from_secs(state.config.request_timeout_secs),
+        ))
+}
+
+/// Downloads wait for one of `max_concurrent_downloads` slots, and are shed
+/// with 503 once `download_queue_limit` are already waiting
+fn limit_downloads(routes: Router<NodeState>, state: &NodeState) -> Router<NodeState> {
+    routes.route_layer(axum::middleware::from_fn_with_state(
+        state.load_shed.clone(),
+        load_shed::limit_downloads,
+    ))
+}
+
+/// Bodies over `max_request_body_bytes` are rejected with 413
+fn limit_body(routes: Router<NodeState>, state: &NodeState) -> Router<NodeState> {
+    routes.layer(RequestBodyLimitLayer::new(state.config.max_request_body_bytes))
+}
+
+/// Request logging, shared by both listeners. axum's fixed 2 MB extractor
+/// cap is lifted here; each route group sets its own body limit instead.
+fn with_common_layers(router: Router<NodeState>, state: &NodeState) -> Router<NodeState> {
+    router
+        .layer(DefaultBodyLimit::disable())
+        .layer(axum::middleware::from_fn_with_state(state.clone(), request_log::record))
+}
+
+/// gzip/deflate for JSON and text responses. Objects and packs are already
+/// zlib-compressed, so every `application/x-git-*` type is left alone.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    let predicate = DefaultPredicate::new()
+        .and(NotForContentType::const_new("application/x-git-"));
+    
+    CompressionLayer::new().compress_when(predicate)
+}
+
+/// CORS policy from `cors_allowed_origins`. With no origins configured the
+/// layer adds no headers, so browsers keep blocking cross-origin calls.
+/// Preflight `OPTIONS` requests are answered by the layer itself.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+    };
+    
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(Any)
+}
+
 async fn get_status(
     State(state): State<NodeState>,
 ) -> Result<Json<StatusResponse>, StatusCode> {
-    let stats = state.stats.read().await;
-    let storage_used = state.storage.get_storage_usage()
+    let storage_used = state.storage.get_storage_usage_async().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.load_shed.set_storage_used(storage_used);
+    let dedup = state.storage.dedup_stats_async().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stats = state.stats.read().await;
     
     let repos = state.hosted_repos.read().await;
     
@@ -100,23 +453,130 @@ async fn get_status(
     
     Ok(Json(StatusResponse {
         node_id: state.config.node_id.clone(),
-        uptime_seconds: stats.uptime_seconds,
+        network_namespace: state.config.network_namespace.clone(),
+        started_at: state.started_at.to_rfc3339(),
+        uptime_seconds: state.start_instant.elapsed().as_secs(),
         storage_used,
-        storage_capacity: state.config.storage_capacity,
+        dedup_saved_bytes: dedup.saved_bytes,
+        storage_capacity: state.capacity.bytes(),
+        capacity_detection: state.capacity.detected().map(|disk| CapacityDetection {
+            disk_total: disk.total,
+            disk_available: disk.available,
+            fraction: state.config.capacity_auto_fraction,
+        }),
         repos_hosted: repos.len(),
         total_requests: stats.total_requests,
         bytes_served: stats.bytes_served,
         is_anchor: state.config.is_anchor,
         replication_count: stats.replication_count,
         failed_requests: stats.failed_requests,
+        onion_address: state.onion.get().map(|service| service.address.clone()),
+        tor: state.proxy.tor_state(),
+        maintenance_mode: state.maintenance.is_enabled(),
         features,
+        capabilities: Capabilities::current(&state.config.network_namespace),
+        object_cache: state.object_cache.stats(),
+        load_shed: state.load_shed.status(state.capacity.bytes()),
+        replication: ReplicationLag {
+            repos_missing: stats.repos_missing,
+            seconds_since_last_pass: stats.last_replication_pass.map(|at| at.elapsed().as_secs()),
+        },
     }))
 }
 
+/// `GET /admin/storage` - per-repo sizes and object counts, largest first,
+/// plus the pool, spool and in-memory cache
+async fn get_storage_breakdown(
+    State(state): State<NodeState>,
+) -> Result<Json<StorageBreakdownResponse>, StatusCode> {
+    let usage = state.storage.usage_breakdown_async(state.config.spool_dir()).await?;
+    
+    Ok(Json(StorageBreakdownResponse {
+        total_bytes: usage.repos.iter().map(|r| r.size).sum(),
+        repos: usage.repos.into_iter().map(|r| RepoUsageEntry {
+            repo_hash: r.repo_hash,
+            size_bytes: r.size,
+            objects: r.objects,
+            pack_cache_bytes: r.pack_cache,
+        }).collect(),
+        pool: PoolUsage {
+            bytes: usage.pool,
+            objects: usage.dedup.pooled_objects,
+            saved_bytes: usage.dedup.saved_bytes,
+        },
+        spool_bytes: usage.spool,
+        object_cache: state.object_cache.stats(),
+    }))
+}
+
+/// Liveness: the process is up and serving requests
 async fn health_check() -> StatusCode {
     StatusCode::OK
 }
 
+/// Readiness: storage is writable, Tor is bootstrapped, the coordinator
+/// accepted our last heartbeat, the node could reach itself at its
+/// advertised address when it last tried, and replication is keeping up.
+/// Maintenance mode is reported but does not fail the check, since reads
+/// are still served.
+async fn ready_check(
+    State(state): State<NodeState>,
+) -> (StatusCode, Json<ReadyResponse>) {
+    let mut reasons = Vec::new();
+    
+    if let Err(e) = state.storage.check_writable_async().await {
+        reasons.push(format!("storage not writable: {}", e));
+    }
+    
+    let tor = state.proxy.tor_state();
+    match tor {
+        TorState::Connecting => reasons.push("tor client not bootstrapped".to_string()),
+        TorState::Failing => reasons.push("tor requests failing on fresh circuits".to_string()),
+        _ => {}
+    }
+    
+    let stats = state.stats.read().await;
+    match stats.last_heartbeat_ok {
+        Some(true) => {}
+        Some(false) => reasons.push("last heartbeat failed".to_string()),
+        None => reasons.push("no heartbeat sent yet".to_string()),
+    }
+    if stats.self_reachable == Some(false) {
+        reasons.push("node unreachable at its advertised address".to_string());
+    }
+    if state.config.auto_replicate && !state.maintenance.is_enabled() && state.config.max_replication_lag_secs > 0 {
+        let max_lag = Duration::from_secs(state.config.max_replication_lag_secs);
+        reasons.extend(replication_lag(&stats, max_lag, state.start_instant, Instant::now()));
+    }
+    drop(stats);
+    
+    let ready = reasons.is_empty();
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    
+    let maintenance_mode = state.maintenance.is_enabled();
+    
+    (status, Json(ReadyResponse { ready, reasons, tor, maintenance_mode }))
+}
+
+/// Ways replication has been behind for longer than `max_lag`: no pass
+/// completed since `started` or since the last one, or assigned repos
+/// missing
+fn replication_lag(stats: &NodeStats, max_lag: Duration, started: Instant, now: Instant) -> Vec<String> {
+    let mut reasons = Vec::new();
+    
+    let since_pass = now.duration_since(stats.last_replication_pass.unwrap_or(started));
+    if since_pass > max_lag {
+        reasons.push(format!("no replication pass completed in {}s", since_pass.as_secs()));
+    }
+    if let (Some(missing), Some(since)) = (stats.repos_missing, stats.repos_missing_since) {
+        let missing_for = now.duration_since(since);
+        if missing_for > max_lag {
+            reasons.push(format!("{} assigned repos missing for {}s", missing, missing_for.as_secs()));
+        }
+    }
+    reasons
+}
+
 async fn list_repos(
     State(state): State<NodeState>,
 ) -> Result<Json<Vec<String>>, StatusCode> {
@@ -127,26 +587,36 @@ async fn list_repos(
 async fn get_object(
     State(state): State<NodeState>,
     Path((repo_hash, object_id)): Path<(String, String)>,
-) -> Result<Vec<u8>, StatusCode> {
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     {
         let mut stats = state.stats.write().await;
         stats.total_requests += 1;
     }
     
-    let data = state.storage
-        .read_object(&repo_hash, &object_id)
-        .map_err(|_| {
-            let mut stats = futures::executor::block_on(state.stats.write());
-            stats.failed_requests += 1;
-            StatusCode::NOT_FOUND
-        })?;
+    let data = match state.object_cache.get(&repo_hash, &object_id) {
+        Some(data) => data,
+        None => match state.storage.read_object_async(&repo_hash, &object_id).await {
+            Ok(data) => {
+                let data = Bytes::from(data);
+                state.object_cache.insert(&repo_hash, &object_id, data.clone());
+                data
+            }
+            Err(e) => {
+                let mut stats = state.stats.write().await;
+                stats.failed_requests += 1;
+                return Err(e.into());
+            }
+        },
+    };
     
     {
         let mut stats = state.stats.write().await;
         stats.bytes_served += data.len() as u64;
     }
+    state.record_repo_access(&repo_hash, data.len() as u64).await;
     
-    Ok(data)
+    Ok(download(&request_headers, "application/x-git-loose-object", &object_id, data))
 }
 
 async fn store_object(
@@ -156,60 +626,360 @@ async fn store_object(
 ) -> Result<Json<StoreObjectResponse>, StatusCode> {
     use base64::{Engine as _, engine::general_purpose};
     
+    if state.load_shed.shed_write(state.capacity.bytes()) {
+        return Err(StatusCode::INSUFFICIENT_STORAGE);
+    }
+    
     let data = general_purpose::STANDARD
         .decode(&payload.data)
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let size = data.len() as u64;
+    
+    if at_object_limit(&state, &repo_hash).await?
+        && !state.storage.object_exists_async(&repo_hash, &payload.object_id).await?
+    {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
     
+    let _permit = state.upload_slots.acquire().await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
     state.storage
-        .store_object(&repo_hash, &payload.object_id, &data)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .store_object_async(&repo_hash, &payload.object_id, data)
+        .await?;
+    state.object_cache.invalidate(&repo_hash, &payload.object_id);
+    state.load_shed.add_storage_used(size);
+    record_store(&state, &repo_hash).await;
     
+    Ok(Json(StoreObjectResponse {
+        success: true,
+        object_id: payload.object_id,
+    }))
+}
+
+/// `PUT /repos/{hash}/objects/{id}` - store an object sent as the raw
+/// request body. It is compressed as it streams in, so neither the client
+/// nor the node needs the base64 copy `POST /repos/{hash}/objects` takes.
+async fn put_object(
+    State(state): State<NodeState>,
+    Path((repo_hash, object_id)): Path<(String, String)>,
+    body: Body,
+) -> Result<Json<StoreObjectResponse>, StatusCode> {
+    if state.load_shed.shed_write(state.capacity.bytes()) {
+        return Err(StatusCode::INSUFFICIENT_STORAGE);
+    }
+    storage::check_object_ref(&repo_hash, &object_id)?;
+    
+    if at_object_limit(&state, &repo_hash).await?
+        && !state.storage.object_exists_async(&repo_hash, &object_id).await?
     {
-        let mut repos = state.hosted_repos.write().await;
-        if !repos.contains(&repo_hash) {
-            repos.push(repo_hash.clone());
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    
+    let _permit = state.upload_slots.acquire().await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    
+    // Compression is CPU-bound, so it runs on a blocking thread that the
+    // body is fed to as it arrives
+    let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::channel::<bytes::Bytes>(UPLOAD_CHUNKS_IN_FLIGHT);
+    let mut upload = state.storage.begin_object();
+    let compress = tokio::task::spawn_blocking(move || -> crate::error::Result<_> {
+        while let Some(chunk) = chunk_rx.blocking_recv() {
+            upload.write(&chunk)?;
+        }
+        Ok(upload)
+    });
+    
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        // The body limit cutting it off; a client that went away never
+        // sees the status anyway
+        let chunk = chunk.map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+        if chunk_tx.send(chunk).await.is_err() {
+            // The writer stopped on an error, returned below
+            break;
         }
     }
+    drop(chunk_tx);
+    let upload = compress.await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    
+    let size = state.storage
+        .finish_object_async(&repo_hash, &object_id, upload)
+        .await?;
+    state.object_cache.invalidate(&repo_hash, &object_id);
+    state.load_shed.add_storage_used(size);
+    record_store(&state, &repo_hash).await;
     
     Ok(Json(StoreObjectResponse {
         success: true,
-        object_id: payload.object_id,
+        object_id,
     }))
 }
 
+/// Body of a batch store. Held to `max_batch_bytes` and `max_batch_objects`
+/// rather than the single-request limits, and rejected with 413 as soon as
+/// either is passed: from `Content-Length` when the client sends one, and
+/// otherwise without reading or decoding the rest of the body.
+struct BatchUpload(BatchStoreRequest);
+
+impl FromRequest<NodeState> for BatchUpload {
+    type Rejection = Response;
+    
+    async fn from_request(req: Request, state: &NodeState) -> Result<Self, Response> {
+        let max_bytes = state.config.max_batch_bytes;
+        let declared = req.headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if declared.is_some_and(|len| len > max_bytes as u64) {
+            return Err(batch_too_large("max_batch_bytes", max_bytes));
+        }
+        
+        let body = axum::body::to_bytes(req.into_body(), max_bytes)
+            .await
+            .map_err(|_| batch_too_large("max_batch_bytes", max_bytes))?;
+        
+        let max_objects = state.config.max_batch_objects;
+        match decode_batch(&body, max_objects) {
+            Ok(payload) => Ok(BatchUpload(payload)),
+            Err(BatchDecodeError::TooManyObjects) => Err(batch_too_large("max_batch_objects", max_objects)),
+            Err(BatchDecodeError::Invalid(e)) => {
+                Err((StatusCode::BAD_REQUEST, format!("Invalid batch: {}", e)).into_response())
+            }
+        }
+    }
+}
+
+fn batch_too_large(limit: &str, value: usize) -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, format!("Batch exceeds {} ({})", limit, value)).into_response()
+}
+
+#[derive(Debug)]
+enum BatchDecodeError {
+    TooManyObjects,
+    Invalid(serde_json::Error),
+}
+
+/// Decode a batch, giving up at the first object past `max_objects`
+fn decode_batch(body: &[u8], max_objects: usize) -> Result<BatchStoreRequest, BatchDecodeError> {
+    use serde::de::DeserializeSeed;
+    
+    let over_limit = std::cell::Cell::new(false);
+    let mut de = serde_json::Deserializer::from_slice(body);
+    let seed = CappedBatch { max_objects, over_limit: &over_limit };
+    match seed.deserialize(&mut de).and_then(|payload| de.end().map(|()| payload)) {
+        Ok(payload) => Ok(payload),
+        Err(_) if over_limit.get() => Err(BatchDecodeError::TooManyObjects),
+        Err(e) => Err(BatchDecodeError::Invalid(e)),
+    }
+}
+
+/// Deserializes a [`BatchStoreRequest`], failing (and setting `over_limit`)
+/// once `objects` holds more than `max_objects` entries
+#[derive(Clone, Copy)]
+struct CappedBatch<'a> {
+    max_objects: usize,
+    over_limit: &'a std::cell::Cell<bool>,
+}
+
+impl<'de> serde::de::DeserializeSeed<'de> for CappedBatch<'_> {
+    type Value = BatchStoreRequest;
+    
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for CappedBatch<'_> {
+    type Value = BatchStoreRequest;
+    
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a batch of objects")
+    }
+    
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut objects = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "objects" {
+                objects = Some(map.next_value_seed(CappedObjects(self))?);
+            } else {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+        let objects = objects.ok_or_else(|| serde::de::Error::missing_field("objects"))?;
+        Ok(BatchStoreRequest { objects })
+    }
+}
+
+struct CappedObjects<'a>(CappedBatch<'a>);
+
+impl<'de> serde::de::DeserializeSeed<'de> for CappedObjects<'_> {
+    type Value = Vec<StoreObjectRequest>;
+    
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for CappedObjects<'_> {
+    type Value = Vec<StoreObjectRequest>;
+    
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a list of objects")
+    }
+    
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let CappedBatch { max_objects, over_limit } = self.0;
+        let mut objects = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(max_objects));
+        while objects.len() < max_objects {
+            match seq.next_element()? {
+                Some(obj) => objects.push(obj),
+                None => return Ok(objects),
+            }
+        }
+        match seq.next_element_seed(PastLimit(over_limit))? {
+            Some(never) => match never {},
+            None => Ok(objects),
+        }
+    }
+}
+
+/// Refuses the element after the last one allowed, before decoding any of it
+struct PastLimit<'a>(&'a std::cell::Cell<bool>);
+
+impl<'de> serde::de::DeserializeSeed<'de> for PastLimit<'_> {
+    type Value = std::convert::Infallible;
+    
+    fn deserialize<D: serde::Deserializer<'de>>(self, _deserializer: D) -> Result<Self::Value, D::Error> {
+        self.0.set(true);
+        Err(serde::de::Error::custom("too many objects"))
+    }
+}
+
 async fn batch_store_objects(
     State(state): State<NodeState>,
     Path(repo_hash): Path<String>,
-    Json(payload): Json<BatchStoreRequest>,
+    BatchUpload(payload): BatchUpload,
 ) -> Result<Json<BatchStoreResponse>, StatusCode> {
-    use base64::{Engine as _, engine::general_purpose};
+    if state.load_shed.shed_write(state.capacity.bytes()) {
+        return Err(StatusCode::INSUFFICIENT_STORAGE);
+    }
+    if at_object_limit(&state, &repo_hash).await? {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    
+    // Stored in parallel, but never more at once than the upload limit
+    let results: Vec<(String, Result<StoreOutcome, StoreFailure>)> = futures::stream::iter(payload.objects)
+        .map(|obj| async {
+            let result = store_batch_object(&state, &repo_hash, &obj).await;
+            (obj.object_id, result)
+        })
+        .buffered(state.config.max_concurrent_uploads as usize)
+        .collect()
+        .await;
+    // One sync for the whole batch under `fsync_policy = "batch"`
+    state.storage.sync_pending_async().await?;
     
     let mut uploaded = 0;
+    let mut skipped = 0;
     let mut failed = Vec::new();
-    
-    for obj in payload.objects {
-        match general_purpose::STANDARD.decode(&obj.data) {
-            Ok(data) => {
-                if state.storage.store_object(&repo_hash, &obj.object_id, &data).is_ok() {
-                    uploaded += 1;
-                } else {
-                    failed.push(obj.object_id);
-                }
-            }
-            Err(_) => {
-                failed.push(obj.object_id);
-            }
+    for (object_id, result) in results {
+        match result {
+            Ok(StoreOutcome::Stored) => uploaded += 1,
+            Ok(StoreOutcome::Skipped) => skipped += 1,
+            Err(reason) => failed.push(FailedObject { object_id, reason }),
         }
     }
     
-    {
+    // A batch that stored nothing leaves the repo as unhosted as before
+    if uploaded + skipped > 0 {
+        record_store(&state, &repo_hash).await;
+    }
+    
+    Ok(Json(BatchStoreResponse {
+        status: batch_status(uploaded + skipped, failed.len()),
+        uploaded,
+        skipped,
+        failed,
+    }))
+}
+
+async fn store_batch_object(
+    state: &NodeState,
+    repo_hash: &str,
+    obj: &StoreObjectRequest,
+) -> Result<StoreOutcome, StoreFailure> {
+    use base64::{Engine as _, engine::general_purpose};
+    
+    // Re-pushes resend objects we already have; don't decode or rewrite them
+    if state.storage.object_exists_async(repo_hash, &obj.object_id).await.unwrap_or(false) {
+        return Ok(StoreOutcome::Skipped);
+    }
+    
+    match at_object_limit(state, repo_hash).await {
+        Ok(false) => {}
+        Ok(true) => return Err(StoreFailure::TooManyObjects),
+        Err(_) => return Err(StoreFailure::StorageError),
+    }
+    
+    let data = general_purpose::STANDARD
+        .decode(&obj.data)
+        .map_err(|_| StoreFailure::InvalidBase64)?;
+    let size = data.len() as u64;
+    
+    let _permit = state.upload_slots.acquire().await
+        .map_err(|_| StoreFailure::StorageError)?;
+    
+    state.storage
+        .store_object_batched_async(repo_hash, &obj.object_id, data)
+        .await
+        .map_err(|e| {
+            tracing::warn!(repo = %repo_hash, object = %obj.object_id, error = %e, "Failed to store object");
+            if e.is_disk_full() {
+                StoreFailure::StorageFull
+            } else {
+                StoreFailure::StorageError
+            }
+        })?;
+    state.object_cache.invalidate(repo_hash, &obj.object_id);
+    state.load_shed.add_storage_used(size);
+    
+    Ok(StoreOutcome::Stored)
+}
+
+/// Count a repo that just received objects as hosted, and queue it for
+/// announcement if that is new
+async fn record_store(state: &NodeState, repo_hash: &str) {
+    let newly_hosted = {
         let mut repos = state.hosted_repos.write().await;
-        if !repos.contains(&repo_hash) {
-            repos.push(repo_hash);
+        let new = !repos.iter().any(|r| r == repo_hash);
+        if new {
+            repos.push(repo_hash.to_string());
         }
+        new
+    };
+    state.store_announcements.stored(repo_hash, newly_hosted);
+}
+
+/// Whether a repo already holds `max_objects_per_repo` objects. Uploads
+/// in flight are checked together, so a burst may pass it by a few.
+async fn at_object_limit(state: &NodeState, repo_hash: &str) -> Result<bool, HyruleError> {
+    object_limit_reached(&state.storage, repo_hash, state.config.max_objects_per_repo).await
+}
+
+async fn object_limit_reached(
+    storage: &Arc<storage::GitStorage>,
+    repo_hash: &str,
+    limit: u64,
+) -> Result<bool, HyruleError> {
+    Ok(limit > 0 && storage.object_count_async(repo_hash).await? >= limit)
+}
+
+fn batch_status(uploaded: usize, failed: usize) -> BatchStatus {
+    match (uploaded, failed) {
+        (_, 0) => BatchStatus::Complete,
+        (0, _) => BatchStatus::Failed,
+        _ => BatchStatus::Partial,
     }
-    
-    Ok(Json(BatchStoreResponse { uploaded, failed }))
 }
 
 async fn list_objects(
@@ -217,8 +987,8 @@ async fn list_objects(
     Path(repo_hash): Path<String>,
 ) -> Result<Json<ListObjectsResponse>, StatusCode> {
     let objects = state.storage
-        .list_objects(&repo_hash)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .list_objects_async(&repo_hash)
+        .await?;
     
     let count = objects.len();
     
@@ -231,12 +1001,134 @@ async fn update_ref(
     Json(payload): Json<UpdateRefRequest>,
 ) -> Result<StatusCode, StatusCode> {
     state.storage
-        .update_ref(&repo_hash, &payload.ref_name, &payload.commit_id)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .update_ref_async(
+            &repo_hash,
+            &payload.ref_name,
+            &payload.commit_id,
+            payload.expected_old.as_deref(),
+        )
+        .await?;
     
     Ok(StatusCode::OK)
 }
 
+/// Apply several ref updates atomically, e.g. a branch and its tags in
+/// one push. Returns 409 with per-ref results if any lease is stale.
+async fn batch_update_refs(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+    Json(req): Json<BatchRefRequest>,
+) -> Result<(StatusCode, Json<BatchRefResponse>), StatusCode> {
+    let updates: Vec<RefUpdate> = req.updates
+        .into_iter()
+        .map(|u| RefUpdate {
+            ref_name: u.ref_name,
+            commit_id: u.commit_id,
+            expected_old: u.expected_old,
+        })
+        .collect();
+    
+    let mut seen = std::collections::HashSet::new();
+    let valid = updates
+        .iter()
+        .all(|u| storage::is_valid_ref_name(&u.ref_name) && seen.insert(u.ref_name.as_str()));
+    if updates.is_empty() || !valid {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let conflicts = state.storage
+        .update_refs_async(&repo_hash, updates.clone())
+        .await?;
+    
+    let applied = conflicts.is_empty();
+    let results = updates
+        .into_iter()
+        .map(|u| match conflicts.iter().find(|c| c.ref_name == u.ref_name) {
+            Some(conflict) => RefUpdateResult {
+                ref_name: u.ref_name,
+                status: "conflict",
+                actual: conflict.actual.clone(),
+            },
+            None => RefUpdateResult {
+                ref_name: u.ref_name,
+                status: if applied { "updated" } else { "skipped" },
+                actual: None,
+            },
+        })
+        .collect();
+    
+    let status = if applied { StatusCode::OK } else { StatusCode::CONFLICT };
+    Ok((status, Json(BatchRefResponse { applied, results })))
+}
+
+async fn delete_ref(
+    State(state): State<NodeState>,
+    Path((repo_hash, ref_name)): Path<(String, String)>,
+    Query(query): Query<DeleteRefQuery>,
+) -> Result<StatusCode, StatusCode> {
+    let decoded_ref = urlencoding::decode(&ref_name)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    
+    if !storage::is_valid_ref_name(&decoded_ref) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    state.storage
+        .delete_ref_async(&repo_hash, &decoded_ref, query.force)
+        .await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a single object, e.g. one no ref leads to any more
+async fn delete_object(
+    State(state): State<NodeState>,
+    Path((repo_hash, object_id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    if !storage::is_object_id(&object_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let deleted = state.storage
+        .delete_object_async(&repo_hash, &object_id)
+        .await?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    state.object_cache.invalidate(&repo_hash, &object_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// All branches and tags of a repo, with HEAD first when it resolves
+async fn list_refs(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+) -> Result<Json<ListRefsResponse>, StatusCode> {
+    if !state.hosted_repos.read().await.contains(&repo_hash) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    let refs = state.storage
+        .list_refs_async(&repo_hash)
+        .await?;
+    
+    let head = state.storage
+        .read_head_async(&repo_hash)
+        .await
+        .ok()
+        .and_then(|head| head.commit_id)
+        .map(|commit_id| ("HEAD".to_string(), commit_id));
+    
+    let refs = head
+        .into_iter()
+        .chain(refs)
+        .map(|(name, commit_id)| RefEntry { name, commit_id })
+        .collect();
+    
+    Ok(Json(ListRefsResponse { refs }))
+}
+
 async fn get_ref(
     State(state): State<NodeState>,
     Path((repo_hash, ref_name)): Path<(String, String)>,
@@ -244,20 +1136,46 @@ async fn get_ref(
     let decoded_ref = urlencoding::decode(&ref_name)
         .map_err(|_| StatusCode::BAD_REQUEST)?;
     
-    let commit_id = state.storage
-        .read_ref(&repo_hash, &decoded_ref)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let commit_id = state.storage.read_ref_async(&repo_hash, &decoded_ref).await?;
     
     Ok(commit_id)
 }
 
+async fn get_head(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+) -> Result<Json<HeadResponse>, StatusCode> {
+    if !state.hosted_repos.read().await.contains(&repo_hash) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    let head = state.storage.read_head_async(&repo_hash).await?;
+    
+    Ok(Json(HeadResponse {
+        target: head.target,
+        commit_id: head.commit_id,
+    }))
+}
+
+async fn set_head(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+    Json(req): Json<SetHeadRequest>,
+) -> Result<Json<HeadResponse>, StatusCode> {
+    if !state.hosted_repos.read().await.contains(&repo_hash) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    state.storage.set_head_async(&repo_hash, &req.target).await?;
+    
+    get_head(State(state), Path(repo_hash)).await
+}
+
 async fn init_repo(
     State(state): State<NodeState>,
     Path(repo_hash): Path<String>,
 ) -> Result<StatusCode, StatusCode> {
-    state.storage
-        .init_repo(&repo_hash)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.storage.init_repo(&repo_hash)?;
     
     {
         let mut repos = state.hosted_repos.write().await;
@@ -269,20 +1187,445 @@ async fn init_repo(
     Ok(StatusCode::CREATED)
 }
 
+async fn get_repo_stats(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+) -> Result<Json<RepoStats>, StatusCode> {
+    if !state.hosted_repos.read().await.contains(&repo_hash) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    let stats = state.repo_stats.read().await
+        .get(&repo_hash)
+        .cloned()
+        .unwrap_or_default();
+    
+    Ok(Json(stats))
+}
+
 async fn get_packfile(
     State(state): State<NodeState>,
     Path(repo_hash): Path<String>,
-) -> Result<Vec<u8>, StatusCode> {
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if !state.hosted_repos.read().await.contains(&repo_hash) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
     let pack_data = state.storage
-        .create_pack(&repo_hash)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .create_pack_async(&repo_hash)
+        .await?;
     
     {
         let mut stats = state.stats.write().await;
         stats.bytes_served += pack_data.len() as u64;
     }
+    state.record_repo_access(&repo_hash, pack_data.len() as u64).await;
     
-    Ok(pack_data)
+    let filename = format!("{}.pack", repo_hash);
+    Ok(download(&request_headers, "application/x-git-packfile", &filename, pack_data.into()))
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectsSinceRequest {
+    /// Objects the client has. Whatever they reference is assumed to be
+    /// there too, so listing the commits it has is enough.
+    #[serde(default)]
+    known: Vec<String>,
+    /// Only send what this ref needs; every ref when unset
+    #[serde(default, rename = "ref")]
+    ref_name: Option<String>,
+    /// Commit the client has `ref` at
+    #[serde(default)]
+    commit: Option<String>,
 }
 
+/// Pack of the objects reachable from the repo's refs (or one ref) that
+/// the client doesn't already have, for incremental fetches
+async fn objects_since(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+    Json(request): Json<ObjectsSinceRequest>,
+) -> Result<Response, StatusCode> {
+    if !state.storage.repo_path(&repo_hash).exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    let mut haves = request.known;
+    haves.extend(request.commit);
+    if !haves.iter().all(|id| storage::is_object_id(id)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let tips = match &request.ref_name {
+        Some(ref_name) => {
+            if !storage::is_valid_ref_name(ref_name) {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            let commit = state.storage.read_ref_async(&repo_hash, ref_name).await?;
+            vec![commit]
+        }
+        None => state.storage.list_refs_async(&repo_hash).await?
+            .into_iter()
+            .map(|(_, commit)| commit)
+            .collect(),
+    };
+    
+    let pack_data = state.storage
+        .pack_since_async(&repo_hash, tips, haves)
+        .await?;
+    
+    {
+        let mut stats = state.stats.write().await;
+        stats.bytes_served += pack_data.len() as u64;
+    }
+    state.record_repo_access(&repo_hash, pack_data.len() as u64).await;
+    
+    Ok(([(header::CONTENT_TYPE, "application/x-git-packfile")], pack_data).into_response())
+}
 
+/// Serve a download, honouring a single `Range: bytes=...` request so
+/// interrupted transfers can be resumed
+fn download(request_headers: &HeaderMap, content_type: &'static str, filename: &str, data: Bytes) -> Response {
+    let len = data.len() as u64;
+    let range = request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| parse_range(v, len));
+    
+    match range {
+        None | Some(Ok(None)) => (download_headers(content_type, filename, data.len()), data).into_response(),
+        Some(Ok(Some((start, end)))) => {
+            let slice = data.slice(start as usize..=end as usize);
+            let mut headers = download_headers(content_type, filename, slice.len());
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::PARTIAL_CONTENT, headers, slice).into_response()
+        }
+        Some(Err(())) => {
+            let mut headers = HeaderMap::new();
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", len)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()
+        }
+    }
+}
+
+/// Parse a `Range` header against a body of `len` bytes into an inclusive
+/// (start, end). `Ok(None)` means serve the whole body: other units and
+/// multi-range requests are ignored, as RFC 9110 allows.
+fn parse_range(value: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    
+    let (first, last) = spec.trim().split_once('-').ok_or(())?;
+    let (start, end) = match (first.trim(), last.trim()) {
+        // Suffix range: the last N bytes
+        ("", suffix) => {
+            let n: u64 = suffix.parse().map_err(|_| ())?;
+            if n == 0 {
+                return Err(());
+            }
+            (len.saturating_sub(n), len.saturating_sub(1))
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, len.saturating_sub(1)),
+        (start, end) => {
+            let start: u64 = start.parse().map_err(|_| ())?;
+            let end: u64 = end.parse().map_err(|_| ())?;
+            (start, end.min(len.saturating_sub(1)))
+        }
+    };
+    
+    if len == 0 || start >= len || start > end {
+        return Err(());
+    }
+    
+    Ok(Some((start, end)))
+}
+
+/// Content headers for raw object and pack downloads
+fn download_headers(content_type: &'static str, filename: &str, len: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    
+    // Filenames come from the request path; only echo back plain ids
+    let safe = filename.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_');
+    if safe {
+        if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename)) {
+            headers.insert(header::CONTENT_DISPOSITION, value);
+        }
+    }
+    
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_replication_lag() {
+        let started = Instant::now();
+        let at = |secs: u64| started + Duration::from_secs(secs);
+        let max_lag = Duration::from_secs(600);
+        let mut stats = NodeStats::default();
+        
+        // A fresh node gets one max_lag to complete its first pass
+        assert!(replication_lag(&stats, max_lag, started, at(600)).is_empty());
+        assert_eq!(replication_lag(&stats, max_lag, started, at(601)), vec!["no replication pass completed in 601s"]);
+        
+        stats.last_replication_pass = Some(at(500));
+        stats.set_repos_missing(2, at(100));
+        // Still missing at the next sync: counted from when it started
+        stats.set_repos_missing(1, at(400));
+        assert_eq!(replication_lag(&stats, max_lag, started, at(701)), vec!["1 assigned repos missing for 601s"]);
+        
+        stats.set_repos_missing(0, at(800));
+        assert!(replication_lag(&stats, max_lag, started, at(900)).is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_compresses_json_but_not_git_objects() {
+        // Realistic object ids: random hex, so only the JSON framing and
+        // the hex alphabet compress
+        let ids: Vec<String> = (0..2000u32)
+            .map(|i| crate::crypto::hash_data(&i.to_le_bytes())[..40].to_string())
+            .collect();
+        let json_len = serde_json::to_vec(&ids).unwrap().len();
+        
+        let app = Router::new()
+            .route("/objects", get(move || {
+                let ids = ids.clone();
+                async move { Json(ids) }
+            }))
+            .route("/object", get(|| async {
+                let headers = download_headers("application/x-git-loose-object", "abc", 4096);
+                (headers, vec![b'x'; 4096])
+            }))
+            .layer(compression_layer());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        let client = reqwest::Client::new();
+        
+        let response = client.get(format!("http://{}/objects", addr))
+            .header("accept-encoding", "gzip")
+            .send().await.unwrap();
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        let compressed_len = response.bytes().await.unwrap().len();
+        // Roughly 86 KB -> 49 KB for 2000 ids
+        assert!(compressed_len * 10 < json_len * 7, "{} vs {}", compressed_len, json_len);
+        
+        let response = client.get(format!("http://{}/object", addr))
+            .header("accept-encoding", "gzip")
+            .send().await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+        assert_eq!(response.bytes().await.unwrap().len(), 4096);
+    }
+    
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Ok(Some((500, 999))));
+        
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=5-2", 1000), Err(()));
+        assert_eq!(parse_range("bytes=abc", 1000), Err(()));
+        
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+    }
+    
+    #[tokio::test]
+    async fn test_range_request_returns_slice() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let expected = data[1000..2000].to_vec();
+        
+        let app = Router::new().route("/pack", get(move |headers: HeaderMap| {
+            let data = data.clone();
+            async move { download(&headers, "application/x-git-packfile", "repo.pack", data.into()) }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/pack", addr);
+        
+        let response = client.get(&url).header("range", "bytes=1000-1999").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 1000-1999/4096");
+        assert_eq!(response.headers()["content-length"], "1000");
+        assert_eq!(response.bytes().await.unwrap().to_vec(), expected);
+        
+        let response = client.get(&url).header("range", "bytes=5000-").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()["content-range"], "bytes */4096");
+        
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        assert_eq!(response.bytes().await.unwrap().len(), 4096);
+    }
+    
+    #[test]
+    fn test_batch_status() {
+        assert_eq!(batch_status(3, 0), BatchStatus::Complete);
+        assert_eq!(batch_status(0, 0), BatchStatus::Complete);
+        assert_eq!(batch_status(2, 1), BatchStatus::Partial);
+        assert_eq!(batch_status(0, 2), BatchStatus::Failed);
+        
+        let failure = FailedObject {
+            object_id: "abc".to_string(),
+            reason: StoreFailure::InvalidBase64,
+        };
+        assert_eq!(
+            serde_json::to_value(&failure).unwrap(),
+            serde_json::json!({ "object_id": "abc", "reason": "invalid_base64" })
+        );
+    }
+    
+    #[test]
+    fn test_storage_error_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage::GitStorage::new(dir.path()).unwrap();
+        let repo = "ab".repeat(32);
+        let object = "a".repeat(40);
+        let status = |e: HyruleError| StatusCode::from(e);
+        
+        assert_eq!(status(storage.read_object(&repo, &object).unwrap_err()), StatusCode::NOT_FOUND);
+        assert_eq!(status(storage.read_object("../x", &object).unwrap_err()), StatusCode::BAD_REQUEST);
+        assert_eq!(status(storage.set_head(&repo, "main").unwrap_err()), StatusCode::BAD_REQUEST);
+        
+        storage.update_ref(&repo, "refs/heads/main", &object, None).unwrap();
+        let stale = storage.update_ref(&repo, "refs/heads/main", &object, Some(storage::ZERO_ID));
+        assert_eq!(status(stale.unwrap_err()), StatusCode::CONFLICT);
+        
+        let full = HyruleError::Io(std::io::Error::from(std::io::ErrorKind::StorageFull));
+        assert_eq!(status(full), StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(status(anyhow::anyhow!("boom").into()), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    
+    #[tokio::test]
+    async fn test_cors_preflight_allows_writes() {
+        use tower::ServiceExt;
+        
+        let app = Router::new()
+            .route("/repos/{hash}", axum::routing::delete(|| async { "deleted" }))
+            .layer(cors_layer(&["https://dashboard.example".to_string()]));
+        
+        for method in ["PUT", "DELETE"] {
+            let request = axum::http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/repos/abc")
+                .header(header::ORIGIN, "https://dashboard.example")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert!(response.status().is_success());
+            let allowed = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap();
+            assert!(allowed.contains(method), "{} not in {}", method, allowed);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_ref_names_cannot_escape_repo() {
+        use tower::ServiceExt;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let state = NodeState::for_tests(crate::config::NodeConfig::generate(), &dir.path().join("store"));
+        std::fs::write(dir.path().join("secret"), b"hunter2").unwrap();
+        let repo = "ab".repeat(32);
+        let object = "a".repeat(40);
+        state.storage.init_repo(&repo).unwrap();
+        state.storage.update_ref(&repo, "refs/heads/main", &object, None).unwrap();
+        let app = create_router(state.clone());
+        
+        let get = |path: String| {
+            let app = app.clone();
+            async move {
+                let request = axum::http::Request::get(path).body(axum::body::Body::empty()).unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+        assert_eq!(get(format!("/repos/{}/refs/refs%2Fheads%2Fmain", repo)).await, StatusCode::OK);
+        assert_eq!(get(format!("/repos/{}/refs/HEAD", repo)).await, StatusCode::OK);
+        assert_eq!(get(format!("/repos/{}/refs/..%2F..%2Fsecret", repo)).await, StatusCode::BAD_REQUEST);
+        assert_eq!(get(format!("/repos/{}/refs/refs%2F..%2F..%2F..%2Fsecret", repo)).await, StatusCode::BAD_REQUEST);
+        assert_eq!(get("/repos/..%2F..%2Fsecret/refs/refs%2Fheads%2Fmain".to_string()).await, StatusCode::BAD_REQUEST);
+        
+        // Writes are held to the same names
+        let escape = state.storage.update_ref(&repo, "../../evil", &object, None);
+        assert_eq!(StatusCode::from(escape.unwrap_err()), StatusCode::BAD_REQUEST);
+        assert!(!dir.path().join("evil").exists());
+    }
+    
+    #[tokio::test]
+    async fn test_failed_batch_does_not_host_repo() {
+        use tower::ServiceExt;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let state = NodeState::for_tests(crate::config::NodeConfig::generate(), &dir.path().join("store"));
+        let repo = "ab".repeat(32);
+        let app = create_router(state.clone());
+        
+        let body = serde_json::json!({ "objects": [{ "object_id": "a".repeat(40), "data": "not base64!" }] });
+        let request = axum::http::Request::post(format!("/repos/{}/objects/batch", repo))
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.hosted_repos.read().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_object_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(storage::GitStorage::new(dir.path()).unwrap());
+        let repo = "ab".repeat(32);
+        
+        for i in 0..3u8 {
+            let data = format!("blob 1\0{}", i).into_bytes();
+            storage.store_object(&repo, &crate::crypto::hash_data(&data)[..40], &data).unwrap();
+        }
+        
+        assert!(!object_limit_reached(&storage, &repo, 4).await.unwrap());
+        assert!(object_limit_reached(&storage, &repo, 3).await.unwrap());
+        // 0 disables the limit
+        assert!(!object_limit_reached(&storage, &repo, 0).await.unwrap());
+        
+        let failure = serde_json::to_value(StoreFailure::TooManyObjects).unwrap();
+        assert_eq!(failure, "too_many_objects");
+    }
+    
+    #[test]
+    fn test_decode_batch_limits() {
+        let obj = serde_json::json!({ "object_id": "abc", "data": "eA==" });
+        let body = |n: usize| serde_json::to_vec(&serde_json::json!({ "objects": vec![obj.clone(); n] })).unwrap();
+        
+        assert_eq!(decode_batch(&body(3), 3).unwrap().objects.len(), 3);
+        assert!(matches!(decode_batch(&body(4), 3), Err(BatchDecodeError::TooManyObjects)));
+        
+        // Anything past the limit is never looked at, even if malformed
+        let mut truncated = body(4);
+        truncated.truncate(truncated.len() - 10);
+        assert!(matches!(decode_batch(&truncated, 3), Err(BatchDecodeError::TooManyObjects)));
+        
+        assert!(matches!(decode_batch(&truncated, 10), Err(BatchDecodeError::Invalid(_))));
+        assert!(matches!(decode_batch(b"{}", 10), Err(BatchDecodeError::Invalid(_))));
+    }
+}
@@ -0,0 +1,237 @@
+// hyrule-node/src/auth.rs
+//
+// Shared bearer token for the operator's own admin endpoints. This is for
+// trusted local administration only; it is not how nodes authenticate to
+// each other. Private deployments can also require a separate peer token
+// for reads, which the nodes of the deployment present to each other.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Probe endpoints that stay open even when reads require auth
+const OPEN_PATHS: &[&str] = &["/health", "/ready"];
+
+/// Token settings the auth middleware runs with
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    pub admin_token: Option<String>,
+    /// Token peers present for reads. Never accepted on admin routes.
+    pub peer_token: Option<String>,
+    /// Require a token on every route except health probes
+    pub require_auth_for_reads: bool,
+}
+
+impl AuthConfig {
+    pub fn from_config(config: &crate::config::NodeConfig) -> Self {
+        Self {
+            admin_token: config.admin_token.clone(),
+            peer_token: config.peer_token.clone(),
+            require_auth_for_reads: config.require_auth_for_reads,
+        }
+    }
+
+    fn requires_token(&self, method: &Method, path: &str) -> bool {
+        is_admin_request(method, path)
+            || (self.require_auth_for_reads && !OPEN_PATHS.contains(&path))
+    }
+
+    /// Whether the request presents the configured admin token
+    pub fn is_authenticated(&self, headers: &HeaderMap) -> bool {
+        match (self.admin_token.as_deref(), bearer_token(headers)) {
+            (Some(expected), Some(token)) => token_matches(token, expected),
+            _ => false,
+        }
+    }
+
+    /// Whether the request may read: it presents the admin token or the
+    /// peer token
+    fn may_read(&self, headers: &HeaderMap) -> bool {
+        self.is_authenticated(headers)
+            || match (self.peer_token.as_deref(), bearer_token(headers)) {
+                (Some(expected), Some(token)) => token_matches(token, expected),
+                _ => false,
+            }
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Whether a request is administrative: everything under `/admin/` and
+/// every DELETE
+fn is_admin_request(method: &Method, path: &str) -> bool {
+    path.starts_with("/admin/") || method == Method::DELETE
+}
+
+/// Compare tokens in constant time. Hashing first also hides the length
+/// of the configured token; `blake3::Hash` equality is constant time.
+fn token_matches(presented: &str, expected: &str) -> bool {
+    blake3::hash(presented.as_bytes()) == blake3::hash(expected.as_bytes())
+}
+
+fn unauthorized(message: &'static str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        message,
+    )
+        .into_response()
+}
+
+/// Middleware requiring `Authorization: Bearer <admin_token>` on admin and
+/// DELETE routes. When `require_auth_for_reads` is set, every other route
+/// but the probes needs the admin token or the peer token. With no token
+/// configured the admin routes are refused.
+pub async fn require_admin_token(
+    State(auth): State<AuthConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !auth.requires_token(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    if !is_admin_request(request.method(), request.uri().path()) {
+        return if auth.may_read(request.headers()) {
+            next.run(request).await
+        } else {
+            unauthorized("Missing or invalid token")
+        };
+    }
+
+    if auth.admin_token.is_none() {
+        return unauthorized("Admin API disabled: set admin_token in the config");
+    }
+
+    if auth.is_authenticated(request.headers()) {
+        next.run(request).await
+    } else {
+        unauthorized("Missing or invalid admin token")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        routing::{delete, get},
+        Router,
+    };
+
+    async fn serve(admin_token: Option<String>, require_auth_for_reads: bool) -> String {
+        serve_with(AuthConfig {
+            admin_token,
+            peer_token: None,
+            require_auth_for_reads,
+        })
+        .await
+    }
+
+    async fn serve_with(auth: AuthConfig) -> String {
+        let app = Router::new()
+            .route("/admin/requests", get(|| async { "admin" }))
+            .route("/status", get(|| async { "status" }))
+            .route("/health", get(|| async { "ok" }))
+            .route("/repos/{hash}", delete(|| async { "deleted" }))
+            .layer(axum::middleware::from_fn_with_state(auth, require_admin_token));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_require_token() {
+        let base = serve(Some("s3cret".to_string()), false).await;
+        let client = reqwest::Client::new();
+
+        let status = |r: reqwest::Response| r.status().as_u16();
+
+        let r = client.get(format!("{}/status", base)).send().await.unwrap();
+        assert_eq!(status(r), 200);
+
+        let r = client.get(format!("{}/admin/requests", base)).send().await.unwrap();
+        assert_eq!(status(r), 401);
+
+        let r = client.get(format!("{}/admin/requests", base))
+            .bearer_auth("wrong")
+            .send().await.unwrap();
+        assert_eq!(status(r), 401);
+
+        let r = client.get(format!("{}/admin/requests", base))
+            .bearer_auth("s3cret")
+            .send().await.unwrap();
+        assert_eq!(status(r), 200);
+
+        let r = client.delete(format!("{}/repos/abc", base)).send().await.unwrap();
+        assert_eq!(status(r), 401);
+
+        let r = client.delete(format!("{}/repos/abc", base))
+            .bearer_auth("s3cret")
+            .send().await.unwrap();
+        assert_eq!(status(r), 200);
+    }
+
+    #[tokio::test]
+    async fn test_admin_disabled_without_token() {
+        let base = serve(None, false).await;
+        let client = reqwest::Client::new();
+
+        let r = client.get(format!("{}/admin/requests", base))
+            .bearer_auth("anything")
+            .send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn test_reads_require_token_when_configured() {
+        let base = serve(Some("s3cret".to_string()), true).await;
+        let client = reqwest::Client::new();
+
+        let r = client.get(format!("{}/status", base)).send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 401);
+
+        let r = client.get(format!("{}/status", base))
+            .bearer_auth("s3cret")
+            .send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 200);
+
+        // Health probes stay open
+        let r = client.get(format!("{}/health", base)).send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_peer_token_reads_but_never_administers() {
+        let base = serve_with(AuthConfig {
+            admin_token: Some("s3cret".to_string()),
+            peer_token: Some("peers".to_string()),
+            require_auth_for_reads: true,
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let r = client.get(format!("{}/status", base))
+            .bearer_auth("peers")
+            .send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 200);
+
+        let r = client.get(format!("{}/admin/requests", base))
+            .bearer_auth("peers")
+            .send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 401);
+
+        let r = client.delete(format!("{}/repos/abc", base))
+            .bearer_auth("peers")
+            .send().await.unwrap();
+        assert_eq!(r.status().as_u16(), 401);
+    }
+}
@@ -0,0 +1,233 @@
+// hyrule-node/src/batch.rs
+//
+// `POST /batch` runs several API requests in one round trip, which matters
+// over Tor where every request costs seconds. Sub-requests go through the
+// same routes, auth checks and rate limit as if they had been sent on
+// their own.
+
+use crate::rate_limit::ClientKey;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    Extension, Json, Router,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+/// Most sub-requests one batch may carry
+pub const MAX_BATCH_REQUESTS: usize = 100;
+
+/// Most response body bytes one batch buffers, across its sub-requests.
+/// Sub-requests whose body would pass it get a 413 of their own.
+const MAX_BATCH_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct SubRequest {
+    method: String,
+    /// Path and query, e.g. `/repos/{hash}/refs`
+    path: String,
+    /// Sent as a JSON body
+    #[serde(default)]
+    body: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SubResponse {
+    status: u16,
+    /// JSON responses are embedded as-is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<serde_json::Value>,
+    /// Anything else, e.g. raw objects, is base64 encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+impl SubResponse {
+    fn error(status: StatusCode) -> Self {
+        Self { status: status.as_u16(), body: None, body_base64: None }
+    }
+}
+
+/// Run sub-requests in order against `routes`, passing the caller's
+/// `Authorization` header and connection on to each, so every sub-request
+/// is charged to the caller's rate limit
+pub async fn run_batch(
+    State(routes): State<Router>,
+    client: Option<Extension<ConnectInfo<ClientKey>>>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<SubRequest>>,
+) -> Result<Json<Vec<SubResponse>>, StatusCode> {
+    if requests.len() > MAX_BATCH_REQUESTS {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let caller = Caller {
+        authorization: headers.get(header::AUTHORIZATION),
+        client: client.map(|Extension(info)| info),
+    };
+    Ok(Json(run_all(&routes, requests, &caller, MAX_BATCH_RESPONSE_BYTES).await))
+}
+
+/// What each sub-request inherits from the batch request
+struct Caller<'a> {
+    authorization: Option<&'a HeaderValue>,
+    client: Option<ConnectInfo<ClientKey>>,
+}
+
+async fn run_all(routes: &Router, requests: Vec<SubRequest>, caller: &Caller<'_>, max_bytes: usize) -> Vec<SubResponse> {
+    let mut remaining = max_bytes;
+    let mut responses = Vec::with_capacity(requests.len());
+    for request in requests {
+        responses.push(run_one(routes, request, caller, &mut remaining).await);
+    }
+    responses
+}
+
+/// Run one sub-request, taking its body out of the `remaining` budget
+async fn run_one(routes: &Router, sub: SubRequest, caller: &Caller<'_>, remaining: &mut usize) -> SubResponse {
+    let Ok(method) = sub.method.to_ascii_uppercase().parse::<Method>() else {
+        return SubResponse::error(StatusCode::BAD_REQUEST);
+    };
+    // Batches don't nest
+    if !sub.path.starts_with('/') || sub.path.split('?').next() == Some("/batch") {
+        return SubResponse::error(StatusCode::BAD_REQUEST);
+    }
+
+    let mut builder = Request::builder().method(method).uri(&sub.path);
+    if let Some(value) = caller.authorization {
+        builder = builder.header(header::AUTHORIZATION, value);
+    }
+    if let Some(client) = caller.client {
+        builder = builder.extension(client);
+    }
+    let request = match sub.body {
+        Some(body) => builder
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => builder.body(Body::empty()),
+    };
+    let Ok(request) = request else {
+        return SubResponse::error(StatusCode::BAD_REQUEST);
+    };
+
+    let Ok(response) = routes.clone().oneshot(request).await;
+    let status = response.status().as_u16();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+
+    // Bodies are only read up to what the batch has left
+    let Ok(bytes) = to_bytes(response.into_body(), *remaining).await else {
+        return SubResponse::error(StatusCode::PAYLOAD_TOO_LARGE);
+    };
+    *remaining -= bytes.len();
+    if bytes.is_empty() {
+        return SubResponse { status, body: None, body_base64: None };
+    }
+
+    match is_json.then(|| serde_json::from_slice(&bytes).ok()).flatten() {
+        Some(json) => SubResponse { status, body: Some(json), body_base64: None },
+        None => SubResponse {
+            status,
+            body: None,
+            body_base64: Some(general_purpose::STANDARD.encode(&bytes)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{get, post};
+
+    fn routes() -> Router {
+        Router::new()
+            .route("/json", get(|| async { Json(serde_json::json!({"ok": true})) }))
+            .route("/raw", get(|| async { vec![0u8, 1, 2] }))
+            .route("/echo", post(|Json(v): Json<serde_json::Value>| async move { Json(v) }))
+            .route("/whoami", get(|headers: HeaderMap| async move {
+                match headers.get(header::AUTHORIZATION) {
+                    Some(_) => StatusCode::OK,
+                    None => StatusCode::UNAUTHORIZED,
+                }
+            }))
+    }
+
+    fn sub(method: &str, path: &str, body: Option<serde_json::Value>) -> SubRequest {
+        SubRequest { method: method.to_string(), path: path.to_string(), body }
+    }
+
+    async fn batch(requests: Vec<SubRequest>, headers: HeaderMap) -> Result<Vec<SubResponse>, StatusCode> {
+        run_batch(State(routes()), None, headers, Json(requests)).await.map(|Json(r)| r)
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_each_request() {
+        let responses = batch(
+            vec![
+                sub("get", "/json", None),
+                sub("GET", "/raw", None),
+                sub("POST", "/echo", Some(serde_json::json!([1, 2]))),
+                sub("GET", "/missing", None),
+                sub("POST", "/batch", Some(serde_json::json!([]))),
+                sub("NOT A METHOD", "/json", None),
+                sub("GET", "/whoami", None),
+            ],
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(responses[0], SubResponse { status: 200, body: Some(serde_json::json!({"ok": true})), body_base64: None });
+        assert_eq!(responses[1].body_base64.as_deref(), Some("AAEC"));
+        assert_eq!(responses[2].body, Some(serde_json::json!([1, 2])));
+        let statuses: Vec<u16> = responses[3..].iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![404, 400, 400, 401]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_forwards_auth_and_is_capped() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+        let responses = batch(vec![sub("GET", "/whoami", None)], headers).await.unwrap();
+        assert_eq!(responses[0].status, 200);
+
+        let too_many = (0..=MAX_BATCH_REQUESTS).map(|_| sub("GET", "/json", None)).collect();
+        assert_eq!(batch(too_many, HeaderMap::new()).await.unwrap_err(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_batch_response_bytes_are_capped() {
+        let routes = routes().route("/big", get(|| async { vec![7u8; 600] }));
+        let caller = Caller { authorization: None, client: None };
+        let requests = vec![
+            sub("GET", "/big", None),
+            sub("GET", "/big", None),
+            sub("GET", "/raw", None),
+        ];
+
+        // The second body doesn't fit in what's left, the small one still does
+        let statuses: Vec<u16> = run_all(&routes, requests, &caller, 1000).await.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![200, 413, 200]);
+    }
+
+    #[tokio::test]
+    async fn test_sub_requests_are_rate_limited() {
+        let mut config = crate::config::NodeConfig::generate();
+        config.rate_limit_per_sec = 1;
+        config.rate_limit_burst = 3;
+        let limiter = std::sync::Arc::new(crate::rate_limit::RateLimiter::from_config(&config).unwrap());
+        let routes = routes().layer(axum::middleware::from_fn_with_state(limiter, crate::rate_limit::limit));
+        let client = ConnectInfo(ClientKey::Addr("203.0.113.7".parse().unwrap()));
+        let caller = Caller { authorization: None, client: Some(client) };
+
+        // One batch can't spend more than the client's burst
+        let requests = (0..5).map(|_| sub("GET", "/json", None)).collect();
+        let statuses: Vec<u16> = run_all(&routes, requests, &caller, usize::MAX).await.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![200, 200, 200, 429, 429]);
+    }
+}
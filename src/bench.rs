@@ -0,0 +1,148 @@
+// hyrule-node/src/bench.rs
+use crate::config::NodeConfig;
+use crate::proxy::ProxyConfig;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// Timing of one download
+struct Sample {
+    first_byte: Duration,
+    total: Duration,
+    bytes: usize,
+}
+
+/// Latency percentiles and throughput over all runs
+#[derive(Debug, PartialEq)]
+struct Summary {
+    latency_median: Duration,
+    latency_p95: Duration,
+    first_byte_median: Duration,
+    /// Bytes per second over the body transfers
+    throughput: f64,
+}
+
+/// Nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarize(samples: &[Sample]) -> Summary {
+    let mut totals: Vec<Duration> = samples.iter().map(|s| s.total).collect();
+    let mut first_bytes: Vec<Duration> = samples.iter().map(|s| s.first_byte).collect();
+    totals.sort();
+    first_bytes.sort();
+
+    let bytes: usize = samples.iter().map(|s| s.bytes).sum();
+    let body_time: Duration = samples.iter().map(|s| s.total - s.first_byte).sum();
+    let throughput = if body_time.is_zero() {
+        0.0
+    } else {
+        bytes as f64 / body_time.as_secs_f64()
+    };
+
+    Summary {
+        latency_median: percentile(&totals, 0.5),
+        latency_p95: percentile(&totals, 0.95),
+        first_byte_median: percentile(&first_bytes, 0.5),
+        throughput,
+    }
+}
+
+/// Download `url` (the coordinator's node list by default) `runs` times
+/// over Tor and report latency, time to first byte and throughput
+pub async fn run(url: Option<String>, runs: u32) -> Result<()> {
+    let config = NodeConfig::load()?;
+    let proxy_config = ProxyConfig::from_config(&config);
+
+    if !proxy_config.enabled {
+        anyhow::bail!("Tor is disabled in config; set enable_proxy = true to benchmark it");
+    }
+    if runs == 0 {
+        anyhow::bail!("--runs must be at least 1");
+    }
+
+    let url = url.unwrap_or_else(|| format!("{}/api/nodes", config.hyrule_server));
+
+    println!("🧅 Bootstrapping Arti Tor client...");
+    let started = Instant::now();
+    proxy_config.init_tor_client().await?;
+    println!("✓ Bootstrapped in {:.1}s", started.elapsed().as_secs_f64());
+    println!();
+    println!("Downloading {} {} times", url, runs);
+
+    let client = proxy_config.build_client()?;
+    let mut samples = Vec::new();
+
+    for run in 1..=runs {
+        let started = Instant::now();
+        let result = async {
+            let response = client.get(&url).send().await?;
+            let first_byte = started.elapsed();
+            let status = response.status();
+            let body = response.bytes().await?;
+            if !status.is_success() {
+                anyhow::bail!("HTTP {}", status);
+            }
+            Ok(Sample { first_byte, total: started.elapsed(), bytes: body.len() })
+        }
+        .await;
+
+        match result {
+            Ok(sample) => {
+                println!(
+                    "  {:>3}: {:>7.0} ms, first byte {:>7.0} ms, {} bytes",
+                    run,
+                    sample.total.as_secs_f64() * 1000.0,
+                    sample.first_byte.as_secs_f64() * 1000.0,
+                    sample.bytes
+                );
+                samples.push(sample);
+            }
+            Err(e) => println!("  {:>3}: failed: {}", run, e),
+        }
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("Every download failed");
+    }
+
+    let summary = summarize(&samples);
+    println!();
+    println!("Successful:        {}/{}", samples.len(), runs);
+    println!("Latency median:    {:.0} ms", summary.latency_median.as_secs_f64() * 1000.0);
+    println!("Latency p95:       {:.0} ms", summary.latency_p95.as_secs_f64() * 1000.0);
+    println!("First byte median: {:.0} ms", summary.first_byte_median.as_secs_f64() * 1000.0);
+    println!("Throughput:        {:.1} KB/s", summary.throughput / 1024.0);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(first_byte_ms: u64, total_ms: u64, bytes: usize) -> Sample {
+        Sample {
+            first_byte: Duration::from_millis(first_byte_ms),
+            total: Duration::from_millis(total_ms),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let samples: Vec<Sample> = (1..=20).map(|i| sample(i * 10, i * 100, 1000)).collect();
+        let summary = summarize(&samples);
+
+        assert_eq!(summary.latency_median, Duration::from_millis(1000));
+        assert_eq!(summary.latency_p95, Duration::from_millis(1900));
+        assert_eq!(summary.first_byte_median, Duration::from_millis(100));
+        // 20 KB over 18.9 s of body transfers
+        assert!((summary.throughput - 20_000.0 / 18.9).abs() < 0.01);
+
+        let single = summarize(&[sample(5, 5, 0)]);
+        assert_eq!(single.latency_p95, Duration::from_millis(5));
+        assert_eq!(single.throughput, 0.0);
+    }
+}
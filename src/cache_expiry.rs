@@ -0,0 +1,224 @@
+// hyrule-node/src/cache_expiry.rs
+//
+// Replicas the replication loop pulls for availability are cached copies:
+// once `cache_ttl_days` pass without a read they are deleted, unless the
+// operator serves the repo explicitly with `hyrule-node serve`. Repos that
+// were uploaded here or served explicitly are never expired.
+
+use crate::jitter::JitteredInterval;
+use crate::storage::{write_atomic, GitStorage};
+use crate::{replication, NodeState};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Cached replicas and when each was last used, in the storage root.
+/// Written only by the running node.
+const CACHED_FILE: &str = "cached-repos.json";
+
+/// Repos served explicitly, in the storage root. Written only by the
+/// `serve` and `unserve` commands and re-read by every sweep.
+const SERVED_FILE: &str = "served-repos.json";
+
+/// How often cached replicas are checked for expiry
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Replicas held for availability, with the Unix time each was last read
+/// (or pulled, if it never was)
+pub struct CachedRepos {
+    path: PathBuf,
+    repos: Mutex<HashMap<String, i64>>,
+}
+
+impl CachedRepos {
+    pub fn load(storage: &GitStorage) -> Self {
+        let path = storage.base_path().join(CACHED_FILE);
+        let repos = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            repos: Mutex::new(repos),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        // Hold the lock across the write so saves land in order
+        let repos = self.repos.lock().unwrap();
+        Ok(write_atomic(&self.path, &serde_json::to_vec(&*repos)?)?)
+    }
+
+    /// Note a replica the replication loop just pulled
+    pub fn mark(&self, repo_hash: &str) {
+        self.mark_at(repo_hash, chrono::Utc::now().timestamp());
+    }
+
+    fn mark_at(&self, repo_hash: &str, now: i64) {
+        self.repos.lock().unwrap().insert(repo_hash.to_string(), now);
+    }
+
+    /// Stop treating a repo as a cached replica, e.g. once it's deleted
+    pub fn unmark(&self, repo_hash: &str) {
+        self.repos.lock().unwrap().remove(repo_hash);
+    }
+
+    /// Move a replica's last use forward to `at`, if that's later
+    fn touch(&self, repo_hash: &str, at: i64) {
+        if let Some(last_used) = self.repos.lock().unwrap().get_mut(repo_hash) {
+            *last_used = (*last_used).max(at);
+        }
+    }
+
+    /// Replicas unused for longer than `ttl` that aren't served explicitly
+    fn expired(&self, served: &HashSet<String>, ttl: Duration, now: i64) -> Vec<String> {
+        let cutoff = now.saturating_sub(ttl.as_secs().try_into().unwrap_or(i64::MAX));
+        let mut expired: Vec<String> = self
+            .repos
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(repo_hash, &last_used)| last_used < cutoff && !served.contains(*repo_hash))
+            .map(|(repo_hash, _)| repo_hash.clone())
+            .collect();
+        expired.sort();
+        expired
+    }
+}
+
+/// Repos the operator serves explicitly; these are never expired
+pub fn served(storage: &GitStorage) -> HashSet<String> {
+    fs::read(storage.base_path().join(SERVED_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Add a repo to the explicitly served set, or remove it
+pub fn set_served(storage: &GitStorage, repo_hash: &str, serve: bool) -> Result<()> {
+    let mut repos = served(storage);
+    let changed = if serve {
+        repos.insert(repo_hash.to_string())
+    } else {
+        repos.remove(repo_hash)
+    };
+    if changed {
+        let mut repos: Vec<String> = repos.into_iter().collect();
+        repos.sort();
+        write_atomic(&storage.base_path().join(SERVED_FILE), &serde_json::to_vec(&repos)?)?;
+    }
+    Ok(())
+}
+
+/// Delete cached replicas nobody has read for `cache_ttl_days`, every
+/// [`SWEEP_INTERVAL`]
+pub async fn expiry_loop(state: NodeState) {
+    let ttl = Duration::from_secs(state.config.cache_ttl_days.saturating_mul(86_400));
+    let mut interval = JitteredInterval::new(&state.config.node_id, "cache expiry", SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        crate::tasks::record_run();
+
+        if state.maintenance.is_enabled() {
+            continue;
+        }
+
+        if let Err(e) = sweep(&state, ttl).await {
+            tracing::warn!(error = %e, "Cache expiry sweep failed");
+        }
+    }
+}
+
+async fn sweep(state: &NodeState, ttl: Duration) -> Result<()> {
+    let cached = &state.cached_repos;
+
+    // Reads since the last sweep count as use
+    for (repo_hash, stats) in state.repo_stats.read().await.iter() {
+        let last_accessed = stats
+            .last_accessed
+            .as_deref()
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok());
+        if let Some(at) = last_accessed {
+            cached.touch(repo_hash, at.timestamp());
+        }
+    }
+
+    let storage = state.storage.clone();
+    let served = tokio::task::spawn_blocking(move || served(&storage)).await?;
+    let expired = cached.expired(&served, ttl, chrono::Utc::now().timestamp());
+
+    for repo_hash in &expired {
+        match expire(state, repo_hash).await {
+            Ok(()) => tracing::info!(repo = %repo_hash, ttl_days = state.config.cache_ttl_days, "Expired unused cached replica"),
+            Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to expire cached replica"),
+        }
+    }
+
+    cached.save()
+}
+
+/// Delete a cached replica and stop advertising it
+async fn expire(state: &NodeState, repo_hash: &str) -> Result<()> {
+    let storage = state.storage.clone();
+    let hash = repo_hash.to_string();
+    tokio::task::spawn_blocking(move || storage.delete_repo(&hash)).await??;
+
+    state.forget_repos(&[repo_hash.to_string()]).await;
+    state.object_cache.invalidate_repo(repo_hash);
+    state.cached_repos.unmark(repo_hash);
+
+    // Otherwise the coordinator sync would pull it straight back
+    let client = state.proxy.build_client()?;
+    replication::withdraw_replica(&state.config.hyrule_server, &state.config.network_namespace, &state.config.node_id, repo_hash, &client).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    #[test]
+    fn test_expires_unused_replicas_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let cached = CachedRepos::load(&storage);
+        let ttl = Duration::from_secs(7 * DAY as u64);
+        let now = 100 * DAY;
+
+        cached.mark_at("idle", now - 10 * DAY);
+        cached.mark_at("read", now - 10 * DAY);
+        cached.mark_at("pinned", now - 10 * DAY);
+        cached.mark_at("fresh", now - DAY);
+        cached.touch("read", now - 2 * DAY);
+        // An older access doesn't move the last use back
+        cached.touch("fresh", now - 20 * DAY);
+        // Repos that weren't pulled as replicas are never tracked
+        cached.touch("uploaded", now);
+
+        set_served(&storage, "pinned", true).unwrap();
+        assert_eq!(cached.expired(&served(&storage), ttl, now), vec!["idle".to_string()]);
+
+        set_served(&storage, "pinned", false).unwrap();
+        assert_eq!(cached.expired(&served(&storage), ttl, now), vec!["idle".to_string(), "pinned".to_string()]);
+    }
+
+    #[test]
+    fn test_cached_repos_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let cached = CachedRepos::load(&storage);
+        cached.mark_at("aa", 5);
+        cached.mark_at("bb", 5);
+        cached.unmark("bb");
+        cached.save().unwrap();
+
+        let reloaded = CachedRepos::load(&storage);
+        assert_eq!(reloaded.expired(&HashSet::new(), Duration::ZERO, 10), vec!["aa".to_string()]);
+    }
+}
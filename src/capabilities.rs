@@ -0,0 +1,84 @@
+// hyrule-node/src/capabilities.rs
+//
+// What this build can do, so coordinators, peers and clients can
+// negotiate instead of guessing from the version string.
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+/// Node protocol version. Bump it for changes older peers can't cope with
+/// even after checking capabilities; additive features only need a new
+/// capability.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Features supported by this build. Clients match on these names, so an
+/// entry may be added or dropped but never renamed.
+const CAPABILITIES: &[&str] = &[
+    // `GET /repos/{hash}/pack` serves version 2 packfiles
+    "pack-v2",
+    // `info/refs` and `git-upload-pack` for `git clone`/`fetch`
+    "smart-http-upload-pack",
+    // `Range: bytes=` on object and pack downloads
+    "range-requests",
+    "batch-objects",
+    "batch-refs",
+    // `POST /repos/{hash}/objects/since` for incremental fetches
+    "objects-since",
+    // DHT announcements are signed by the announcing node
+    "signed-dht-announcements",
+    // `POST /batch` runs several requests in one round trip
+    "batch-requests",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub node_version: &'static str,
+    pub capabilities: Vec<&'static str>,
+    /// Network the node belongs to; peers on another one don't talk to it
+    pub network_namespace: String,
+}
+
+impl Capabilities {
+    pub fn current(network_namespace: &str) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            node_version: env!("CARGO_PKG_VERSION"),
+            capabilities: CAPABILITIES.to_vec(),
+            network_namespace: network_namespace.to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    fn supports(&self, capability: &str) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// `GET /capabilities`
+pub async fn get_capabilities(State(state): State<crate::NodeState>) -> Json<Capabilities> {
+    Json(Capabilities::current(&state.config.network_namespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = Capabilities::current("main");
+        assert_eq!(caps.protocol_version, PROTOCOL_VERSION);
+        assert!(caps.supports("smart-http-upload-pack"));
+        assert!(!caps.supports("delta-objects"));
+
+        let mut names = caps.capabilities.clone();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), CAPABILITIES.len());
+
+        let json = serde_json::to_value(&caps).unwrap();
+        assert_eq!(json["protocol_version"], PROTOCOL_VERSION);
+        assert!(json["capabilities"].as_array().unwrap().contains(&"range-requests".into()));
+        assert_eq!(json["network_namespace"], "main");
+    }
+}
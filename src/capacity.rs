@@ -0,0 +1,97 @@
+// hyrule-node/src/capacity.rs
+use crate::config::NodeConfig;
+use crate::storage::{self, DiskSpace};
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Storage capacity the node works with: fixed by the config, or a share
+/// of the disk holding the storage path when `capacity_auto` is set
+#[derive(Debug)]
+pub struct Capacity {
+    bytes: AtomicU64,
+    /// Share of the reachable disk space to use; None for a fixed capacity
+    auto_fraction: Option<f64>,
+    /// Disk the capacity was last sized from
+    detected: Mutex<Option<DiskSpace>>,
+}
+
+impl Capacity {
+    pub fn from_config(config: &NodeConfig) -> Self {
+        Self {
+            bytes: AtomicU64::new(config.total_capacity()),
+            auto_fraction: config.capacity_auto.then_some(config.capacity_auto_fraction),
+            detected: Mutex::new(None),
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.auto_fraction.is_some()
+    }
+
+    /// Disk figures from the last detection, None for a fixed capacity
+    pub fn detected(&self) -> Option<DiskSpace> {
+        *self.detected.lock().unwrap()
+    }
+
+    /// Re-size an automatic capacity from the disk holding `path`, given
+    /// the bytes the node stores now. A fixed capacity is left alone.
+    pub fn refresh(&self, path: &Path, used: u64) -> Result<u64> {
+        let Some(fraction) = self.auto_fraction else {
+            return Ok(self.bytes());
+        };
+
+        let disk = storage::disk_space(path)?;
+        let bytes = auto_capacity(disk, used, fraction);
+        self.bytes.store(bytes, Ordering::Relaxed);
+        *self.detected.lock().unwrap() = Some(disk);
+
+        Ok(bytes)
+    }
+}
+
+/// The node can grow into whatever is free on the disk, so its share is
+/// taken of what it already stores plus the free space
+fn auto_capacity(disk: DiskSpace, used: u64, fraction: f64) -> u64 {
+    let reachable = used.saturating_add(disk.available).min(disk.total);
+    (reachable as f64 * fraction) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_capacity() {
+        let disk = DiskSpace { total: 1000, available: 400 };
+        assert_eq!(auto_capacity(disk, 0, 0.5), 200);
+        assert_eq!(auto_capacity(disk, 100, 0.5), 250);
+
+        // Usage is counted at most up to the disk size
+        assert_eq!(auto_capacity(disk, 900, 1.0), 1000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = NodeConfig::generate();
+
+        let fixed = Capacity::from_config(&config);
+        assert_eq!(fixed.refresh(dir.path(), 0).unwrap(), config.storage_capacity);
+        assert!(fixed.detected().is_none());
+
+        config.capacity_auto = true;
+        let auto = Capacity::from_config(&config);
+        let bytes = auto.refresh(dir.path(), 0).unwrap();
+        let disk = auto.detected().unwrap();
+        assert!(disk.total >= disk.available);
+        assert_eq!(bytes, auto_capacity(disk, 0, config.capacity_auto_fraction));
+        assert_eq!(auto.bytes(), bytes);
+    }
+}
@@ -3,58 +3,336 @@
 // ============================================================================
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use anyhow::Result;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use crate::error::{bail, HyruleError, Result};
+use crate::crypto;
+
+/// Pins the config signing key from outside the config file
+const SIGNING_KEY_ENV: &str = "HYRULE_CONFIG_SIGNING_KEY";
+
+/// Settings `config show` never prints
+const SECRET_KEYS: &[&str] = &["private_key", "admin_token", "peer_token", "alert_webhook"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeConfig {
     /// Unique node identifier (hex string)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub node_id: String,
     
     /// Ed25519 public key (hex encoded)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub public_key: String,
     
     /// Ed25519 private key (hex encoded) - Keep this secure!
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub private_key: String,
     
+    /// File holding `node_id`, `public_key` and `private_key` instead of
+    /// this config, so editing settings never touches the keys. It should
+    /// be readable by its owner only.
+    #[serde(default)]
+    pub identity_path: Option<PathBuf>,
+    
     /// Hyrule server address (defaults to onion address)
+    #[serde(default = "default_hyrule_server")]
     pub hyrule_server: String,
     
+    /// Logical network this node belongs to, e.g. `main` or `staging`.
+    /// DHT announcements are keyed and signed per network and the
+    /// coordinator is told at registration, so nodes on different networks
+    /// never see each other's content.
+    #[serde(default = "default_network_namespace")]
+    pub network_namespace: String,
+    
     /// Port to listen on
+    #[serde(default = "default_port")]
     pub port: u16,
     
-    /// Storage path for repositories
+    /// IP address to bind the HTTP listener to (IPv4 or IPv6)
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    
+    /// Storage path for repositories. `~` and `$VAR` are expanded; unset
+    /// means a directory under the user's data dir.
+    #[serde(default = "default_storage_path")]
     pub storage_path: String,
     
     /// Storage capacity in bytes
+    #[serde(default = "default_storage_capacity")]
     pub storage_capacity: u64,
     
+    /// Size the capacity from the disk holding `storage_path` instead of
+    /// using `storage_capacity`, re-checked as the disk fills up. Unix
+    /// only, and not used with `storage_tiers`.
+    #[serde(default)]
+    pub capacity_auto: bool,
+    
+    /// Share of the space the node can reach, what it stores plus what is
+    /// free on the disk, used as capacity with `capacity_auto`
+    #[serde(default = "default_capacity_auto_fraction")]
+    pub capacity_auto_fraction: f64,
+    
+    /// Object stores, fastest first, e.g. an SSD then a large HDD. New
+    /// objects go to the first tier with room; hot repos are promoted and
+    /// idle ones demoted in the background. The first tier's path must be
+    /// `storage_path`. Empty means a single store at `storage_path`.
+    #[serde(default)]
+    pub storage_tiers: Vec<StorageTier>,
+    
+    /// Where replicas are assembled before going live, so readers never
+    /// see a half-fetched repo. Defaults to `spool` under `storage_path`;
+    /// elsewhere on the same filesystem keeps the final move instant.
+    #[serde(default)]
+    pub spool_path: Option<PathBuf>,
+    
+    /// zlib compression level for stored objects (0 = none, 9 = smallest)
+    #[serde(default = "default_compression_level")]
+    pub compression_level: u32,
+    
+    /// Store objects shared by several repos (e.g. forks) once, hard-linked
+    /// from a pool under `storage_path`. Unix only.
+    #[serde(default)]
+    pub dedup_objects: bool,
+    
+    /// When object writes are flushed to disk. `always` survives power
+    /// loss at the cost of one fsync per object; `batch` syncs once per
+    /// batch upload, replica, repair or import, and single uploads as
+    /// `always` does; `never` leaves it to the OS. Refs are always synced.
+    #[serde(default)]
+    pub fsync_policy: FsyncPolicy,
+    
+    /// Memory for recently served objects, kept decompressed so repeat
+    /// reads skip the disk. 0 disables the cache.
+    #[serde(default = "default_object_cache_mb")]
+    pub object_cache_mb: u64,
+    
     /// Whether this is an anchor node
+    #[serde(default)]
     pub is_anchor: bool,
     
     /// Maximum bandwidth in Mbps
+    #[serde(default = "default_max_bandwidth")]
     pub max_bandwidth_mbps: u32,
     
     /// Enable Tor proxy for all connections
+    #[serde(default = "default_true")]
     pub enable_proxy: bool,
     
-    /// SOCKS5 proxy address (Tor) - NOT optional
+    /// Whether Tor traffic goes through the embedded Arti client or an
+    /// existing Tor daemon's SOCKS5 port at `proxy_addr`
+    #[serde(default)]
+    pub tor_mode: TorMode,
+    
+    /// SOCKS5 address of the Tor daemon used with `tor_mode = "socks"`
+    #[serde(default = "default_proxy_addr")]
     pub proxy_addr: String,
     
     /// Enable onion routing
+    #[serde(default = "default_true")]
     pub enable_onion_routing: bool,
     
+    /// Publish this node as a Tor onion service so peers can reach it
+    #[serde(default = "default_true")]
+    pub enable_onion_service: bool,
+    
     /// Enable DHT for content discovery
+    #[serde(default = "default_true")]
     pub enable_dht: bool,
     
     /// Automatically replicate unhealthy repositories
+    #[serde(default = "default_true")]
     pub auto_replicate: bool,
     
+    /// Only replicate repos matching one of these patterns. A pattern with
+    /// `*` or `?` is a glob over the repo hash, anything else a prefix.
+    #[serde(default)]
+    pub replication_allowlist: Vec<String>,
+    
+    /// Never replicate repos matching these patterns. Ignored when
+    /// `replication_allowlist` is set.
+    #[serde(default)]
+    pub replication_denylist: Vec<String>,
+    
+    /// Re-fetch objects that fail the periodic verification from peers
+    #[serde(default)]
+    pub auto_repair: bool,
+    
+    /// Seconds between heartbeats to the coordinator. Longer intervals cut
+    /// coordinator load on large networks but make the node look stale for
+    /// longer after it goes away.
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval_secs: u64,
+    
+    /// Seconds between checks for under-replicated repositories. Shorter
+    /// intervals restore redundancy faster at the cost of more coordinator
+    /// queries.
+    #[serde(default = "default_replication_interval")]
+    pub replication_interval_secs: u64,
+    
+    /// Seconds between comparing the repos this node hosts with the ones
+    /// the coordinator expects it to host
+    #[serde(default = "default_coordinator_sync_interval")]
+    pub coordinator_sync_interval_secs: u64,
+    
+    /// Seconds replication may lag before `/ready` fails: without a
+    /// completed replication pass, or with repos the coordinator assigned
+    /// to this node still missing. 0 leaves lag out of readiness.
+    #[serde(default = "default_max_replication_lag")]
+    pub max_replication_lag_secs: u64,
+    
+    /// Most repositories replicated in one pass; the rest wait for the
+    /// next pass so a large backlog can't monopolize bandwidth
+    #[serde(default = "default_max_replications_per_cycle")]
+    pub max_replications_per_cycle: usize,
+    
+    /// Replicas a repository should have across the network. Repos at or
+    /// above it are not pulled; the coordinator can override it per repo.
+    #[serde(default = "default_target_replication_factor")]
+    pub target_replication_factor: u32,
+    
+    /// Days a replica pulled for availability is kept without being read
+    /// before it is deleted. Repos uploaded here or served explicitly with
+    /// `hyrule-node serve` are never expired. 0 keeps replicas forever.
+    #[serde(default)]
+    pub cache_ttl_days: u64,
+    
+    /// Seconds an outgoing request to a peer or the coordinator may take
+    /// to connect and respond, and again to deliver its body. A peer that
+    /// times out is skipped in favour of the next one.
+    #[serde(default = "default_peer_request_timeout")]
+    pub peer_request_timeout_secs: u64,
+    
+    /// Seconds `start` waits for Tor before serving without it. Bootstrap
+    /// keeps retrying in the background; until it succeeds only local
+    /// reads work. 0 starts serving straight away.
+    #[serde(default = "default_tor_start_timeout")]
+    pub tor_start_timeout_secs: u64,
+    
+    /// Seconds between DHT announcements of hosted repositories. Peers may
+    /// not find newly hosted repos until the next announcement.
+    #[serde(default = "default_dht_announce_interval")]
+    pub dht_announce_interval_secs: u64,
+    
+    /// Announce a repo to the DHT and the coordinator a few seconds after
+    /// its first upload, instead of waiting for the next announcement
+    #[serde(default)]
+    pub announce_on_store: bool,
+    
+    /// Largest request body the API accepts, in bytes. Bigger uploads are
+    /// rejected with 413.
+    #[serde(default = "default_max_request_body_bytes")]
+    pub max_request_body_bytes: usize,
+    
+    /// Most objects a single batch store may carry. Checked while the
+    /// body is decoded, independently of `max_request_body_bytes`.
+    #[serde(default = "default_max_batch_objects")]
+    pub max_batch_objects: usize,
+    
+    /// Largest batch store body, in bytes. Replaces
+    /// `max_request_body_bytes` for batch stores.
+    #[serde(default = "default_max_batch_bytes")]
+    pub max_batch_bytes: usize,
+    
+    /// Seconds a write request may take, including reading its body,
+    /// before it is cut off with 408
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout_secs: u64,
+    
+    /// Requests per second each client may make on the public port, with
+    /// bursts up to `rate_limit_burst`. Excess requests get 429. Clients
+    /// are told apart by IP, or per connection for onion streams. Requests
+    /// carrying the admin token are not limited. 0 disables the limit.
+    #[serde(default = "default_rate_limit_per_sec")]
+    pub rate_limit_per_sec: u32,
+    
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    
     /// Maximum concurrent uploads
+    #[serde(default = "default_max_concurrent_uploads")]
     pub max_concurrent_uploads: u32,
     
     /// Maximum concurrent downloads
+    #[serde(default = "default_max_concurrent_downloads")]
     pub max_concurrent_downloads: u32,
+    
+    /// Downloads allowed to wait for a free slot. Beyond this, reads are
+    /// shed with 503 until the backlog clears.
+    #[serde(default = "default_download_queue_limit")]
+    pub download_queue_limit: u32,
+    
+    /// Share of storage capacity above which uploads are refused with 507
+    #[serde(default = "default_write_shed_fraction")]
+    pub write_shed_fraction: f64,
+    
+    /// Objects a single repo may hold before uploads of new ones are
+    /// refused with 413, so tiny objects can't exhaust inodes. 0 disables
+    /// the limit.
+    #[serde(default = "default_max_objects_per_repo")]
+    pub max_objects_per_repo: u64,
+    
+    /// Address peers should use to reach this node. Defaults to the node's
+    /// onion address, or the local IP when Tor is disabled.
+    #[serde(default)]
+    pub advertised_address: Option<String>,
+    
+    /// `User-Agent` sent on outbound requests, instead of
+    /// `hyrule-node/<version>`
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    
+    /// Webhook URL that receives storage and corruption alerts
+    #[serde(default)]
+    pub alert_webhook: Option<String>,
+    
+    /// Bearer token for `/admin/*` and DELETE routes. Meant for the
+    /// operator's own tooling, not node-to-node auth. Unset disables them.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    
+    /// Serve `/admin/*` and DELETE routes on this Unix socket instead of
+    /// the public port. Only local users who can open the socket file
+    /// reach them, so no token is needed there.
+    #[serde(default)]
+    pub admin_socket: Option<PathBuf>,
+    
+    /// Require a token on every route except `/health` and `/ready`, for
+    /// private clusters. Reads accept `admin_token` or `peer_token`.
+    #[serde(default)]
+    pub require_auth_for_reads: bool,
+    
+    /// Token the nodes of a private deployment share. Sent on requests to
+    /// peers and accepted for reads when `require_auth_for_reads` is set;
+    /// it never grants admin access.
+    #[serde(default)]
+    pub peer_token: Option<String>,
+    
+    /// Origins allowed to call the API from a browser, e.g.
+    /// "https://dashboard.example". Empty means no cross-origin access.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    
+    /// PEM certificate chain for serving HTTPS on clearnet deployments.
+    /// Ignored while Tor is enabled; set together with `tls_key_path`.
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+    
+    /// PEM private key matching `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+    
+    /// Refuse to load this file unless `<config>.sig` holds a valid
+    /// signature over it by `config_signing_key`. For managed fleets; a
+    /// signed config is never rewritten by the node.
+    #[serde(default)]
+    pub config_signature_required: bool,
+    
+    /// Hex ed25519 public key trusted to sign the config. The
+    /// HYRULE_CONFIG_SIGNING_KEY environment variable overrides it and
+    /// enforces the check even if the file was edited to turn it off.
+    #[serde(default)]
+    pub config_signing_key: Option<String>,
 }
 
 impl NodeConfig {
@@ -77,19 +355,67 @@ impl NodeConfig {
             node_id,
             public_key: public_key_hex,
             private_key: private_key_hex,
-            hyrule_server: "http://hyrule4e3tu7pfdkvvca43senvgvgisi6einpe3d3kpidlk3uyjf7lqd.onion".to_string(),
-            port: 8080,
-            storage_path: "node-storage".to_string(),
-            storage_capacity: 10 * 1024 * 1024 * 1024, // 10 GB
+            identity_path: None,
+            hyrule_server: default_hyrule_server(),
+            network_namespace: default_network_namespace(),
+            port: default_port(),
+            bind_address: default_bind_address(),
+            storage_path: default_storage_path(),
+            storage_capacity: default_storage_capacity(),
+            capacity_auto: false,
+            capacity_auto_fraction: default_capacity_auto_fraction(),
+            storage_tiers: Vec::new(),
+            spool_path: None,
+            compression_level: default_compression_level(),
+            dedup_objects: false,
+            fsync_policy: FsyncPolicy::default(),
+            object_cache_mb: default_object_cache_mb(),
             is_anchor: false,
-            max_bandwidth_mbps: 100,
+            max_bandwidth_mbps: default_max_bandwidth(),
             enable_proxy: true,
-            proxy_addr: "127.0.0.1:9050".to_string(),
+            tor_mode: TorMode::default(),
+            proxy_addr: default_proxy_addr(),
             enable_onion_routing: true,
+            enable_onion_service: true,
             enable_dht: true,
             auto_replicate: true,
-            max_concurrent_uploads: 5,
-            max_concurrent_downloads: 10,
+            replication_allowlist: Vec::new(),
+            replication_denylist: Vec::new(),
+            auto_repair: false,
+            max_replications_per_cycle: default_max_replications_per_cycle(),
+            target_replication_factor: default_target_replication_factor(),
+            cache_ttl_days: 0,
+            peer_request_timeout_secs: default_peer_request_timeout(),
+            tor_start_timeout_secs: default_tor_start_timeout(),
+            heartbeat_interval_secs: default_heartbeat_interval(),
+            replication_interval_secs: default_replication_interval(),
+            coordinator_sync_interval_secs: default_coordinator_sync_interval(),
+            max_replication_lag_secs: default_max_replication_lag(),
+            dht_announce_interval_secs: default_dht_announce_interval(),
+            announce_on_store: false,
+            max_request_body_bytes: default_max_request_body_bytes(),
+            max_batch_objects: default_max_batch_objects(),
+            max_batch_bytes: default_max_batch_bytes(),
+            request_timeout_secs: default_request_timeout(),
+            rate_limit_per_sec: default_rate_limit_per_sec(),
+            rate_limit_burst: default_rate_limit_burst(),
+            max_concurrent_uploads: default_max_concurrent_uploads(),
+            max_concurrent_downloads: default_max_concurrent_downloads(),
+            download_queue_limit: default_download_queue_limit(),
+            write_shed_fraction: default_write_shed_fraction(),
+            max_objects_per_repo: default_max_objects_per_repo(),
+            advertised_address: None,
+            user_agent: None,
+            alert_webhook: None,
+            admin_token: None,
+            admin_socket: None,
+            require_auth_for_reads: false,
+            peer_token: None,
+            cors_allowed_origins: Vec::new(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            config_signature_required: false,
+            config_signing_key: None,
         }
     }
     
@@ -109,7 +435,7 @@ impl NodeConfig {
         
         // Priority 3: Fall back to system config directory
         let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+            .ok_or_else(|| HyruleError::Config("Could not find config directory".to_string()))?;
         
         let hyrule_dir = config_dir.join("hyrule-node");
         std::fs::create_dir_all(&hyrule_dir)?;
@@ -123,43 +449,147 @@ impl NodeConfig {
         let path = Self::config_path()?;
         
         if !path.exists() {
-            anyhow::bail!(
+            bail!(
+                Config,
                 "Config file not found at {}. Run 'hyrule-node init' first.",
                 path.display()
             );
         }
         
         let content = std::fs::read_to_string(&path)?;
-        let config: Self = toml::from_str(&content)
-            .map_err(|e| anyhow::anyhow!("Failed to parse config: {}", e))?;
+        let mut config: Self = toml::from_str(&content)
+            .map_err(|e| HyruleError::Config(format!("Failed to parse config: {}", e)))?;
+        
+        if let Some(key) = config.signing_key()? {
+            let signature_path = signature_path(&path);
+            let signature = std::fs::read_to_string(&signature_path).map_err(|e| {
+                HyruleError::Config(format!("Config must be signed, but {} can't be read: {}", signature_path.display(), e))
+            })?;
+            check_signature(content.as_bytes(), &signature, &key)?;
+            tracing::debug!("Config signature verified");
+        }
+        
+        config.expand_paths()?;
+        config.load_identity()?;
+        check_compression_level(config.compression_level)?;
+        config.check_storage_tiers()?;
+        config.check_limits()?;
+        for origin in &config.cors_allowed_origins {
+            check_cors_origin(origin)?;
+        }
         
         tracing::debug!("Loaded config from: {}", path.display());
         
         Ok(config)
     }
     
-    /// Load config or create a new one if it doesn't exist
+    /// Parse a config file written by any earlier version, filling fields
+    /// it predates with defaults and noting keys the current schema dropped
+    pub fn upgrade(content: &str) -> Result<ConfigMigration> {
+        let existing: toml::Table = toml::from_str(content)
+            .map_err(|e| HyruleError::Config(format!("Failed to parse config: {}", e)))?;
+        let config: Self = toml::from_str(content)
+            .map_err(|e| HyruleError::Config(format!("Failed to parse config: {}", e)))?;
+        
+        let current = toml::Table::try_from(&config).map_err(|e| HyruleError::Config(e.to_string()))?;
+        let added = current.keys()
+            .filter(|key| !existing.contains_key(*key))
+            .cloned()
+            .collect();
+        let removed = existing.keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+        
+        Ok(ConfigMigration { config, added, removed })
+    }
+    
+    /// Every setting of this config, loaded from `content`, with where its
+    /// value came from. Secrets are redacted.
+    pub fn effective_settings(&self, content: &str) -> Result<Vec<Setting>> {
+        let file: toml::Table = toml::from_str(content)
+            .map_err(|e| HyruleError::Config(format!("Failed to parse config: {}", e)))?;
+        let serde_json::Value::Object(values) = serde_json::to_value(self)
+            .map_err(|e| HyruleError::Config(e.to_string()))?
+        else {
+            bail!(Config, "Config doesn't serialize to a table");
+        };
+        
+        let identity_keys = ["node_id", "public_key", "private_key"];
+        let settings = values
+            .into_iter()
+            .map(|(key, value)| {
+                let source = if file.contains_key(&key) {
+                    SettingSource::File
+                } else if self.identity_path.is_some() && identity_keys.contains(&key.as_str()) {
+                    SettingSource::Identity
+                } else {
+                    SettingSource::Default
+                };
+                let value = if SECRET_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    "<redacted>".into()
+                } else {
+                    value
+                };
+                Setting { key, value, source }
+            })
+            .collect();
+        Ok(settings)
+    }
+    
+    /// Rewrite the config file in the current schema, keeping a `.bak`
+    /// copy of the original when anything changes
+    pub fn migrate() -> Result<ConfigMigration> {
+        let path = Self::config_path()?;
+        
+        if !path.exists() {
+            bail!(
+                Config,
+                "Config file not found at {}. Run 'hyrule-node init' first.",
+                path.display()
+            );
+        }
+        
+        let content = std::fs::read_to_string(&path)?;
+        let migration = Self::upgrade(&content)?;
+        migration.config.validate()?;
+        
+        if !migration.is_noop() {
+            let mut backup = path.clone().into_os_string();
+            backup.push(".bak");
+            std::fs::copy(&path, &backup)?;
+            migration.config.save()?;
+        }
+        
+        Ok(migration)
+    }
+    
+    /// Load config or create a new one if it doesn't exist. A config that
+    /// exists but fails to load is an error, never replaced.
     pub fn load_or_create() -> Result<Self> {
-        match Self::load() {
-            Ok(config) => Ok(config),
-            Err(_) => {
-                tracing::info!("No config found, generating new one...");
-                let config = Self::generate();
-                config.save()?;
-                Ok(config)
-            }
+        if Self::config_path()?.exists() {
+            return Self::load();
         }
+        
+        tracing::info!("No config found, generating new one...");
+        let config = Self::generate();
+        config.save()?;
+        Ok(config)
     }
     
     /// Save configuration to file - preserves ALL fields exactly as they are
     pub fn save(&self) -> Result<()> {
+        if self.signing_key()?.is_some() {
+            bail!(Config, "The config is signed and can't be changed by the node; edit and re-sign it instead");
+        }
+        
         let path = Self::config_path()?;
         
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
         
-        let content = toml::to_string_pretty(self)?;
+        let content = toml::to_string_pretty(&self.settings()).map_err(|e| HyruleError::Config(e.to_string()))?;
         std::fs::write(&path, content)?;
         
         tracing::debug!("Configuration saved to {}", path.display());
@@ -167,18 +597,71 @@ impl NodeConfig {
         Ok(())
     }
     
+    /// The config as written to its file: keys kept in `identity_path` are
+    /// left out
+    pub fn settings(&self) -> Self {
+        let mut settings = self.clone();
+        if settings.identity_path.is_some() {
+            settings.node_id.clear();
+            settings.public_key.clear();
+            settings.private_key.clear();
+        }
+        settings
+    }
+    
+    pub fn identity(&self) -> Identity {
+        Identity {
+            node_id: self.node_id.clone(),
+            public_key: self.public_key.clone(),
+            private_key: self.private_key.clone(),
+        }
+    }
+    
+    /// Save `node_id` and the keys wherever they are kept: `identity_path`,
+    /// or the config itself
+    pub fn save_identity(&self) -> Result<()> {
+        match &self.identity_path {
+            Some(path) => self.identity().save(path),
+            None => self.save(),
+        }
+    }
+    
+    /// Fill in the keys from `identity_path`, if set
+    fn load_identity(&mut self) -> Result<()> {
+        let Some(path) = &self.identity_path else {
+            if self.private_key.is_empty() {
+                bail!(Config, "Config has no private_key; set it or identity_path");
+            }
+            return Ok(());
+        };
+        
+        if !self.node_id.is_empty() || !self.public_key.is_empty() || !self.private_key.is_empty() {
+            bail!(
+                Config,
+                "identity_path is set, so node_id, public_key and private_key must be removed from the config"
+            );
+        }
+        
+        let identity = Identity::load(path)?;
+        self.node_id = identity.node_id;
+        self.public_key = identity.public_key;
+        self.private_key = identity.private_key;
+        Ok(())
+    }
+    
     /// Update specific fields and save - ONLY updates provided values
-    pub fn update_and_save(
-        &mut self,
-        server: Option<String>,
-        port: Option<u16>,
-        storage_path: Option<String>,
-        capacity_gb: Option<u64>,
-        is_anchor: Option<bool>,
-        enable_proxy: Option<bool>,
-        proxy_addr: Option<String>,
-        enable_dht: Option<bool>,
-    ) -> Result<bool> {
+    pub fn update_and_save(&mut self, overrides: ConfigOverrides) -> Result<bool> {
+        let ConfigOverrides {
+            server,
+            port,
+            bind_address,
+            storage_path,
+            capacity_gb,
+            is_anchor,
+            enable_proxy,
+            proxy_addr,
+            enable_dht,
+        } = overrides;
         let mut changed = false;
         
         if let Some(srv) = server {
@@ -195,6 +678,13 @@ impl NodeConfig {
             }
         }
         
+        if let Some(bind) = bind_address {
+            if self.bind_address != bind {
+                self.bind_address = bind;
+                changed = true;
+            }
+        }
+        
         if let Some(path) = storage_path {
             if self.storage_path != path {
                 self.storage_path = path;
@@ -250,35 +740,241 @@ impl NodeConfig {
     pub fn validate(&self) -> Result<()> {
         // Validate port
         if self.port == 0 {
-            anyhow::bail!("Invalid port number");
+            bail!(Config, "Invalid port number");
         }
         
+        // Validate bind address
+        self.bind_socket_addr()?;
+        
         // Validate storage capacity
         if self.storage_capacity == 0 {
-            anyhow::bail!("Storage capacity must be greater than 0");
+            bail!(Config, "Storage capacity must be greater than 0");
         }
         
+        // Validate storage tiers
+        self.check_storage_tiers()?;
+        
+        // Validate capacity detection
+        if !(self.capacity_auto_fraction > 0.0 && self.capacity_auto_fraction <= 1.0) {
+            bail!(Config, "capacity_auto_fraction must be above 0 and at most 1");
+        }
+        if self.capacity_auto && !self.storage_tiers.is_empty() {
+            bail!(Config, "capacity_auto cannot be combined with storage_tiers");
+        }
+        
+        // Validate compression level
+        check_compression_level(self.compression_level)?;
+        
+        check_network_namespace(&self.network_namespace)?;
+        
         // Validate public key format
         if hex::decode(&self.public_key).is_err() {
-            anyhow::bail!("Invalid public key format");
+            bail!(Config, "Invalid public key format");
         }
         
         // Validate private key
         if hex::decode(&self.private_key).is_err() {
-            anyhow::bail!("Invalid private key format");
+            bail!(Config, "Invalid private key format");
+        }
+        
+        // Validate request limits and background task intervals
+        self.check_limits()?;
+        
+        // Validate admin token
+        if self.admin_token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            bail!(Config, "admin_token must not be empty");
+        }
+        if self.peer_token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            bail!(Config, "peer_token must not be empty");
+        }
+        if self.require_auth_for_reads && self.peer_token.is_none() {
+            bail!(Config, "require_auth_for_reads needs peer_token to be set");
+        }
+        
+        // Validate CORS origins
+        for origin in &self.cors_allowed_origins {
+            check_cors_origin(origin)?;
+        }
+        
+        let mut patterns = self.replication_allowlist.iter().chain(&self.replication_denylist);
+        if patterns.any(|p| p.trim().is_empty()) {
+            bail!(Config, "replication_allowlist and replication_denylist must not contain empty patterns");
+        }
+        
+        if let Some(agent) = &self.user_agent {
+            if agent.trim().is_empty() || hyper::header::HeaderValue::from_str(agent).is_err() {
+                bail!(Config, "user_agent must be non-empty printable text");
+            }
+        }
+        
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            bail!(Config, "tls_cert_path and tls_key_path must be set together");
         }
         
         // Validate Tor settings
         if self.enable_proxy && self.proxy_addr.is_empty() {
-            anyhow::bail!("Proxy enabled but no proxy address configured");
+            bail!(Config, "Proxy enabled but no proxy address configured");
+        }
+        
+        Ok(())
+    }
+    
+    fn check_storage_tiers(&self) -> Result<()> {
+        let Some(first) = self.storage_tiers.first() else {
+            return Ok(());
+        };
+        
+        if first.path != self.storage_path {
+            bail!(
+                Config,
+                "The first storage tier must be storage_path ({}), got {}",
+                self.storage_path,
+                first.path
+            );
+        }
+        
+        for (i, tier) in self.storage_tiers.iter().enumerate() {
+            if tier.capacity == 0 {
+                bail!(Config, "Storage tier {} has zero capacity", tier.path);
+            }
+            if self.storage_tiers[..i].iter().any(|t| t.path == tier.path) {
+                bail!(Config, "Storage tier {} is listed twice", tier.path);
+            }
         }
         
         Ok(())
     }
     
+    /// Total storage capacity: the sum of the tiers when configured,
+    /// otherwise `storage_capacity`
+    pub fn total_capacity(&self) -> u64 {
+        if self.storage_tiers.is_empty() {
+            self.storage_capacity
+        } else {
+            self.storage_tiers.iter().map(|t| t.capacity).sum()
+        }
+    }
+    
+    /// Key the config must be signed with, if signing is enforced by the
+    /// environment or by the file itself
+    fn signing_key(&self) -> Result<Option<String>> {
+        if let Some(key) = std::env::var(SIGNING_KEY_ENV).ok().filter(|k| !k.is_empty()) {
+            return Ok(Some(key));
+        }
+        if !self.config_signature_required {
+            return Ok(None);
+        }
+        
+        match &self.config_signing_key {
+            Some(key) => Ok(Some(key.clone())),
+            None => bail!(Config, "config_signature_required is set but config_signing_key is not"),
+        }
+    }
+    
+    /// Expand `~` and environment variables in path settings, so the rest
+    /// of the node only sees concrete paths
+    fn expand_paths(&mut self) -> Result<()> {
+        self.storage_path = expand_path(&self.storage_path)?;
+        for tier in &mut self.storage_tiers {
+            tier.path = expand_path(&tier.path)?;
+        }
+        
+        let optional = [
+            &mut self.identity_path,
+            &mut self.spool_path,
+            &mut self.admin_socket,
+            &mut self.tls_cert_path,
+            &mut self.tls_key_path,
+        ];
+        for path in optional.into_iter().flatten() {
+            *path = PathBuf::from(expand_path(&path.to_string_lossy())?);
+        }
+        
+        Ok(())
+    }
+    
+    /// Directory replicas are fetched into, see `spool_path`
+    pub fn spool_dir(&self) -> PathBuf {
+        self.spool_path
+            .clone()
+            .unwrap_or_else(|| Path::new(&self.storage_path).join(crate::storage::SPOOL_DIR))
+    }
+    
+    /// Storage tiers as (path, capacity) pairs for `GitStorage::with_tiers`
+    pub fn tier_paths(&self) -> Vec<(PathBuf, u64)> {
+        self.storage_tiers
+            .iter()
+            .map(|t| (PathBuf::from(&t.path), t.capacity))
+            .collect()
+    }
+    
+    fn check_limits(&self) -> Result<()> {
+        if self.max_request_body_bytes == 0 {
+            bail!(Config, "max_request_body_bytes must be greater than 0");
+        }
+        if self.max_batch_objects == 0 {
+            bail!(Config, "max_batch_objects must be greater than 0");
+        }
+        if self.max_batch_bytes == 0 {
+            bail!(Config, "max_batch_bytes must be greater than 0");
+        }
+        if self.max_replications_per_cycle == 0 {
+            bail!(Config, "max_replications_per_cycle must be greater than 0");
+        }
+        if self.target_replication_factor == 0 {
+            bail!(Config, "target_replication_factor must be greater than 0");
+        }
+        if self.rate_limit_per_sec > 0 && self.rate_limit_burst == 0 {
+            bail!(Config, "rate_limit_burst must be greater than 0 while rate limiting is enabled");
+        }
+        if self.max_concurrent_uploads == 0 {
+            bail!(Config, "max_concurrent_uploads must be greater than 0");
+        }
+        if self.max_concurrent_downloads == 0 {
+            bail!(Config, "max_concurrent_downloads must be greater than 0");
+        }
+        if !(self.write_shed_fraction > 0.0 && self.write_shed_fraction <= 1.0) {
+            bail!(Config, "write_shed_fraction must be above 0 and at most 1");
+        }
+        
+        let intervals = [
+            ("heartbeat_interval_secs", self.heartbeat_interval_secs),
+            ("replication_interval_secs", self.replication_interval_secs),
+            ("coordinator_sync_interval_secs", self.coordinator_sync_interval_secs),
+            ("dht_announce_interval_secs", self.dht_announce_interval_secs),
+            ("request_timeout_secs", self.request_timeout_secs),
+            ("peer_request_timeout_secs", self.peer_request_timeout_secs),
+        ];
+        for (name, secs) in intervals {
+            if secs == 0 {
+                bail!(Config, "{} must be greater than 0", name);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Socket address to listen on, built from `bind_address` and `port`
+    pub fn bind_socket_addr(&self) -> Result<SocketAddr> {
+        let ip: IpAddr = self.bind_address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| HyruleError::Config(format!("Invalid bind address: {}", self.bind_address)))?;
+        
+        Ok(SocketAddr::new(ip, self.port))
+    }
+    
     /// Get storage capacity in human-readable format
     pub fn storage_capacity_gb(&self) -> f64 {
-        self.storage_capacity as f64 / (1024.0 * 1024.0 * 1024.0)
+        self.total_capacity() as f64 / (1024.0 * 1024.0 * 1024.0)
+    }
+    
+    /// Whether the allow and deny lists let this node replicate a repo
+    pub fn replicates(&self, repo_hash: &str) -> bool {
+        if !self.replication_allowlist.is_empty() {
+            return self.replication_allowlist.iter().any(|p| matches_repo_pattern(p, repo_hash));
+        }
+        !self.replication_denylist.iter().any(|p| matches_repo_pattern(p, repo_hash))
     }
     
     /// Check if Tor is properly configured
@@ -286,6 +982,15 @@ impl NodeConfig {
         self.enable_proxy && !self.proxy_addr.is_empty()
     }
     
+    /// Certificate and key to serve HTTPS with. Only used with Tor disabled:
+    /// the onion service forwards plain HTTP and is encrypted already.
+    pub fn tls_files(&self) -> Option<(&Path, &Path)> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) if !self.enable_proxy => Some((cert, key)),
+            _ => None,
+        }
+    }
+    
     /// Get the proxy address
     pub fn get_proxy_addr(&self) -> String {
         self.proxy_addr.clone()
@@ -296,12 +1001,499 @@ impl NodeConfig {
         self.hyrule_server.contains(".onion")
     }
     
-    /// Get public address for registration (returns node_id based address)
-    pub fn public_address(&self) -> String {
-        // For Tor nodes, we use the node_id as the identifier
-        // The actual .onion address would be configured separately
-        format!("{}.local", &self.node_id[..16])
+    /// Directory where Arti keeps its state, including onion service keys.
+    /// Scoped per node so two nodes on one machine get distinct onions.
+    pub fn tor_state_dir(&self) -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("hyrule-node")
+            .join(&self.node_id[..16])
+            .join("arti")
     }
+    
+    /// Check that `node_id` is derived from `public_key` and that the two
+    /// keys form a pair. Returns every inconsistency found.
+    pub fn check_identity(&self) -> Vec<IdentityProblem> {
+        let mut problems = Vec::new();
+        
+        match crypto::node_id_for_key(&self.public_key) {
+            Ok(expected) if expected != self.node_id => {
+                problems.push(IdentityProblem::NodeIdMismatch { expected });
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(IdentityProblem::InvalidKey(format!("public_key: {}", e))),
+        }
+        
+        let message = b"hyrule-node verify-identity";
+        let round_trip = crypto::sign_data(&self.private_key, message)
+            .and_then(|signature| crypto::verify_signature(&self.public_key, message, &signature));
+        match round_trip {
+            Ok(true) => {}
+            Ok(false) => problems.push(IdentityProblem::KeyMismatch),
+            Err(e) => problems.push(IdentityProblem::InvalidKey(e.to_string())),
+        }
+        
+        problems
+    }
+}
+
+fn default_hyrule_server() -> String {
+    "http://hyrule4e3tu7pfdkvvca43senvgvgisi6einpe3d3kpidlk3uyjf7lqd.onion".to_string()
+}
+
+pub(crate) fn default_network_namespace() -> String {
+    "main".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+/// Under the XDG data dir, e.g. `~/.local/share/hyrule-node/storage`, so a
+/// service doesn't drop data wherever it was started. The subdirectory keeps
+/// repos apart from the per-node Tor state kept alongside.
+fn default_storage_path() -> String {
+    dirs::data_dir()
+        .map(|dir| dir.join("hyrule-node").join("storage").to_string_lossy().into_owned())
+        .unwrap_or_else(|| "node-storage".to_string())
+}
+
+/// Expand a leading `~` and `$VAR` / `${VAR}` references in a configured
+/// path. Paths without either are returned unchanged.
+fn expand_path(path: &str) -> Result<String> {
+    let mut expanded = String::new();
+    
+    let mut rest = if path == "~" || path.starts_with("~/") {
+        let home = dirs::home_dir()
+            .ok_or_else(|| HyruleError::Config(format!("Cannot expand ~ in {}: no home directory", path)))?;
+        expanded.push_str(&home.to_string_lossy());
+        &path[1..]
+    } else {
+        path
+    };
+    
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}')
+                    .ok_or_else(|| HyruleError::Config(format!("Unclosed ${{ in {}", path)))?;
+                (&braced[..end], end + 2)
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        
+        if name.is_empty() {
+            bail!(Config, "Empty variable reference in {}", path);
+        }
+        let value = std::env::var(name)
+            .map_err(|_| HyruleError::Config(format!("{} refers to unset environment variable {}", path, name)))?;
+        expanded.push_str(&value);
+        rest = &after[len..];
+    }
+    
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn default_storage_capacity() -> u64 {
+    10 * 1024 * 1024 * 1024 // 10 GB
+}
+
+fn default_max_bandwidth() -> u32 {
+    100
+}
+
+fn default_proxy_addr() -> String {
+    "127.0.0.1:9050".to_string()
+}
+
+fn default_max_replications_per_cycle() -> usize {
+    10
+}
+
+fn default_target_replication_factor() -> u32 {
+    3
+}
+
+fn default_max_concurrent_uploads() -> u32 {
+    5
+}
+
+fn default_max_concurrent_downloads() -> u32 {
+    10
+}
+
+fn default_download_queue_limit() -> u32 {
+    64
+}
+
+fn default_write_shed_fraction() -> f64 {
+    0.95
+}
+
+/// About the size of the Linux kernel's history
+fn default_max_objects_per_repo() -> u64 {
+    10_000_000
+}
+
+/// When object writes are flushed to disk, see `NodeConfig::fsync_policy`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    #[default]
+    Always,
+    Batch,
+    Never,
+}
+
+/// How the node reaches the Tor network
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TorMode {
+    /// Bootstrap an embedded Arti client
+    #[default]
+    Arti,
+    /// Use a system Tor daemon's SOCKS5 port. Faster to start, but the
+    /// daemon has to publish the onion service itself.
+    Socks,
+}
+
+/// One object store in a tiered storage setup
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageTier {
+    pub path: String,
+    /// Capacity in bytes
+    pub capacity: u64,
+}
+
+/// Settings given on the command line for [`NodeConfig::update_and_save`].
+/// `None` keeps the configured value.
+#[derive(Debug, Default)]
+pub struct ConfigOverrides {
+    pub server: Option<String>,
+    pub port: Option<u16>,
+    pub bind_address: Option<String>,
+    pub storage_path: Option<String>,
+    pub capacity_gb: Option<u64>,
+    pub is_anchor: Option<bool>,
+    pub enable_proxy: Option<bool>,
+    pub proxy_addr: Option<String>,
+    pub enable_dht: Option<bool>,
+}
+
+/// Result of upgrading a config file to the current schema
+pub struct ConfigMigration {
+    pub config: NodeConfig,
+    /// Fields missing from the file that were filled with defaults
+    pub added: Vec<String>,
+    /// Keys in the file the current schema no longer uses
+    pub removed: Vec<String>,
+}
+
+impl ConfigMigration {
+    pub fn is_noop(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// One setting of the config in effect, as printed by `config show`
+#[derive(Debug, Serialize)]
+pub struct Setting {
+    pub key: String,
+    pub value: serde_json::Value,
+    pub source: SettingSource,
+}
+
+/// Where the value of a setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    File,
+    /// Not in the file, so the default
+    Default,
+    /// Read from `identity_path`
+    Identity,
+}
+
+/// The node's keys, as kept in `identity_path`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    pub node_id: String,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+impl Identity {
+    /// Read an identity file, warning if anyone but its owner can read it
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            HyruleError::Config(format!("Failed to read identity file {}: {}", path.display(), e))
+        })?;
+        
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            
+            let mode = std::fs::metadata(path)?.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                tracing::warn!(
+                    "Identity file {} is accessible to other users (mode {:o}); run `chmod 600` on it",
+                    path.display(),
+                    mode
+                );
+            }
+        }
+        
+        toml::from_str(&content).map_err(|e| {
+            HyruleError::Config(format!("Failed to parse identity file {}: {}", path.display(), e))
+        })
+    }
+    
+    /// Write the identity file, readable and writable by its owner only.
+    /// It goes to a temp file in the same directory that is renamed over
+    /// the old one, so a crash never leaves a truncated key behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        use std::io::Write;
+        
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(dir)?;
+        let content = toml::to_string_pretty(self).map_err(|e| HyruleError::Config(e.to_string()))?;
+        
+        let file_name = path.file_name()
+            .ok_or_else(|| HyruleError::Config(format!("Invalid identity path: {}", path.display())))?
+            .to_string_lossy();
+        let tmp_path = dir.join(format!(".{}.{}.tmp", file_name, rand::random::<u32>()));
+        
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        
+        let result = (|| -> Result<()> {
+            let mut file = options.open(&tmp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result?;
+        
+        #[cfg(unix)]
+        if let Ok(dir_handle) = std::fs::File::open(dir) {
+            let _ = dir_handle.sync_all();
+        }
+        Ok(())
+    }
+}
+
+/// Where `init` puts the identity file for a config at `config_path`:
+/// alongside it, e.g. `config.identity.toml` for `config.toml`
+pub fn identity_path_for(config_path: &Path) -> PathBuf {
+    config_path.with_extension("identity.toml")
+}
+
+/// An inconsistency between `node_id`, `public_key` and `private_key`
+#[derive(Debug, PartialEq)]
+pub enum IdentityProblem {
+    /// `node_id` isn't the hash of `public_key`
+    NodeIdMismatch { expected: String },
+    /// The private key doesn't sign for the public key
+    KeyMismatch,
+    /// A key can't be decoded at all
+    InvalidKey(String),
+}
+
+impl std::fmt::Display for IdentityProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NodeIdMismatch { expected } => {
+                write!(f, "node_id does not match public_key (expected {})", expected)
+            }
+            Self::KeyMismatch => write!(f, "public_key does not match private_key"),
+            Self::InvalidKey(e) => write!(f, "invalid key: {}", e),
+        }
+    }
+}
+
+fn check_compression_level(level: u32) -> Result<()> {
+    if level > 9 {
+        bail!(Config, "compression_level must be between 0 and 9, got {}", level);
+    }
+    Ok(())
+}
+
+/// Namespaces are part of DHT keys, so they're kept to a plain label
+fn check_network_namespace(namespace: &str) -> Result<()> {
+    let valid = !namespace.is_empty()
+        && namespace.len() <= 64
+        && namespace.bytes().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-' || c == b'_');
+    if !valid {
+        bail!(Config, "network_namespace must be 1-64 lowercase letters, digits, '-' or '_', got {:?}", namespace);
+    }
+    Ok(())
+}
+
+/// Match a repo hash against a glob (`*` any run, `?` one character) or,
+/// without wildcards, a prefix
+fn matches_repo_pattern(pattern: &str, repo_hash: &str) -> bool {
+    let pattern = pattern.trim();
+    if !pattern.contains(['*', '?']) {
+        return repo_hash.starts_with(pattern);
+    }
+    
+    let (pattern, text) = (pattern.as_bytes(), repo_hash.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*`, and where in the text it started matching
+    let mut backtrack = None;
+    
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn check_cors_origin(origin: &str) -> Result<()> {
+    if origin == "*" {
+        return Ok(());
+    }
+    let valid = (origin.starts_with("http://") || origin.starts_with("https://"))
+        && !origin.ends_with('/')
+        && origin.parse::<axum::http::HeaderValue>().is_ok();
+    if !valid {
+        bail!(Config, "Invalid CORS origin {:?}: expected \"*\" or scheme://host[:port]", origin);
+    }
+    Ok(())
+}
+
+/// Detached signature for a config file: `<config>.sig`
+pub fn signature_path(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// Sign config file contents, as hex for the `.sig` file
+pub fn sign_config(content: &[u8], private_key_hex: &str) -> Result<String> {
+    Ok(hex::encode(crypto::sign_data(private_key_hex, content)?))
+}
+
+/// Check a hex signature over the exact bytes of a config file
+fn check_signature(content: &[u8], signature_hex: &str, public_key_hex: &str) -> Result<()> {
+    let signature = hex::decode(signature_hex.trim())
+        .map_err(|_| HyruleError::Config("Config signature is not valid hex".to_string()))?;
+    
+    if !crypto::verify_signature(public_key_hex, content, &signature)? {
+        bail!(Config, "Config signature does not match; refusing to load a modified config");
+    }
+    
+    Ok(())
+}
+
+fn default_capacity_auto_fraction() -> f64 {
+    0.9
+}
+
+fn default_compression_level() -> u32 {
+    6
+}
+
+fn default_heartbeat_interval() -> u64 {
+    60
+}
+
+fn default_replication_interval() -> u64 {
+    300
+}
+
+fn default_coordinator_sync_interval() -> u64 {
+    3600
+}
+
+fn default_max_replication_lag() -> u64 {
+    7200
+}
+
+fn default_dht_announce_interval() -> u64 {
+    300
+}
+
+fn default_object_cache_mb() -> u64 {
+    64
+}
+
+fn default_max_request_body_bytes() -> usize {
+    32 * 1024 * 1024 // 32 MB
+}
+
+fn default_max_batch_objects() -> usize {
+    10_000
+}
+
+fn default_max_batch_bytes() -> usize {
+    64 * 1024 * 1024 // 64 MB
+}
+
+fn default_request_timeout() -> u64 {
+    60
+}
+
+fn default_peer_request_timeout() -> u64 {
+    30
+}
+
+fn default_tor_start_timeout() -> u64 {
+    60
+}
+
+fn default_rate_limit_per_sec() -> u32 {
+    50
+}
+
+fn default_rate_limit_burst() -> u32 {
+    200
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bind_address() -> String {
+    "0.0.0.0".to_string()
 }
 
 impl Default for NodeConfig {
@@ -325,6 +1517,107 @@ mod tests {
         assert!(config.enable_dht);
     }
     
+    #[test]
+    fn test_check_identity() {
+        let config = NodeConfig::generate();
+        assert!(config.check_identity().is_empty());
+        
+        let mut renamed = config.clone();
+        renamed.node_id = "0".repeat(64);
+        assert_eq!(
+            renamed.check_identity(),
+            vec![IdentityProblem::NodeIdMismatch { expected: config.node_id.clone() }]
+        );
+        
+        let mut swapped = config.clone();
+        swapped.public_key = NodeConfig::generate().public_key;
+        let problems = swapped.check_identity();
+        assert!(matches!(problems[0], IdentityProblem::NodeIdMismatch { .. }));
+        assert_eq!(problems[1], IdentityProblem::KeyMismatch);
+        
+        let mut truncated = config.clone();
+        truncated.private_key.truncate(32);
+        assert!(matches!(truncated.check_identity()[..], [IdentityProblem::InvalidKey(_)]));
+    }
+    
+    #[test]
+    fn test_identity_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let identity_path = identity_path_for(&config_path);
+        assert_eq!(identity_path, dir.path().join("config.identity.toml"));
+        
+        let mut config = NodeConfig::generate();
+        config.identity().save(&identity_path).unwrap();
+        config.identity_path = Some(identity_path.clone());
+        
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&identity_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        
+        // Saving again replaces the file whole and leaves no temp file
+        config.identity().save(&identity_path).unwrap();
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("config.identity.toml")]);
+        
+        // The config file itself holds no keys
+        let content = toml::to_string_pretty(&config.settings()).unwrap();
+        assert!(!content.contains("private_key"));
+        assert!(!content.contains(&config.private_key));
+        
+        let mut loaded: NodeConfig = toml::from_str(&content).unwrap();
+        loaded.load_identity().unwrap();
+        assert_eq!(loaded.node_id, config.node_id);
+        assert_eq!(loaded.private_key, config.private_key);
+        assert!(loaded.check_identity().is_empty());
+        
+        // Keys in both places are ambiguous
+        let mut both = config.clone();
+        assert!(both.load_identity().is_err());
+        
+        // And in neither, missing
+        let mut neither: NodeConfig = toml::from_str(&content).unwrap();
+        neither.identity_path = None;
+        assert!(neither.load_identity().is_err());
+    }
+    
+    #[test]
+    fn test_replication_lists() {
+        let mut config = NodeConfig::generate();
+        assert!(config.replicates("abc123"));
+        
+        config.replication_denylist = vec!["abc".to_string(), "*ff".to_string()];
+        assert!(!config.replicates("abc123"));
+        assert!(!config.replicates("00ff"));
+        assert!(config.replicates("00fe"));
+        
+        // The allowlist wins over the denylist
+        config.replication_allowlist = vec!["a?c*".to_string(), "0123".to_string()];
+        assert!(config.replicates("abc123"));
+        assert!(config.replicates("axc"));
+        assert!(config.replicates("0123ff"));
+        assert!(!config.replicates("00fe"));
+        assert!(!config.replicates("bc"));
+        
+        config.replication_denylist.push(" ".to_string());
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_matches_repo_pattern() {
+        assert!(matches_repo_pattern("*", "anything"));
+        assert!(matches_repo_pattern("ab*ef", "abcdef"));
+        assert!(matches_repo_pattern("ab*ef", "abef"));
+        assert!(matches_repo_pattern("*c*e*", "abcdef"));
+        assert!(matches_repo_pattern("a*b*c", "aXbYbZc"));
+        assert!(!matches_repo_pattern("ab*ef", "abcde"));
+        assert!(!matches_repo_pattern("a?c", "abcd"));
+        assert!(!matches_repo_pattern("a?c", "ac"));
+    }
+    
     #[test]
     fn test_default_hyrule_server() {
         let config = NodeConfig::generate();
@@ -338,6 +1631,321 @@ mod tests {
         assert!(config.validate().is_ok());
     }
     
+    #[test]
+    fn test_bind_socket_addr() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.bind_socket_addr().unwrap().to_string(), "0.0.0.0:8080");
+        
+        config.bind_address = "::1".to_string();
+        assert_eq!(config.bind_socket_addr().unwrap().to_string(), "[::1]:8080");
+        
+        config.bind_address = "[::]".to_string();
+        assert_eq!(config.bind_socket_addr().unwrap().to_string(), "[::]:8080");
+        
+        config.bind_address = "localhost".to_string();
+        assert!(config.bind_socket_addr().is_err());
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_compression_level_validated() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.compression_level, 6);
+        
+        config.compression_level = 9;
+        assert!(config.validate().is_ok());
+        
+        config.compression_level = 10;
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_network_namespace_validated() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.network_namespace, "main");
+        
+        config.network_namespace = "staging-2".to_string();
+        assert!(config.validate().is_ok());
+        
+        for bad in ["", "Main", "test:net", "a b"] {
+            config.network_namespace = bad.to_string();
+            assert!(config.validate().is_err(), "{:?}", bad);
+        }
+    }
+    
+    #[test]
+    fn test_cors_origins_validated() {
+        let mut config = NodeConfig::generate();
+        assert!(config.cors_allowed_origins.is_empty());
+        
+        config.cors_allowed_origins = vec!["https://dash.example".to_string(), "*".to_string()];
+        assert!(config.validate().is_ok());
+        
+        config.cors_allowed_origins = vec!["https://dash.example/".to_string()];
+        assert!(config.validate().is_err());
+        
+        config.cors_allowed_origins = vec!["dash.example".to_string()];
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_intervals_validated() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.heartbeat_interval_secs, 60);
+        assert_eq!(config.replication_interval_secs, 300);
+        assert_eq!(config.dht_announce_interval_secs, 300);
+        
+        config.dht_announce_interval_secs = 0;
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_request_limits_validated() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.max_request_body_bytes, 32 * 1024 * 1024);
+        assert_eq!(config.request_timeout_secs, 60);
+        
+        config.max_request_body_bytes = 0;
+        assert!(config.validate().is_err());
+        
+        config.max_request_body_bytes = 1024;
+        assert_eq!(config.max_batch_objects, 10_000);
+        config.max_batch_objects = 0;
+        assert!(config.validate().is_err());
+        
+        config.max_batch_objects = 10_000;
+        assert_eq!(config.max_batch_bytes, 64 * 1024 * 1024);
+        config.max_batch_bytes = 0;
+        assert!(config.validate().is_err());
+        
+        config.max_batch_bytes = 1024;
+        config.request_timeout_secs = 0;
+        assert!(config.validate().is_err());
+        
+        config.request_timeout_secs = 60;
+        assert_eq!(config.peer_request_timeout_secs, 30);
+        config.peer_request_timeout_secs = 0;
+        assert!(config.validate().is_err());
+        
+        config.peer_request_timeout_secs = 30;
+        assert_eq!(config.target_replication_factor, 3);
+        config.target_replication_factor = 0;
+        assert!(config.validate().is_err());
+        
+        config.target_replication_factor = 3;
+        config.rate_limit_burst = 0;
+        assert!(config.validate().is_err());
+        config.rate_limit_per_sec = 0;
+        assert!(config.validate().is_ok());
+        
+        assert_eq!(config.write_shed_fraction, 0.95);
+        config.write_shed_fraction = 0.0;
+        assert!(config.validate().is_err());
+        config.write_shed_fraction = 1.0;
+        config.max_concurrent_downloads = 0;
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_auth_for_reads_needs_token() {
+        let mut config = NodeConfig::generate();
+        assert!(!config.require_auth_for_reads);
+        
+        config.require_auth_for_reads = true;
+        assert!(config.validate().is_err());
+        
+        // The admin token is not shared with peers, so it isn't enough
+        config.admin_token = Some("s3cret".to_string());
+        assert!(config.validate().is_err());
+        
+        config.peer_token = Some("peers".to_string());
+        assert!(config.validate().is_ok());
+    }
+    
+    #[test]
+    fn test_expand_path() {
+        std::env::set_var("HYRULE_TEST_DATA", "/srv/hyrule");
+        
+        assert_eq!(expand_path("/var/lib/hyrule").unwrap(), "/var/lib/hyrule");
+        assert_eq!(expand_path("node-storage").unwrap(), "node-storage");
+        assert_eq!(expand_path("$HYRULE_TEST_DATA/repos").unwrap(), "/srv/hyrule/repos");
+        assert_eq!(expand_path("${HYRULE_TEST_DATA}-ssd").unwrap(), "/srv/hyrule-ssd");
+        
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path("~/hyrule").unwrap(), home.join("hyrule").to_string_lossy());
+        // Only a leading ~ is special
+        assert_eq!(expand_path("/a/~b").unwrap(), "/a/~b");
+        
+        assert!(expand_path("$HYRULE_TEST_UNSET_VAR/x").is_err());
+        assert!(expand_path("${HYRULE_TEST_DATA").is_err());
+    }
+    
+    #[test]
+    fn test_tls_only_without_tor() {
+        let mut config = NodeConfig::generate();
+        assert!(config.tls_files().is_none());
+        
+        config.tls_cert_path = Some(PathBuf::from("/etc/hyrule/cert.pem"));
+        assert!(config.validate().is_err());
+        
+        config.tls_key_path = Some(PathBuf::from("/etc/hyrule/key.pem"));
+        assert!(config.validate().is_ok());
+        assert!(config.tls_files().is_none());
+        
+        config.enable_proxy = false;
+        let (cert, key) = config.tls_files().unwrap();
+        assert_eq!(cert, Path::new("/etc/hyrule/cert.pem"));
+        assert_eq!(key, Path::new("/etc/hyrule/key.pem"));
+    }
+    
+    #[test]
+    fn test_config_signature() {
+        let signer = NodeConfig::generate();
+        let content = b"hyrule_server = \"http://coordinator.onion\"\n";
+        let signature = sign_config(content, &signer.private_key).unwrap();
+        
+        assert!(check_signature(content, &signature, &signer.public_key).is_ok());
+        assert!(check_signature(b"hyrule_server = \"http://evil.onion\"\n", &signature, &signer.public_key).is_err());
+        assert!(check_signature(content, &signature, &NodeConfig::generate().public_key).is_err());
+        assert!(check_signature(content, "not hex", &signer.public_key).is_err());
+        
+        assert_eq!(
+            signature_path(Path::new("/etc/hyrule/config.toml")),
+            Path::new("/etc/hyrule/config.toml.sig")
+        );
+    }
+    
+    #[test]
+    fn test_signing_key_required_when_enforced() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.signing_key().unwrap(), None);
+        
+        config.config_signature_required = true;
+        assert!(config.signing_key().is_err());
+        
+        config.config_signing_key = Some(config.public_key.clone());
+        assert_eq!(config.signing_key().unwrap(), Some(config.public_key.clone()));
+    }
+    
+    #[test]
+    fn test_upgrade_minimal_old_config() {
+        let identity = NodeConfig::generate();
+        let old = format!(
+            r#"
+node_id = "{}"
+public_key = "{}"
+private_key = "{}"
+hyrule_server = "http://coordinator.example"
+port = 9000
+storage_path = "/var/lib/hyrule"
+storage_capacity = 1073741824
+is_anchor = true
+legacy_field = "unused"
+"#,
+            identity.node_id, identity.public_key, identity.private_key
+        );
+        
+        let migration = NodeConfig::upgrade(&old).unwrap();
+        let config = &migration.config;
+        
+        // Existing values are preserved
+        assert_eq!(config.node_id, identity.node_id);
+        assert_eq!(config.hyrule_server, "http://coordinator.example");
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.storage_path, "/var/lib/hyrule");
+        assert!(config.is_anchor);
+        
+        // Missing fields get defaults
+        assert_eq!(config.bind_address, "0.0.0.0");
+        assert_eq!(config.proxy_addr, "127.0.0.1:9050");
+        assert!(config.enable_proxy);
+        assert_eq!(config.tor_mode, TorMode::Arti);
+        assert_eq!(config.heartbeat_interval_secs, 60);
+        assert!(config.validate().is_ok());
+        
+        assert!(migration.added.contains(&"bind_address".to_string()));
+        assert!(migration.added.contains(&"enable_dht".to_string()));
+        assert!(!migration.added.contains(&"port".to_string()));
+        assert_eq!(migration.removed, vec!["legacy_field".to_string()]);
+        
+        // The rewritten file is already current
+        let rewritten = toml::to_string_pretty(config).unwrap();
+        let again = NodeConfig::upgrade(&rewritten).unwrap();
+        assert!(again.is_noop());
+        assert_eq!(again.config.port, 9000);
+        
+        let socks = rewritten.replace(r#"tor_mode = "arti""#, r#"tor_mode = "socks""#);
+        assert_eq!(NodeConfig::upgrade(&socks).unwrap().config.tor_mode, TorMode::Socks);
+    }
+    
+    #[test]
+    fn test_effective_settings() {
+        let content = r#"
+private_key = "secret"
+admin_token = "token"
+alert_webhook = "https://hooks.example/T000/secret"
+port = 9000
+"#;
+        let mut config: NodeConfig = toml::from_str(content).unwrap();
+        let settings = config.effective_settings(content).unwrap();
+        let setting = |settings: &[Setting], key: &str| {
+            let setting = settings.iter().find(|s| s.key == key).unwrap();
+            (setting.value.clone(), setting.source)
+        };
+        
+        assert_eq!(setting(&settings, "port"), (9000.into(), SettingSource::File));
+        assert_eq!(setting(&settings, "bind_address"), ("0.0.0.0".into(), SettingSource::Default));
+        assert_eq!(setting(&settings, "advertised_address"), (serde_json::Value::Null, SettingSource::Default));
+        assert_eq!(setting(&settings, "private_key"), ("<redacted>".into(), SettingSource::File));
+        assert_eq!(setting(&settings, "admin_token"), ("<redacted>".into(), SettingSource::File));
+        // Webhook URLs carry their own credentials
+        assert_eq!(setting(&settings, "alert_webhook"), ("<redacted>".into(), SettingSource::File));
+        
+        config.identity_path = Some(PathBuf::from("/etc/hyrule/identity.toml"));
+        config.node_id = "abcd".to_string();
+        let settings = config.effective_settings(content).unwrap();
+        assert_eq!(setting(&settings, "node_id"), ("abcd".into(), SettingSource::Identity));
+        assert!(!settings.iter().any(|s| s.value.as_str() == Some("secret") || s.value.as_str() == Some("token")));
+    }
+    
+    #[test]
+    fn test_storage_tiers_validated() {
+        let mut config = NodeConfig::generate();
+        assert_eq!(config.total_capacity(), config.storage_capacity);
+        
+        config.storage_tiers = vec![
+            StorageTier { path: config.storage_path.clone(), capacity: 100 },
+            StorageTier { path: "/mnt/hdd/hyrule".to_string(), capacity: 1000 },
+        ];
+        assert!(config.validate().is_ok());
+        assert_eq!(config.total_capacity(), 1100);
+        
+        config.storage_tiers[0].path = "/mnt/ssd/other".to_string();
+        assert!(config.validate().is_err());
+        
+        config.storage_tiers[0].path = config.storage_path.clone();
+        config.storage_tiers[1].capacity = 0;
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_capacity_auto_validated() {
+        let mut config = NodeConfig::generate();
+        config.capacity_auto = true;
+        assert!(config.validate().is_ok());
+        
+        config.capacity_auto_fraction = 0.0;
+        assert!(config.validate().is_err());
+        config.capacity_auto_fraction = 1.5;
+        assert!(config.validate().is_err());
+        
+        config.capacity_auto_fraction = 1.0;
+        config.storage_tiers = vec![
+            StorageTier { path: config.storage_path.clone(), capacity: 100 },
+        ];
+        assert!(config.validate().is_err());
+    }
+    
     #[test]
     fn test_is_tor_enabled() {
         let config = NodeConfig::generate();
@@ -1,6 +1,7 @@
 // hyrule-node/src/crypto.rs
 use ed25519_dalek::{SigningKey, VerifyingKey, Signature, Signer, Verifier};
 use anyhow::Result;
+use sha1::{Digest, Sha1};
 
 /// Sign data with node's private key
 pub fn sign_data(private_key_hex: &str, data: &[u8]) -> Result<Vec<u8>> {
@@ -24,6 +25,12 @@ pub fn verify_signature(public_key_hex: &str, data: &[u8], signature: &[u8]) ->
     Ok(verifying_key.verify(data, &signature).is_ok())
 }
 
+/// Node id belonging to a public key: the BLAKE3 hash of the key bytes
+pub fn node_id_for_key(public_key_hex: &str) -> Result<String> {
+    let public_key_bytes = hex::decode(public_key_hex)?;
+    Ok(hex::encode(blake3::hash(&public_key_bytes).as_bytes()))
+}
+
 /// Hash data using BLAKE3
 pub fn hash_data(data: &[u8]) -> String {
     hex::encode(blake3::hash(data).as_bytes())
@@ -34,3 +41,19 @@ pub fn verify_object_hash(data: &[u8], expected_hash: &str) -> bool {
     let actual_hash = hash_data(data);
     actual_hash == expected_hash
 }
+
+/// Git object id: SHA-1 of the raw object (`<type> <size>\0<content>`)
+pub fn git_object_id(raw_object: &[u8]) -> String {
+    hex::encode(Sha1::digest(raw_object))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_object_id() {
+        // `git hash-object` of an empty file
+        assert_eq!(git_object_id(b"blob 0\0"), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+    }
+}
@@ -2,62 +2,492 @@
 // Node/src/dht.rs - DHT Implementation
 // ============================================================================
 
+use crate::config::NodeConfig;
+use crate::crypto;
+use crate::storage::{write_atomic, GitStorage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Routing table file in the storage root
+const TABLE_FILE: &str = "dht-table.json";
+
+/// Announce rounds an announcement outlives without being renewed
+const TTL_INTERVALS: u64 = 3;
+
+/// How far in the future an announcement may be dated, for clock skew
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// What a node announced about its copy of a repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentRecord {
+    pub node_id: String,
+    pub object_count: u64,
+    /// Bytes on disk, across all tiers
+    pub total_size: u64,
+    /// No object in the copy references one that is missing
+    pub is_complete: bool,
+}
+
+impl ContentRecord {
+    /// Describe this node's copy of a repo. Completeness means reading
+    /// every object, so it is carried over from `previous` while the
+    /// object count and size are unchanged.
+    pub fn from_storage(
+        storage: &GitStorage,
+        repo_hash: &str,
+        node_id: &str,
+        previous: Option<&ContentRecord>,
+    ) -> Result<Self> {
+        let object_count = storage.list_objects(repo_hash)?.len() as u64;
+        let total_size = storage.get_repo_size(repo_hash)?;
+
+        let is_complete = match previous {
+            Some(prev) if prev.object_count == object_count && prev.total_size == total_size => {
+                prev.is_complete
+            }
+            _ => storage.is_complete(repo_hash).unwrap_or(false),
+        };
+
+        Ok(Self {
+            node_id: node_id.to_string(),
+            object_count,
+            total_size,
+            is_complete,
+        })
+    }
+}
+
+/// A node's signed claim to host a repo. The signature covers the network
+/// namespace, the repo, the record and the time, and must be made with the
+/// key the node id is derived from, so nobody can announce on another
+/// node's behalf and announcements can't cross networks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    #[serde(flatten)]
+    pub record: ContentRecord,
+    /// Unix time the announcement was made
+    pub announced_at: i64,
+    /// Hex ed25519 key of the announcing node
+    pub public_key: String,
+    /// Hex signature over the repo hash, record and time
+    pub signature: String,
+}
+
+impl Announcement {
+    /// Sign a record about this node's copy of a repo
+    pub fn sign(repo_hash: &str, record: ContentRecord, config: &NodeConfig) -> Result<Self> {
+        if record.node_id != config.node_id {
+            anyhow::bail!("Can only announce records for this node");
+        }
+        
+        let announced_at = chrono::Utc::now().timestamp();
+        let signature = crypto::sign_data(
+            &config.private_key,
+            &signed_bytes(&config.network_namespace, repo_hash, &record, announced_at),
+        )?;
+        
+        Ok(Self {
+            record,
+            announced_at,
+            public_key: config.public_key.clone(),
+            signature: hex::encode(signature),
+        })
+    }
+    
+    /// Check the announcement was signed by the node it names, for this
+    /// repo on the `namespace` network
+    pub fn verify(&self, namespace: &str, repo_hash: &str) -> Result<()> {
+        if crypto::node_id_for_key(&self.public_key)? != self.record.node_id {
+            anyhow::bail!("Announcement key does not belong to node {}", self.record.node_id);
+        }
+        
+        let signature = hex::decode(&self.signature)?;
+        let data = signed_bytes(namespace, repo_hash, &self.record, self.announced_at);
+        if !crypto::verify_signature(&self.public_key, &data, &signature)? {
+            anyhow::bail!("Invalid announcement signature from node {}", self.record.node_id);
+        }
+        
+        Ok(())
+    }
+}
+
+/// What an announcement's signature covers
+fn signed_bytes(namespace: &str, repo_hash: &str, record: &ContentRecord, announced_at: i64) -> Vec<u8> {
+    format!(
+        "hyrule-dht-announce\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+        namespace,
+        repo_hash,
+        record.node_id,
+        record.object_count,
+        record.total_size,
+        record.is_complete,
+        announced_at
+    )
+    .into_bytes()
+}
 
 /// Simple DHT for content discovery
 pub struct DHT {
     node_id: String,
-    routing_table: HashMap<String, Vec<String>>, // repo_hash -> [node_ids]
+    /// Network the node belongs to; part of every key, so networks sharing
+    /// a table never see each other's announcements
+    namespace: String,
+    /// Announcements by [`DHT::key`]
+    routing_table: HashMap<String, Vec<Announcement>>,
+    /// How long an announcement is kept without being renewed
+    ttl: Duration,
+    /// File the table is persisted to, None for an in-memory table
+    path: Option<PathBuf>,
 }
 
 impl DHT {
-    pub fn new(node_id: String) -> Self {
+    pub fn new(node_id: String, namespace: String, ttl: Duration) -> Self {
         Self {
             node_id,
+            namespace,
             routing_table: HashMap::new(),
+            ttl,
+            path: None,
         }
     }
     
-    /// Announce that this node hosts a repository
-    pub fn announce_content(&mut self, repo_hash: &str, node_id: &str) {
-        self.routing_table
-            .entry(repo_hash.to_string())
-            .or_insert_with(Vec::new)
-            .push(node_id.to_string());
+    /// Load the table persisted under the storage root; a missing or
+    /// unreadable file starts empty
+    pub fn load(node_id: String, namespace: String, ttl: Duration, storage: &GitStorage) -> Self {
+        let path = storage.base_path().join(TABLE_FILE);
+        let routing_table = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        
+        Self {
+            node_id,
+            namespace,
+            routing_table,
+            ttl,
+            path: Some(path),
+        }
     }
     
-    /// Query which nodes host a repository
-    pub fn query_content(&self, repo_hash: &str) -> Vec<String> {
-        self.routing_table
-            .get(repo_hash)
-            .cloned()
-            .unwrap_or_default()
+    /// Routing table key of a repo on this node's network
+    fn key(&self, repo_hash: &str) -> String {
+        format!("{}/{}", self.namespace, repo_hash)
+    }
+    
+    /// Persist the table, if it was loaded from storage
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => Ok(write_atomic(path, &serde_json::to_vec(&self.routing_table)?)?),
+            None => Ok(()),
+        }
+    }
+    
+    /// Accept a node's announcement that it hosts a repository, replacing
+    /// what it announced before. Unsigned, forged, stale and replayed
+    /// announcements are rejected.
+    pub fn announce_content(&mut self, repo_hash: &str, announcement: Announcement) -> Result<()> {
+        announcement.verify(&self.namespace, repo_hash)?;
+        
+        let now = chrono::Utc::now().timestamp();
+        if announcement.announced_at > now + MAX_CLOCK_SKEW_SECS {
+            anyhow::bail!("Announcement is dated in the future");
+        }
+        if self.is_expired(&announcement, now) {
+            anyhow::bail!("Announcement has already expired");
+        }
+        
+        let entries = self.routing_table.entry(self.key(repo_hash)).or_default();
+        match entries.iter_mut().find(|e| e.record.node_id == announcement.record.node_id) {
+            Some(existing) if existing.announced_at > announcement.announced_at => {
+                anyhow::bail!("Announcement is older than the one already held");
+            }
+            Some(existing) => *existing = announcement,
+            None => entries.push(announcement),
+        }
+        
+        Ok(())
+    }
+    
+    /// Query which nodes host a repository, best copies first: complete
+    /// ones, then those with the most objects
+    pub fn query_content(&self, repo_hash: &str) -> Vec<ContentRecord> {
+        let now = chrono::Utc::now().timestamp();
+        let mut records: Vec<ContentRecord> = self.routing_table
+            .get(&self.key(repo_hash))
+            .into_iter()
+            .flatten()
+            .filter(|a| !self.is_expired(a, now))
+            .map(|a| a.record.clone())
+            .collect();
+        
+        records.sort_by_key(|r| std::cmp::Reverse((r.is_complete, r.object_count)));
+        records
     }
     
     /// Remove announcement
     pub fn unannounce_content(&mut self, repo_hash: &str, node_id: &str) {
-        if let Some(nodes) = self.routing_table.get_mut(repo_hash) {
-            nodes.retain(|n| n != node_id);
+        let key = self.key(repo_hash);
+        if let Some(entries) = self.routing_table.get_mut(&key) {
+            entries.retain(|e| e.record.node_id != node_id);
         }
     }
+    
+    /// Unix time after which an announcement is dropped unless renewed
+    pub fn expires_at(&self, announcement: &Announcement) -> i64 {
+        announcement.announced_at + self.ttl.as_secs() as i64
+    }
+    
+    pub fn is_expired(&self, announcement: &Announcement, now: i64) -> bool {
+        now >= self.expires_at(announcement)
+    }
+    
+    /// Every announcement on this node's network, expired ones included,
+    /// grouped by repo in repo order
+    pub fn entries(&self) -> Vec<(&str, &[Announcement])> {
+        let prefix = self.key("");
+        let mut entries: Vec<(&str, &[Announcement])> = self.routing_table
+            .iter()
+            .filter_map(|(key, entries)| Some((key.strip_prefix(&prefix)?, entries.as_slice())))
+            .collect();
+        entries.sort_by_key(|(repo_hash, _)| *repo_hash);
+        entries
+    }
+    
+    /// Drop expired announcements, returning how many were dropped
+    pub fn prune(&mut self) -> usize {
+        self.prune_at(chrono::Utc::now().timestamp())
+    }
+    
+    fn prune_at(&mut self, now: i64) -> usize {
+        let ttl = self.ttl.as_secs() as i64;
+        let mut dropped = 0;
+        self.routing_table.retain(|_, entries| {
+            let before = entries.len();
+            entries.retain(|e| now < e.announced_at + ttl);
+            dropped += before - entries.len();
+            !entries.is_empty()
+        });
+        dropped
+    }
+    
+    /// Forget every announcement, returning how many there were
+    pub fn clear(&mut self) -> usize {
+        let count = self.routing_table.values().map(Vec::len).sum();
+        self.routing_table.clear();
+        count
+    }
+}
+
+/// How long announcements live: a few announce rounds, so a node that
+/// misses one isn't dropped but one that went away is
+pub fn announcement_ttl(config: &NodeConfig) -> Duration {
+    Duration::from_secs(config.dht_announce_interval_secs.saturating_mul(TTL_INTERVALS))
 }
 
 /// Periodically announce hosted repos to the DHT
 pub async fn announcement_loop(state: crate::NodeState) {
-    use tokio::time::{interval, Duration};
+    use crate::jitter::JitteredInterval;
     
-    let mut interval = interval(Duration::from_secs(300)); // Every 5 minutes
+    let mut interval = JitteredInterval::new(
+        &state.config.node_id,
+        "dht",
+        Duration::from_secs(state.config.dht_announce_interval_secs),
+    );
+    // Last announcement per repo, so unchanged repos aren't rescanned
+    let mut announced: HashMap<String, ContentRecord> = HashMap::new();
     
     loop {
         interval.tick().await;
+        crate::tasks::record_run();
         
+        state.drop_missing_repos().await;
         let repos = state.hosted_repos.read().await.clone();
+        announced.retain(|repo_hash, _| repos.contains(repo_hash));
+        
+        for repo_hash in repos {
+            let storage = state.storage.clone();
+            let node_id = state.config.node_id.clone();
+            let hash = repo_hash.clone();
+            let previous = announced.get(&repo_hash).cloned();
+            
+            let record = tokio::task::spawn_blocking(move || {
+                ContentRecord::from_storage(&storage, &hash, &node_id, previous.as_ref())
+            }).await.map_err(anyhow::Error::from).and_then(|r| r);
+            
+            match record {
+                Ok(record) => {
+                    announced.insert(repo_hash, record);
+                }
+                Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to describe repo for DHT"),
+            }
+        }
         
         if let Some(dht) = state.dht.write().await.as_mut() {
-            for repo_hash in repos {
-                dht.announce_content(&repo_hash, &state.config.node_id);
-                tracing::debug!("Announced {} to DHT", &repo_hash[..8]);
+            for (repo_hash, record) in &announced {
+                let result = Announcement::sign(repo_hash, record.clone(), &state.config)
+                    .and_then(|announcement| dht.announce_content(repo_hash, announcement));
+                match result {
+                    Ok(()) => tracing::debug!(
+                        repo = %repo_hash,
+                        objects = record.object_count,
+                        complete = record.is_complete,
+                        "Announced to DHT"
+                    ),
+                    Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to announce to DHT"),
+                }
             }
+            
+            let expired = dht.prune();
+            if expired > 0 {
+                tracing::debug!(expired, "Pruned expired DHT announcements");
+            }
+            if let Err(e) = dht.save() {
+                tracing::warn!(error = %e, "Failed to save DHT routing table");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn record(node: &NodeConfig, object_count: u64, is_complete: bool) -> ContentRecord {
+        ContentRecord {
+            node_id: node.node_id.clone(),
+            object_count,
+            total_size: object_count * 100,
+            is_complete,
+        }
+    }
+
+    /// An announcement by `node` dated `announced_at`
+    fn signed_at(node: &NodeConfig, repo_hash: &str, record: ContentRecord, announced_at: i64) -> Announcement {
+        let signature = crypto::sign_data(&node.private_key, &signed_bytes(&node.network_namespace, repo_hash, &record, announced_at)).unwrap();
+        Announcement {
+            record,
+            announced_at,
+            public_key: node.public_key.clone(),
+            signature: hex::encode(signature),
+        }
+    }
+
+    fn announce(dht: &mut DHT, node: &NodeConfig, repo_hash: &str, object_count: u64, is_complete: bool) {
+        let announcement = Announcement::sign(repo_hash, record(node, object_count, is_complete), node).unwrap();
+        dht.announce_content(repo_hash, announcement).unwrap();
+    }
+
+    #[test]
+    fn test_query_ranks_complete_copies_first() {
+        let (big, complete, small) = (NodeConfig::generate(), NodeConfig::generate(), NodeConfig::generate());
+        let mut dht = DHT::new("self".to_string(), "main".to_string(), TTL);
+        announce(&mut dht, &big, "repo", 90, false);
+        announce(&mut dht, &complete, "repo", 50, true);
+        announce(&mut dht, &small, "repo", 10, false);
+
+        let nodes: Vec<String> = dht.query_content("repo").into_iter().map(|r| r.node_id).collect();
+        assert_eq!(nodes, vec![complete.node_id.clone(), big.node_id.clone(), small.node_id.clone()]);
+
+        // Re-announcing replaces the node's record instead of adding one
+        announce(&mut dht, &small, "repo", 100, true);
+        let records = dht.query_content("repo");
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0], record(&small, 100, true));
+
+        dht.unannounce_content("repo", &complete.node_id);
+        assert_eq!(dht.query_content("repo").len(), 2);
+        assert!(dht.query_content("other").is_empty());
+    }
+
+    #[test]
+    fn test_rejects_forged_and_stale_announcements() {
+        let (alice, mallory) = (NodeConfig::generate(), NodeConfig::generate());
+        let mut dht = DHT::new("self".to_string(), "main".to_string(), TTL);
+        let now = chrono::Utc::now().timestamp();
+
+        // Nodes can only sign for themselves
+        assert!(Announcement::sign("repo", record(&alice, 10, true), &mallory).is_err());
+
+        // Mallory claiming Alice hosts the repo, with either key
+        let mut forged = signed_at(&mallory, "repo", record(&alice, 10, true), now);
+        assert!(dht.announce_content("repo", forged.clone()).is_err());
+        forged.public_key = alice.public_key.clone();
+        assert!(dht.announce_content("repo", forged).is_err());
+
+        // Edited after signing, or replayed for another repo
+        let mut tampered = signed_at(&alice, "repo", record(&alice, 10, false), now);
+        tampered.record.is_complete = true;
+        assert!(dht.announce_content("repo", tampered).is_err());
+        assert!(dht.announce_content("other", signed_at(&alice, "repo", record(&alice, 10, true), now)).is_err());
+
+        // Out of date or dated too far ahead
+        assert!(dht.announce_content("repo", signed_at(&alice, "repo", record(&alice, 10, true), now - 61)).is_err());
+        assert!(dht.announce_content("repo", signed_at(&alice, "repo", record(&alice, 10, true), now + 3600)).is_err());
+
+        // An older announcement can't roll back a newer one
+        dht.announce_content("repo", signed_at(&alice, "repo", record(&alice, 20, true), now)).unwrap();
+        assert!(dht.announce_content("repo", signed_at(&alice, "repo", record(&alice, 5, false), now - 10)).is_err());
+        assert_eq!(dht.query_content("repo"), vec![record(&alice, 20, true)]);
+    }
+
+    #[test]
+    fn test_expired_entries_are_hidden_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let (live, stale) = (NodeConfig::generate(), NodeConfig::generate());
+        let now = chrono::Utc::now().timestamp();
+
+        let mut dht = DHT::load("self".to_string(), "main".to_string(), TTL, &storage);
+        announce(&mut dht, &live, "repo", 10, true);
+        // Lapsed announcements, as left behind by a node that went away
+        for repo_hash in ["repo", "gone"] {
+            let expired = signed_at(&stale, repo_hash, record(&stale, 10, true), now - 61);
+            dht.routing_table.entry(dht.key(repo_hash)).or_default().push(expired);
         }
+
+        // Expired entries still show in a dump but not in queries
+        assert_eq!(dht.entries().len(), 2);
+        assert_eq!(dht.query_content("repo"), vec![record(&live, 10, true)]);
+        assert!(dht.query_content("gone").is_empty());
+
+        assert_eq!(dht.prune_at(now), 2);
+        assert_eq!(dht.entries().len(), 1);
+        dht.save().unwrap();
+
+        // The table survives a reload, and clearing empties it
+        let mut reloaded = DHT::load("self".to_string(), "main".to_string(), TTL, &storage);
+        assert_eq!(reloaded.query_content("repo"), vec![record(&live, 10, true)]);
+        assert_eq!(reloaded.clear(), 1);
+        assert!(reloaded.entries().is_empty());
+    }
+
+    #[test]
+    fn test_networks_are_kept_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let mut node = NodeConfig::generate();
+        node.network_namespace = "staging".to_string();
+
+        let mut staging = DHT::load("self".to_string(), "staging".to_string(), TTL, &storage);
+        announce(&mut staging, &node, "repo", 10, true);
+        staging.save().unwrap();
+
+        // The same table read as another network holds nothing for it
+        let mut main = DHT::load("self".to_string(), "main".to_string(), TTL, &storage);
+        assert!(main.query_content("repo").is_empty());
+        assert!(main.entries().is_empty());
+
+        // And an announcement signed for staging isn't valid on main
+        let announcement = Announcement::sign("repo", record(&node, 10, true), &node).unwrap();
+        assert!(main.announce_content("repo", announcement.clone()).is_err());
+        staging.announce_content("repo", announcement).unwrap();
+        assert_eq!(staging.entries().len(), 1);
+        assert_eq!(staging.entries()[0].0, "repo");
     }
 }
@@ -0,0 +1,195 @@
+// hyrule-node/src/doctor.rs
+use crate::config::NodeConfig;
+use crate::proxy::ProxyConfig;
+use crate::storage::GitStorage;
+use anyhow::Result;
+use std::time::Duration;
+
+/// Below this much free disk the node can't accept new replicas
+const MIN_FREE_BYTES: u64 = 100 * 1024 * 1024;
+
+enum Outcome {
+    Pass(String),
+    Fail(String),
+    Skip(String),
+}
+
+impl From<Result<String>> for Outcome {
+    fn from(result: Result<String>) -> Self {
+        match result {
+            Ok(detail) => Outcome::Pass(detail),
+            Err(e) => Outcome::Fail(e.to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+    passed: usize,
+}
+
+impl Report {
+    fn print(&mut self, name: &str, outcome: Outcome) {
+        match outcome {
+            Outcome::Pass(detail) => {
+                self.passed += 1;
+                println!("✓ {:<14} {}", name, detail);
+            }
+            Outcome::Fail(detail) => {
+                self.failed += 1;
+                println!("✗ {:<14} {}", name, detail);
+            }
+            Outcome::Skip(detail) => {
+                println!("- {:<14} {}", name, detail);
+            }
+        }
+    }
+}
+
+/// `hyrule-node doctor` - check everything the node needs to start
+pub async fn run() -> Result<()> {
+    println!("🩺 Checking node setup...");
+    println!();
+
+    let mut report = Report::default();
+
+    let config = match load_config() {
+        Ok((config, path)) => {
+            report.print("Config", Outcome::Pass(format!("loaded from {}", path)));
+            Some(config)
+        }
+        Err(e) => {
+            report.print("Config", Outcome::Fail(e.to_string()));
+            None
+        }
+    };
+
+    let Some(config) = config else {
+        for name in ["Keys", "Storage", "Listen port", "Tor", "Hyrule server"] {
+            report.print(name, Outcome::Skip("needs a valid config".to_string()));
+        }
+        return finish(report);
+    };
+
+    report.print("Keys", check_keys(&config).into());
+    report.print("Storage", check_storage(&config).into());
+    report.print("Listen port", check_port(&config).into());
+
+    let proxy = ProxyConfig::from_config(&config);
+    let tor_ok = if config.enable_proxy {
+        let outcome: Outcome = check_tor(&proxy).await.into();
+        let ok = matches!(outcome, Outcome::Pass(_));
+        report.print("Tor", outcome);
+        ok
+    } else {
+        report.print("Tor", Outcome::Skip("disabled in config".to_string()));
+        true
+    };
+
+    if tor_ok {
+        report.print("Hyrule server", check_server(&config, &proxy).await.into());
+    } else {
+        report.print("Hyrule server", Outcome::Skip("needs a working Tor connection".to_string()));
+    }
+
+    finish(report)
+}
+
+fn finish(report: Report) -> Result<()> {
+    println!();
+    if report.failed > 0 {
+        anyhow::bail!("{} check(s) failed, {} passed", report.failed, report.passed);
+    }
+    println!("✓ All {} checks passed", report.passed);
+    Ok(())
+}
+
+fn load_config() -> Result<(NodeConfig, String)> {
+    let path = NodeConfig::config_path()?;
+    let config = NodeConfig::load()?;
+    config.validate()?;
+    Ok((config, path.display().to_string()))
+}
+
+/// Sign and verify a message to prove the key pair belongs together
+fn check_keys(config: &NodeConfig) -> Result<String> {
+    if let Some(problem) = config.check_identity().first() {
+        anyhow::bail!("{} (see `hyrule-node verify-identity`)", problem);
+    }
+
+    Ok("sign/verify round trip ok, node_id matches public_key".to_string())
+}
+
+fn check_storage(config: &NodeConfig) -> Result<String> {
+    let storage = GitStorage::new(&config.storage_path)?;
+    storage.check_writable()?;
+
+    let free = storage.available_disk_space()?;
+    let free_gb = free as f64 / (1024.0 * 1024.0 * 1024.0);
+    if free < MIN_FREE_BYTES {
+        anyhow::bail!("only {:.2} GB free at {}", free_gb, config.storage_path);
+    }
+
+    Ok(format!("{} is writable, {:.2} GB free", config.storage_path, free_gb))
+}
+
+fn check_port(config: &NodeConfig) -> Result<String> {
+    let addr = config.bind_socket_addr()?;
+    std::net::TcpListener::bind(addr)
+        .map_err(|e| anyhow::anyhow!("cannot bind {}: {}", addr, e))?;
+
+    Ok(format!("{} is free", addr))
+}
+
+async fn check_tor(proxy: &ProxyConfig) -> Result<String> {
+    proxy.init_tor_client().await?;
+    proxy.validate_tor_connection().await?;
+    Ok("bootstrapped and connected".to_string())
+}
+
+/// Any HTTP response counts; we only care that the coordinator is reachable
+async fn check_server(config: &NodeConfig, proxy: &ProxyConfig) -> Result<String> {
+    let url = format!("{}/api/nodes", config.hyrule_server);
+    let timeout = Duration::from_secs(30);
+
+    let status = if proxy.enabled {
+        let client = proxy.build_client()?;
+        client.get(&url).timeout(timeout).send().await?.status().as_u16()
+    } else {
+        let client = reqwest::Client::new();
+        client.get(&url)
+            .headers(proxy.identity.headers(&url))
+            .timeout(timeout)
+            .send().await?.status().as_u16()
+    };
+
+    Ok(format!("{} responded with {}", config.hyrule_server, status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_keys() {
+        let mut config = NodeConfig::generate();
+        assert!(check_keys(&config).is_ok());
+
+        config.public_key = NodeConfig::generate().public_key;
+        assert!(check_keys(&config).is_err());
+    }
+
+    #[test]
+    fn test_check_port_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut config = NodeConfig::generate();
+        config.bind_address = "127.0.0.1".to_string();
+        config.port = listener.local_addr().unwrap().port();
+
+        assert!(check_port(&config).is_err());
+        drop(listener);
+        assert!(check_port(&config).is_ok());
+    }
+}
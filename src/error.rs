@@ -0,0 +1,119 @@
+// hyrule-node/src/error.rs
+use crate::storage::{RefConflict, RefIsHead};
+use std::io::ErrorKind;
+
+/// Failures of the storage and config APIs that callers may want to tell
+/// apart. The binary still deals in `anyhow`, which wraps these as-is.
+#[derive(Debug, thiserror::Error)]
+pub enum HyruleError {
+    /// A repo, object or ref that doesn't exist
+    #[error("{0} not found")]
+    NotFound(String),
+
+    /// Malformed ids, ref names and the like
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("{0}")]
+    TooLarge(String),
+
+    /// Stored data that doesn't decode
+    #[error("Object {object_id} is corrupt: {reason}")]
+    Corrupt { object_id: String, reason: String },
+
+    /// Every storage tier (or the one asked for) is at capacity
+    #[error("{0}")]
+    StorageFull(String),
+
+    /// Something already there that the request would replace, e.g. a
+    /// repo promoted over a stored one
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    RefConflict(#[from] RefConflict),
+
+    #[error(transparent)]
+    RefIsHead(#[from] RefIsHead),
+
+    /// A peer or the coordinator couldn't be reached or dropped the request
+    #[error("{0}")]
+    Network(String),
+
+    /// A peer or the coordinator didn't answer in time
+    #[error("{0}")]
+    Timeout(String),
+
+    /// Credentials that were missing or refused
+    #[error("{0}")]
+    Auth(String),
+
+    /// A config that can't be loaded or doesn't validate
+    #[error("{0}")]
+    Config(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<walkdir::Error> for HyruleError {
+    fn from(e: walkdir::Error) -> Self {
+        HyruleError::Io(e.into())
+    }
+}
+
+pub type Result<T, E = HyruleError> = std::result::Result<T, E>;
+
+/// Return early with a `HyruleError` variant holding a formatted message,
+/// e.g. `bail!(Invalid, "Invalid ref name: {}", name)`
+macro_rules! bail {
+    ($variant:ident, $($arg:tt)*) => {
+        return Err($crate::error::HyruleError::$variant(format!($($arg)*)))
+    };
+}
+pub(crate) use bail;
+
+impl HyruleError {
+    /// Whether this was caused by running out of space, either on the
+    /// disk or within the configured capacity
+    pub fn is_disk_full(&self) -> bool {
+        match self {
+            HyruleError::StorageFull(_) => true,
+            HyruleError::Io(e) => e.kind() == ErrorKind::StorageFull,
+            HyruleError::Other(e) => e.chain().any(is_disk_full),
+            _ => false,
+        }
+    }
+}
+
+/// Whether one error in a chain means the disk is full
+pub fn is_disk_full(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = err.downcast_ref::<HyruleError>() {
+        return e.is_disk_full();
+    }
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::StorageFull)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_disk_full() {
+        assert!(HyruleError::StorageFull("All storage tiers are full".into()).is_disk_full());
+        assert!(HyruleError::from(std::io::Error::from(ErrorKind::StorageFull)).is_disk_full());
+
+        // Still found once wrapped in anyhow context, either way round
+        let wrapped = anyhow::Error::from(HyruleError::StorageFull("full".into())).context("storing object");
+        assert!(wrapped.chain().any(is_disk_full));
+        let inner = anyhow::Error::from(std::io::Error::from(ErrorKind::StorageFull)).context("writing");
+        assert!(HyruleError::from(inner).is_disk_full());
+
+        assert!(!HyruleError::NotFound("Object abc".into()).is_disk_full());
+        assert!(!HyruleError::from(std::io::Error::from(ErrorKind::NotFound)).is_disk_full());
+    }
+}
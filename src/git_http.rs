@@ -0,0 +1,287 @@
+// hyrule-node/src/git_http.rs
+//
+// Read-only Git smart-HTTP (protocol v0) so a stock `git clone` works
+// against `http://<node>/repos/<hash>`.
+
+use crate::storage::{GitStorage, Head, ZERO_ID};
+use crate::NodeState;
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::io::Read;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct ServiceQuery {
+    service: Option<String>,
+}
+
+pub fn router() -> Router<NodeState> {
+    Router::new()
+        .route("/repos/{hash}/info/refs", get(info_refs))
+        .route("/repos/{hash}/git-upload-pack", post(upload_pack))
+}
+
+/// Encode one pkt-line: 4 hex digits of total length, then the payload
+fn pkt_line(out: &mut Vec<u8>, payload: &[u8]) {
+    out.extend_from_slice(format!("{:04x}", payload.len() + 4).as_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn flush_pkt(out: &mut Vec<u8>) {
+    out.extend_from_slice(b"0000");
+}
+
+/// Split a pkt-line stream into payloads; flush packets are skipped
+fn read_pkt_lines(mut data: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut lines = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < 4 {
+            anyhow::bail!("Truncated pkt-line");
+        }
+        let len = usize::from_str_radix(std::str::from_utf8(&data[..4])?, 16)?;
+
+        if len == 0 {
+            data = &data[4..];
+            continue;
+        }
+        if len < 4 || len > data.len() {
+            anyhow::bail!("Invalid pkt-line length {}", len);
+        }
+
+        lines.push(&data[4..len]);
+        data = &data[len..];
+    }
+
+    Ok(lines)
+}
+
+/// Build the ref advertisement body for `info/refs?service=git-upload-pack`
+fn advertise_refs(head: Option<&Head>, refs: &[(String, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    pkt_line(&mut out, b"# service=git-upload-pack\n");
+    flush_pkt(&mut out);
+
+    // HEAD is only advertised once it resolves to a commit
+    let head = head.and_then(|h| Some((h.target.as_deref(), h.commit_id.as_deref()?)));
+
+    let mut capabilities = format!("agent=hyrule-node/{}", env!("CARGO_PKG_VERSION"));
+    if let Some((Some(target), _)) = head {
+        capabilities = format!("symref=HEAD:{} {}", target, capabilities);
+    }
+
+    let mut lines: Vec<(&str, &str)> = Vec::new();
+    if let Some((_, commit_id)) = head {
+        lines.push(("HEAD", commit_id));
+    }
+    lines.extend(refs.iter().map(|(name, id)| (name.as_str(), id.as_str())));
+
+    if lines.is_empty() {
+        // Empty repository: advertise capabilities on a placeholder ref
+        pkt_line(&mut out, format!("{} capabilities^{{}}\0{}\n", ZERO_ID, capabilities).as_bytes());
+    }
+
+    for (i, (name, id)) in lines.iter().enumerate() {
+        let line = if i == 0 {
+            format!("{} {}\0{}\n", id, name, capabilities)
+        } else {
+            format!("{} {}\n", id, name)
+        };
+        pkt_line(&mut out, line.as_bytes());
+    }
+
+    flush_pkt(&mut out);
+    out
+}
+
+async fn info_refs(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+    Query(query): Query<ServiceQuery>,
+) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+    // Only smart HTTP fetches are supported; no dumb HTTP, no push
+    if query.service.as_deref() != Some("git-upload-pack") {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if !state.storage.repo_path(&repo_hash).exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let refs = state.storage
+        .list_refs_async(&repo_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let head = state.storage.read_head_async(&repo_hash).await.ok();
+
+    let headers = git_headers("application/x-git-upload-pack-advertisement");
+    Ok((headers, advertise_refs(head.as_ref(), &refs)))
+}
+
+async fn upload_pack(
+    State(state): State<NodeState>,
+    Path(repo_hash): Path<String>,
+    request_headers: HeaderMap,
+    body: Bytes,
+) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+    if !state.storage.repo_path(&repo_hash).exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Git gzips larger negotiation requests
+    let gzipped = request_headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let body = if gzipped {
+        gunzip(&body, state.config.max_request_body_bytes)?
+    } else {
+        body.to_vec()
+    };
+
+    let lines = read_pkt_lines(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let wants = lines.iter().filter(|l| l.starts_with(b"want ")).count();
+    let done = lines.iter().any(|l| l.starts_with(b"done"));
+
+    let headers = git_headers("application/x-git-upload-pack-result");
+    let mut out = Vec::new();
+
+    if wants == 0 {
+        return Ok((headers, out));
+    }
+
+    // We never report common commits, so every negotiation round gets a
+    // NAK until the client says "done", then it receives a full pack
+    pkt_line(&mut out, b"NAK\n");
+    if !done {
+        return Ok((headers, out));
+    }
+
+    let pack = build_pack(state.storage.clone(), repo_hash.clone())
+        .await
+        .map_err(|e| {
+            tracing::warn!("Failed to build pack: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    out.extend_from_slice(&pack);
+
+    {
+        let mut stats = state.stats.write().await;
+        stats.bytes_served += out.len() as u64;
+    }
+    state.record_repo_access(&repo_hash, out.len() as u64).await;
+
+    Ok((headers, out))
+}
+
+/// Inflate a gzipped request body. The body limit only caps the bytes on
+/// the wire, so the inflated size is held to it here too.
+fn gunzip(body: &[u8], limit: usize) -> Result<Vec<u8>, StatusCode> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(body)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if decoded.len() > limit {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    Ok(decoded)
+}
+
+/// Pack every object in the repository
+async fn build_pack(storage: Arc<GitStorage>, repo_hash: String) -> Result<Vec<u8>> {
+    Ok(tokio::task::spawn_blocking(move || storage.write_pack(&repo_hash, &storage.list_objects(&repo_hash)?))
+        .await??)
+}
+
+fn git_headers(content_type: &'static str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+
+    #[test]
+    fn test_pkt_line_roundtrip() {
+        let mut out = Vec::new();
+        pkt_line(&mut out, b"want abc\n");
+        flush_pkt(&mut out);
+        pkt_line(&mut out, b"done\n");
+
+        assert_eq!(&out[..4], b"000d");
+        let lines = read_pkt_lines(&out).unwrap();
+        assert_eq!(lines, vec![&b"want abc\n"[..], &b"done\n"[..]]);
+
+        assert!(read_pkt_lines(b"00ff").is_err());
+        assert!(read_pkt_lines(b"00").is_err());
+    }
+
+    #[test]
+    fn test_advertise_refs() {
+        let head = Head {
+            target: Some("refs/heads/main".to_string()),
+            commit_id: Some(COMMIT.to_string()),
+        };
+        let refs = vec![("refs/heads/main".to_string(), COMMIT.to_string())];
+
+        let body = advertise_refs(Some(&head), &refs);
+        let lines = read_pkt_lines(&body).unwrap();
+
+        assert_eq!(lines[0], b"# service=git-upload-pack\n");
+        let first = String::from_utf8_lossy(lines[1]);
+        assert!(first.starts_with(&format!("{} HEAD\0", COMMIT)));
+        assert!(first.contains("symref=HEAD:refs/heads/main"));
+        assert_eq!(lines[2], format!("{} refs/heads/main\n", COMMIT).as_bytes());
+        assert!(body.ends_with(b"0000"));
+    }
+
+    #[tokio::test]
+    async fn test_gzip_bomb_is_rejected() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::NodeConfig::generate();
+        config.max_request_body_bytes = 64 * 1024;
+        let state = NodeState::for_tests(config, &dir.path().join("store"));
+        let repo = "ab".repeat(32);
+        state.storage.init_repo(&repo).unwrap();
+        let app = router().with_state(state);
+
+        // A few KB on the wire, 16 MB once inflated
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&vec![b'0'; 16 * 1024 * 1024]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(bomb.len() < 64 * 1024);
+
+        let request = axum::http::Request::post(format!("/repos/{}/git-upload-pack", repo))
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(axum::body::Body::from(bomb))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_advertise_empty_repo() {
+        let body = advertise_refs(None, &[]);
+        let lines = read_pkt_lines(&body).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with(format!("{} capabilities^{{}}\0", ZERO_ID).as_bytes()));
+    }
+}
@@ -0,0 +1,351 @@
+// hyrule-node/src/gitdir.rs
+use crate::crypto;
+use crate::storage::{is_object_id, is_valid_ref_name, write_atomic, GitStorage, RefUpdate};
+use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// What an import copied into storage
+#[derive(Debug)]
+pub struct ImportReport {
+    pub objects: usize,
+    pub refs: usize,
+    /// Branch or commit HEAD was set to, if the source had one we could use
+    pub head: Option<String>,
+}
+
+/// What an export wrote out
+#[derive(Debug)]
+pub struct ExportReport {
+    pub objects: usize,
+    pub refs: usize,
+}
+
+/// Minimal config marking the directory as a bare repository
+const BARE_CONFIG: &str = "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = true\n";
+
+/// Resolve a path to the Git directory itself: a bare repo, or the `.git`
+/// inside a work tree
+fn find_git_dir(path: &Path) -> Result<PathBuf> {
+    let dot_git = path.join(".git");
+    let git_dir = if dot_git.is_dir() { dot_git } else { path.to_path_buf() };
+
+    if !git_dir.join("objects").is_dir() {
+        anyhow::bail!("{} is not a Git directory", path.display());
+    }
+    Ok(git_dir)
+}
+
+/// Copy the loose objects, refs (loose and packed) and HEAD of a Git
+/// directory into storage under `repo_hash`. Every object is hashed before
+/// it is stored; if any doesn't match its id the import stops, and a repo
+/// it created is removed again.
+pub fn import_repo(storage: &GitStorage, path: &Path, repo_hash: &str) -> Result<ImportReport> {
+    let git_dir = find_git_dir(path)?;
+
+    // Packed objects would be silently missing, leaving refs dangling
+    let packs = count_packs(&git_dir)?;
+    if packs > 0 {
+        anyhow::bail!(
+            "{} has {} packfile(s) and only loose objects can be imported; \
+             unpack them first with `git unpack-objects`",
+            git_dir.display(),
+            packs
+        );
+    }
+
+    let created = !storage.repo_path(repo_hash).exists();
+    if created {
+        storage.init_repo(repo_hash)?;
+    }
+
+    let result = import_contents(storage, &git_dir, repo_hash);
+    if result.is_err() && created {
+        let _ = storage.delete_repo(repo_hash);
+    }
+    result
+}
+
+fn import_contents(storage: &GitStorage, git_dir: &Path, repo_hash: &str) -> Result<ImportReport> {
+    let mut objects = 0;
+    for (object_id, path) in loose_objects(&git_dir.join("objects"))? {
+        let mut raw = Vec::new();
+        ZlibDecoder::new(fs::File::open(&path)?)
+            .read_to_end(&mut raw)
+            .with_context(|| format!("Failed to decompress object {}", object_id))?;
+
+        if crypto::git_object_id(&raw) != object_id {
+            anyhow::bail!("Object {} doesn't match its hash", object_id);
+        }
+
+        storage.store_object_batched(repo_hash, &object_id, &raw)?;
+        objects += 1;
+    }
+    // Refs must not point at objects a crash could still lose
+    storage.sync_pending()?;
+
+    let updates: Vec<RefUpdate> = read_refs(git_dir)?
+        .into_iter()
+        .map(|(ref_name, commit_id)| RefUpdate {
+            ref_name,
+            commit_id,
+            expected_old: None,
+        })
+        .collect();
+    storage.update_refs(repo_hash, &updates)?;
+
+    let head = fs::read_to_string(git_dir.join("HEAD"))
+        .ok()
+        .map(|content| content.trim().to_string())
+        .map(|content| content.strip_prefix("ref: ").map(str::to_string).unwrap_or(content))
+        .filter(|target| is_object_id(target) || is_valid_ref_name(target));
+    if let Some(target) = &head {
+        storage.set_head(repo_hash, target)?;
+    }
+
+    Ok(ImportReport {
+        objects,
+        refs: updates.len(),
+        head,
+    })
+}
+
+/// Write a repo out as a bare Git directory at `path`, with loose
+/// objects, its refs and HEAD, so native git tools can read it. The
+/// target must not exist or be an empty directory.
+pub fn export_repo(storage: &GitStorage, repo_hash: &str, path: &Path) -> Result<ExportReport> {
+    if !storage.repo_path(repo_hash).exists() {
+        anyhow::bail!("Repository not found: {}", repo_hash);
+    }
+    if path.exists() && fs::read_dir(path)?.next().is_some() {
+        anyhow::bail!("{} already exists and is not empty", path.display());
+    }
+
+    fs::create_dir_all(path.join("objects"))?;
+    fs::create_dir_all(path.join("refs").join("heads"))?;
+    fs::create_dir_all(path.join("refs").join("tags"))?;
+
+    let mut objects = 0;
+    for object_id in storage.list_objects(repo_hash)? {
+        let raw = storage.read_object(repo_hash, &object_id)?;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw)?;
+
+        let dir = path.join("objects").join(&object_id[..2]);
+        fs::create_dir_all(&dir)?;
+        write_atomic(&dir.join(&object_id[2..]), &encoder.finish()?)?;
+        objects += 1;
+    }
+
+    let refs = storage.list_refs(repo_hash)?;
+    for (ref_name, commit_id) in &refs {
+        let ref_path = path.join(ref_name);
+        if let Some(parent) = ref_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&ref_path, format!("{}\n", commit_id).as_bytes())?;
+    }
+
+    let head = match storage.read_head(repo_hash) {
+        Ok(head) => match (head.target, head.commit_id) {
+            (Some(target), _) => format!("ref: {}\n", target),
+            (None, Some(commit_id)) => format!("{}\n", commit_id),
+            (None, None) => "ref: refs/heads/main\n".to_string(),
+        },
+        Err(_) => "ref: refs/heads/main\n".to_string(),
+    };
+    write_atomic(&path.join("HEAD"), head.as_bytes())?;
+    write_atomic(&path.join("config"), BARE_CONFIG.as_bytes())?;
+
+    Ok(ExportReport {
+        objects,
+        refs: refs.len(),
+    })
+}
+
+fn count_packs(git_dir: &Path) -> Result<usize> {
+    let pack_dir = git_dir.join("objects").join("pack");
+    if !pack_dir.is_dir() {
+        return Ok(0);
+    }
+
+    let mut packs = 0;
+    for entry in fs::read_dir(pack_dir)? {
+        if entry?.path().extension().is_some_and(|ext| ext == "pack") {
+            packs += 1;
+        }
+    }
+    Ok(packs)
+}
+
+/// Loose objects under `objects/xx/yyyy...`, as (id, path)
+fn loose_objects(objects_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut objects = Vec::new();
+
+    for dir in fs::read_dir(objects_dir)? {
+        let dir = dir?;
+        let prefix = dir.file_name().to_string_lossy().into_owned();
+        if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+            continue;
+        }
+
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let object_id = format!("{}{}", prefix, file.file_name().to_string_lossy());
+            if is_object_id(&object_id) {
+                objects.push((object_id.to_ascii_lowercase(), file.path()));
+            }
+        }
+    }
+
+    Ok(objects)
+}
+
+/// Refs pointing at objects, from `packed-refs` and then the loose ref
+/// files, which take precedence as in git. Symbolic refs are skipped.
+fn read_refs(git_dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut refs = BTreeMap::new();
+
+    if let Ok(packed) = fs::read_to_string(git_dir.join("packed-refs")) {
+        // `#` starts the header, `^` the peeled value of the tag above
+        for line in packed.lines().filter(|l| !l.starts_with('#') && !l.starts_with('^')) {
+            if let Some((commit_id, ref_name)) = line.split_once(' ') {
+                refs.insert(ref_name.trim().to_string(), commit_id.to_string());
+            }
+        }
+    }
+
+    let refs_dir = git_dir.join("refs");
+    if refs_dir.is_dir() {
+        for entry in walkdir::WalkDir::new(&refs_dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let relative = entry.path().strip_prefix(git_dir)?;
+            let ref_name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let commit_id = fs::read_to_string(entry.path())?.trim().to_string();
+            refs.insert(ref_name, commit_id);
+        }
+    }
+
+    refs.retain(|ref_name, commit_id| is_valid_ref_name(ref_name) && is_object_id(commit_id));
+    Ok(refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const REPO: &str = "a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+    const BLOB: &[u8] = b"blob 5\0hello";
+    const BLOB_ID: &str = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0";
+
+    fn write_loose(git_dir: &Path, object_id: &str, raw: &[u8]) {
+        let dir = git_dir.join("objects").join(&object_id[..2]);
+        fs::create_dir_all(&dir).unwrap();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        fs::write(dir.join(&object_id[2..]), encoder.finish().unwrap()).unwrap();
+    }
+
+    /// A bare repo with one blob, a loose branch, a packed tag and a
+    /// symbolic remote HEAD
+    fn bare_repo(dir: &Path) {
+        write_loose(dir, BLOB_ID, BLOB);
+        fs::create_dir_all(dir.join("refs/heads")).unwrap();
+        fs::create_dir_all(dir.join("refs/remotes/origin")).unwrap();
+        fs::write(dir.join("refs/heads/main"), format!("{}\n", BLOB_ID)).unwrap();
+        fs::write(dir.join("refs/remotes/origin/HEAD"), "ref: refs/remotes/origin/main\n").unwrap();
+        fs::write(
+            dir.join("packed-refs"),
+            format!("# pack-refs with: peeled fully-peeled sorted\n{id} refs/tags/v1\n^{id}\n", id = BLOB_ID),
+        )
+        .unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+    }
+
+    #[test]
+    fn test_import_bare_repo() {
+        let source = tempfile::tempdir().unwrap();
+        bare_repo(source.path());
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+
+        let report = import_repo(&storage, source.path(), REPO).unwrap();
+        assert_eq!(report.objects, 1);
+        assert_eq!(report.refs, 2);
+        assert_eq!(report.head.as_deref(), Some("refs/heads/main"));
+
+        assert_eq!(storage.read_object(REPO, BLOB_ID).unwrap(), BLOB);
+        assert_eq!(
+            storage.list_refs(REPO).unwrap(),
+            vec![
+                ("refs/heads/main".to_string(), BLOB_ID.to_string()),
+                ("refs/tags/v1".to_string(), BLOB_ID.to_string()),
+            ]
+        );
+        assert_eq!(storage.read_head(REPO).unwrap().commit_id.as_deref(), Some(BLOB_ID));
+    }
+
+    #[test]
+    fn test_export_round_trips() {
+        let source = tempfile::tempdir().unwrap();
+        bare_repo(source.path());
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        import_repo(&storage, source.path(), REPO).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let exported = out.path().join("repo.git");
+        let report = export_repo(&storage, REPO, &exported).unwrap();
+        assert_eq!(report.objects, 1);
+        assert_eq!(report.refs, 2);
+        assert_eq!(fs::read_to_string(exported.join("HEAD")).unwrap(), "ref: refs/heads/main\n");
+        assert!(fs::read_to_string(exported.join("config")).unwrap().contains("bare = true"));
+
+        // Importing the export gives back the same repo
+        let copy = "f".repeat(64);
+        import_repo(&storage, &exported, &copy).unwrap();
+        assert_eq!(storage.read_object(&copy, BLOB_ID).unwrap(), BLOB);
+        assert_eq!(storage.list_refs(&copy).unwrap(), storage.list_refs(REPO).unwrap());
+
+        // Never writes over existing data
+        assert!(export_repo(&storage, REPO, &exported).is_err());
+    }
+
+    #[test]
+    fn test_import_rejects_bad_objects_and_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+
+        // Content that doesn't hash to its file name
+        let source = tempfile::tempdir().unwrap();
+        bare_repo(source.path());
+        write_loose(source.path(), BLOB_ID, b"blob 5\0HELLO");
+        assert!(import_repo(&storage, source.path(), REPO).is_err());
+        assert!(!storage.repo_path(REPO).exists());
+
+        let source = tempfile::tempdir().unwrap();
+        bare_repo(source.path());
+        fs::create_dir_all(source.path().join("objects/pack")).unwrap();
+        fs::write(source.path().join("objects/pack/pack-1.pack"), b"PACK").unwrap();
+        let err = import_repo(&storage, source.path(), REPO).unwrap_err();
+        assert!(err.to_string().contains("git unpack-objects"));
+    }
+}
@@ -1,38 +1,62 @@
 // hyrule-node/src/health.rs
-use crate::NodeState;
+use crate::alerts::AlertKind;
+use crate::jitter::JitteredInterval;
+use crate::config::{NodeConfig, TorMode};
+use crate::proxy::{ProxyConfig, TorState};
+use crate::verify_index::VerifyIndex;
+use crate::{onion, registration, replication, NodeState};
 use serde::Serialize;
 use std::time::Duration;
 use tokio::time;
 
+/// How often hosted objects are re-verified
+const VERIFY_INTERVAL_SECS: u64 = 3600;
+
+/// Wait before retrying Tor bootstrap or the test connection, doubled
+/// after each failure up to `TOR_RETRY_MAX`
+const TOR_RETRY_MIN: Duration = Duration::from_secs(5);
+const TOR_RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// How often the node checks that it can reach itself the way peers do
+const SELF_CHECK_INTERVAL: Duration = Duration::from_secs(900);
+
+/// Wait before the first self check, giving a new onion service time to
+/// publish its descriptor
+const SELF_CHECK_DELAY: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Serialize)]
 struct HeartbeatRequest {
     node_id: String,
+    network_namespace: String,
     storage_used: i64,
     hosted_repos: Vec<String>,
 }
 
 /// Send periodic heartbeats to the Hyrule server
 pub async fn heartbeat_loop(state: NodeState) {
-    let mut interval = time::interval(Duration::from_secs(60)); // Every minute
-    let mut uptime = 0u64;
+    let period = state.config.heartbeat_interval_secs;
+    let mut interval = JitteredInterval::new(
+        &state.config.node_id,
+        "heartbeat",
+        Duration::from_secs(period),
+    );
+    let mut since_verify = 0u64;
     
     loop {
         interval.tick().await;
-        uptime += 60;
-        
-        // Update uptime in stats
-        {
-            let mut stats = state.stats.write().await;
-            stats.uptime_seconds = uptime;
-        }
+        crate::tasks::record_run();
+        since_verify += period;
         
         // Send heartbeat
-        if let Err(e) = send_heartbeat(&state).await {
+        let result = send_heartbeat(&state).await;
+        if let Err(e) = &result {
             tracing::warn!("Heartbeat failed: {}", e);
         }
+        state.stats.write().await.last_heartbeat_ok = Some(result.is_ok());
         
         // Verify storage integrity periodically (every hour)
-        if uptime % 3600 == 0 {
+        if since_verify >= VERIFY_INTERVAL_SECS {
+            since_verify = 0;
             tokio::spawn({
                 let state = state.clone();
                 async move {
@@ -45,15 +69,66 @@ pub async fn heartbeat_loop(state: NodeState) {
     }
 }
 
+/// Bring Tor up without holding the node back: bootstrap until it works,
+/// publish the onion service, register with the coordinator, then retry
+/// the test connection until one gets through
+pub async fn tor_bootstrap(state: NodeState) {
+    let mut backoff = TOR_RETRY_MIN;
+    while let Err(e) = state.proxy.init_tor_client().await {
+        tracing::warn!("Tor bootstrap failed: {}, retrying in {:?}", e, backoff);
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(TOR_RETRY_MAX);
+    }
+    crate::tasks::record_run();
+    
+    if state.config.enable_onion_service && state.config.tor_mode == TorMode::Socks {
+        // The daemon owns the onion service; it isn't ours to launch
+        tracing::warn!("⚠️  enable_onion_service has no effect with tor_mode = \"socks\"");
+        tracing::warn!("   Configure a HiddenService in torrc and set advertised_address to it");
+    } else if state.config.enable_onion_service && state.onion.get().is_none() {
+        let launched = state.config.bind_socket_addr()
+            .map_err(anyhow::Error::from)
+            .and_then(|addr| onion::launch(&state.proxy, addr));
+        match launched {
+            Ok(service) => {
+                tracing::info!("🧅 Onion service: {}", service.address);
+                let _ = state.onion.set(service);
+            }
+            Err(e) => {
+                tracing::warn!("⚠️  Failed to launch onion service: {}", e);
+                tracing::warn!("   Peers will not be able to reach this node over Tor");
+            }
+        }
+    }
+    
+    tracing::info!("🔗 Registering with Hyrule server...");
+    let onion_address = state.onion.get().map(|s| s.address.as_str());
+    match registration::register_node(&state.config, &state.proxy, onion_address, state.capacity.bytes()).await {
+        Ok(_) => tracing::info!("✓ Successfully registered with network"),
+        Err(e) => tracing::warn!("⚠️  Registration failed: {}. Will retry...", e),
+    }
+    
+    let mut backoff = TOR_RETRY_MIN;
+    while let Err(e) = state.proxy.validate_tor_connection().await {
+        tracing::warn!("Tor test connection failed: {}, retrying in {:?}", e, backoff);
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(TOR_RETRY_MAX);
+    }
+    tracing::info!("✓ Tor connection validated successfully");
+}
+
 async fn send_heartbeat(state: &NodeState) -> anyhow::Result<()> {
     // Use the Tor client from state's proxy config
     let client = state.proxy.build_client()?;
 
-    let storage_used = state.storage.get_storage_usage()? as i64;
+    let storage_used = state.storage.get_storage_usage_async().await? as i64;
+    // Don't report repos that are no longer on disk
+    state.drop_missing_repos().await;
     let hosted_repos = state.hosted_repos.read().await.clone();
 
     let request = HeartbeatRequest {
         node_id: state.config.node_id.clone(),
+        network_namespace: state.config.network_namespace.clone(),
         storage_used,
         hosted_repos: hosted_repos.clone(),
     };
@@ -92,21 +167,46 @@ async fn verify_all_repos(state: &NodeState) -> anyhow::Result<()> {
         let objects = state.storage.list_objects(&repo_hash)?;
         total_objects += objects.len();
         
+        // Only objects changed since the last scan are actually re-read
+        let mut index = VerifyIndex::load(&state.storage, &repo_hash);
+        let mut bad = Vec::new();
+        
         for object_id in objects {
-            match state.storage.verify_object(&repo_hash, &object_id) {
+            match index.verify(&state.storage, &repo_hash, &object_id, false) {
                 Ok(true) => {
                     // Object is valid
                 }
                 Ok(false) | Err(_) => {
-                    tracing::warn!("Corrupted object: {}:{}", &repo_hash[..8], &object_id[..8]);
+                    tracing::warn!(repo = %repo_hash, object = %object_id, "Corrupted object");
                     corrupted += 1;
+                    bad.push(object_id);
                 }
             }
         }
+        
+        if let Err(e) = index.save() {
+            tracing::warn!(repo = %repo_hash, error = %e, "Failed to save verification index");
+        }
+        
+        if state.config.auto_repair && !bad.is_empty() {
+            let client = state.proxy.build_client()?;
+            match replication::repair_objects(&state.storage, &state.config.hyrule_server, &state.config.network_namespace, &repo_hash, &bad, &client, &state.peer_scores).await {
+                Ok(repaired) => {
+                    for object_id in &repaired {
+                        state.object_cache.invalidate(&repo_hash, object_id);
+                    }
+                    tracing::info!(repo = %repo_hash, repaired = repaired.len(), corrupted = bad.len(), "Repaired objects");
+                }
+                Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Repair failed"),
+            }
+        }
     }
     
     if corrupted > 0 {
         tracing::warn!(" Found {} corrupted objects out of {}", corrupted, total_objects);
+        
+        let detail = format!("{} corrupted objects out of {}", corrupted, total_objects);
+        state.alerts.notify(&state.proxy, AlertKind::Corruption, &detail).await;
     } else {
         tracing::info!(" All {} objects verified successfully", total_objects);
     }
@@ -114,20 +214,87 @@ async fn verify_all_repos(state: &NodeState) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Periodically fetch our own `/health` through the advertised address,
+/// over Tor when it's enabled. A node peers can't reach is a black hole
+/// for replication, so failures are logged loudly and fail `/ready`.
+pub async fn self_check_loop(state: NodeState) {
+    time::sleep(SELF_CHECK_DELAY).await;
+    let mut interval = time::interval(SELF_CHECK_INTERVAL);
+    
+    loop {
+        interval.tick().await;
+        crate::tasks::record_run();
+        
+        // Until Tor is up there's no way to dial the onion address
+        if state.config.enable_proxy && state.proxy.tor_state() == TorState::Connecting {
+            continue;
+        }
+        
+        let onion_address = state.onion.get().map(|s| s.address.as_str());
+        let result = check_self_reachable(&state.config, &state.proxy, onion_address).await;
+        match &result {
+            Ok(url) => tracing::debug!("Reached ourselves at {}", url),
+            Err(e) => {
+                tracing::warn!("⚠️  This node can't reach itself at its advertised address: {}", e);
+                tracing::warn!("   Peers can't fetch from it; check port forwarding, advertised_address or the onion service");
+            }
+        }
+        state.stats.write().await.self_reachable = Some(result.is_ok());
+    }
+}
+
+async fn check_self_reachable(
+    config: &NodeConfig,
+    proxy: &ProxyConfig,
+    onion_address: Option<&str>,
+) -> anyhow::Result<String> {
+    let url = format!("{}/health", registration::advertised_url(config, onion_address)?);
+    let timeout = Duration::from_secs(config.peer_request_timeout_secs);
+    
+    let status = if proxy.enabled {
+        let client = proxy.build_client()?;
+        client.get(&url).timeout(timeout).send().await?.status().as_u16()
+    } else {
+        let client = reqwest::Client::new();
+        client.get(&url)
+            .headers(proxy.identity.headers(&url))
+            .timeout(timeout)
+            .send().await?.status().as_u16()
+    };
+    
+    if !(200..300).contains(&status) {
+        anyhow::bail!("{} responded with {}", url, status);
+    }
+    Ok(url)
+}
+
 /// Monitor storage capacity and alert if nearly full
 pub async fn monitor_storage(state: NodeState) {
     let mut interval = time::interval(Duration::from_secs(300)); // Every 5 minutes
     
     loop {
         interval.tick().await;
+        crate::tasks::record_run();
         
-        match state.storage.get_storage_usage() {
+        match state.storage.get_storage_usage_async().await {
             Ok(used) => {
-                let capacity = state.config.storage_capacity;
+                state.load_shed.set_storage_used(used);
+                
+                // The disk may have filled or freed up since the last check
+                let capacity = match state.capacity.refresh(state.storage.base_path(), used) {
+                    Ok(capacity) => capacity,
+                    Err(e) => {
+                        tracing::warn!("Failed to detect storage capacity: {}", e);
+                        state.capacity.bytes()
+                    }
+                };
                 let usage_percent = (used as f64 / capacity as f64) * 100.0;
                 
                 if usage_percent > 90.0 {
                     tracing::error!(" Storage nearly full: {:.1}%", usage_percent);
+                    
+                    let detail = format!("Storage at {:.1}% of capacity", usage_percent);
+                    state.alerts.notify(&state.proxy, AlertKind::StorageFull, &detail).await;
                 } else if usage_percent > 80.0 {
                     tracing::warn!(" Storage usage high: {:.1}%", usage_percent);
                 }
@@ -144,3 +311,50 @@ pub async fn monitor_storage(state: NodeState) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[tokio::test]
+    async fn test_self_check_without_tor() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = axum::Router::new().route("/health", axum::routing::get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        let mut config = NodeConfig::generate();
+        config.enable_proxy = false;
+        config.advertised_address = Some("127.0.0.1".to_string());
+        config.port = port;
+        let proxy = ProxyConfig::from_config(&config);
+        
+        let url = check_self_reachable(&config, &proxy, None).await.unwrap();
+        assert_eq!(url, format!("http://127.0.0.1:{}/health", port));
+        
+        // Nothing listens on the advertised port any more
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        config.port = closed;
+        assert!(check_self_reachable(&config, &proxy, None).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_self_check_through_socks() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/health", axum::routing::get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let (proxy_addr, mut requested) = crate::socks::tests::fake_proxy(target).await;
+        
+        let mut config = NodeConfig::generate();
+        config.tor_mode = TorMode::Socks;
+        config.proxy_addr = proxy_addr;
+        config.advertised_address = Some("example.onion".to_string());
+        let proxy = ProxyConfig::from_config(&config);
+        proxy.init_tor_client().await.unwrap();
+        assert_eq!(proxy.tor_state(), TorState::Bootstrapped);
+        
+        check_self_reachable(&config, &proxy, None).await.unwrap();
+        assert_eq!(requested.recv().await.unwrap(), format!("example.onion:{}", config.port));
+    }
+}
@@ -4,49 +4,349 @@ use hyper::body::HttpBody;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use anyhow::{Result, Context};
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::error::HyruleError;
+use crate::socks::SocksConnector;
 
-// Import the specific client type from proxy.rs or define it generically
-// We'll use a generic wrapper to handle both standard and Tor clients if needed,
-// but for now, let's focus on the Hyper client structure.
+/// Node protocol version, sent on every outbound request
+pub const PROTOCOL_HEADER: &str = "x-hyrule-protocol";
+
+/// Our node id, sent only on control traffic to the coordinator
+pub const NODE_ID_HEADER: &str = "x-hyrule-node-id";
+
+/// Times a request is retried on a fresh circuit after its circuit or
+/// connection drops
+const CIRCUIT_RETRIES: u32 = 2;
+
+/// Requests in a row that must fail, retries included, before Tor is
+/// reported as failing
+const FAILING_AFTER: u32 = 3;
+
+/// How this node identifies itself on outbound requests
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    pub user_agent: String,
+    /// Coordinator base URL and our node id. Requests under that URL are
+    /// control traffic and carry the id; peers and webhooks don't get it.
+    control: Option<(String, String)>,
+    /// Sent only on requests marked with [`RequestBuilder::peer_auth`]
+    peer_token: Option<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_config(config: &crate::config::NodeConfig) -> Self {
+        Self {
+            user_agent: config
+                .user_agent
+                .clone()
+                .unwrap_or_else(|| format!("hyrule-node/{}", env!("CARGO_PKG_VERSION"))),
+            control: Some((config.hyrule_server.clone(), config.node_id.clone())),
+            peer_token: config.peer_token.clone(),
+        }
+    }
+
+    /// Identity headers for a request to `url`
+    pub fn headers(&self, url: &str) -> hyper::HeaderMap {
+        let mut headers = hyper::HeaderMap::new();
+        if let Ok(agent) = hyper::header::HeaderValue::from_str(&self.user_agent) {
+            headers.insert(hyper::header::USER_AGENT, agent);
+        }
+        headers.insert(PROTOCOL_HEADER, crate::capabilities::PROTOCOL_VERSION.into());
+
+        if let Some((server, node_id)) = &self.control {
+            let under_server = url
+                .strip_prefix(server.trim_end_matches('/'))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+            if under_server {
+                if let Ok(value) = hyper::header::HeaderValue::from_str(node_id) {
+                    headers.insert(NODE_ID_HEADER, value);
+                }
+            }
+        }
+        headers
+    }
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("hyrule-node/{}", env!("CARGO_PKG_VERSION")),
+            control: None,
+            peer_token: None,
+        }
+    }
+}
+
+type ArtiRuntime = tor_rtcompat::tokio::TokioNativeTlsRuntime;
+
+type ArtiClient = Client<arti_hyper::ArtiHttpConnector<ArtiRuntime, tls_api_native_tls::TlsConnector>, Body>;
+
+type SocksClient = Client<hyper_tls::HttpsConnector<SocksConnector>, Body>;
+
+/// How requests reach Tor: the embedded Arti client, or an external
+/// daemon's SOCKS5 port
+enum Transport {
+    Arti { tor: arti_client::TorClient<ArtiRuntime>, client: ArtiClient },
+    Socks { proxy: String, client: SocksClient },
+}
+
+impl Transport {
+    fn arti(tor: arti_client::TorClient<ArtiRuntime>) -> Result<Self> {
+        use tls_api::{TlsConnector as _, TlsConnectorBuilder as _};
+
+        let tls = tls_api_native_tls::TlsConnector::builder()?.build()?;
+        let client = Client::builder().build(arti_hyper::ArtiHttpConnector::new(tor.clone(), tls));
+        Ok(Transport::Arti { tor, client })
+    }
+
+    fn socks(proxy: &str) -> Result<Self> {
+        let tls = hyper_tls::native_tls::TlsConnector::new().context("Failed to set up TLS")?;
+        let connector = hyper_tls::HttpsConnector::from((SocksConnector::new(proxy), tls.into()));
+        Ok(Transport::Socks { proxy: proxy.to_string(), client: Client::builder().build(connector) })
+    }
+
+    /// The same route with no pooled connections, on new circuits for Arti.
+    /// An external daemon replaces its own broken circuits.
+    fn fresh(&self) -> Result<Self> {
+        match self {
+            Transport::Arti { tor, .. } => Self::arti(tor.isolated_client()),
+            Transport::Socks { proxy, .. } => Self::socks(proxy),
+        }
+    }
+
+    fn request(&self, req: Request<Body>) -> hyper::client::ResponseFuture {
+        match self {
+            Transport::Arti { client, .. } => client.request(req),
+            Transport::Socks { client, .. } => client.request(req),
+        }
+    }
+}
+
+/// Requests in a row that failed because of Tor itself (see
+/// [`is_tor_failure`]), retries included. Shared by every client built from
+/// one `ProxyConfig`, which reports Tor as failing while this is high.
+#[derive(Debug, Default)]
+pub struct CircuitHealth {
+    failures: AtomicU32,
+}
+
+impl CircuitHealth {
+    pub fn is_failing(&self) -> bool {
+        self.failures.load(Ordering::Relaxed) >= FAILING_AFTER
+    }
+
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
 #[derive(Clone)]
 pub struct HyruleClient {
-    // We store the inner Hyper client. We use a dynamic dispatch approach or specific type
-    // depending on how strict we want to be. For simplicity in your current setup:
-    inner: Client<arti_hyper::ArtiHttpConnector<tor_rtcompat::tokio::TokioNativeTlsRuntime, tls_api_native_tls::TlsConnector>, Body>,
+    /// Shared by clones, so one reconnect serves all of them
+    inner: Arc<Mutex<Arc<Transport>>>,
+    /// Applied to requests that don't set their own timeout
+    timeout: Option<Duration>,
+    identity: ClientIdentity,
+    circuits: Arc<CircuitHealth>,
 }
 
 impl HyruleClient {
-    pub fn new(inner: Client<arti_hyper::ArtiHttpConnector<tor_rtcompat::tokio::TokioNativeTlsRuntime, tls_api_native_tls::TlsConnector>, Body>) -> Self {
-        Self { inner }
+    fn from_transport(transport: Transport) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Arc::new(transport))),
+            timeout: None,
+            identity: ClientIdentity::default(),
+            circuits: Arc::default(),
+        }
+    }
+
+    /// Client that sends everything through the embedded Arti client
+    pub fn arti(tor: arti_client::TorClient<ArtiRuntime>) -> Result<Self> {
+        Ok(Self::from_transport(Transport::arti(tor)?))
+    }
+
+    /// Client that sends everything through the SOCKS5 proxy at `proxy`
+    pub fn socks(proxy: &str) -> Result<Self> {
+        Ok(Self::from_transport(Transport::socks(proxy)?))
+    }
+
+    /// Where to count requests lost to dropped circuits
+    pub fn with_circuit_health(mut self, circuits: Arc<CircuitHealth>) -> Self {
+        self.circuits = circuits;
+        self
+    }
+
+    /// User agent and protocol headers to send
+    pub fn with_identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Default limit for connecting and getting response headers, and
+    /// separately for each wait on the next chunk of the response body
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
     }
 
     pub fn get(&self, url: &str) -> RequestBuilder {
-        RequestBuilder::new(self.inner.clone(), Method::GET, url.to_string())
+        self.request(Method::GET, url)
     }
 
     pub fn post(&self, url: &str) -> RequestBuilder {
-        RequestBuilder::new(self.inner.clone(), Method::POST, url.to_string())
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: &str) -> RequestBuilder {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.request(Method::DELETE, url)
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let mut builder = RequestBuilder::new(self.clone(), method, url.to_string());
+        builder.timeout = self.timeout;
+        builder.headers = self.identity.headers(url);
+        builder
+    }
+
+    fn transport(&self) -> Arc<Transport> {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Replace `failed` with a fresh transport, unless a request that
+    /// failed alongside it already has
+    fn reconnect(&self, failed: &Arc<Transport>) -> Result<()> {
+        let mut current = self.inner.lock().unwrap();
+        if Arc::ptr_eq(&current, failed) {
+            *current = Arc::new(failed.fresh()?);
+        }
+        Ok(())
+    }
+}
+
+/// A request or body read took longer than its timeout
+fn timed_out() -> HyruleError {
+    HyruleError::Timeout("Request timed out".to_string())
+}
+
+/// Whether an error chain contains a [`HyruleError::Timeout`]
+pub fn is_timeout(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| matches!(cause.downcast_ref::<HyruleError>(), Some(HyruleError::Timeout(_))))
+}
+
+/// Mark a request that failed in transit as a [`HyruleError::Network`],
+/// keeping the original error in the chain below it
+fn network_error(e: anyhow::Error, method: &Method, url: &str) -> anyhow::Error {
+    if is_timeout(&e) {
+        return e;
+    }
+    let message = format!("{} {} failed: {}", method, url, e);
+    e.context(HyruleError::Network(message))
+}
+
+/// Whether a request failed because its circuit or pooled connection went
+/// away, rather than being answered or refused by the far end. A fresh
+/// circuit may get it through.
+fn is_circuit_failure(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<hyper::Error>() {
+            return e.is_closed() || e.is_incomplete_message() || e.is_canceled();
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind::*;
+            if matches!(e.kind(), ConnectionReset | ConnectionAborted | BrokenPipe | UnexpectedEof) {
+                return true;
+            }
+            // Arti streams report circuit errors wrapped in io::Error
+            return e.get_ref().is_some_and(|inner| is_tor_circuit_error(inner));
+        }
+        is_tor_circuit_error(cause)
+    })
+}
+
+fn is_tor_circuit_error(cause: &(dyn std::error::Error + 'static)) -> bool {
+    use arti_client::{ErrorKind, HasKind};
+
+    cause.downcast_ref::<arti_client::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            ErrorKind::TorAccessFailed
+                | ErrorKind::TorNetworkTimeout
+                | ErrorKind::CircuitCollapse
+                | ErrorKind::TorDirectoryUnavailable
+                | ErrorKind::LocalNetworkError
+        )
+    })
+}
+
+/// Whether a request failed because of Tor rather than the far end: an
+/// Arti circuit error, or the SOCKS proxy being unreachable or failing.
+/// A peer hanging up or being offline says nothing about Tor.
+fn is_tor_failure(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<tokio_socks::Error>() {
+            return matches!(
+                e,
+                tokio_socks::Error::Io(_)
+                    | tokio_socks::Error::ProxyServerUnreachable
+                    | tokio_socks::Error::GeneralSocksServerFailure
+                    | tokio_socks::Error::NetworkUnreachable
+            );
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return e.get_ref().is_some_and(|inner| is_tor_circuit_error(inner));
+        }
+        is_tor_circuit_error(cause)
+    })
+}
+
+/// Whether a request failed before any of it was sent
+fn is_connect_failure(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.downcast_ref::<hyper::Error>().is_some_and(|e| e.is_connect()))
+}
+
+/// Await `fut`, giving up with [`HyruleError::Timeout`] after `limit`
+async fn within<T, E>(limit: Option<Duration>, fut: impl Future<Output = Result<T, E>>) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match limit {
+        Some(limit) => match tokio::time::timeout(limit, fut).await {
+            Ok(res) => Ok(res?),
+            Err(_) => Err(timed_out().into()),
+        },
+        None => Ok(fut.await?),
     }
 }
 
 pub struct RequestBuilder {
-    client: Client<arti_hyper::ArtiHttpConnector<tor_rtcompat::tokio::TokioNativeTlsRuntime, tls_api_native_tls::TlsConnector>, Body>,
+    client: HyruleClient,
     method: Method,
     url: String,
-    body: Body,
+    /// Kept whole so the request can be resent on a fresh circuit
+    body: bytes::Bytes,
     headers: hyper::HeaderMap,
     timeout: Option<std::time::Duration>,
 }
 
 impl RequestBuilder {
-    pub fn new(client: Client<arti_hyper::ArtiHttpConnector<tor_rtcompat::tokio::TokioNativeTlsRuntime, tls_api_native_tls::TlsConnector>, Body>, method: Method, url: String) -> Self {
+    fn new(client: HyruleClient, method: Method, url: String) -> Self {
         Self {
             client,
             method,
             url,
-            body: Body::empty(),
+            body: bytes::Bytes::new(),
             headers: hyper::HeaderMap::new(),
             timeout: None,
         }
@@ -54,45 +354,85 @@ impl RequestBuilder {
 
     pub fn json<T: Serialize>(mut self, json: &T) -> Self {
         let bytes = serde_json::to_vec(json).expect("Failed to serialize JSON");
-        self.body = Body::from(bytes);
+        self.body = bytes.into();
         self.headers.insert(hyper::header::CONTENT_TYPE, "application/json".parse().unwrap());
         self
     }
+
+    /// Send raw bytes, e.g. an object for `PUT /repos/{hash}/objects/{id}`
+    pub fn body(mut self, body: impl Into<bytes::Bytes>) -> Self {
+        self.body = body.into();
+        self.headers.insert(hyper::header::CONTENT_TYPE, "application/octet-stream".parse().unwrap());
+        self
+    }
     
     pub fn timeout(mut self, duration: std::time::Duration) -> Self {
         self.timeout = Some(duration);
         self
     }
 
+    /// Present the configured `peer_token`, if any. For requests to other
+    /// nodes only; the coordinator and webhooks never see it.
+    pub fn peer_auth(mut self) -> Self {
+        let token = self.client.identity.peer_token.as_deref();
+        if let Some(value) = token.and_then(|t| hyper::header::HeaderValue::from_str(&format!("Bearer {}", t)).ok()) {
+            self.headers.insert(hyper::header::AUTHORIZATION, value);
+        }
+        self
+    }
+
+    /// Send the request. If its circuit or connection drops, it is resent
+    /// on a fresh one up to [`CIRCUIT_RETRIES`] times: always when the
+    /// failure came before anything was sent, otherwise only for
+    /// idempotent methods.
     pub async fn send(self) -> Result<HyruleResponse> {
         let uri = Uri::from_str(&self.url).context("Invalid URL")?;
         
-        let mut builder = Request::builder()
-            .method(self.method)
-            .uri(uri);
-            
-        for (key, value) in self.headers.iter() {
-            builder = builder.header(key, value);
-        }
+        let mut retries = 0;
+        loop {
+            let mut builder = Request::builder()
+                .method(self.method.clone())
+                .uri(uri.clone());
+                
+            for (key, value) in self.headers.iter() {
+                builder = builder.header(key, value);
+            }
 
-        let req = builder.body(self.body).context("Failed to build request")?;
+            let req = builder.body(Body::from(self.body.clone())).context("Failed to build request")?;
 
-        // Handle timeout if set
-        let resp = if let Some(duration) = self.timeout {
-             match tokio::time::timeout(duration, self.client.request(req)).await {
-                 Ok(res) => res?,
-                 Err(_) => anyhow::bail!("Request timed out"),
-             }
-        } else {
-            self.client.request(req).await?
-        };
+            let transport = self.client.transport();
+            let err = match within(self.timeout, transport.request(req)).await {
+                Ok(resp) => {
+                    self.client.circuits.record_success();
+                    // Each body chunk gets a fresh timeout of its own
+                    return Ok(HyruleResponse { inner: resp, timeout: self.timeout });
+                }
+                Err(e) if !is_circuit_failure(&e) => {
+                    if is_tor_failure(&e) {
+                        self.client.circuits.record_failure();
+                    }
+                    return Err(network_error(e, &self.method, &self.url));
+                }
+                Err(e) => e,
+            };
 
-        Ok(HyruleResponse { inner: resp })
+            let resendable = self.method.is_idempotent() || is_connect_failure(&err);
+            if retries == CIRCUIT_RETRIES || !resendable {
+                if is_tor_failure(&err) {
+                    self.client.circuits.record_failure();
+                }
+                return Err(network_error(err, &self.method, &self.url));
+            }
+            retries += 1;
+            tracing::debug!(url = %self.url, error = %err, retries, "Connection dropped, retrying on a fresh circuit");
+            self.client.reconnect(&transport)?;
+        }
     }
 }
 
 pub struct HyruleResponse {
     inner: hyper::Response<Body>,
+    timeout: Option<Duration>,
 }
 
 impl HyruleResponse {
@@ -101,14 +441,227 @@ impl HyruleResponse {
     }
 
     pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
-        let bytes = hyper::body::to_bytes(self.inner.into_body()).await?;
+        let bytes = self.bytes().await?;
         let obj = serde_json::from_slice(&bytes)?;
         Ok(obj)
     }
     
+    /// Read the whole body. The timeout is an idle limit on each chunk,
+    /// so a large body that keeps arriving is never cut off, while one
+    /// that stalls still fails.
+    pub async fn bytes(self) -> Result<bytes::Bytes> {
+        let mut body = self.inner.into_body();
+        let mut data = bytes::BytesMut::new();
+        loop {
+            let next = match self.timeout {
+                Some(limit) => tokio::time::timeout(limit, body.data()).await.map_err(|_| timed_out())?,
+                None => body.data().await,
+            };
+            match next {
+                Some(chunk) => {
+                    let chunk = chunk.map_err(|e| HyruleError::Network(format!("Reading response body failed: {}", e)))?;
+                    data.extend_from_slice(&chunk);
+                }
+                None => return Ok(data.freeze()),
+            }
+        }
+    }
+    
     // Helper to get text for errors/debugging
     pub async fn text(self) -> Result<String> {
-        let bytes = hyper::body::to_bytes(self.inner.into_body()).await?;
+        let bytes = self.bytes().await?;
         Ok(String::from_utf8_lossy(&bytes).to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    const LIMIT: Duration = Duration::from_millis(200);
+
+    /// A server that accepts connections, optionally writes `preamble`,
+    /// then goes silent while holding the socket open
+    async fn stalling_server(preamble: &'static [u8]) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                socket.write_all(preamble).await.unwrap();
+                held.push(socket);
+            }
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fires_on_stalled_response() {
+        let url = stalling_server(b"").await;
+        let client = Client::new();
+
+        let err = within(Some(LIMIT), client.get(url.parse().unwrap())).await.unwrap_err();
+        assert!(is_timeout(&err));
+    }
+
+    #[tokio::test]
+    async fn test_transport_errors_are_network_errors() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let target = flaky_server(vec![None; CIRCUIT_RETRIES as usize + 1]).await;
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+
+        for proxy in [closed.to_string(), proxy] {
+            let client = HyruleClient::socks(&proxy).unwrap();
+            let Err(err) = client.get("http://peer.onion/health").send().await else {
+                panic!("request through {} succeeded", proxy);
+            };
+            assert!(matches!(err.downcast_ref::<HyruleError>(), Some(HyruleError::Network(_))), "{:#}", err);
+            assert!(!is_timeout(&err));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fires_on_stalled_body() {
+        let url = stalling_server(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial").await;
+        let client = Client::new();
+
+        let resp = within(Some(LIMIT), client.get(url.parse().unwrap())).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let err = within(Some(LIMIT), hyper::body::to_bytes(resp.into_body())).await.unwrap_err();
+        assert!(is_timeout(&err));
+    }
+
+    #[tokio::test]
+    async fn test_slow_steady_body_is_not_cut_off() {
+        use tokio::io::AsyncReadExt;
+
+        // Five chunks, LIMIT / 2 apart: longer than LIMIT in total
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut [0u8; 4096]).await;
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n").await.unwrap();
+            for _ in 0..5 {
+                tokio::time::sleep(LIMIT / 2).await;
+                socket.write_all(b"x").await.unwrap();
+            }
+        });
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap().with_timeout(LIMIT);
+
+        let resp = client.get("http://peer.onion/object").send().await.unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "xxxxx");
+
+        // A body that stalls still times out
+        let url = stalling_server(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial").await;
+        let target = url.trim_start_matches("http://").trim_end_matches('/').parse().unwrap();
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap().with_timeout(LIMIT);
+        let resp = client.get("http://peer.onion/object").send().await.unwrap();
+        assert!(is_timeout(&resp.bytes().await.unwrap_err()));
+    }
+
+    const OK: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+
+    /// Answers one request per connection with the next of `replies`,
+    /// hanging up without a reply (like a dropped circuit) on `None` and
+    /// once they run out
+    async fn flaky_server(replies: Vec<Option<&'static [u8]>>) -> std::net::SocketAddr {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut replies = replies.into_iter();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                if let Some(Some(reply)) = replies.next() {
+                    socket.write_all(reply).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_fresh_connection() {
+        let target = flaky_server(vec![None, Some(OK)]).await;
+        let (proxy, mut connects) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap();
+
+        let resp = client.get("http://peer.onion/health").send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "ok");
+        assert_eq!(connects.recv().await.unwrap(), "peer.onion:80");
+        assert_eq!(connects.recv().await.unwrap(), "peer.onion:80");
+
+        // A POST may already have been acted on, so it isn't resent
+        let target = flaky_server(vec![None, Some(OK)]).await;
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap();
+        assert!(client.post("http://peer.onion/repos").send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_health() {
+        let circuits = Arc::new(CircuitHealth::default());
+
+        // Peers hanging up are their problem, not Tor's
+        let target = flaky_server(vec![None; (CIRCUIT_RETRIES as usize + 1) * FAILING_AFTER as usize]).await;
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap().with_circuit_health(circuits.clone());
+        for _ in 0..FAILING_AFTER {
+            assert!(client.get("http://peer.onion/health").send().await.is_err());
+        }
+        assert!(!circuits.is_failing());
+
+        // A proxy that can't be reached is
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = HyruleClient::socks(&closed.to_string()).unwrap().with_circuit_health(circuits.clone());
+        for _ in 0..FAILING_AFTER {
+            assert!(!circuits.is_failing());
+            assert!(client.get("http://peer.onion/health").send().await.is_err());
+        }
+        assert!(circuits.is_failing());
+
+        // One request getting through is enough to clear it
+        let target = flaky_server(vec![Some(OK)]).await;
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = HyruleClient::socks(&proxy).unwrap().with_circuit_health(circuits.clone());
+        client.get("http://peer.onion/health").send().await.unwrap();
+        assert!(!circuits.is_failing());
+    }
+
+    #[test]
+    fn test_identity_headers() {
+        let mut config = crate::config::NodeConfig::generate();
+        config.hyrule_server = "http://coordinator.onion".to_string();
+        let identity = ClientIdentity::from_config(&config);
+
+        let control = identity.headers("http://coordinator.onion/api/nodes/heartbeat");
+        assert!(control[hyper::header::USER_AGENT].to_str().unwrap().starts_with("hyrule-node/"));
+        assert_eq!(control[PROTOCOL_HEADER], crate::capabilities::PROTOCOL_VERSION.to_string().as_str());
+        assert_eq!(control[NODE_ID_HEADER], config.node_id.as_str());
+
+        // Peers and lookalike hosts don't learn our node id
+        for url in ["http://peer.onion:8080/repos/abc/objects", "http://coordinator.onion.evil/api"] {
+            let headers = identity.headers(url);
+            assert!(headers.contains_key(PROTOCOL_HEADER));
+            assert!(!headers.contains_key(NODE_ID_HEADER));
+        }
+
+        config.user_agent = Some("fleet-a/1.0".to_string());
+        let headers = ClientIdentity::from_config(&config).headers("http://peer.onion/");
+        assert_eq!(headers[hyper::header::USER_AGENT], "fleet-a/1.0");
+
+        // The peer token goes only on requests marked as peer traffic
+        config.peer_token = Some("peers".to_string());
+        let client = HyruleClient::socks("127.0.0.1:9050").unwrap().with_identity(ClientIdentity::from_config(&config));
+        assert!(!client.get("http://coordinator.onion/api").headers.contains_key(hyper::header::AUTHORIZATION));
+        let peer = client.get("http://peer.onion/repos").peer_auth();
+        assert_eq!(peer.headers[hyper::header::AUTHORIZATION], "Bearer peers");
+    }
+}
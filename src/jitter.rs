@@ -0,0 +1,75 @@
+// hyrule-node/src/jitter.rs
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+use tokio::time;
+
+/// Maximum deviation of each tick from the nominal period
+const TICK_JITTER: f64 = 0.15;
+
+/// A periodic timer that spreads nodes out over time. The first tick waits
+/// a random fraction of the period, later ticks wait the period ±15%, so
+/// nodes started together don't hit the coordinator in lockstep.
+pub struct JitteredInterval {
+    rng: StdRng,
+    period: Duration,
+    started: bool,
+}
+
+impl JitteredInterval {
+    /// Jitter is seeded from the node id and task name, so a node keeps the
+    /// same offsets across restarts while its tasks stay spread apart
+    pub fn new(node_id: &str, task: &str, period: Duration) -> Self {
+        let seed = blake3::hash(format!("{}:{}", node_id, task).as_bytes());
+        Self {
+            rng: StdRng::from_seed(*seed.as_bytes()),
+            period,
+            started: false,
+        }
+    }
+
+    pub async fn tick(&mut self) {
+        let delay = self.next_delay();
+        time::sleep(delay).await;
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        if !self.started {
+            self.started = true;
+            return self.period.mul_f64(self.rng.gen_range(0.0..1.0));
+        }
+        self.period
+            .mul_f64(1.0 + self.rng.gen_range(-TICK_JITTER..=TICK_JITTER))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_within_bounds() {
+        let period = Duration::from_secs(60);
+        let mut interval = JitteredInterval::new("node-a", "heartbeat", period);
+
+        assert!(interval.next_delay() < period);
+        for _ in 0..100 {
+            let delay = interval.next_delay();
+            assert!(delay >= period.mul_f64(1.0 - TICK_JITTER));
+            assert!(delay <= period.mul_f64(1.0 + TICK_JITTER));
+        }
+    }
+
+    #[test]
+    fn test_seeded_per_node_and_task() {
+        let period = Duration::from_secs(300);
+        let delays = |node: &str, task: &str| {
+            let mut interval = JitteredInterval::new(node, task, period);
+            (0..5).map(|_| interval.next_delay()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(delays("node-a", "replication"), delays("node-a", "replication"));
+        assert_ne!(delays("node-a", "replication"), delays("node-b", "replication"));
+        assert_ne!(delays("node-a", "replication"), delays("node-a", "dht"));
+    }
+}
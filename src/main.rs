@@ -1,6 +1,7 @@
 // Node/src/main.rs - Upgraded version with Arti Tor support
 mod http_client;
 mod config;
+mod error;
 mod storage;
 mod api;
 mod registration;
@@ -9,66 +10,248 @@ mod health;
 mod crypto;
 mod dht;
 mod proxy;
+mod socks;
+mod alerts;
+mod request_log;
+mod onion;
+mod verify_index;
+mod pack;
+mod git_http;
+mod maintenance;
+mod jitter;
+mod doctor;
+mod auth;
+mod tiering;
+mod tasks;
+mod peer_score;
+mod gitdir;
+mod capacity;
+mod rate_limit;
+mod capabilities;
+mod object_cache;
+mod load_shed;
+mod bench;
+mod batch;
+mod announce;
+mod stats_store;
+mod cache_expiry;
 
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use rate_limit::ClientKey;
 use tower_http::trace::TraceLayer;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
 
 #[derive(Parser)]
 #[command(name = "hyrule-node")]
 #[command(version, about = "Distributed storage node for Hyrule network")]
 struct Cli {
+    /// Log output format; json emits one object per line for log collectors
+    #[arg(long, global = true, value_enum, env = "HYRULE_LOG_FORMAT", default_value = "text")]
+    log_format: LogFormat,
+    
+    /// Log verbosity: off, error, warn, info, debug or trace, or filter
+    /// directives such as `hyrule_node=debug,info`. Falls back to RUST_LOG,
+    /// then info.
+    #[arg(long, global = true, env = "HYRULE_LOG", value_parser = parse_log_filter)]
+    log_level: Option<String>,
+    
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Options of `start`, applied over the config file
+#[derive(Args)]
+struct StartArgs {
+    #[arg(short, long)]
+    port: Option<u16>,
+    
+    /// IP address to listen on, e.g. 127.0.0.1 or ::
+    #[arg(long)]
+    bind: Option<String>,
+    
+    #[arg(short, long)]
+    server: Option<String>,
+    
+    #[arg(long)]
+    storage_path: Option<String>,
+    
+    #[arg(long)]
+    capacity: Option<u64>,
+    
+    #[arg(long)]
+    anchor: bool,
+    
+    #[arg(long)]
+    enable_dht: bool,
+    
+    #[arg(long)]
+    disable_tor: bool,
+    
+    #[arg(long)]
+    proxy_addr: Option<String>,
+    
+    /// Start in read-only maintenance mode
+    #[arg(long)]
+    maintenance: bool,
+    
+    /// Seconds to wait for Tor before starting without it; bootstrap
+    /// carries on in the background either way
+    #[arg(long, value_name = "SECS")]
+    tor_start_timeout: Option<u64>,
+    
+    /// Start the lifetime request and traffic counters from zero
+    #[arg(long)]
+    reset_stats: bool,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    Start {
-        #[arg(short, long)]
-        port: Option<u16>,
-        
+    Start(StartArgs),
+    
+    Init {
         #[arg(short, long)]
-        server: Option<String>,
-        
+        output: Option<String>,
+    },
+    
+    Status,
+    
+    Repos {
+        /// Include per-repo request counts from the running node
         #[arg(long)]
-        storage_path: Option<String>,
+        stats: bool,
+    },
+    
+    Serve {
+        repo_hash: String,
+    },
+    
+    Unserve {
+        repo_hash: String,
+    },
+    
+    Verify {
+        repo_hash: Option<String>,
         
-        #[arg(long)]
-        capacity: Option<u64>,
+        /// Only verify a random sample of this percentage of objects
+        #[arg(long, value_parser = parse_percent)]
+        sample: Option<f64>,
         
+        /// Re-fetch corrupted objects from peers
         #[arg(long)]
-        anchor: bool,
+        fix: bool,
         
+        /// Ignore cached results and re-read every object
         #[arg(long)]
-        enable_dht: bool,
+        force: bool,
         
-        #[arg(long)]
-        disable_tor: bool,
+        /// Also check that no objects are missing, against the coordinator's
+        /// manifest and a peer's object list
+        #[arg(long, requires = "repo_hash")]
+        complete: bool,
+    },
+    
+    /// Remove objects no ref or HEAD leads to
+    Gc {
+        /// Only this repository instead of every hosted one
+        repo_hash: Option<String>,
         
+        /// List what would be removed without deleting anything
         #[arg(long)]
-        proxy_addr: Option<String>,
+        dry_run: bool,
+        
+        /// Keep unreferenced objects written in the last SECS seconds, which
+        /// may belong to a push that hasn't updated its ref yet
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        grace: u64,
     },
     
-    Init {
-        #[arg(short, long)]
-        output: Option<String>,
+    /// Pull a repository from peers now instead of waiting for the
+    /// replication loop
+    Replicate {
+        repo_hash: String,
     },
     
-    Status,
-    Repos,
+    /// Re-fetch corrupted or missing objects of a repository from peers
+    Repair {
+        repo_hash: String,
+    },
     
-    Serve {
+    /// Build the packfile served for a repository and check that it
+    /// parses back as a valid pack
+    PackVerify {
         repo_hash: String,
     },
     
-    Unserve {
+    /// Upload a repository's objects and refs to a peer, e.g. to seed a
+    /// new replica
+    Push {
         repo_hash: String,
+        /// Node id (or a unique prefix of one), or host:port, with an
+        /// https:// prefix for peers serving TLS
+        peer: String,
     },
     
-    Verify {
-        repo_hash: Option<String>,
+    /// Load a repository from a local Git directory (bare or work tree)
+    Import {
+        path: std::path::PathBuf,
+        repo_hash: String,
+    },
+    
+    /// Write a repository out as a bare Git directory
+    Export {
+        repo_hash: String,
+        path: std::path::PathBuf,
+    },
+    
+    /// List peer nodes known to the coordinator
+    Peers {
+        /// Print peers as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Check config, keys, storage, port, Tor and coordinator reachability
+    Doctor,
+    
+    /// Rewrite the config file in the current schema, filling new fields
+    MigrateConfig,
+    
+    /// Inspect the node configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    
+    /// Check that node_id matches public_key and the keys form a pair
+    VerifyIdentity {
+        /// Recompute node_id from public_key when it doesn't match
+        #[arg(long)]
+        fix: bool,
+    },
+    
+    /// Write a detached signature for the config file, for nodes that set
+    /// `config_signature_required`
+    SignConfig {
+        /// File holding the hex ed25519 private key to sign with
+        key_file: std::path::PathBuf,
+    },
+    
+    /// Inspect and manage the DHT routing table
+    Dht {
+        #[command(subcommand)]
+        action: DhtAction,
     },
     
     DhtTest {
@@ -79,6 +262,40 @@ enum Commands {
     },
     
     TestTor,
+    
+    /// Measure latency and throughput over Tor
+    BenchTor {
+        /// URL to download; defaults to the coordinator's node list
+        #[arg(long)]
+        url: Option<String>,
+        
+        /// Number of downloads
+        #[arg(short = 'n', long, default_value_t = 5)]
+        runs: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every setting in effect and whether it came from the config
+    /// file or a default. Secrets are redacted.
+    Show {
+        /// Print settings as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DhtAction {
+    /// Print which nodes host each repo and when their announcements expire
+    Dump,
+    
+    /// Drop expired announcements
+    Prune,
+    
+    /// Forget every announcement
+    Clear,
 }
 
 #[derive(Clone)]
@@ -89,34 +306,176 @@ pub struct NodeState {
     pub stats: Arc<RwLock<NodeStats>>,
     pub dht: Arc<RwLock<Option<dht::DHT>>>,
     pub proxy: crate::proxy::ProxyConfig,
+    pub alerts: Arc<alerts::Alerter>,
+    pub request_log: Arc<request_log::RequestLog>,
+    /// Our onion service, once Tor is up and it has been published
+    pub onion: Arc<std::sync::OnceLock<onion::OnionService>>,
+    pub maintenance: Arc<maintenance::Maintenance>,
+    /// When this process started; uptime is measured from here
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub start_instant: Instant,
+    pub repo_stats: Arc<RwLock<HashMap<String, RepoStats>>>,
+    /// Per-peer reliability, used to pick replication sources
+    pub peer_scores: Arc<peer_score::PeerScores>,
+    /// Object writes allowed at once, across all upload requests
+    pub upload_slots: Arc<tokio::sync::Semaphore>,
+    /// Storage capacity, re-detected from the disk with `capacity_auto`
+    pub capacity: Arc<capacity::Capacity>,
+    /// Liveness and restarts of the background loops
+    pub task_health: Arc<tasks::TaskHealth>,
+    /// Decompressed copies of recently served objects
+    pub object_cache: Arc<object_cache::ObjectCache>,
+    /// Download slots and the storage level at which uploads are refused
+    pub load_shed: Arc<load_shed::LoadShed>,
+    /// Repos to announce soon after their first upload
+    pub store_announcements: Arc<announce::StoreAnnouncements>,
+    /// Replicas pulled for availability, deleted after `cache_ttl_days`
+    /// without reads
+    pub cached_repos: Arc<cache_expiry::CachedRepos>,
+    /// Repos being pulled right now, by any task
+    pub replicating: Arc<replication::InFlight>,
+}
+
+impl NodeState {
+    /// Count a read served from a repository. Only hosted repos are
+    /// tracked, so arbitrary request paths can't grow the table.
+    pub async fn record_repo_access(&self, repo_hash: &str, bytes: u64) {
+        if !self.hosted_repos.read().await.iter().any(|r| r == repo_hash) {
+            return;
+        }
+        
+        let mut repo_stats = self.repo_stats.write().await;
+        let entry = repo_stats.entry(repo_hash.to_string()).or_default();
+        entry.requests += 1;
+        entry.bytes_served += bytes;
+        entry.last_accessed = Some(chrono::Utc::now().to_rfc3339());
+    }
+    
+    /// Forget hosted repos that are gone from disk, e.g. deleted by hand,
+    /// and withdraw their DHT announcements. Returns the repos dropped.
+    pub async fn drop_missing_repos(&self) -> Vec<String> {
+        let repos = self.hosted_repos.read().await.clone();
+        let mut missing = Vec::new();
+        for repo_hash in repos {
+            match self.storage.repo_on_disk_async(&repo_hash).await {
+                Ok(true) => {}
+                Ok(false) => missing.push(repo_hash),
+                Err(e) => tracing::debug!(repo = %repo_hash, error = %e, "Failed to check repo on disk"),
+            }
+        }
+        if missing.is_empty() {
+            return missing;
+        }
+        
+        for repo_hash in &missing {
+            tracing::warn!(repo = %repo_hash, "Hosted repo is missing from disk, no longer advertising it");
+        }
+        self.forget_repos(&missing).await;
+        missing
+    }
+    
+    /// Stop hosting repos that are no longer on disk: drop their stats
+    /// and withdraw their DHT announcements
+    pub async fn forget_repos(&self, repos: &[String]) {
+        self.hosted_repos.write().await.retain(|r| !repos.contains(r));
+        self.repo_stats.write().await.retain(|r, _| !repos.contains(r));
+        if let Some(dht) = self.dht.write().await.as_mut() {
+            for repo_hash in repos {
+                dht.unannounce_content(repo_hash, &self.config.node_id);
+            }
+        }
+    }
 }
 
-#[derive(Default, Clone)]
+#[cfg(test)]
+impl NodeState {
+    /// A node with no background tasks, DHT or Tor, storing under `path`
+    pub fn for_tests(config: config::NodeConfig, path: &std::path::Path) -> Self {
+        let storage = Arc::new(storage::GitStorage::new(path).unwrap());
+        Self {
+            proxy: proxy::ProxyConfig::from_config(&config),
+            alerts: Arc::new(alerts::Alerter::new(None, config.node_id.clone())),
+            capacity: Arc::new(capacity::Capacity::from_config(&config)),
+            upload_slots: Arc::new(tokio::sync::Semaphore::new(config.max_concurrent_uploads as usize)),
+            object_cache: Arc::new(object_cache::ObjectCache::new(1 << 20)),
+            load_shed: Arc::new(load_shed::LoadShed::from_config(&config)),
+            store_announcements: Arc::new(announce::StoreAnnouncements::new(false)),
+            peer_scores: Arc::new(peer_score::PeerScores::load(&storage)),
+            cached_repos: Arc::new(cache_expiry::CachedRepos::load(&storage)),
+            replicating: Arc::default(),
+            storage,
+            hosted_repos: Arc::new(RwLock::new(Vec::new())),
+            stats: Arc::new(RwLock::new(NodeStats::default())),
+            dht: Arc::new(RwLock::new(None)),
+            request_log: Arc::new(request_log::RequestLog::new()),
+            onion: Arc::new(std::sync::OnceLock::new()),
+            maintenance: Arc::new(maintenance::Maintenance::new(false)),
+            started_at: chrono::Utc::now(),
+            start_instant: Instant::now(),
+            repo_stats: Arc::new(RwLock::new(HashMap::new())),
+            task_health: Arc::new(tasks::TaskHealth::default()),
+            config,
+        }
+    }
+}
+
+/// Access counters for a single hosted repository
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RepoStats {
+    pub requests: u64,
+    pub bytes_served: u64,
+    pub last_accessed: Option<String>,
+}
+
+/// Node-wide counters. The totals are kept across restarts by
+/// [`stats_store`]; the rest describes the running process.
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct NodeStats {
     total_requests: u64,
     bytes_served: u64,
+    #[serde(skip)]
     repos_hosted: usize,
-    uptime_seconds: u64,
     replication_count: u64,
     failed_requests: u64,
+    /// Outcome of the most recent heartbeat, `None` before the first one
+    #[serde(skip)]
+    last_heartbeat_ok: Option<bool>,
+    /// Whether the last self check reached us at our advertised address
+    #[serde(skip)]
+    self_reachable: Option<bool>,
+    /// Repos the coordinator has this node down as hosting that it still
+    /// lacks after the last coordinator sync, `None` before the first one
+    #[serde(skip)]
+    repos_missing: Option<usize>,
+    /// Since when `repos_missing` has been above zero
+    #[serde(skip)]
+    repos_missing_since: Option<Instant>,
+    /// When the replication loop last completed a pass
+    #[serde(skip)]
+    last_replication_pass: Option<Instant>,
+}
+
+impl NodeStats {
+    /// Note how many assigned repos a coordinator sync left missing
+    fn set_repos_missing(&mut self, missing: usize, now: Instant) {
+        self.repos_missing = Some(missing);
+        if missing == 0 {
+            self.repos_missing_since = None;
+        } else {
+            self.repos_missing_since.get_or_insert(now);
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_level(true)
-        .init();
-    
     let cli = Cli::parse();
+    init_logging(cli.log_format, cli.log_level.as_deref());
     
     match cli.command {
-        Commands::Start { 
-            port, server, storage_path, capacity, anchor, 
-            enable_dht, disable_tor, proxy_addr 
-        } => {
-            start_node(port, server, storage_path, capacity, anchor, enable_dht, !disable_tor, proxy_addr).await?;
+        Commands::Start(args) => {
+            start_node(args).await?;
         }
         Commands::Init { output } => {
             init_node(output)?;
@@ -124,8 +483,8 @@ async fn main() -> anyhow::Result<()> {
         Commands::Status => {
             show_status().await?;
         }
-        Commands::Repos => {
-            list_repos().await?;
+        Commands::Repos { stats } => {
+            list_repos(stats).await?;
         }
         Commands::Serve { repo_hash } => {
             serve_repo(repo_hash).await?;
@@ -133,8 +492,53 @@ async fn main() -> anyhow::Result<()> {
         Commands::Unserve { repo_hash } => {
             unserve_repo(repo_hash).await?;
         }
-        Commands::Verify { repo_hash } => {
-            verify_storage(repo_hash).await?;
+        Commands::Verify { repo_hash, sample, fix, force, complete } => {
+            verify_storage(repo_hash.clone(), sample, fix, force).await?;
+            if let (true, Some(repo_hash)) = (complete, repo_hash) {
+                verify_complete(repo_hash).await?;
+            }
+        }
+        Commands::Gc { repo_hash, dry_run, grace } => {
+            collect_garbage(repo_hash, dry_run, grace)?;
+        }
+        Commands::Replicate { repo_hash } => {
+            replicate_repo(repo_hash).await?;
+        }
+        Commands::Repair { repo_hash } => {
+            repair_repo(repo_hash).await?;
+        }
+        Commands::PackVerify { repo_hash } => {
+            verify_pack(repo_hash)?;
+        }
+        Commands::Push { repo_hash, peer } => {
+            push_repo(repo_hash, peer).await?;
+        }
+        Commands::Import { path, repo_hash } => {
+            import_repo(path, repo_hash).await?;
+        }
+        Commands::Export { repo_hash, path } => {
+            export_repo(repo_hash, path).await?;
+        }
+        Commands::Peers { json } => {
+            list_peers(json).await?;
+        }
+        Commands::Doctor => {
+            doctor::run().await?;
+        }
+        Commands::MigrateConfig => {
+            migrate_config()?;
+        }
+        Commands::Config { action: ConfigAction::Show { json } } => {
+            show_config(json)?;
+        }
+        Commands::VerifyIdentity { fix } => {
+            verify_identity(fix)?;
+        }
+        Commands::SignConfig { key_file } => {
+            sign_config(&key_file)?;
+        }
+        Commands::Dht { action } => {
+            manage_dht(action)?;
         }
         Commands::DhtTest { repo_hash, action } => {
             test_dht(repo_hash, action).await?;
@@ -142,85 +546,112 @@ async fn main() -> anyhow::Result<()> {
         Commands::TestTor => {
             test_tor().await?;
         }
+        Commands::BenchTor { url, runs } => {
+            bench::run(url, runs).await?;
+        }
     }
     
     Ok(())
 }
 
-async fn start_node(
-    port: Option<u16>,
-    server: Option<String>,
-    storage_path: Option<String>,
-    capacity_gb: Option<u64>,
-    _is_anchor: bool,
-    _enable_dht: bool,
-    enable_tor: bool,
-    proxy_addr: Option<String>,
-) -> anyhow::Result<()> {
+async fn start_node(args: StartArgs) -> anyhow::Result<()> {
     tracing::info!("🧅 Starting Hyrule Storage Node v0.3.0 (Arti Edition)");
     
     let mut config = config::NodeConfig::load_or_create()?;
     
-    let disable_tor_flag_passed = !enable_tor;
+    let StartArgs {
+        port,
+        bind,
+        server,
+        storage_path,
+        capacity,
+        anchor: _,
+        enable_dht: _,
+        disable_tor,
+        proxy_addr,
+        maintenance: maintenance_mode,
+        tor_start_timeout,
+        reset_stats,
+    } = args;
     
-    let config_changed = config.update_and_save(
+    let config_changed = config.update_and_save(config::ConfigOverrides {
         server,
         port,
+        bind_address: bind,
         storage_path,
-        capacity_gb,
-        None,
-        if disable_tor_flag_passed { Some(false) } else { None },
+        capacity_gb: capacity,
+        enable_proxy: if disable_tor { Some(false) } else { None },
         proxy_addr,
-        None,
-    )?;
+        ..Default::default()
+    })?;
     
     if config_changed {
         tracing::info!("💾 Configuration updated and saved");
     }
     
-    tracing::info!("📁 Storage path: {}", config.storage_path);
-    tracing::info!("💾 Capacity: {:.2} GB", config.storage_capacity_gb());
+    let addr = config.bind_socket_addr()?;
+    
+    // Load TLS up front so a bad cert or key fails before anything starts
+    let tls = match config.tls_files() {
+        Some((cert, key)) => {
+            let tls = RustlsConfig::from_pem_file(cert, key).await.with_context(|| {
+                format!("Failed to load TLS certificate {} and key {}", cert.display(), key.display())
+            })?;
+            tracing::info!("🔐 Serving HTTPS with {}", cert.display());
+            Some(tls)
+        }
+        None => {
+            if config.tls_cert_path.is_some() {
+                tracing::warn!("⚠️  TLS is only used with Tor disabled, serving plain HTTP");
+            }
+            None
+        }
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    
+    let storage_path = std::path::absolute(&config.storage_path)?;
+    tracing::info!("📁 Storage path: {}", storage_path.display());
     tracing::info!("🆔 Node ID: {}", &config.node_id[..16]);
     tracing::info!("🏷️  Type: {}", if config.is_anchor { "Anchor Node" } else { "P2P Node" });
+    tracing::info!("🌐 Network: {}", config.network_namespace);
+    
+    // Lock storage before anything else so a second instance fails fast
+    let storage = Arc::new(
+        storage::GitStorage::open_exclusive(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
     
-    // Initialize Arti Tor client
-    let mut proxy_config = proxy::ProxyConfig::from_config(&config);
+    let capacity = capacity::Capacity::from_config(&config);
+    if capacity.is_auto() {
+        capacity.refresh(&storage_path, storage.get_storage_usage()?)
+            .context("Failed to detect storage capacity")?;
+        tracing::info!(
+            "💾 Capacity: {:.2} GB ({:.0}% of disk, detected)",
+            capacity.bytes() as f64 / (1024.0 * 1024.0 * 1024.0),
+            config.capacity_auto_fraction * 100.0
+        );
+    } else {
+        tracing::info!("💾 Capacity: {:.2} GB", config.storage_capacity_gb());
+    }
+    let capacity = Arc::new(capacity);
     
-if config.enable_proxy {
-    tracing::info!("🧅 Initializing Arti Tor client...");
-    tracing::info!("🌐 Hyrule server: {}", config.hyrule_server);
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
     
-    match proxy_config.init_tor_client().await {
-        Ok(_) => {
-            tracing::info!("✓ Arti Tor client initialized and bootstrapped");
-            
-            // Make validation non-fatal - it will work once circuits are built
-            tracing::info!("⏳ Building initial Tor circuits...");
-            match proxy_config.validate_tor_connection().await {
-                Ok(_) => {
-                    tracing::info!("✓ Tor connection validated successfully");
-                }
-                Err(e) => {
-                    tracing::warn!("⚠️  Initial Tor validation timed out: {}", e);
-                    tracing::warn!("   This is normal on first run. Circuits will be built as needed.");
-                }
-            }
-        }
-        Err(e) => {
-            tracing::error!("✗ Failed to initialize Arti: {}", e);
-            tracing::error!("  Make sure you have internet connectivity");
-            anyhow::bail!("Cannot start without Tor");
-        }
-    }
-} else {
-    tracing::warn!("⚠️  Tor disabled - traffic will NOT be anonymous!");
-    tracing::warn!("   This is NOT RECOMMENDED for production use");
-}    
-    let storage = Arc::new(storage::GitStorage::new(&config.storage_path)?);
+    let stats = if reset_stats {
+        // Saved right away, so a crash can't bring the old totals back
+        stats_store::save(&storage, &NodeStats::default())?;
+        tracing::info!("📊 Stats reset");
+        NodeStats::default()
+    } else {
+        stats_store::load(&storage)
+    };
     
     let dht = if config.enable_dht {
         tracing::info!("🔍 Initializing DHT...");
-        Some(dht::DHT::new(config.node_id.clone()))
+        Some(dht::DHT::load(config.node_id.clone(), config.network_namespace.clone(), dht::announcement_ttl(&config), &storage))
     } else {
         None
     };
@@ -229,11 +660,40 @@ if config.enable_proxy {
         config: config.clone(),
         storage: storage.clone(),
         hosted_repos: Arc::new(RwLock::new(Vec::new())),
-        stats: Arc::new(RwLock::new(NodeStats::default())),
+        stats: Arc::new(RwLock::new(stats)),
         dht: Arc::new(RwLock::new(dht)),
         proxy: proxy_config.clone(),
+        alerts: Arc::new(alerts::Alerter::new(
+            config.alert_webhook.clone(),
+            config.node_id.clone(),
+        )),
+        request_log: Arc::new(request_log::RequestLog::new()),
+        onion: Arc::new(std::sync::OnceLock::new()),
+        maintenance: Arc::new(maintenance::Maintenance::new(maintenance_mode)),
+        started_at: chrono::Utc::now(),
+        start_instant: Instant::now(),
+        repo_stats: Arc::new(RwLock::new(HashMap::new())),
+        peer_scores: Arc::new(peer_score::PeerScores::load(&storage)),
+        upload_slots: Arc::new(tokio::sync::Semaphore::new(config.max_concurrent_uploads as usize)),
+        capacity: capacity.clone(),
+        task_health: Arc::new(tasks::TaskHealth::default()),
+        object_cache: Arc::new(object_cache::ObjectCache::new(
+            config.object_cache_mb.saturating_mul(1024 * 1024) as usize,
+        )),
+        load_shed: Arc::new(load_shed::LoadShed::from_config(&config)),
+        store_announcements: Arc::new(announce::StoreAnnouncements::new(config.announce_on_store)),
+        cached_repos: Arc::new(cache_expiry::CachedRepos::load(&storage)),
+        replicating: Arc::default(),
     };
     
+    if maintenance_mode {
+        tracing::warn!("🔧 Starting in maintenance mode, writes will be rejected");
+    }
+    
+    if config.admin_token.is_none() && config.admin_socket.is_none() {
+        tracing::info!("🔒 No admin_token configured, admin endpoints are disabled");
+    }
+    
     // Load existing repos
     {
         let repos = storage.list_hosted_repos()?;
@@ -242,52 +702,300 @@ if config.enable_proxy {
         tracing::info!("📦 Loaded {} existing repositories", hosted.len());
     }
     
-    // Register with Hyrule server
-// Register with Hyrule server
-tracing::info!("🔗 Registering with Hyrule server...");
-match registration::register_node(&config, &proxy_config).await {
-    Ok(_) => tracing::info!("✓ Successfully registered with network"),
-    Err(e) => {
-        tracing::warn!("⚠️  Registration failed: {}. Will retry...", e);
+    let mut tasks = tasks::BackgroundTasks::with_health(state.task_health.clone());
+    
+    if config.enable_proxy {
+        tracing::info!("🌐 Hyrule server: {}", config.hyrule_server);
+        tracing::info_span!("node", node_id = %config.node_id).in_scope(|| {
+            tasks.spawn("tor bootstrap", with_state(&state, health::tor_bootstrap));
+        });
+        
+        let wait = Duration::from_secs(tor_start_timeout.unwrap_or(config.tor_start_timeout_secs));
+        if proxy_config.wait_for_tor(wait).await {
+            tracing::info!("✓ Tor is up");
+        } else {
+            tracing::warn!("⚠️  Tor not bootstrapped after {}s, starting without it", wait.as_secs());
+            tracing::warn!("   Local reads are served; peers and the coordinator are reachable once Tor connects");
+        }
+    } else {
+        tracing::warn!("⚠️  Tor disabled - traffic will NOT be anonymous!");
+        tracing::warn!("   This is NOT RECOMMENDED for production use");
+        
+        tracing::info!("🔗 Registering with Hyrule server...");
+        match registration::register_node(&config, &proxy_config, None, capacity.bytes()).await {
+            Ok(_) => tracing::info!("✓ Successfully registered with network"),
+            Err(e) => tracing::warn!("⚠️  Registration failed: {}. Will retry...", e),
+        }
+    }
+    
+    // Start background tasks; their logs carry the node id
+    tracing::info_span!("node", node_id = %config.node_id).in_scope(|| {
+        tasks.spawn("heartbeat", with_state(&state, health::heartbeat_loop));
+        tasks.spawn("replication", with_state(&state, replication::replication_loop));
+        tasks.spawn("coordinator sync", with_state(&state, replication::coordinator_sync_loop));
+        tasks.spawn("storage monitor", with_state(&state, health::monitor_storage));
+        tasks.spawn("self check", with_state(&state, health::self_check_loop));
+        tasks.spawn("stats snapshot", with_state(&state, stats_store::snapshot_loop));
+        
+        if storage.tier_count() > 1 {
+            tasks.spawn("tier rebalance", with_state(&state, tiering::rebalance_loop));
+        }
+        
+        if config.announce_on_store {
+            tasks.spawn("announce on store", with_state(&state, announce::announce_loop));
+        }
+        
+        if config.cache_ttl_days > 0 {
+            tasks.spawn("cache expiry", with_state(&state, cache_expiry::expiry_loop));
+        }
+        
+        if config.enable_dht {
+            tasks.spawn("dht announce", with_state(&state, dht::announcement_loop));
+        }
+    });
+    
+    let stats = state.stats.clone();
+    let admin_app = api::create_admin_router(state.clone())
+        .layer(TraceLayer::new_for_http());
+    let app = api::create_router(state)
+        .layer(TraceLayer::new_for_http());
+    
+    tracing::info!("🚀 Node listening on {}", addr);
+    tracing::info!("📊 Status: {}://localhost:{}/status", scheme, config.port);
+    tracing::info!("");
+    tracing::info!("✓ Node is ready to accept connections");
+    
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let public = async {
+        match tls {
+            Some(tls) => serve_tls(listener, tls, app).await?,
+            None => {
+                axum::serve(listener, app.into_make_service_with_connect_info::<ClientKey>())
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await?
+            }
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    
+    match config.admin_socket.clone() {
+        Some(path) => {
+            tokio::try_join!(public, serve_admin_socket(path, admin_app))?;
+        }
+        None => public.await?,
+    }
+    
+    let stuck = tasks.shutdown(Duration::from_secs(TASK_SHUTDOWN_TIMEOUT_SECS)).await;
+    if !stuck.is_empty() {
+        tracing::warn!("⚠️  {} background task(s) had to be aborted", stuck.len());
+    }
+    
+    if let Err(e) = stats_store::save(&storage, &*stats.read().await) {
+        tracing::warn!("⚠️  Failed to save node stats: {}", e);
     }
+    
+    tracing::info!("👋 Node shut down, releasing storage lock");
+    
+    Ok(())
 }
 
-// Clone the initialized proxy_config for background tasks
-let proxy_for_tasks = proxy_config.clone();
+fn init_logging(format: LogFormat, level: Option<&str>) {
+    let filter = match level {
+        Some(directives) => EnvFilter::new(directives),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_thread_ids(false)
+        .with_level(true);
+    
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
 
-// Start background tasks
-let heartbeat_state = state.clone();
-tokio::spawn(async move {
-    health::heartbeat_loop(heartbeat_state).await;
-});    
-    let replication_state = state.clone();
+/// Serve HTTPS on an already bound listener until shutdown
+async fn serve_tls(
+    listener: tokio::net::TcpListener,
+    tls: RustlsConfig,
+    app: axum::Router,
+) -> anyhow::Result<()> {
+    let handle = axum_server::Handle::new();
+    let shutdown = handle.clone();
     tokio::spawn(async move {
-        replication::replication_loop(replication_state).await;
+        shutdown_signal().await;
+        shutdown.graceful_shutdown(None);
     });
     
-    let monitor_state = state.clone();
-    tokio::spawn(async move {
-        health::monitor_storage(monitor_state).await;
-    });
+    axum_server::from_tcp_rustls(listener.into_std()?, tls)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<ClientKey>())
+        .await?;
     
-    if config.enable_dht {
-        let dht_state = state.clone();
-        tokio::spawn(async move {
-            dht::announcement_loop(dht_state).await;
-        });
+    Ok(())
+}
+
+/// Serve the admin routes on a Unix socket until shutdown, then remove it
+#[cfg(unix)]
+async fn serve_admin_socket(path: std::path::PathBuf, app: axum::Router) -> anyhow::Result<()> {
+    let listener = bind_admin_socket(&path)?;
+    tracing::info!("🔐 Admin socket listening on {}", path.display());
+    
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await;
+    let _ = std::fs::remove_file(&path);
+    
+    Ok(result?)
+}
+
+#[cfg(not(unix))]
+async fn serve_admin_socket(_path: std::path::PathBuf, _app: axum::Router) -> anyhow::Result<()> {
+    anyhow::bail!("admin_socket is only supported on Unix")
+}
+
+/// Bind the admin socket, readable and writable by its owner only. A
+/// socket left behind by an unclean shutdown is replaced; any other file
+/// at the path is left alone.
+#[cfg(unix)]
+fn bind_admin_socket(path: &std::path::Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)?;
     }
     
-    let app = api::create_router(state)
-        .layer(TraceLayer::new_for_http());
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
     
-    let addr = format!("0.0.0.0:{}", config.port);
-    tracing::info!("🚀 Node listening on {}", addr);
-    tracing::info!("📊 Status: http://localhost:{}/status", config.port);
-    tracing::info!("");
-    tracing::info!("✓ Node is ready to accept connections");
+    Ok(listener)
+}
+
+/// Build a background loop from the node state, giving each (re)start its
+/// own copy
+fn with_state<T, F>(state: &NodeState, task: T) -> impl Fn() -> F + Send + 'static
+where
+    T: Fn(NodeState) -> F + Send + 'static,
+{
+    let state = state.clone();
+    move || task(state.clone())
+}
+
+/// How long background tasks get to stop once the server has shut down
+const TASK_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+
+async fn shutdown_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("Failed to listen for shutdown signal: {}", e);
+        std::future::pending::<()>().await;
+    }
+    tracing::info!("🛑 Shutdown requested");
+}
+
+fn migrate_config() -> anyhow::Result<()> {
+    let migration = config::NodeConfig::migrate()?;
+    
+    if migration.is_noop() {
+        println!("✓ Config is already up to date");
+        return Ok(());
+    }
+    
+    for field in &migration.added {
+        println!("  + {} (default)", field);
+    }
+    for field in &migration.removed {
+        println!("  - {} (no longer used)", field);
+    }
+    
+    println!();
+    println!("✓ Config migrated to the current format");
+    println!("  Previous version saved as {}.bak", config::NodeConfig::config_path()?.display());
+    
+    Ok(())
+}
+
+fn show_config(json: bool) -> anyhow::Result<()> {
+    let path = config::NodeConfig::config_path()?;
+    let config = config::NodeConfig::load()?;
+    let settings = config.effective_settings(&std::fs::read_to_string(&path)?)?;
+    
+    if json {
+        let output = serde_json::json!({ "path": path, "settings": settings });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    
+    println!("⚙️  Effective configuration ({})", path.display());
+    println!();
+    
+    for setting in &settings {
+        let value = match &setting.value {
+            serde_json::Value::Null => "(unset)".to_string(),
+            value => value.to_string(),
+        };
+        match setting.source {
+            config::SettingSource::File => println!("{} = {}", setting.key, value),
+            config::SettingSource::Default => println!("{} = {}  # default", setting.key, value),
+            config::SettingSource::Identity => println!("{} = {}  # identity file", setting.key, value),
+        }
+    }
+    
+    Ok(())
+}
+
+fn verify_identity(fix: bool) -> anyhow::Result<()> {
+    let mut config = config::NodeConfig::load()?;
+    let problems = config.check_identity();
+    
+    if problems.is_empty() {
+        println!("✓ Identity is consistent");
+        println!("  Node ID: {}", config.node_id);
+        return Ok(());
+    }
+    
+    for problem in &problems {
+        println!("❌ {}", problem);
+    }
+    
+    let expected = match &problems[..] {
+        [config::IdentityProblem::NodeIdMismatch { expected }] => expected.clone(),
+        _ if fix => anyhow::bail!("Keys are invalid or mismatched; restore them from a backup or run 'hyrule-node init'"),
+        _ => anyhow::bail!("Node identity is inconsistent"),
+    };
+    if !fix {
+        anyhow::bail!("Node identity is inconsistent; run with --fix to recompute node_id");
+    }
+    
+    let previous = std::mem::replace(&mut config.node_id, expected);
+    config.save_identity()?;
+    
+    println!();
+    println!("✓ node_id recomputed from public_key");
+    println!("  {} -> {}", previous, config.node_id);
+    println!("  The node re-registers under the new id and gets a new onion address");
+    
+    Ok(())
+}
+
+fn sign_config(key_file: &std::path::Path) -> anyhow::Result<()> {
+    let private_key = std::fs::read_to_string(key_file)
+        .with_context(|| format!("Failed to read signing key {}", key_file.display()))?;
+    
+    let path = config::NodeConfig::config_path()?;
+    let content = std::fs::read(&path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    
+    let signature_path = config::signature_path(&path);
+    std::fs::write(&signature_path, config::sign_config(&content, private_key.trim())?)?;
     
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    println!("✓ Signed {}", path.display());
+    println!("  Signature written to {}", signature_path.display());
     
     Ok(())
 }
@@ -295,7 +1003,7 @@ tokio::spawn(async move {
 fn init_node(output: Option<String>) -> anyhow::Result<()> {
     println!("🔑 Generating node identity...");
     
-    let config = config::NodeConfig::generate();
+    let mut config = config::NodeConfig::generate();
     
     let config_path = if let Some(path) = output {
         std::path::PathBuf::from(path)
@@ -307,7 +1015,12 @@ fn init_node(output: Option<String>) -> anyhow::Result<()> {
         std::fs::create_dir_all(parent)?;
     }
     
-    let config_str = toml::to_string_pretty(&config)?;
+    // Keys go in their own owner-only file, out of the way of config edits
+    let identity_path = config::identity_path_for(&config_path);
+    config.identity().save(&identity_path)?;
+    config.identity_path = Some(identity_path.clone());
+    
+    let config_str = toml::to_string_pretty(&config.settings())?;
     std::fs::write(&config_path, config_str)?;
     
     println!("✓ Node identity created!");
@@ -318,6 +1031,7 @@ fn init_node(output: Option<String>) -> anyhow::Result<()> {
     println!("🧅 Tor: Enabled (using Arti embedded client)");
     println!();
     println!("Config saved to: {}", config_path.display());
+    println!("Identity saved to: {} (keep it private and backed up)", identity_path.display());
     println!();
     println!("ℹ️  Arti will bootstrap automatically on first start");
     println!("   No need to install Tor separately!");
@@ -333,16 +1047,19 @@ async fn show_status() -> anyhow::Result<()> {
     println!();
     
     let config = config::NodeConfig::load()?;
-    let storage = storage::GitStorage::new(&config.storage_path)?;
+    let storage = storage::GitStorage::new(&config.storage_path)?
+        .with_tiers(&config.tier_paths())?;
     
     println!("Node ID: {}", &config.node_id[..16]);
     println!("Port: {}", config.port);
     println!("Type: {}", if config.is_anchor { "Anchor" } else { "P2P" });
     println!("Storage: {}", config.storage_path);
     println!("Hyrule Server: {}", config.hyrule_server);
+    println!("Network: {}", config.network_namespace);
     
     let usage = storage.get_storage_usage()?;
-    let capacity = config.storage_capacity;
+    let capacity = capacity::Capacity::from_config(&config)
+        .refresh(std::path::Path::new(&config.storage_path), usage)?;
     let usage_pct = (usage as f64 / capacity as f64) * 100.0;
     
     println!("Usage: {:.2} GB / {:.2} GB ({:.1}%)", 
@@ -354,7 +1071,9 @@ async fn show_status() -> anyhow::Result<()> {
     let repos = storage.list_hosted_repos()?;
     println!("Repositories: {}", repos.len());
     
-    if config.enable_proxy {
+    if config.enable_proxy && config.tor_mode == config::TorMode::Socks {
+        println!("🧅 Tor: Enabled (SOCKS proxy at {})", config.proxy_addr);
+    } else if config.enable_proxy {
         println!("🧅 Tor: Enabled (Arti embedded client)");
     } else {
         println!("⚠️  Tor: Disabled");
@@ -363,12 +1082,13 @@ async fn show_status() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn list_repos() -> anyhow::Result<()> {
+async fn list_repos(show_stats: bool) -> anyhow::Result<()> {
     println!("📦 Hosted Repositories");
     println!();
     
     let config = config::NodeConfig::load()?;
-    let storage = storage::GitStorage::new(&config.storage_path)?;
+    let storage = storage::GitStorage::new(&config.storage_path)?
+        .with_tiers(&config.tier_paths())?;
     
     let repos = storage.list_hosted_repos()?;
     
@@ -377,6 +1097,13 @@ async fn list_repos() -> anyhow::Result<()> {
         return Ok(());
     }
     
+    // Access stats live in the running node, so ask it over loopback
+    let node_url = format!("http://{}", onion::local_target(config.bind_socket_addr()?));
+    let identity = http_client::ClientIdentity::from_config(&config);
+    let client = reqwest::Client::new();
+    let mut total = RepoStats::default();
+    let mut node_reachable = true;
+    
     for (i, repo_hash) in repos.iter().enumerate() {
         let size = storage.get_repo_size(repo_hash)?;
         let object_count = storage.list_objects(repo_hash)?.len();
@@ -384,6 +1111,49 @@ async fn list_repos() -> anyhow::Result<()> {
         println!("{}. {}", i + 1, &repo_hash[..16]);
         println!("   Size: {:.2} MB", size as f64 / 1e6);
         println!("   Objects: {}", object_count);
+        
+        if show_stats && node_reachable {
+            let url = format!("{}/repos/{}/stats", node_url, repo_hash);
+            match client.get(&url).headers(identity.headers(&url)).send().await {
+                Ok(response) => {
+                    // A repo the node hasn't loaded yet, or any other
+                    // refusal, only costs that repo its stats
+                    let stats: RepoStats = match response.error_for_status() {
+                        Ok(response) => match response.json().await {
+                            Ok(stats) => stats,
+                            Err(e) => {
+                                println!("   Stats unavailable: {}", e);
+                                continue;
+                            }
+                        },
+                        Err(e) => {
+                            println!("   Stats unavailable: {}", e.status().map_or(e.to_string(), |s| s.to_string()));
+                            continue;
+                        }
+                    };
+                    println!("   Requests: {}", stats.requests);
+                    println!("   Served: {:.2} MB", stats.bytes_served as f64 / 1e6);
+                    if let Some(last) = &stats.last_accessed {
+                        println!("   Last accessed: {}", last);
+                    }
+                    total.requests += stats.requests;
+                    total.bytes_served += stats.bytes_served;
+                }
+                Err(_) => node_reachable = false,
+            }
+        }
+    }
+    
+    if show_stats {
+        println!();
+        if node_reachable {
+            println!("Total: {} requests, {:.2} MB served since node start",
+                total.requests,
+                total.bytes_served as f64 / 1e6
+            );
+        } else {
+            println!("⚠️  Node is not running at {}, access stats unavailable", node_url);
+        }
     }
     
     Ok(())
@@ -399,22 +1169,30 @@ async fn serve_repo(repo_hash: String) -> anyhow::Result<()> {
         storage.init_repo(&repo_hash)?;
         println!("✓ Initialized local storage for {}", &repo_hash[..16]);
     }
+    // Served explicitly, so never expired as a cached replica
+    cache_expiry::set_served(&storage, &repo_hash, true)?;
     
-    let mut proxy_config = proxy::ProxyConfig::from_config(&config);
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
     if config.enable_proxy {
         proxy_config.init_tor_client().await?;
     }
     
     let client = proxy_config.build_client()?;
-    let url = format!("{}/api/repos/{}/replicate", config.hyrule_server, repo_hash);
+    let url = registration::coordinator_url(
+        &config.hyrule_server,
+        &config.network_namespace,
+        &format!("/api/repos/{}/replicate", repo_hash),
+    );
     
     #[derive(serde::Serialize)]
     struct AnnounceReq {
         node_id: String,
+        network_namespace: String,
     }
     
     let req = AnnounceReq {
         node_id: config.node_id,
+        network_namespace: config.network_namespace,
     };
     
     let response = client.post(&url).json(&req).send().await?;
@@ -430,16 +1208,116 @@ async fn serve_repo(repo_hash: String) -> anyhow::Result<()> {
 
 async fn unserve_repo(repo_hash: String) -> anyhow::Result<()> {
     println!("📥 Removing repository from serving list...");
+    
+    let config = config::NodeConfig::load()?;
+    let storage = storage::GitStorage::new(&config.storage_path)?;
+    cache_expiry::set_served(&storage, &repo_hash, false)?;
+    
     println!("✓ Repository {} no longer advertised", &repo_hash[..16]);
     println!("  (Data preserved in storage)");
+    if config.cache_ttl_days > 0 {
+        println!("  A cached replica of it expires after {} days without reads", config.cache_ttl_days);
+    }
+    Ok(())
+}
+
+fn parse_log_filter(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    
+    // A bare word must be a level; EnvFilter would take it as a target name
+    if !s.contains('=') && !s.contains(',') {
+        s.parse::<LevelFilter>().map_err(|_| {
+            format!("unknown log level '{}', expected off, error, warn, info, debug or trace", s)
+        })?;
+    } else {
+        EnvFilter::try_new(s).map_err(|e| format!("invalid log filter '{}': {}", s, e))?;
+    }
+    
+    Ok(s.to_string())
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+    if value <= 0.0 || value > 100.0 {
+        return Err("sample must be between 0 and 100".to_string());
+    }
+    Ok(value)
+}
+
+fn collect_garbage(repo_hash: Option<String>, dry_run: bool, grace: u64) -> anyhow::Result<()> {
+    println!("🧹 Collecting unreferenced objects...");
+    
+    let config = config::NodeConfig::load()?;
+    // A running node could be storing objects that reference what we
+    // delete, so a real run needs the storage lock; a dry run only reads
+    let storage = if dry_run {
+        storage::GitStorage::new(&config.storage_path)?
+    } else {
+        storage::GitStorage::open_exclusive(&config.storage_path)
+            .context("Stop the node before collecting garbage, or use --dry-run")?
+    };
+    let storage = storage
+        .with_tiers(&config.tier_paths())?
+        .with_dedup(config.dedup_objects)
+        .with_fsync_policy(config.fsync_policy);
+    
+    let repos = match repo_hash {
+        Some(hash) => vec![hash],
+        None => storage.list_hosted_repos()?,
+    };
+    let cutoff = std::time::SystemTime::now() - Duration::from_secs(grace);
+    
+    let mut removed = 0;
+    for repo in repos {
+        // Objects of a partial replica may be waiting for the commits that
+        // reference them
+        if !storage.is_complete(&repo)? {
+            println!("  {}: partial replica, skipped", &repo[..16]);
+            continue;
+        }
+        
+        let garbage = storage.unreachable_objects(&repo, cutoff)?;
+        for object_id in &garbage {
+            if dry_run {
+                println!("  would remove {}", object_id);
+            } else if storage.delete_object(&repo, object_id)? {
+                removed += 1;
+            }
+        }
+        println!("  {}: {} unreferenced objects", &repo[..16], garbage.len());
+    }
+    
+    println!();
+    if dry_run {
+        println!("✓ Dry run, nothing removed");
+    } else {
+        println!("✓ Removed {} objects", removed);
+    }
+    
     Ok(())
 }
 
-async fn verify_storage(repo_hash: Option<String>) -> anyhow::Result<()> {
+async fn verify_storage(
+    repo_hash: Option<String>,
+    sample: Option<f64>,
+    fix: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    use rand::seq::SliceRandom;
+    
     println!("🔍 Verifying storage integrity...");
+    if let Some(pct) = sample {
+        println!("   Sampling {:.1}% of objects", pct);
+    }
     
     let config = config::NodeConfig::load()?;
-    let storage = storage::GitStorage::new(&config.storage_path)?;
+    let storage = Arc::new(
+        storage::GitStorage::new(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
     
     let repos = if let Some(hash) = repo_hash {
         vec![hash]
@@ -448,59 +1326,469 @@ async fn verify_storage(repo_hash: Option<String>) -> anyhow::Result<()> {
     };
     
     let mut total_objects = 0;
+    let mut checked_objects = 0;
     let mut corrupted = 0;
+    let mut corrupted_by_repo: Vec<(String, Vec<String>)> = Vec::new();
     
     for repo in repos {
         println!("\nVerifying {}...", &repo[..16]);
         
-        let objects = storage.list_objects(&repo)?;
+        let mut objects = storage.list_objects(&repo)?;
         total_objects += objects.len();
         
+        if let Some(pct) = sample {
+            let sample_size = ((objects.len() as f64 * pct / 100.0).ceil() as usize).min(objects.len());
+            objects.shuffle(&mut rand::thread_rng());
+            objects.truncate(sample_size);
+        }
+        checked_objects += objects.len();
+        
+        let mut index = verify_index::VerifyIndex::load(&storage, &repo);
+        let mut bad = Vec::new();
         for object_id in objects {
-            match storage.verify_object(&repo, &object_id) {
+            match index.verify(&storage, &repo, &object_id, force) {
                 Ok(true) => {},
                 Ok(false) => {
                     println!("   ✗ Corrupted: {}", &object_id[..8]);
                     corrupted += 1;
+                    bad.push(object_id);
                 }
                 Err(e) => {
                     println!("   ✗ Error reading {}: {}", &object_id[..8], e);
                     corrupted += 1;
+                    bad.push(object_id);
                 }
             }
         }
+        
+        if let Err(e) = index.save() {
+            println!("   ⚠️  Could not save verification index: {}", e);
+        }
+        
+        if !bad.is_empty() {
+            corrupted_by_repo.push((repo, bad));
+        }
     }
     
     println!();
     println!("═══════════════════════");
     println!("Total objects: {}", total_objects);
+    if sample.is_some() {
+        println!("Sampled: {}", checked_objects);
+    }
     println!("Corrupted: {}", corrupted);
     
+    if sample.is_some() && checked_objects > 0 {
+        let rate = corrupted as f64 / checked_objects as f64 * 100.0;
+        println!(
+            "Estimated corruption rate: {:.2}% (sample of {} objects, ~{} corrupted overall)",
+            rate,
+            checked_objects,
+            (rate / 100.0 * total_objects as f64).round() as u64
+        );
+    }
+    
     if corrupted == 0 {
         println!("✓ All objects verified successfully!");
-    } else {
-        println!("✗ Found {} corrupted objects", corrupted);
+        return Ok(());
+    }
+    
+    println!("✗ Found {} corrupted objects", corrupted);
+    
+    if !fix {
+        println!("  Run with --fix to re-fetch them from peers");
+        return Ok(());
+    }
+    
+    println!();
+    println!("🔧 Re-fetching corrupted objects from peers...");
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    let client = proxy_config.build_client()?;
+    let scores = peer_score::PeerScores::load(&storage);
+    
+    let mut repaired_total = 0;
+    for (repo, bad) in corrupted_by_repo {
+        match replication::repair_objects(&storage, &config.hyrule_server, &config.network_namespace, &repo, &bad, &client, &scores).await {
+            Ok(repaired) => {
+                println!("   {} : repaired {}/{}", &repo[..16], repaired.len(), bad.len());
+                repaired_total += repaired.len();
+            }
+            Err(e) => {
+                println!("   {} : repair failed: {}", &repo[..16], e);
+            }
+        }
+    }
+    
+    println!("Repaired {} of {} corrupted objects", repaired_total, corrupted);
+    
+    Ok(())
+}
+
+/// Look for objects missing from a repo, as opposed to corrupted ones
+async fn verify_complete(repo_hash: String) -> anyhow::Result<()> {
+    println!();
+    println!("🔍 Checking {} is complete...", &repo_hash[..16]);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = Arc::new(
+        storage::GitStorage::new(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    let client = proxy_config.build_client()?;
+    let scores = peer_score::PeerScores::load(&storage);
+    
+    let report = replication::check_complete(&storage, &config.hyrule_server, &config.network_namespace, &repo_hash, &client, &scores).await?;
+    
+    println!("   Expected objects: {} (from {})", report.expected, report.source);
+    if report.missing.is_empty() {
+        println!("✓ No objects missing");
+        return Ok(());
+    }
+    
+    for object_id in &report.missing {
+        println!("   ✗ Missing: {}", object_id);
+    }
+    println!("✗ {} objects missing", report.missing.len());
+    println!("  Run `hyrule-node repair {}` to fetch them from peers", repo_hash);
+    
+    Ok(())
+}
+
+async fn replicate_repo(repo_hash: String) -> anyhow::Result<()> {
+    println!("📥 Replicating {}...", &repo_hash[..16]);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = Arc::new(
+        storage::GitStorage::new(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
+    
+    // Pulls only add whole repos; filling gaps in one is what repair is for
+    if storage.repo_path(&repo_hash).exists() {
+        anyhow::bail!(
+            "{} is already stored here; use `hyrule-node repair` to fetch missing objects",
+            &repo_hash[..16]
+        );
+    }
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    let client = proxy_config.build_client()?;
+    let scores = peer_score::PeerScores::load(&storage);
+    
+    let report = replication::pull_repo(
+        &storage,
+        &config.spool_dir(),
+        &config.hyrule_server,
+        &config.network_namespace,
+        &repo_hash,
+        &client,
+        &scores,
+    )
+    .await?;
+    println!("✓ Fetched {} objects from peer {}", report.fetched, &report.peer_id[..8]);
+    
+    replication::announce_replica(&config.hyrule_server, &config.network_namespace, &config.node_id, &repo_hash, &client).await?;
+    println!("✓ Announced as a replica");
+    println!("  A running node serves it after its next restart");
+    
+    Ok(())
+}
+
+async fn repair_repo(repo_hash: String) -> anyhow::Result<()> {
+    println!("🔧 Repairing {}...", &repo_hash[..16]);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = Arc::new(
+        storage::GitStorage::new(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    let client = proxy_config.build_client()?;
+    let scores = peer_score::PeerScores::load(&storage);
+    
+    let damaged = replication::find_damaged_objects(&storage, &config.hyrule_server, &config.network_namespace, &repo_hash, &client, &scores).await?;
+    if damaged.is_empty() {
+        println!("✓ No corrupted or missing objects");
+        return Ok(());
+    }
+    
+    println!("   {} corrupted or missing objects, fetching from peers...", damaged.len());
+    let repaired = replication::repair_objects(&storage, &config.hyrule_server, &config.network_namespace, &repo_hash, &damaged, &client, &scores).await?;
+    
+    println!("Repaired {} of {} objects", repaired.len(), damaged.len());
+    if repaired.len() < damaged.len() {
+        anyhow::bail!("{} objects could not be repaired", damaged.len() - repaired.len());
+    }
+    
+    Ok(())
+}
+
+fn verify_pack(repo_hash: String) -> anyhow::Result<()> {
+    println!("📦 Verifying pack for {}...", &repo_hash[..16]);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = storage::GitStorage::new(&config.storage_path)?
+        .with_tiers(&config.tier_paths())?
+        .with_compression_level(config.compression_level)
+        .with_dedup(config.dedup_objects)
+        .with_fsync_policy(config.fsync_policy);
+    
+    check_repo_pack(&storage, &repo_hash)
+}
+
+/// Encode a repo the way smart-HTTP clones receive it and check the pack
+fn check_repo_pack(storage: &storage::GitStorage, repo_hash: &str) -> anyhow::Result<()> {
+    let objects = storage.list_objects(repo_hash)?;
+    let stored = objects.len();
+    let pack_data = storage.write_pack(repo_hash, &objects)?;
+    
+    let summary = match pack::verify_pack(&pack_data) {
+        Ok(summary) => summary,
+        Err(e) => {
+            println!("❌ Not a valid packfile ({} bytes): {:#}", pack_data.len(), e);
+            anyhow::bail!("Pack verification failed");
+        }
+    };
+    
+    println!("✓ Valid version {} packfile, {} bytes", summary.version, pack_data.len());
+    println!("  Objects: {} ({} commits, {} trees, {} blobs, {} tags, {} deltas)",
+        summary.objects, summary.commits, summary.trees, summary.blobs, summary.tags, summary.deltas);
+    if summary.objects as usize != stored {
+        println!("⚠️  The repository stores {} objects", stored);
+    }
+    
+    Ok(())
+}
+
+async fn push_repo(repo_hash: String, peer: String) -> anyhow::Result<()> {
+    println!("📤 Pushing {} to {}...", &repo_hash[..16], peer);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = Arc::new(
+        storage::GitStorage::new(&config.storage_path)?
+            .with_tiers(&config.tier_paths())?
+            .with_compression_level(config.compression_level)
+            .with_dedup(config.dedup_objects)
+            .with_fsync_policy(config.fsync_policy),
+    );
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    let client = proxy_config.build_client()?;
+    
+    let report = replication::push_repo_to_peer(&storage, &config.hyrule_server, &config.network_namespace, &repo_hash, &peer, &client).await?;
+    
+    println!("✓ Pushed to {}", &report.peer_id[..16.min(report.peer_id.len())]);
+    println!("  Objects: {} uploaded, {} already there", report.uploaded, report.skipped);
+    println!("  Refs updated: {}", report.refs);
+    
+    Ok(())
+}
+
+async fn import_repo(path: std::path::PathBuf, repo_hash: String) -> anyhow::Result<()> {
+    println!("📦 Importing {} as {}...", path.display(), &repo_hash[..16]);
+    
+    let config = config::NodeConfig::load()?;
+    let storage = storage::GitStorage::new(&config.storage_path)?
+        .with_tiers(&config.tier_paths())?
+        .with_compression_level(config.compression_level)
+        .with_dedup(config.dedup_objects)
+        .with_fsync_policy(config.fsync_policy);
+    
+    let report = tokio::task::spawn_blocking(move || {
+        gitdir::import_repo(&storage, &path, &repo_hash)
+    }).await??;
+    
+    println!("✓ Imported {} objects and {} refs", report.objects, report.refs);
+    if let Some(head) = report.head {
+        println!("  HEAD → {}", head);
+    }
+    println!("  A running node serves it after its next restart");
+    
+    Ok(())
+}
+
+async fn export_repo(repo_hash: String, path: std::path::PathBuf) -> anyhow::Result<()> {
+    println!("📤 Exporting {} to {}...", &repo_hash[..16], path.display());
+    
+    let config = config::NodeConfig::load()?;
+    let storage = storage::GitStorage::new(&config.storage_path)?
+        .with_tiers(&config.tier_paths())?;
+    
+    let target = path.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        gitdir::export_repo(&storage, &repo_hash, &target)
+    }).await??;
+    
+    println!("✓ Exported {} objects and {} refs", report.objects, report.refs);
+    println!("  Check it with: git --git-dir {} fsck", path.display());
+    
+    Ok(())
+}
+
+async fn list_peers(json: bool) -> anyhow::Result<()> {
+    let config = config::NodeConfig::load()?;
+    
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    if config.enable_proxy {
+        proxy_config.init_tor_client().await?;
+    }
+    
+    let peers = registration::discover_peers(&config, &proxy_config).await?;
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&peers)?);
+        return Ok(());
+    }
+    
+    println!("🌐 Known Peers");
+    println!();
+    
+    if peers.is_empty() {
+        println!("No peers registered with the coordinator.");
+        return Ok(());
+    }
+    
+    for peer in &peers {
+        let marker = if peer.node_id == config.node_id { " (this node)" } else { "" };
+        println!("{}{}", &peer.node_id[..16.min(peer.node_id.len())], marker);
+        println!("   Address: {}", peer.url());
+        println!("   Type: {}", if peer.is_anchor != 0 { "Anchor" } else { "P2P" });
+        println!("   Last seen: {}", peer.last_seen);
+    }
+    
+    println!();
+    println!("Total: {} peers", peers.len());
+    
+    Ok(())
+}
+
+fn manage_dht(action: DhtAction) -> anyhow::Result<()> {
+    let config = config::NodeConfig::load()?;
+    
+    match action {
+        DhtAction::Dump => {
+            let storage = storage::GitStorage::new(&config.storage_path)?;
+            let dht = dht::DHT::load(config.node_id.clone(), config.network_namespace.clone(), dht::announcement_ttl(&config), &storage);
+            let entries = dht.entries();
+            let now = chrono::Utc::now().timestamp();
+            
+            println!("🔍 DHT routing table");
+            println!();
+            
+            if entries.is_empty() {
+                println!("No announcements");
+                return Ok(());
+            }
+            
+            for (repo_hash, nodes) in &entries {
+                println!("{}", repo_hash);
+                for entry in nodes.iter() {
+                    let record = &entry.record;
+                    let ttl = if dht.is_expired(entry, now) {
+                        "expired".to_string()
+                    } else {
+                        format!("expires in {}s", dht.expires_at(entry) - now)
+                    };
+                    println!(
+                        "  - {} ({} objects, {} bytes, {}, {})",
+                        &record.node_id[..16.min(record.node_id.len())],
+                        record.object_count,
+                        record.total_size,
+                        if record.is_complete { "complete" } else { "incomplete" },
+                        ttl
+                    );
+                }
+            }
+            
+            println!();
+            println!("Total: {} repos", entries.len());
+        }
+        DhtAction::Prune => {
+            let (_storage, mut dht) = open_dht_table(&config)?;
+            let dropped = dht.prune();
+            dht.save()?;
+            println!("✓ Removed {} expired announcements", dropped);
+        }
+        DhtAction::Clear => {
+            let (_storage, mut dht) = open_dht_table(&config)?;
+            let dropped = dht.clear();
+            dht.save()?;
+            println!("✓ Removed {} announcements", dropped);
+        }
     }
     
     Ok(())
 }
 
+/// Load the DHT table for changing it. A running node rewrites the table
+/// every round, so this takes the storage lock to make sure none is.
+fn open_dht_table(config: &config::NodeConfig) -> anyhow::Result<(storage::GitStorage, dht::DHT)> {
+    let storage = storage::GitStorage::open_exclusive(&config.storage_path)
+        .context("Stop the node before changing its DHT table")?;
+    let dht = dht::DHT::load(config.node_id.clone(), config.network_namespace.clone(), dht::announcement_ttl(config), &storage);
+    Ok((storage, dht))
+}
+
 async fn test_dht(repo_hash: String, action: String) -> anyhow::Result<()> {
     println!("🔍 Testing DHT functionality...");
     
     let config = config::NodeConfig::load()?;
-    let mut dht = dht::DHT::new(config.node_id.clone());
+    let storage = storage::GitStorage::new(&config.storage_path)?;
+    let mut dht = dht::DHT::load(config.node_id.clone(), config.network_namespace.clone(), dht::announcement_ttl(&config), &storage);
     
     match action.as_str() {
         "announce" => {
-            dht.announce_content(&repo_hash, &config.node_id);
-            println!("✓ Announced {} to DHT", &repo_hash[..16]);
+            let record = dht::ContentRecord::from_storage(&storage, &repo_hash, &config.node_id, None)?;
+            let announcement = dht::Announcement::sign(&repo_hash, record.clone(), &config)?;
+            dht.announce_content(&repo_hash, announcement)?;
+            dht.save()?;
+            println!(
+                "✓ Announced {} to DHT ({} objects, {} bytes, {})",
+                &repo_hash[..16],
+                record.object_count,
+                record.total_size,
+                if record.is_complete { "complete" } else { "incomplete" }
+            );
         }
         "query" => {
-            let nodes = dht.query_content(&repo_hash);
-            println!("Found {} nodes hosting {}", nodes.len(), &repo_hash[..16]);
-            for node in nodes {
-                println!("  - {}", &node[..16]);
+            let records = dht.query_content(&repo_hash);
+            println!("Found {} nodes hosting {}", records.len(), &repo_hash[..16]);
+            for record in records {
+                println!(
+                    "  - {} ({} objects, {} bytes, {})",
+                    &record.node_id[..16],
+                    record.object_count,
+                    record.total_size,
+                    if record.is_complete { "complete" } else { "incomplete" }
+                );
             }
         }
         _ => {
@@ -513,11 +1801,16 @@ async fn test_dht(repo_hash: String, action: String) -> anyhow::Result<()> {
 }
 
 async fn test_tor() -> anyhow::Result<()> {
-    println!("🧅 Testing Arti Tor connection...");
-    println!();
-    
     let config = config::NodeConfig::load()?;
-    let mut proxy_config = proxy::ProxyConfig::from_config(&config);
+    let proxy_config = proxy::ProxyConfig::from_config(&config);
+    let socks = config.tor_mode == config::TorMode::Socks;
+    
+    if socks {
+        println!("🧅 Testing Tor connection through {}...", proxy_config.addr);
+    } else {
+        println!("🧅 Testing Arti Tor connection...");
+    }
+    println!();
     
     if !proxy_config.enabled {
         println!("✗ Tor is disabled in config");
@@ -525,23 +1818,21 @@ async fn test_tor() -> anyhow::Result<()> {
         return Ok(());
     }
     
-    println!("Initializing Arti client and bootstrapping...");
+    if !socks {
+        println!("Initializing Arti client and bootstrapping...");
+    }
     
     match proxy_config.init_tor_client().await {
-        Ok(_) => {
-            println!("✓ Arti client initialized");
-            
-            match proxy_config.validate_tor_connection().await {
-                Ok(_) => {
-                    println!();
-                    println!("✓ Tor connection successful!");
-                    println!("  Your traffic is being routed through the Tor network");
-                }
-                Err(e) => {
-                    println!();
-                    println!("✗ Tor connection validation failed: {}", e);
-                }
-            }
+        Ok(_) if socks => println!("✓ Tor SOCKS proxy is listening"),
+        Ok(_) => println!("✓ Arti client initialized"),
+        Err(e) if socks => {
+            println!();
+            println!("✗ {}", e);
+            println!();
+            println!("Troubleshooting:");
+            println!("  1. Make sure the Tor daemon is running");
+            println!("  2. Check proxy_addr matches its SocksPort");
+            return Ok(());
         }
         Err(e) => {
             println!();
@@ -551,8 +1842,81 @@ async fn test_tor() -> anyhow::Result<()> {
             println!("  1. Make sure you have internet connectivity");
             println!("  2. Check your firewall allows outbound connections");
             println!("  3. Arti needs to bootstrap to the Tor network");
+            return Ok(());
+        }
+    }
+    
+    match proxy_config.validate_tor_connection().await {
+        Ok(_) => {
+            println!();
+            println!("✓ Tor connection successful!");
+            println!("  Your traffic is being routed through the Tor network");
+        }
+        Err(e) => {
+            println!();
+            println!("✗ Tor connection validation failed: {}", e);
         }
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_log_filter() {
+        assert_eq!(parse_log_filter("debug").unwrap(), "debug");
+        assert_eq!(parse_log_filter("hyrule_node=trace,warn").unwrap(), "hyrule_node=trace,warn");
+        
+        let err = parse_log_filter("verbose").unwrap_err();
+        assert!(err.contains("unknown log level 'verbose'"));
+        assert!(parse_log_filter("hyrule_node=loud").is_err());
+    }
+    
+    #[test]
+    fn test_stored_repo_pack_verifies() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage::GitStorage::new(dir.path()).unwrap();
+        let repo = "ab".repeat(32);
+        for data in [&b"blob 5\0hello"[..], b"blob 5\0world"] {
+            storage.store_object(&repo, &crypto::hash_data(data)[..40], data).unwrap();
+        }
+        
+        check_repo_pack(&storage, &repo).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_admin_socket_replaces_stale_socket() {
+        use std::os::unix::fs::PermissionsExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("admin.sock");
+        
+        // Left over from a previous run
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        
+        let listener = bind_admin_socket(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        
+        let app = axum::Router::new().route("/admin/ping", axum::routing::get(|| async { "pong" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"GET /admin/ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("pong"));
+        
+        // Regular files are never clobbered
+        let file = dir.path().join("not-a-socket");
+        std::fs::write(&file, b"keep").unwrap();
+        assert!(bind_admin_socket(&file).is_err());
+        assert_eq!(std::fs::read(&file).unwrap(), b"keep");
+    }
+}
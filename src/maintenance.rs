@@ -0,0 +1,113 @@
+// hyrule-node/src/maintenance.rs
+use crate::NodeState;
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Seconds clients are asked to wait before retrying a rejected write
+const RETRY_AFTER_SECS: u64 = 300;
+
+/// Node-wide read-only switch. Reads keep working; writes get a 503.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    enabled: AtomicBool,
+}
+
+impl Maintenance {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceResponse {
+    maintenance_mode: bool,
+}
+
+/// Route layer for write endpoints: reject with 503 + Retry-After while
+/// maintenance mode is on
+pub async fn reject_writes(
+    State(maintenance): State<Arc<Maintenance>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if maintenance.is_enabled() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+            "Node is in maintenance mode",
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// `POST /admin/maintenance` - turn maintenance mode on or off
+pub async fn set_maintenance(
+    State(state): State<NodeState>,
+    Json(payload): Json<MaintenanceRequest>,
+) -> Json<MaintenanceResponse> {
+    if state.maintenance.is_enabled() != payload.enabled {
+        tracing::warn!(
+            "🔧 Maintenance mode {}",
+            if payload.enabled { "enabled, rejecting writes" } else { "disabled" }
+        );
+    }
+    state.maintenance.set(payload.enabled);
+
+    Json(MaintenanceResponse {
+        maintenance_mode: payload.enabled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+
+    #[tokio::test]
+    async fn test_writes_rejected_during_maintenance() {
+        let maintenance = Arc::new(Maintenance::new(true));
+
+        let app = Router::new()
+            .route("/write", post(|| async { StatusCode::OK }))
+            .route_layer(axum::middleware::from_fn_with_state(maintenance.clone(), reject_writes));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/write", addr);
+
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "300");
+
+        maintenance.set(false);
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+}
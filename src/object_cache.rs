@@ -0,0 +1,197 @@
+// hyrule-node/src/object_cache.rs
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Objects larger than this share of the cache are never kept, so one big
+/// blob can't flush every hot object
+const MAX_ENTRY_FRACTION: usize = 8;
+
+type Key = (String, String);
+
+#[derive(Default)]
+struct Lru {
+    /// Object bytes and the tick of their last use
+    entries: HashMap<Key, (Bytes, u64)>,
+    /// Last-use tick to key, oldest first
+    order: BTreeMap<u64, Key>,
+    used: usize,
+    tick: u64,
+}
+
+impl Lru {
+    fn remove(&mut self, key: &Key) {
+        if let Some((data, tick)) = self.entries.remove(key) {
+            self.order.remove(&tick);
+            self.used -= data.len();
+        }
+    }
+}
+
+/// Bounded LRU of decompressed objects, so popular objects aren't read
+/// and inflated from disk on every request
+pub struct ObjectCache {
+    capacity: usize,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Cache counters reported in `/status`
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectCacheStats {
+    pub capacity_bytes: usize,
+    pub used_bytes: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ObjectCache {
+    /// A cache holding up to `capacity` bytes; 0 disables it
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, repo_hash: &str, object_id: &str) -> Option<Bytes> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        let key = (repo_hash.to_string(), object_id.to_string());
+        lru.tick += 1;
+        let tick = lru.tick;
+
+        let Some((data, last_used)) = lru.entries.get_mut(&key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        let previous = std::mem::replace(last_used, tick);
+        let data = data.clone();
+        lru.order.remove(&previous);
+        lru.order.insert(tick, key);
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(data)
+    }
+
+    pub fn insert(&self, repo_hash: &str, object_id: &str, data: Bytes) {
+        if self.capacity == 0 || data.len() > self.capacity / MAX_ENTRY_FRACTION {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        let key = (repo_hash.to_string(), object_id.to_string());
+        lru.remove(&key);
+
+        while lru.used + data.len() > self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.remove(&oldest);
+        }
+
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.used += data.len();
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(key, (data, tick));
+    }
+
+    /// Drop an object whose stored copy was rewritten or removed
+    pub fn invalidate(&self, repo_hash: &str, object_id: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(&(repo_hash.to_string(), object_id.to_string()));
+    }
+
+    /// Drop every object of a repo that was deleted
+    pub fn invalidate_repo(&self, repo_hash: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        let keys: Vec<Key> = lru.entries.keys().filter(|(repo, _)| repo == repo_hash).cloned().collect();
+        for key in &keys {
+            lru.remove(key);
+        }
+    }
+
+    pub fn stats(&self) -> ObjectCacheStats {
+        let lru = self.lru.lock().unwrap();
+        ObjectCacheStats {
+            capacity_bytes: self.capacity,
+            used_bytes: lru.used,
+            entries: lru.entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPO: &str = "repo";
+
+    fn object(len: usize) -> Bytes {
+        Bytes::from(vec![0u8; len])
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = ObjectCache::new(80);
+        cache.insert(REPO, "a", object(10));
+        cache.insert(REPO, "b", object(10));
+        cache.insert(REPO, "c", object(10));
+
+        // Touch "a" so "b" is the oldest
+        assert!(cache.get(REPO, "a").is_some());
+        for id in ["d", "e", "f", "g", "h"] {
+            cache.insert(REPO, id, object(10));
+        }
+        cache.insert(REPO, "i", object(10));
+
+        assert!(cache.get(REPO, "b").is_none());
+        assert!(cache.get(REPO, "a").is_some());
+        assert!(cache.get(REPO, "c").is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.used_bytes, 80);
+        assert_eq!(stats.entries, 8);
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+    }
+
+    #[test]
+    fn test_oversized_and_invalidated_entries() {
+        let cache = ObjectCache::new(80);
+
+        cache.insert(REPO, "big", object(11));
+        assert!(cache.get(REPO, "big").is_none());
+
+        cache.insert(REPO, "a", object(10));
+        cache.insert("other", "a", object(10));
+        cache.invalidate(REPO, "a");
+        assert!(cache.get(REPO, "a").is_none());
+        assert!(cache.get("other", "a").is_some());
+
+        // Replacing an entry doesn't count it twice
+        cache.insert("other", "a", object(5));
+        assert_eq!(cache.stats().used_bytes, 5);
+
+        cache.insert("other", "b", object(5));
+        cache.insert(REPO, "c", object(5));
+        cache.invalidate_repo("other");
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().used_bytes, 5);
+
+        let disabled = ObjectCache::new(0);
+        disabled.insert(REPO, "a", object(0));
+        assert!(disabled.get(REPO, "a").is_none());
+    }
+}
@@ -0,0 +1,94 @@
+// hyrule-node/src/onion.rs
+use crate::proxy::ProxyConfig;
+use anyhow::Result;
+use futures::StreamExt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tor_cell::relaycell::msg::Connected;
+use tor_hsservice::config::OnionServiceConfigBuilder;
+use tor_hsservice::{HsNickname, RunningOnionService, StreamRequest};
+
+/// Nickname of the node's onion service. Arti keys the service identity by
+/// nickname in its keystore, so the .onion address survives restarts.
+const SERVICE_NICKNAME: &str = "hyrule-node";
+
+/// A running onion service forwarding to the node's HTTP listener
+pub struct OnionService {
+    pub address: String,
+    // Dropping the handle shuts the service down
+    _service: Arc<RunningOnionService>,
+}
+
+/// Publish the node's HTTP port as an onion service so peers can reach it over Tor
+pub fn launch(proxy: &ProxyConfig, listen_addr: SocketAddr) -> Result<OnionService> {
+    let tor_client = proxy
+        .get_tor_client()
+        .ok_or_else(|| anyhow::anyhow!("Tor client not initialized"))?;
+
+    let nickname = HsNickname::new(SERVICE_NICKNAME.to_string())?;
+    let config = OnionServiceConfigBuilder::default()
+        .nickname(nickname)
+        .build()?;
+
+    let (service, rend_requests) = tor_client.launch_onion_service(config)?;
+
+    let address = service
+        .onion_name()
+        .ok_or_else(|| anyhow::anyhow!("Onion service has no address"))?
+        .to_string();
+
+    let target = local_target(listen_addr);
+
+    tokio::spawn(async move {
+        let mut stream_requests = Box::pin(tor_hsservice::handle_rend_requests(rend_requests));
+
+        while let Some(request) = stream_requests.next().await {
+            tokio::spawn(async move {
+                if let Err(e) = forward(request, target).await {
+                    tracing::debug!("Onion stream closed with error: {}", e);
+                }
+            });
+        }
+
+        tracing::warn!("Onion service stopped accepting connections");
+    });
+
+    Ok(OnionService {
+        address,
+        _service: service,
+    })
+}
+
+/// Accept an incoming onion stream and pipe it to the local HTTP listener
+async fn forward(request: StreamRequest, target: SocketAddr) -> Result<()> {
+    let mut local = tokio::net::TcpStream::connect(target).await?;
+    let mut onion_stream = request.accept(Connected::new_empty()).await?;
+
+    tokio::io::copy_bidirectional(&mut onion_stream, &mut local).await?;
+    Ok(())
+}
+
+/// Address to dial for the local listener; wildcard binds go via loopback
+pub fn local_target(listen_addr: SocketAddr) -> SocketAddr {
+    let ip = match listen_addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    SocketAddr::new(ip, listen_addr.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_target() {
+        let target = |addr: &str| local_target(addr.parse().unwrap()).to_string();
+
+        assert_eq!(target("0.0.0.0:8080"), "127.0.0.1:8080");
+        assert_eq!(target("[::]:8080"), "[::1]:8080");
+        assert_eq!(target("10.0.0.5:8080"), "10.0.0.5:8080");
+        assert_eq!(target("127.0.0.1:9000"), "127.0.0.1:9000");
+    }
+}
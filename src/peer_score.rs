@@ -0,0 +1,238 @@
+// hyrule-node/src/peer_score.rs
+use crate::registration::PeerNode;
+use crate::storage::{write_atomic, GitStorage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Sidecar file in the storage root
+const SCORES_FILE: &str = "peer-scores.json";
+
+/// Consecutive failures before a peer is put on cooldown
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a failing peer is skipped
+const COOLDOWN_SECS: i64 = 600;
+
+/// Weight of the newest sample in the moving latency average
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// Latency assumed for peers we haven't timed yet, and the latency at which
+/// a peer's score is halved
+const REFERENCE_LATENCY_MS: f64 = 1000.0;
+
+/// Score multiplier for anchor nodes, which are expected to stay up
+const ANCHOR_BONUS: f64 = 1.5;
+
+/// What we've seen of one peer across replications and repairs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PeerRecord {
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    /// Moving average of request latency
+    latency_ms: Option<f64>,
+    /// Unix time until which the peer is skipped
+    cooldown_until: Option<i64>,
+}
+
+impl PeerRecord {
+    fn score(&self, is_anchor: bool) -> f64 {
+        // Laplace smoothing: an unknown peer starts at 0.5
+        let success_rate = (self.successes + 1) as f64 / (self.successes + self.failures + 2) as f64;
+        let latency = self.latency_ms.unwrap_or(REFERENCE_LATENCY_MS);
+        let score = success_rate / (1.0 + latency / REFERENCE_LATENCY_MS);
+
+        if is_anchor {
+            score * ANCHOR_BONUS
+        } else {
+            score
+        }
+    }
+
+    fn cooling_down(&self, now: i64) -> bool {
+        self.cooldown_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Persisted per-peer success rate and latency, used to try the most
+/// reliable peers first and to skip ones that keep failing
+pub struct PeerScores {
+    path: PathBuf,
+    peers: Mutex<HashMap<String, PeerRecord>>,
+}
+
+impl PeerScores {
+    /// Load the scoreboard; a missing or unreadable file starts empty
+    pub fn load(storage: &GitStorage) -> Self {
+        let path = storage.base_path().join(SCORES_FILE);
+        let peers = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            peers: Mutex::new(peers),
+        }
+    }
+
+    /// Persist the scoreboard
+    pub fn save(&self) -> Result<()> {
+        // Hold the lock across the write so saves land in order
+        let peers = self.peers.lock().unwrap();
+        Ok(write_atomic(&self.path, &serde_json::to_vec(&*peers)?)?)
+    }
+
+    pub fn record_success(&self, node_id: &str, latency: Duration) {
+        let mut peers = self.peers.lock().unwrap();
+        let record = peers.entry(node_id.to_string()).or_default();
+
+        let sample = latency.as_secs_f64() * 1000.0;
+        record.latency_ms = Some(match record.latency_ms {
+            Some(avg) => avg + LATENCY_SMOOTHING * (sample - avg),
+            None => sample,
+        });
+        record.successes += 1;
+        record.consecutive_failures = 0;
+        record.cooldown_until = None;
+    }
+
+    pub fn record_failure(&self, node_id: &str) {
+        self.record_failure_at(node_id, chrono::Utc::now().timestamp());
+    }
+
+    fn record_failure_at(&self, node_id: &str, now: i64) {
+        let mut peers = self.peers.lock().unwrap();
+        let record = peers.entry(node_id.to_string()).or_default();
+
+        record.failures += 1;
+        record.consecutive_failures += 1;
+        if record.consecutive_failures >= FAILURE_THRESHOLD {
+            record.cooldown_until = Some(now + COOLDOWN_SECS);
+        }
+    }
+
+    /// Order peers best-first, dropping ones on cooldown. If every peer is
+    /// cooling down they are all returned anyway, since trying a flaky
+    /// peer beats not trying at all.
+    pub fn rank(&self, peers: Vec<PeerNode>) -> Vec<PeerNode> {
+        self.rank_at(peers, chrono::Utc::now().timestamp())
+    }
+
+    fn rank_at(&self, peers: Vec<PeerNode>, now: i64) -> Vec<PeerNode> {
+        let records = self.peers.lock().unwrap();
+        let unknown = PeerRecord::default();
+
+        let mut scored: Vec<(f64, bool, PeerNode)> = peers
+            .into_iter()
+            .map(|peer| {
+                let record = records.get(&peer.node_id).unwrap_or(&unknown);
+                (record.score(peer.is_anchor != 0), record.cooling_down(now), peer)
+            })
+            .collect();
+
+        if scored.iter().any(|(_, cooling, _)| !cooling) {
+            scored.retain(|(_, cooling, _)| !cooling);
+        }
+
+        // Stable, so equal scores keep the coordinator's order
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().map(|(_, _, peer)| peer).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(node_id: &str, is_anchor: bool) -> PeerNode {
+        PeerNode {
+            node_id: node_id.to_string(),
+            address: "127.0.0.1".to_string(),
+            port: 8080,
+            is_anchor: is_anchor as i64,
+            last_seen: String::new(),
+            tls: false,
+        }
+    }
+
+    fn ids(peers: &[PeerNode]) -> Vec<&str> {
+        peers.iter().map(|p| p.node_id.as_str()).collect()
+    }
+
+    fn scores() -> (tempfile::TempDir, PeerScores) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let scores = PeerScores::load(&storage);
+        (dir, scores)
+    }
+
+    #[test]
+    fn test_ranks_reliable_fast_and_anchor_peers_first() {
+        let (_dir, scores) = scores();
+
+        scores.record_success("fast", Duration::from_millis(50));
+        scores.record_success("slow", Duration::from_millis(3000));
+        scores.record_success("flaky", Duration::from_millis(50));
+        scores.record_failure("flaky");
+        scores.record_failure("flaky");
+
+        let ranked = scores.rank(vec![
+            peer("flaky", false),
+            peer("slow", false),
+            peer("new", false),
+            peer("fast", false),
+        ]);
+        assert_eq!(ids(&ranked), vec!["fast", "flaky", "new", "slow"]);
+
+        // Untimed anchors beat untimed regular peers
+        let ranked = scores.rank(vec![peer("new", false), peer("anchor", true)]);
+        assert_eq!(ids(&ranked), vec!["anchor", "new"]);
+    }
+
+    #[test]
+    fn test_failing_peer_cools_down() {
+        let (_dir, scores) = scores();
+        let now = 1_000_000;
+
+        for _ in 0..FAILURE_THRESHOLD {
+            scores.record_failure_at("bad", now);
+        }
+
+        let ranked = scores.rank_at(vec![peer("bad", true), peer("good", false)], now);
+        assert_eq!(ids(&ranked), vec!["good"]);
+
+        // Still used when it's the only option
+        let ranked = scores.rank_at(vec![peer("bad", true)], now);
+        assert_eq!(ids(&ranked), vec!["bad"]);
+
+        // Eligible again once the cooldown expires
+        let ranked = scores.rank_at(vec![peer("bad", true), peer("good", false)], now + COOLDOWN_SECS);
+        assert_eq!(ids(&ranked), vec!["good", "bad"]);
+
+        // A success clears it
+        scores.record_success("bad", Duration::from_millis(10));
+        let ranked = scores.rank_at(vec![peer("bad", true), peer("good", false)], now);
+        assert_eq!(ids(&ranked), vec!["bad", "good"]);
+    }
+
+    #[test]
+    fn test_scores_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+
+        let scores = PeerScores::load(&storage);
+        scores.record_success("fast", Duration::from_millis(10));
+        scores.record_failure("slow");
+        scores.save().unwrap();
+
+        let reloaded = PeerScores::load(&storage);
+        let ranked = reloaded.rank(vec![peer("slow", false), peer("fast", false)]);
+        assert_eq!(ids(&ranked), vec!["fast", "slow"]);
+    }
+}
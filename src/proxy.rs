@@ -1,58 +1,140 @@
 // src/proxy.rs
 
-use arti_client::{TorClient, TorClientConfig};
-use arti_hyper::ArtiHttpConnector;
+use arti_client::TorClient;
+use arti_client::config::TorClientConfigBuilder;
 use tor_rtcompat::tokio::TokioNativeTlsRuntime;
-use tls_api::{TlsConnector as TlsConnectorTrait, TlsConnectorBuilder}; // Added Builder trait
-use tls_api_native_tls::TlsConnector;
 use anyhow::Result;
-use std::sync::Arc;
-use hyper::{Client as HyperClient, Body};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::watch;
 
 // Import our new wrapper
-use crate::http_client::HyruleClient;
+use crate::config::TorMode;
+use crate::http_client::{CircuitHealth, ClientIdentity, HyruleClient};
 
-// We keep the raw type alias for internal use if needed
-type InnerHttpClient = HyperClient<ArtiHttpConnector<TokioNativeTlsRuntime, TlsConnector>, Body>;
+/// How far the Tor client has got. Shared by every clone of a
+/// `ProxyConfig`, so handlers see a client bootstrapped in the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TorState {
+    Disabled,
+    /// Bootstrapping, or waiting to retry
+    Connecting,
+    /// Bootstrapped, but no test connection has succeeded yet
+    Bootstrapped,
+    /// A test connection through Tor succeeded
+    Connected,
+    /// Up, but requests keep failing even on fresh circuits
+    Failing,
+}
+
+/// Onion service used to check that Tor can reach hidden services
+const TEST_ONION: (&str, u16) = ("hyrule4e3tu7pfdkvvca43senvgvgisi6einpe3d3kpidlk3uyjf7lqd.onion", 80);
 
 #[derive(Clone)]
 pub struct ProxyConfig {
     pub enabled: bool,
+    pub mode: TorMode,
+    /// SOCKS5 address of the Tor daemon in `TorMode::Socks`
     pub addr: String,
-    tor_client: Option<Arc<TorClient<TokioNativeTlsRuntime>>>,
+    /// Arti state/cache directory; holds the onion service keystore
+    pub state_dir: PathBuf,
+    /// Default timeout for requests made through the built client
+    pub request_timeout: Duration,
+    /// Identity headers for outbound requests, also used without Tor
+    pub identity: ClientIdentity,
+    tor_client: Arc<OnceLock<Arc<TorClient<TokioNativeTlsRuntime>>>>,
+    tor_state: Arc<watch::Sender<TorState>>,
+    circuits: Arc<CircuitHealth>,
 }
 
 impl ProxyConfig {
     pub fn from_config(config: &crate::config::NodeConfig) -> Self {
         Self {
             enabled: config.enable_proxy,
+            mode: config.tor_mode,
             addr: if config.proxy_addr.is_empty() {
                 "127.0.0.1:9050".to_string()
             } else {
                 config.proxy_addr.clone()
             },
-            tor_client: None,
+            state_dir: config.tor_state_dir(),
+            request_timeout: Duration::from_secs(config.peer_request_timeout_secs),
+            identity: ClientIdentity::from_config(config),
+            tor_client: Arc::new(OnceLock::new()),
+            tor_state: Arc::new(watch::Sender::new(if config.enable_proxy {
+                TorState::Connecting
+            } else {
+                TorState::Disabled
+            })),
+            circuits: Arc::default(),
         }
     }
     
-pub async fn init_tor_client(&mut self) -> Result<()> {
-    if !self.enabled {
+    pub fn tor_state(&self) -> TorState {
+        match *self.tor_state.borrow() {
+            TorState::Bootstrapped | TorState::Connected if self.circuits.is_failing() => TorState::Failing,
+            state => state,
+        }
+    }
+    
+    /// Wait up to `timeout` for the Tor client to finish bootstrapping.
+    /// Returns whether it did.
+    pub async fn wait_for_tor(&self, timeout: Duration) -> bool {
+        let mut state = self.tor_state.subscribe();
+        let ready = tokio::time::timeout(timeout, state.wait_for(|s| *s != TorState::Connecting)).await;
+        matches!(ready, Ok(Ok(_)))
+    }
+    
+pub async fn init_tor_client(&self) -> Result<()> {
+    if !self.enabled || self.tor_client.get().is_some() {
         return Ok(());
     }
+    if self.mode == TorMode::Socks {
+        return self.check_socks_proxy().await;
+    }
     tracing::info!("🧅 Bootstrapping Arti Tor client...");
     
-    let config = TorClientConfig::default();
+    let config = TorClientConfigBuilder::from_directories(
+        self.state_dir.join("state"),
+        self.state_dir.join("cache"),
+    )
+    .build()?;
     let runtime = TokioNativeTlsRuntime::current()?;
     let tor_client = TorClient::with_runtime(runtime)
         .config(config)
         .create_bootstrapped()
         .await?;
     tracing::info!("✓ Arti Tor client bootstrapped successfully");
-    self.tor_client = Some(Arc::new(tor_client));
+    if self.tor_client.set(Arc::new(tor_client)).is_ok() {
+        self.tor_state.send_replace(TorState::Bootstrapped);
+    }
     Ok(())
 }
+    /// Nothing to bootstrap in SOCKS mode; just make sure the daemon is
+    /// listening
+    async fn check_socks_proxy(&self) -> Result<()> {
+        let connect = tokio::net::TcpStream::connect(&self.addr);
+        match tokio::time::timeout(Duration::from_secs(10), connect).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => anyhow::bail!("Tor SOCKS proxy at {} is not reachable: {}", self.addr, e),
+            Err(_) => anyhow::bail!("Tor SOCKS proxy at {} did not answer", self.addr),
+        }
+        tracing::info!("✓ Using the Tor SOCKS proxy at {}", self.addr);
+        self.tor_state.send_if_modified(|state| {
+            let ready = *state == TorState::Connecting;
+            if ready {
+                *state = TorState::Bootstrapped;
+            }
+            ready
+        });
+        Ok(())
+    }
+    
     pub fn get_tor_client(&self) -> Option<Arc<TorClient<TokioNativeTlsRuntime>>> {
-        self.tor_client.clone()
+        self.tor_client.get().cloned()
     }
     
     // CHANGED: Return HyruleClient instead of generic Hyper Client
@@ -62,25 +144,26 @@ pub fn build_client(&self) -> Result<HyruleClient> {
         anyhow::bail!("Tor is disabled in config");
     }
     
-    if self.tor_client.is_none() {
-        anyhow::bail!("Tor client not initialized - call init_tor_client() first");
+    if self.mode == TorMode::Socks {
+        return Ok(HyruleClient::socks(&self.addr)?
+            .with_timeout(self.request_timeout)
+            .with_identity(self.identity.clone())
+            .with_circuit_health(self.circuits.clone()));
     }
+    
+    let Some(tor_client) = self.tor_client.get() else {
+        anyhow::bail!("Tor client not initialized - call init_tor_client() first");
+    };
 
     tracing::debug!("Building client with initialized Tor");
     
     // deref Arc and clone to get TorClient
-    let tor_client = (**self.tor_client.as_ref().unwrap()).clone();
+    let tor_client = (**tor_client).clone();
 
-    // Build TLS connector
-    let tls_conn = <TlsConnector as TlsConnectorTrait>::builder()?.build()?;
-
-    // Create connector
-    let connector = ArtiHttpConnector::new(tor_client, tls_conn);
-
-    // Build Hyper client
-    let inner_client = HyperClient::builder().build(connector);
-
-    Ok(HyruleClient::new(inner_client))
+    Ok(HyruleClient::arti(tor_client)?
+        .with_timeout(self.request_timeout)
+        .with_identity(self.identity.clone())
+        .with_circuit_health(self.circuits.clone()))
 }
     
     pub fn build_tor_client(&self) -> Result<HyruleClient> {
@@ -88,26 +171,65 @@ pub fn build_client(&self) -> Result<HyruleClient> {
     }
 
 pub async fn validate_tor_connection(&self) -> Result<()> {
-    if !self.enabled || self.tor_client.is_none() {
+    if !self.enabled {
         anyhow::bail!("Tor is not enabled");
     }
-    let tor_client = self.tor_client.as_ref().unwrap();
-    
-    // Create stream preferences that allow onion addresses
-    let mut prefs = arti_client::StreamPrefs::new();
-    prefs.connect_to_onion_services(arti_client::config::BoolOrAuto::Explicit(true));
-    
-    let test_addr = ("hyrule4e3tu7pfdkvvca43senvgvgisi6einpe3d3kpidlk3uyjf7lqd.onion", 80);
     
     // Increase timeout to 60 seconds for initial connection
-    match tokio::time::timeout(
-        std::time::Duration::from_secs(60), 
-        tor_client.connect_with_prefs(test_addr, &prefs)
-    ).await {
-        Ok(Ok(_)) => Ok(()),
-        Ok(Err(e)) => anyhow::bail!("Tor connection failed: {}", e),
-        Err(_) => anyhow::bail!("Tor connection timed out after 60s"),
+    let timeout = std::time::Duration::from_secs(60);
+    let connected = if self.mode == TorMode::Socks {
+        let (host, port) = TEST_ONION;
+        match tokio::time::timeout(timeout, crate::socks::connect(&self.addr, host, port)).await {
+            Ok(result) => result.map(drop).map_err(anyhow::Error::from),
+            Err(_) => anyhow::bail!("Tor connection timed out after 60s"),
+        }
+    } else {
+        let Some(tor_client) = self.tor_client.get() else {
+            anyhow::bail!("Tor is not enabled");
+        };
+        
+        // Create stream preferences that allow onion addresses
+        let mut prefs = arti_client::StreamPrefs::new();
+        prefs.connect_to_onion_services(arti_client::config::BoolOrAuto::Explicit(true));
+        
+        match tokio::time::timeout(timeout, tor_client.connect_with_prefs(TEST_ONION, &prefs)).await {
+            Ok(result) => result.map(drop).map_err(anyhow::Error::from),
+            Err(_) => anyhow::bail!("Tor connection timed out after 60s"),
+        }
+    };
+    
+    match connected {
+        Ok(()) => {
+            self.tor_state.send_replace(TorState::Connected);
+            Ok(())
+        }
+        Err(e) => anyhow::bail!("Tor connection failed: {}", e),
     }
 }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tor_state_is_shared_between_clones() {
+        let mut config = crate::config::NodeConfig::generate();
+        config.enable_proxy = false;
+        let proxy = ProxyConfig::from_config(&config);
+        assert_eq!(proxy.tor_state(), TorState::Disabled);
+        assert!(proxy.wait_for_tor(Duration::ZERO).await);
+
+        config.enable_proxy = true;
+        let proxy = ProxyConfig::from_config(&config);
+        let handle = proxy.clone();
+        assert_eq!(handle.tor_state(), TorState::Connecting);
+        assert!(!handle.wait_for_tor(Duration::from_millis(10)).await);
+
+        // Bootstrap finishing elsewhere wakes the waiter
+        let waiter = tokio::spawn(async move { handle.wait_for_tor(Duration::from_secs(5)).await });
+        proxy.tor_state.send_replace(TorState::Bootstrapped);
+        assert!(waiter.await.unwrap());
+    }
+}
@@ -0,0 +1,266 @@
+// hyrule-node/src/rate_limit.rs
+//
+// Per-client token buckets for the public port, so one client hammering
+// object reads can't starve the rest. Direct clients are keyed by IP.
+// Onion streams reach us through the local forwarder and all come from
+// loopback, so each of those connections gets its own anonymous key.
+
+use crate::auth::AuthConfig;
+use axum::{
+    extract::{
+        connect_info::{ConnectInfo, Connected},
+        Request, State,
+    },
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    serve::IncomingStream,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Clients tracked at most; beyond it the least recently seen is dropped.
+/// Every loopback connection is a client of its own, so this is reached
+/// easily over Tor.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+/// Who a request is charged to, fixed when the connection is accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Addr(IpAddr),
+    /// One loopback connection, normally a forwarded onion stream
+    Connection(u64),
+}
+
+impl ClientKey {
+    fn for_peer(addr: SocketAddr) -> Self {
+        if addr.ip().is_loopback() {
+            ClientKey::Connection(NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed))
+        } else {
+            ClientKey::Addr(addr.ip())
+        }
+    }
+}
+
+impl Connected<IncomingStream<'_, tokio::net::TcpListener>> for ClientKey {
+    fn connect_info(stream: IncomingStream<'_, tokio::net::TcpListener>) -> Self {
+        Self::for_peer(*stream.remote_addr())
+    }
+}
+
+/// For the TLS listener, which hands over the peer address directly
+impl Connected<SocketAddr> for ClientKey {
+    fn connect_info(addr: SocketAddr) -> Self {
+        Self::for_peer(addr)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Position in [`Buckets::by_use`]
+    used: u64,
+}
+
+/// Buckets plus the order they were last used in, so the stalest can be
+/// dropped without scanning them all
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<ClientKey, Bucket>,
+    by_use: BTreeMap<u64, ClientKey>,
+    next_use: u64,
+}
+
+pub struct RateLimiter {
+    per_sec: f64,
+    burst: f64,
+    auth: AuthConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// `None` when `rate_limit_per_sec` is 0
+    pub fn from_config(config: &crate::config::NodeConfig) -> Option<Self> {
+        (config.rate_limit_per_sec > 0).then(|| {
+            Self::new(
+                config.rate_limit_per_sec,
+                config.rate_limit_burst,
+                AuthConfig::from_config(config),
+            )
+        })
+    }
+
+    fn new(per_sec: u32, burst: u32, auth: AuthConfig) -> Self {
+        Self {
+            per_sec: per_sec as f64,
+            burst: burst as f64,
+            auth,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Take a token for `key`, or return how long until one is available
+    fn check_at(&self, key: ClientKey, now: Instant) -> Result<(), Duration> {
+        self.check_within(key, now, MAX_TRACKED_CLIENTS)
+    }
+
+    fn check_within(&self, key: ClientKey, now: Instant, max_clients: usize) -> Result<(), Duration> {
+        let mut guard = self.buckets.lock().unwrap();
+        let buckets = &mut *guard;
+
+        if buckets.by_key.len() >= max_clients && !buckets.by_key.contains_key(&key) {
+            if let Some((_, stalest)) = buckets.by_use.pop_first() {
+                buckets.by_key.remove(&stalest);
+            }
+        }
+
+        let used = buckets.next_use;
+        buckets.next_use += 1;
+        let bucket = buckets.by_key.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+            used,
+        });
+        buckets.by_use.remove(&bucket.used);
+        buckets.by_use.insert(used, key);
+        bucket.used = used;
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_sec))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_sec).min(self.burst)
+    }
+}
+
+/// Middleware answering 429 with `Retry-After` once a client runs out of
+/// tokens. Requests with the admin token, and in-process requests without
+/// connection info, are let through.
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(key)) = request.extensions().get::<ConnectInfo<ClientKey>>().copied() else {
+        return next.run(request).await;
+    };
+
+    if limiter.auth.is_authenticated(request.headers()) {
+        return next.run(request).await;
+    }
+
+    match limiter.check_at(key, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, (wait.as_secs_f64().ceil() as u64).max(1).to_string())],
+            "Rate limit exceeded",
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+
+    #[test]
+    fn test_bucket_refills_at_rate() {
+        let limiter = RateLimiter::new(2, 3, AuthConfig::default());
+        let client = ClientKey::Addr("203.0.113.7".parse().unwrap());
+        let other = ClientKey::Addr("203.0.113.8".parse().unwrap());
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at(client, start).unwrap();
+        }
+        let wait = limiter.check_at(client, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // Other clients have their own bucket
+        limiter.check_at(other, start).unwrap();
+
+        // Half a second buys one more request, not a fresh burst
+        let later = start + Duration::from_millis(500);
+        limiter.check_at(client, later).unwrap();
+        assert!(limiter.check_at(client, later).is_err());
+
+        // Idle time never banks more than the burst
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            limiter.check_at(client, much_later).unwrap();
+        }
+        assert!(limiter.check_at(client, much_later).is_err());
+    }
+
+    #[test]
+    fn test_tracked_clients_are_capped() {
+        let limiter = RateLimiter::new(1, 1, AuthConfig::default());
+        let client = |n: u64| ClientKey::Connection(n);
+        let now = Instant::now();
+
+        for n in 0..3 {
+            limiter.check_within(client(n), now, 3).unwrap();
+        }
+        // Client 0 is used again, so client 1 is the stalest
+        assert!(limiter.check_within(client(0), now, 3).is_err());
+        limiter.check_within(client(3), now, 3).unwrap();
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_key.len(), 3);
+        assert_eq!(buckets.by_use.len(), 3);
+        assert!(!buckets.by_key.contains_key(&client(1)));
+        assert!(buckets.by_key.contains_key(&client(0)));
+    }
+
+    #[tokio::test]
+    async fn test_limits_each_connection_and_exempts_admin() {
+        let auth = AuthConfig {
+            admin_token: Some("s3cret".to_string()),
+            peer_token: None,
+            require_auth_for_reads: false,
+        };
+        let limiter = Arc::new(RateLimiter::new(1, 2, auth));
+        let app = Router::new()
+            .route("/status", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(limiter, limit));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<ClientKey>())
+                .await
+                .unwrap()
+        });
+        let url = format!("http://{}/status", addr);
+
+        // One kept-alive connection, as an onion stream would be
+        let client = reqwest::Client::new();
+        for _ in 0..2 {
+            assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+        }
+        let r = client.get(&url).send().await.unwrap();
+        assert_eq!(r.status(), 429);
+        assert_eq!(r.headers()["retry-after"], "1");
+
+        let r = client.get(&url).bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(r.status(), 200);
+
+        // A new loopback connection is a new client
+        let fresh = reqwest::Client::new();
+        assert_eq!(fresh.get(&url).send().await.unwrap().status(), 200);
+    }
+}
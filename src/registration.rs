@@ -1,12 +1,18 @@
 // hyrule-node/src/registration.rs
 use crate::config::NodeConfig;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, UdpSocket};
 
 #[derive(Debug, Serialize)]
 struct RegisterNodeRequest {
     node_id: String,
+    /// Network the node joins; the coordinator only pairs it with nodes on
+    /// the same one
+    network_namespace: String,
     address: String,
     port: i32,
+    /// Whether peers must dial the node over HTTPS
+    tls: bool,
     storage_capacity: i64,
     is_anchor: bool,
 }
@@ -17,17 +23,25 @@ struct RegisterNodeResponse {
     message: String,
 }
 
-/// Register this node with the Hyrule server
-pub async fn register_node(config: &NodeConfig, proxy: &crate::proxy::ProxyConfig) -> anyhow::Result<()> {
-    let client = proxy.build_client()?;
+/// Register this node with the Hyrule server, advertising its onion
+/// address when it hosts one
+pub async fn register_node(
+    config: &NodeConfig,
+    proxy: &crate::proxy::ProxyConfig,
+    onion_address: Option<&str>,
+    storage_capacity: u64,
+) -> anyhow::Result<()> {
+    let address = advertised_address(config, onion_address, get_local_ip)?;
     
-    let address = config.public_address();
+    let client = proxy.build_client()?;
     
     let request = RegisterNodeRequest {
         node_id: config.node_id.clone(),
+        network_namespace: config.network_namespace.clone(),
         address,
         port: config.port as i32,
-        storage_capacity: config.storage_capacity as i64,
+        tls: config.tls_files().is_some(),
+        storage_capacity: storage_capacity as i64,
         is_anchor: config.is_anchor,
     };
     
@@ -50,11 +64,68 @@ pub async fn register_node(config: &NodeConfig, proxy: &crate::proxy::ProxyConfi
     Ok(())
 }
 
+/// Base URL peers use to reach this node
+pub fn advertised_url(config: &NodeConfig, onion_address: Option<&str>) -> anyhow::Result<String> {
+    let address = advertised_address(config, onion_address, get_local_ip)?;
+    Ok(node_url(&address, config.port.into(), config.tls_files().is_some()))
+}
+
+/// URL of a coordinator endpoint, scoped to the `namespace` network.
+/// `path` starts with `/` and may carry a query of its own.
+pub fn coordinator_url(server: &str, namespace: &str, path: &str) -> String {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    format!("{}{}{}network_namespace={}", server, path, separator, namespace)
+}
+
+/// Base URL of a node serving at `address:port`, over HTTPS if `tls`.
+/// Every URL of another node is built here.
+pub fn node_url(address: &str, port: i32, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    if address.contains(':') && !address.starts_with('[') {
+        format!("{}://[{}]:{}", scheme, address, port)
+    } else {
+        format!("{}://{}:{}", scheme, address, port)
+    }
+}
+
+/// Pick the address peers should dial: an explicit `advertised_address`
+/// wins, then the hosted onion service, and only with Tor disabled the
+/// local IP
+fn advertised_address(
+    config: &NodeConfig,
+    onion_address: Option<&str>,
+    local_ip: impl FnOnce() -> Option<IpAddr>,
+) -> anyhow::Result<String> {
+    let address = if let Some(addr) = config.advertised_address.as_deref() {
+        addr.trim().to_string()
+    } else if let Some(onion) = onion_address {
+        onion.to_string()
+    } else if !config.enable_proxy {
+        local_ip().map(|ip| ip.to_string()).unwrap_or_default()
+    } else {
+        anyhow::bail!("No reachable address: onion service is not running and no advertised_address is configured");
+    };
+    
+    if address.is_empty() {
+        anyhow::bail!("Advertised address is empty; set advertised_address in the config");
+    }
+    
+    Ok(address)
+}
+
+/// Best-effort local IP of the interface used for outbound traffic.
+/// Connecting a UDP socket sends no packets, it only picks a route.
+fn get_local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
 /// Discover peer nodes from the network
 pub async fn discover_peers(config: &NodeConfig, proxy: &crate::proxy::ProxyConfig) -> anyhow::Result<Vec<PeerNode>> {
     let client = proxy.build_client()?;
     
-    let url = format!("{}/api/nodes", config.hyrule_server);
+    let url = coordinator_url(&config.hyrule_server, &config.network_namespace, "/api/nodes");
     
     let response = client
         .get(&url)
@@ -70,11 +141,95 @@ pub async fn discover_peers(config: &NodeConfig, proxy: &crate::proxy::ProxyConf
     Ok(nodes)
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerNode {
     pub node_id: String,
     pub address: String,
     pub port: i32,
     pub is_anchor: i64,
     pub last_seen: String,
+    /// Serves HTTPS; coordinators that predate TLS leave it out
+    #[serde(default)]
+    pub tls: bool,
+}
+
+impl PeerNode {
+    /// Base URL to reach this peer at
+    pub fn url(&self) -> String {
+        node_url(&self.address, self.port, self.tls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn lan_ip() -> Option<IpAddr> {
+        Some("192.168.1.20".parse().unwrap())
+    }
+    
+    #[test]
+    fn test_explicit_address_wins() {
+        let mut config = NodeConfig::generate();
+        config.advertised_address = Some("node.example.org".to_string());
+        
+        let addr = advertised_address(&config, Some("abc.onion"), lan_ip).unwrap();
+        assert_eq!(addr, "node.example.org");
+    }
+    
+    #[test]
+    fn test_onion_address_used_with_tor() {
+        let config = NodeConfig::generate();
+        
+        let addr = advertised_address(&config, Some("abc.onion"), lan_ip).unwrap();
+        assert_eq!(addr, "abc.onion");
+        
+        // With Tor on, never leak the local IP
+        assert!(advertised_address(&config, None, lan_ip).is_err());
+    }
+    
+    #[test]
+    fn test_local_ip_only_without_tor() {
+        let mut config = NodeConfig::generate();
+        config.enable_proxy = false;
+        
+        let addr = advertised_address(&config, None, lan_ip).unwrap();
+        assert_eq!(addr, "192.168.1.20");
+        
+        assert!(advertised_address(&config, None, || None).is_err());
+    }
+    
+    #[test]
+    fn test_coordinator_urls_carry_the_network() {
+        assert_eq!(
+            coordinator_url("http://coord.onion", "lab", "/api/repos/abc/nodes"),
+            "http://coord.onion/api/repos/abc/nodes?network_namespace=lab"
+        );
+        assert_eq!(
+            coordinator_url("http://coord.onion", "main", "/api/repos?unhealthy=true"),
+            "http://coord.onion/api/repos?unhealthy=true&network_namespace=main"
+        );
+    }
+    
+    #[test]
+    fn test_node_urls_carry_the_scheme() {
+        assert_eq!(node_url("abc.onion", 8080, false), "http://abc.onion:8080");
+        assert_eq!(node_url("node.example.org", 443, true), "https://node.example.org:443");
+        assert_eq!(node_url("::1", 8080, false), "http://[::1]:8080");
+        
+        let mut config = NodeConfig::generate();
+        config.enable_proxy = false;
+        config.advertised_address = Some("node.example.org".to_string());
+        config.tls_cert_path = Some("/etc/hyrule/cert.pem".into());
+        config.tls_key_path = Some("/etc/hyrule/key.pem".into());
+        assert!(advertised_url(&config, None).unwrap().starts_with("https://"));
+    }
+    
+    #[test]
+    fn test_empty_address_rejected() {
+        let mut config = NodeConfig::generate();
+        config.advertised_address = Some("  ".to_string());
+        
+        assert!(advertised_address(&config, Some("abc.onion"), lan_ip).is_err());
+    }
 }
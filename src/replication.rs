@@ -1,35 +1,222 @@
-use crate::{registration, NodeState};
+use crate::jitter::JitteredInterval;
+use crate::peer_score::PeerScores;
+use crate::storage::{self, GitStorage};
+use crate::verify_index::VerifyIndex;
+use crate::http_client::is_timeout;
+use crate::{crypto, registration, NodeState};
 use anyhow::Context;
-use std::time::Duration;
-use tokio::time;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use bytes::Bytes;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Repositories replicated at the same time within one pass
+const REPLICATION_CONCURRENCY: usize = 3;
+
+/// Repos with a pull under way. Two pulls of one repo would share its
+/// spool directory and wipe each other's objects, so each pull claims the
+/// repo here first.
+#[derive(Debug, Default)]
+pub struct InFlight {
+    repos: std::sync::Mutex<HashSet<String>>,
+}
+
+impl InFlight {
+    /// Claim `repo_hash` until the guard drops; `None` if it's taken
+    pub fn claim(self: &Arc<Self>, repo_hash: &str) -> Option<InFlightGuard> {
+        if !self.repos.lock().unwrap().insert(repo_hash.to_string()) {
+            return None;
+        }
+        Some(InFlightGuard { set: self.clone(), repo_hash: repo_hash.to_string() })
+    }
+}
+
+pub struct InFlightGuard {
+    set: Arc<InFlight>,
+    repo_hash: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.set.repos.lock().unwrap().remove(&self.repo_hash);
+    }
+}
+
+/// Another task is already pulling the repo
+#[derive(Debug)]
+pub struct AlreadyReplicating;
+
+impl std::fmt::Display for AlreadyReplicating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Already being replicated")
+    }
+}
+
+impl std::error::Error for AlreadyReplicating {}
+
+fn is_already_replicating(e: &anyhow::Error) -> bool {
+    e.is::<AlreadyReplicating>()
+}
 
 /// Replication loop runs periodically and attempts to replicate unhealthy repos
 pub async fn replication_loop(state: NodeState) {
-    let mut interval = time::interval(Duration::from_secs(300)); // every 5 minutes
+    let mut interval = JitteredInterval::new(
+        &state.config.node_id,
+        "replication",
+        Duration::from_secs(state.config.replication_interval_secs),
+    );
+    // Repos left over from the previous pass
+    let mut queue: Vec<UnhealthyRepo> = Vec::new();
 
     loop {
         interval.tick().await;
+        crate::tasks::record_run();
 
-        if !state.config.auto_replicate {
+        if !state.config.auto_replicate || state.maintenance.is_enabled() {
             continue;
         }
 
-        if let Err(e) = check_and_replicate(&state).await {
-            tracing::warn!("Replication check failed: {}", e);
+        match check_and_replicate(&state, &mut queue).await {
+            Ok(()) => state.stats.write().await.last_replication_pass = Some(Instant::now()),
+            Err(e) => tracing::warn!("Replication check failed: {}", e),
         }
     }
 }
 
-async fn check_and_replicate(state: &NodeState) -> anyhow::Result<()> {
+/// A repository the coordinator reports as under-replicated
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct UnhealthyRepo {
+    pub repo_hash: String,
+    /// Number of nodes currently holding a replica
+    #[serde(default)]
+    pub replica_count: u32,
+    /// Coordinator-assigned priority, higher is more urgent
+    #[serde(default)]
+    pub priority: i32,
+    /// Repository size in bytes, if the coordinator included it
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Replicas wanted for this repo, overriding `target_replication_factor`
+    #[serde(default)]
+    pub target_replicas: Option<u32>,
+}
+
+impl UnhealthyRepo {
+    pub fn target(&self, default_target: u32) -> u32 {
+        self.target_replicas.unwrap_or(default_target)
+    }
+
+    /// Whether another replica would bring the repo closer to its target
+    pub fn below_target(&self, default_target: u32) -> bool {
+        self.replica_count < self.target(default_target)
+    }
+}
+
+/// How one repo in a replication pass ended
+enum PassResult {
+    Done(anyhow::Result<()>),
+    /// Other nodes brought it up to its target first
+    TargetMet(u32),
+    /// Not started; tried again first thing next pass
+    Deferred,
+}
+
+/// Older coordinators return bare repo hashes instead of detailed entries
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum UnhealthyEntry {
+    Hash(String),
+    Detailed(UnhealthyRepo),
+}
+
+impl From<UnhealthyEntry> for UnhealthyRepo {
+    fn from(entry: UnhealthyEntry) -> Self {
+        match entry {
+            UnhealthyEntry::Hash(repo_hash) => UnhealthyRepo {
+                repo_hash,
+                replica_count: 0,
+                priority: 0,
+                size: None,
+                target_replicas: None,
+            },
+            UnhealthyEntry::Detailed(repo) => repo,
+        }
+    }
+}
+
+/// Order the work queue so the most under-replicated repos come first,
+/// then by coordinator priority, then smallest first to fit more repos
+pub fn prioritize(repos: &mut [UnhealthyRepo]) {
+    repos.sort_by(|a, b| {
+        a.replica_count
+            .cmp(&b.replica_count)
+            .then(b.priority.cmp(&a.priority))
+            .then(a.size.unwrap_or(u64::MAX).cmp(&b.size.unwrap_or(u64::MAX)))
+    });
+}
+
+/// Running count of bytes committed during a single replication pass, so
+/// later repos in the pass can't overcommit space claimed by earlier ones
+#[derive(Debug)]
+pub struct SpaceReservation {
+    available: u64,
+    reserved: u64,
+}
+
+impl SpaceReservation {
+    pub fn new(available: u64) -> Self {
+        Self { available, reserved: 0 }
+    }
+    
+    pub fn remaining(&self) -> u64 {
+        self.available.saturating_sub(self.reserved)
+    }
+    
+    /// Reserve `size` bytes if they fit in what's left
+    pub fn try_reserve(&mut self, size: u64) -> bool {
+        if size > self.remaining() {
+            return false;
+        }
+        self.reserved += size;
+        true
+    }
+    
+    /// Give back a reservation for a replication that didn't complete
+    pub fn release(&mut self, size: u64) {
+        self.reserved = self.reserved.saturating_sub(size);
+    }
+}
+
+/// Reuse sizes looked up for repos carried over from the previous pass,
+/// so a long backlog doesn't re-query the coordinator every cycle
+fn carry_sizes(candidates: &mut [UnhealthyRepo], carried: &[UnhealthyRepo]) {
+    let known: HashMap<&str, u64> = carried
+        .iter()
+        .filter_map(|r| Some((r.repo_hash.as_str(), r.size?)))
+        .collect();
+
+    for repo in candidates.iter_mut().filter(|r| r.size.is_none()) {
+        repo.size = known.get(repo.repo_hash.as_str()).copied();
+    }
+}
+
+/// Whether an error was caused by the disk running out of space
+pub fn is_disk_full(err: &anyhow::Error) -> bool {
+    err.chain().any(crate::error::is_disk_full)
+}
+
+/// One replication pass: replicate up to `max_replications_per_cycle` of
+/// the most urgent repos, a few at a time, and leave the rest in `queue`
+/// for the next pass
+async fn check_and_replicate(state: &NodeState, queue: &mut Vec<UnhealthyRepo>) -> anyhow::Result<()> {
     // Use the initialized proxy from state instead of creating a new one
     let client = state.proxy.build_client()?;
 
     // get list of unhealthy repos from server
-    let url = format!("{}/api/repos?unhealthy=true", state.config.hyrule_server);
-    let response = client.get(&url).send().await?;
-    // get list of unhealthy repos from server
-    let url = format!("{}/api/repos?unhealthy=true", state.config.hyrule_server);
+    let url = registration::coordinator_url(&state.config.hyrule_server, &state.config.network_namespace, "/api/repos?unhealthy=true");
     let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
@@ -37,83 +224,172 @@ async fn check_and_replicate(state: &NodeState) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let unhealthy_repos: Vec<String> = response.json().await?;
+    let entries: Vec<UnhealthyEntry> = response.json().await?;
 
-    if unhealthy_repos.is_empty() {
+    // snapshot hosted repos
+    let hosted = state.hosted_repos.read().await.clone();
+
+    let default_target = state.config.target_replication_factor;
+    let mut candidates: Vec<UnhealthyRepo> = entries
+        .into_iter()
+        .map(UnhealthyRepo::from)
+        .filter(|repo| !hosted.contains(&repo.repo_hash))
+        .filter(|repo| state.config.replicates(&repo.repo_hash))
+        .filter(|repo| repo.below_target(default_target))
+        .collect();
+
+    carry_sizes(&mut candidates, queue);
+    queue.clear();
+
+    if candidates.is_empty() {
         return Ok(());
     }
 
+    // Fill in sizes the coordinator didn't include
+    for repo in candidates.iter_mut().filter(|r| r.size.is_none()) {
+        match get_repo_size(&state.config.hyrule_server, &state.config.network_namespace, &repo.repo_hash, &client).await {
+            Ok(size) => repo.size = Some(size),
+            Err(e) => {
+                tracing::warn!(repo = %repo.repo_hash, error = %e, "Failed to get repo size");
+            }
+        }
+    }
+
+    prioritize(&mut candidates);
+
+    let batch_len = candidates.len().min(state.config.max_replications_per_cycle);
+    *queue = candidates.split_off(batch_len);
+
     tracing::info!(
-        "Found {} repositories needing replication",
-        unhealthy_repos.len()
+        batch = candidates.len(),
+        queue_depth = queue.len(),
+        "Found repositories needing replication"
     );
 
-    // Get current storage usage and available space
-    let storage_used = state.storage.get_storage_usage()?;
-    let storage_available = state.config.storage_capacity.saturating_sub(storage_used);
+    // Get current storage usage and available space, bounded by what the
+    // disk actually has free in case the configured capacity is optimistic
+    let storage_used = state.storage.get_storage_usage_async().await?;
+    let storage_available = state
+        .capacity
+        .bytes()
+        .saturating_sub(storage_used)
+        .min(state.storage.available_disk_space()?);
 
-    // snapshot hosted repos
-    let hosted = state.hosted_repos.read().await.clone();
+    // Claim space in priority order before any replication starts, since
+    // they then run concurrently
+    let mut budget = SpaceReservation::new(storage_available);
+    let mut batch = Vec::new();
 
-    for repo_hash in unhealthy_repos {
-        if hosted.contains(&repo_hash) {
+    for repo in candidates {
+        let Some(size) = repo.size else { continue };
+
+        if !budget.try_reserve(size) {
+            tracing::warn!(repo = %repo.repo_hash, "Not enough space for repo");
             continue;
         }
 
-        match get_repo_size(&state.config.hyrule_server, &repo_hash, &client).await {
-            Ok(size) => {
-                if size > storage_available {
-                    tracing::warn!("Not enough space for repo {}", &repo_hash[..8]);
-                    continue;
+        // Re-check the disk itself; other writers may share the volume
+        let disk_free = state.storage.available_disk_space()?;
+        if size > disk_free {
+            tracing::warn!(
+                repo = %repo.repo_hash,
+                needed = size,
+                free = disk_free,
+                "Not enough free disk for repo"
+            );
+            budget.release(size);
+            continue;
+        }
+
+        batch.push(repo);
+    }
+
+    let disk_full = AtomicBool::new(false);
+    let results: Vec<(UnhealthyRepo, PassResult)> = futures::stream::iter(batch)
+        .map(|repo| {
+            let (client, disk_full) = (&client, &disk_full);
+            async move {
+                // Maintenance may be switched on mid-pass
+                if state.maintenance.is_enabled() || disk_full.load(Ordering::Relaxed) {
+                    return (repo, PassResult::Deferred);
                 }
 
-                match replicate_repo(state, &repo_hash, &client).await {
-                    Ok(_) => {
-                        tracing::info!("Successfully replicated {}", &repo_hash[..8]);
-
-                        // Update stats
-                        {
-                            let mut stats = state.stats.write().await;
-                            stats.replication_count += 1;
-                        }
-
-                        let _ = announce_replica(
-                            &state.config.hyrule_server,
-                            &state.config.node_id,
-                            &repo_hash,
-                            &client,
-                        )
-                        .await;
-                    }
-                    Err(e) => {
-                        tracing::warn!("Failed to replicate {}: {}", &repo_hash[..8], e);
+                // Other nodes replicate the same list, so the count may
+                // have caught up since it was fetched
+                let target = repo.target(default_target);
+                if let Ok(nodes) = get_repo_nodes(&state.config.hyrule_server, &state.config.network_namespace, &repo.repo_hash, client).await {
+                    if nodes.len() as u32 >= target {
+                        return (repo, PassResult::TargetMet(nodes.len() as u32));
                     }
                 }
+
+                let result = replicate_repo(state, &repo.repo_hash, client).await;
+                if result.as_ref().is_err_and(is_disk_full) {
+                    tracing::error!("Disk full, stopping replication pass");
+                    disk_full.store(true, Ordering::Relaxed);
+                }
+                (repo, PassResult::Done(result))
             }
-            Err(e) => {
-                tracing::warn!("Failed to get size for {}: {}", &repo_hash[..8], e);
+        })
+        .buffer_unordered(REPLICATION_CONCURRENCY)
+        .collect()
+        .await;
+
+    for (repo, result) in results {
+        let repo_hash = &repo.repo_hash;
+        match result {
+            PassResult::Done(Ok(())) => {
+                tracing::info!(repo = %repo_hash, "Successfully replicated");
+
+                // Update stats
+                {
+                    let mut stats = state.stats.write().await;
+                    stats.replication_count += 1;
+                }
+
+                let _ = announce_replica(
+                    &state.config.hyrule_server,
+                    &state.config.network_namespace,
+                    &state.config.node_id,
+                    repo_hash,
+                    &client,
+                )
+                .await;
+            }
+            PassResult::Done(Err(e)) if is_already_replicating(&e) => {
+                tracing::debug!(repo = %repo_hash, "Already being replicated");
             }
+            PassResult::Done(Err(e)) => {
+                tracing::warn!(repo = %repo_hash, error = %e, "Failed to replicate");
+            }
+            PassResult::TargetMet(replicas) => {
+                tracing::debug!(repo = %repo_hash, replicas, "Replication target already met");
+            }
+            PassResult::Deferred => queue.insert(0, repo),
         }
     }
 
     Ok(())
 }
 
-async fn announce_replica(
+pub async fn announce_replica(
     server: &str,
+    namespace: &str,
     node_id: &str,
     repo_hash: &str,
     client: &crate::http_client::HyruleClient,
 ) -> anyhow::Result<()> {
-    let url = format!("{}/api/repos/{}/replicate", server, repo_hash);
+    let url = registration::coordinator_url(server, namespace, &format!("/api/repos/{}/replicate", repo_hash));
 
     #[derive(serde::Serialize)]
     struct AnnounceRequest {
         node_id: String,
+        network_namespace: String,
     }
 
     let request = AnnounceRequest {
         node_id: node_id.to_string(),
+        network_namespace: namespace.to_string(),
     };
 
     let response = client.post(&url).json(&request).send().await?;
@@ -125,55 +401,331 @@ async fn announce_replica(
     Ok(())
 }
 
+/// Tell the coordinator this node no longer hosts a repo
+pub async fn withdraw_replica(
+    server: &str,
+    namespace: &str,
+    node_id: &str,
+    repo_hash: &str,
+    client: &crate::http_client::HyruleClient,
+) -> anyhow::Result<()> {
+    let url = registration::coordinator_url(server, namespace, &format!("/api/repos/{}/nodes/{}", repo_hash, node_id));
+    let response = client.delete(&url).send().await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to withdraw replica: {}", response.status());
+    }
+    Ok(())
+}
+
+/// Periodically reconcile the repos hosted here with the coordinator's
+/// record of them
+pub async fn coordinator_sync_loop(state: NodeState) {
+    let mut interval = JitteredInterval::new(
+        &state.config.node_id,
+        "coordinator sync",
+        Duration::from_secs(state.config.coordinator_sync_interval_secs),
+    );
+
+    loop {
+        interval.tick().await;
+        crate::tasks::record_run();
+
+        if let Err(e) = sync_with_coordinator(&state).await {
+            tracing::warn!("Coordinator sync failed: {}", e);
+        }
+    }
+}
+
+/// Repos the coordinator and this node disagree about
+#[derive(Debug, Default, PartialEq)]
+struct ServedDiff {
+    /// Expected by the coordinator but not stored here
+    missing: Vec<String>,
+    /// Stored here but not on the coordinator's record
+    unlisted: Vec<String>,
+}
+
+fn diff_served(expected: &[String], hosted: &[String]) -> ServedDiff {
+    let expected_set: HashSet<&String> = expected.iter().collect();
+    let hosted_set: HashSet<&String> = hosted.iter().collect();
+
+    let mut diff = ServedDiff {
+        missing: expected_set.difference(&hosted_set).map(|r| r.to_string()).collect(),
+        unlisted: hosted_set.difference(&expected_set).map(|r| r.to_string()).collect(),
+    };
+    diff.missing.sort();
+    diff.unlisted.sort();
+    diff
+}
+
+/// Re-replicate repos the coordinator expects but that are gone from
+/// here, withdrawing those that can't be, and announce hosted repos the
+/// coordinator doesn't know about
+async fn sync_with_coordinator(state: &NodeState) -> anyhow::Result<()> {
+    let client = state.proxy.build_client()?;
+    let server = &state.config.hyrule_server;
+    let namespace = &state.config.network_namespace;
+    let node_id = &state.config.node_id;
+
+    let expected = get_node_repos(server, namespace, node_id, &client).await?;
+    state.drop_missing_repos().await;
+    let hosted = state.hosted_repos.read().await.clone();
+
+    let diff = diff_served(&expected, &hosted);
+    if diff == ServedDiff::default() {
+        tracing::debug!(repos = hosted.len(), "Hosted repos match the coordinator's record");
+        state.stats.write().await.set_repos_missing(0, Instant::now());
+        return Ok(());
+    }
+    tracing::info!(
+        missing = ?diff.missing,
+        unlisted = ?diff.unlisted,
+        "Hosted repos differ from the coordinator's record"
+    );
+
+    for repo_hash in &diff.unlisted {
+        if let Err(e) = announce_replica(server, namespace, node_id, repo_hash, &client).await {
+            tracing::warn!(repo = %repo_hash, error = %e, "Failed to announce unlisted repo");
+        }
+    }
+
+    // Repos still assigned here that still aren't here
+    let mut replicated = 0;
+    let mut withdrawals = Vec::new();
+    for repo_hash in &diff.missing {
+        let can_replicate = state.config.auto_replicate
+            && !state.maintenance.is_enabled()
+            && state.config.replicates(repo_hash);
+        if !can_replicate {
+            withdrawals.push(repo_hash);
+            continue;
+        }
+
+        // A failed pull is retried next time; the coordinator keeps
+        // counting on us meanwhile
+        match replicate_repo(state, repo_hash, &client).await {
+            Ok(()) => {
+                tracing::info!(repo = %repo_hash, "Re-replicated repo missing from this node");
+                replicated += 1;
+            }
+            Err(e) if is_already_replicating(&e) => {
+                tracing::debug!(repo = %repo_hash, "Missing repo is already being replicated");
+            }
+            Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to re-replicate missing repo"),
+        }
+    }
+    // Withdrawn repos count as missing until the next sync confirms the
+    // coordinator no longer expects them
+    let still_missing = diff.missing.len() - replicated;
+    state.stats.write().await.set_repos_missing(still_missing, Instant::now());
+
+    for repo_hash in withdrawals {
+        match withdraw_replica(server, namespace, node_id, repo_hash, &client).await {
+            Ok(()) => tracing::info!(repo = %repo_hash, "Reported repo as no longer hosted here"),
+            Err(e) => tracing::warn!(repo = %repo_hash, error = %e, "Failed to report removed repo"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Repos the coordinator has this node down as hosting
+async fn get_node_repos(
+    server: &str,
+    namespace: &str,
+    node_id: &str,
+    client: &crate::http_client::HyruleClient,
+) -> anyhow::Result<Vec<String>> {
+    let url = registration::coordinator_url(server, namespace, &format!("/api/nodes/{}/repos", node_id));
+    let response = client.get(&url).send().await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to get node repos: {}", response.status());
+    }
+
+    response.json().await
+}
+
 async fn replicate_repo(
     state: &NodeState,
     repo_hash: &str,
     client: &crate::http_client::HyruleClient,
 ) -> anyhow::Result<()> {
-    tracing::info!("Starting replication of {}...", &repo_hash[..8]);
+    let Some(_claim) = state.replicating.claim(repo_hash) else {
+        return Err(AlreadyReplicating.into());
+    };
+    // A pull that held the claim a moment ago may have just finished it
+    if state.hosted_repos.read().await.iter().any(|r| r == repo_hash) {
+        return Ok(());
+    }
+    pull_repo(
+        &state.storage,
+        &state.config.spool_dir(),
+        &state.config.hyrule_server,
+        &state.config.network_namespace,
+        repo_hash,
+        client,
+        &state.peer_scores,
+    )
+    .await?;
 
-    let peers = get_repo_nodes(&state.config.hyrule_server, repo_hash, client).await?;
+    // Add to hosted repos
+    let mut repos = state.hosted_repos.write().await;
+    if !repos.contains(&repo_hash.to_string()) {
+        repos.push(repo_hash.to_string());
+
+        // Pulled for availability, so kept only while it's used
+        state.cached_repos.mark(repo_hash);
+        if let Err(e) = state.cached_repos.save() {
+            tracing::warn!(error = %e, "Failed to save cached replicas");
+        }
+    }
+    Ok(())
+}
+
+/// Objects transferred while copying a repo from a peer
+#[derive(Debug)]
+pub struct FetchReport {
+    pub peer_id: String,
+    pub fetched: usize,
+}
+
+/// Copy a repo from the best-scoring peer hosting it that can serve it.
+/// The copy is assembled in `spool_path` and only moved live once every
+/// object has arrived, so readers never see a partial replica and a peer
+/// failing midway leaves nothing behind.
+pub async fn pull_repo(
+    storage: &Arc<GitStorage>,
+    spool_path: &Path,
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    client: &crate::http_client::HyruleClient,
+    scores: &PeerScores,
+) -> anyhow::Result<FetchReport> {
+    tracing::info!(repo = %repo_hash, "Starting replication");
+
+    let peers = scores.rank(get_repo_nodes(server, namespace, repo_hash, client).await?);
 
     if peers.is_empty() {
         anyhow::bail!("No nodes hosting this repository");
     }
 
+    // Only a manifest that doesn't match fails the pull; a coordinator
+    // that can't say is no reason to leave the repo under-replicated
+    let expected = match get_expected_manifest(server, namespace, repo_hash, client).await {
+        Ok(None) => {
+            tracing::debug!(repo = %repo_hash, "Coordinator has no manifest, replica can't be checked against it");
+            None
+        }
+        Ok(expected) => expected,
+        Err(e) => {
+            tracing::warn!(repo = %repo_hash, error = %e, "Couldn't get the coordinator's manifest, replica won't be checked against it");
+            None
+        }
+    };
+
+    let spool = Arc::new(storage.spool(spool_path)?);
+
     // Try each peer until successful
+    let mut outcome = None;
     for peer in peers.iter() {
-        match fetch_repo_from_peer(state, repo_hash, peer, client).await {
-            Ok(_) => {
-                // Add to hosted repos
-                let mut repos = state.hosted_repos.write().await;
-                if !repos.contains(&repo_hash.to_string()) {
-                    repos.push(repo_hash.to_string());
-                }
-                return Ok(());
+        // Left over from an attempt that was interrupted
+        spool.delete_repo(repo_hash)?;
+
+        let started = Instant::now();
+        let fetched = match fetch_repo_from_peer(&spool, namespace, repo_hash, peer, client).await {
+            Ok(report) => check_manifest(&spool, repo_hash, expected.as_deref()).await.map(|()| report),
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok(report) => {
+                // Average time per request, list included
+                let requests = (report.fetched + 1) as u32;
+                scores.record_success(&peer.node_id, started.elapsed() / requests);
+                outcome = Some(Ok(report));
+                break;
             }
             Err(e) => {
                 tracing::warn!("Failed to fetch from peer {}: {}", &peer.node_id[..8], e);
-                continue;
+                spool.delete_repo(repo_hash)?;
+
+                // Running out of space is our problem, not the peer's
+                if is_disk_full(&e) {
+                    outcome = Some(Err(e.context("disk filled during replication")));
+                    break;
+                }
+                scores.record_failure(&peer.node_id);
             }
         }
     }
 
-    anyhow::bail!("Failed to replicate from all peers")
+    save_scores(scores);
+    let report = outcome.unwrap_or_else(|| Err(anyhow::anyhow!("Failed to replicate from all peers")))?;
+
+    if let Err(e) = storage.promote_async(&spool, repo_hash).await {
+        spool.delete_repo(repo_hash)?;
+        return Err(anyhow::Error::from(e).context("moving the replica out of the spool"));
+    }
+    Ok(report)
+}
+
+fn save_scores(scores: &PeerScores) {
+    if let Err(e) = scores.save() {
+        tracing::warn!(error = %e, "Failed to save peer scores");
+    }
 }
 
+/// Fetch every object a peer lists into the spool. Any object that can't
+/// be fetched fails the whole copy, since an incomplete replica is no use.
 async fn fetch_repo_from_peer(
-    state: &NodeState,
+    spool: &Arc<GitStorage>,
+    namespace: &str,
     repo_hash: &str,
     peer: &registration::PeerNode,
     client: &crate::http_client::HyruleClient,
-) -> anyhow::Result<()> {
-    let peer_url = format!("http://{}:{}", peer.address, peer.port);
+) -> anyhow::Result<FetchReport> {
+    let peer_url = peer.url();
+    check_peer_network(client, &peer_url, namespace).await?;
 
-    // Initialize repo locally
-    state.storage.init_repo(repo_hash)?;
+    spool.init_repo(repo_hash)?;
 
-    // Get list of objects from peer (JSON)
+    let objects = fetch_object_list(client, &peer_url, repo_hash).await?;
+
+    tracing::info!("Fetching {} objects from peer...", objects.len());
+
+    for object_id in &objects {
+        let data = fetch_object(client, &peer_url, repo_hash, object_id)
+            .await
+            .with_context(|| format!("fetching object {}", object_id))?;
+        spool.store_object_batched_async(repo_hash, object_id, data.to_vec()).await?;
+    }
+    spool.sync_pending_async().await?;
+
+    // Everything listed must be on disk before the copy can go live
+    let stored: HashSet<String> = spool.list_objects_async(repo_hash).await?.into_iter().collect();
+    if let Some(missing) = objects.iter().find(|id| !stored.contains(*id)) {
+        anyhow::bail!("object {} missing after fetch", missing);
+    }
+
+    tracing::info!("Completed replication from peer {}", &peer.node_id[..8]);
+    Ok(FetchReport {
+        peer_id: peer.node_id.clone(),
+        fetched: objects.len(),
+    })
+}
+
+/// Ids of every object a peer stores for a repo
+async fn fetch_object_list(
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    repo_hash: &str,
+) -> anyhow::Result<Vec<String>> {
     let objects_url = format!("{}/repos/{}/objects", peer_url, repo_hash);
-    let response = client.get(&objects_url).send().await?;
+    let response = client.get(&objects_url).peer_auth().send().await?;
 
+    check_peer_auth(response.status())?;
     if !response.status().is_success() {
         anyhow::bail!("Failed to get object list: {}", response.status());
     }
@@ -184,51 +736,571 @@ async fn fetch_repo_from_peer(
     }
 
     let obj_list: ObjectList = response.json().await?;
+    // Ids end up in URLs, paths and logs, so a bad one spoils the list
+    if let Some(bad) = obj_list.objects.iter().find(|id| !storage::is_object_id(id)) {
+        anyhow::bail!("Peer listed an invalid object id {:?}", bad);
+    }
+    Ok(obj_list.objects)
+}
 
-    tracing::info!("Fetching {} objects from peer...", obj_list.objects.len());
-
-    // We'll use a plain reqwest::Client to fetch raw object bytes.
-    // (Reason: your HyruleResponse wrapper does not expose `.bytes()`.)
-    // This bypasses any special behavior HyruleClient applies (tor/proxy). If you need
-    // Tor/proxy for object downloads, we can add a `get_raw_bytes` helper on HyruleClient.
-    let raw_client = reqwest::Client::new();
-
-    for object_id in obj_list.objects {
-        let obj_url = format!("{}/repos/{}/objects/{}", peer_url, repo_hash, object_id);
-
-        match raw_client.get(&obj_url).send().await {
-            Ok(resp) if resp.status().is_success() => {
-                let data: Bytes = resp
-                    .bytes()
-                    .await
-                    .context("reading object bytes from peer")?;
-                state
-                    .storage
-                    .store_object(repo_hash, &object_id, data.as_ref())?;
-            }
-            Ok(resp) => {
-                tracing::warn!(
-                    "Failed to fetch object {}: {}",
-                    &object_id[..8],
-                    resp.status()
-                );
+/// Fetch the raw bytes of a single object from a peer node. Goes through
+/// Tor so peers advertising a .onion address are reachable.
+async fn fetch_object(
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    repo_hash: &str,
+    object_id: &str,
+) -> anyhow::Result<Bytes> {
+    let obj_url = format!("{}/repos/{}/objects/{}", peer_url, repo_hash, object_id);
+    let resp = client.get(&obj_url).peer_auth().send().await?;
+
+    check_peer_auth(resp.status())?;
+    if !resp.status().is_success() {
+        return Err(PeerAnswered(resp.status()).into());
+    }
+
+    resp.bytes().await.context("reading object bytes from peer")
+}
+
+/// Fail with [`HyruleError::Auth`] if a peer refused our `peer_token`, or
+/// wants one and none is configured
+fn check_peer_auth(status: hyper::StatusCode) -> crate::error::Result<()> {
+    if status == hyper::StatusCode::UNAUTHORIZED || status == hyper::StatusCode::FORBIDDEN {
+        crate::error::bail!(Auth, "Peer refused our credentials ({}); check peer_token", status);
+    }
+    Ok(())
+}
+
+fn is_auth_error(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<crate::error::HyruleError>(), Some(crate::error::HyruleError::Auth(_)))
+}
+
+/// A peer answered a request with an error status, e.g. 404 for an
+/// object it doesn't have. It's reachable, so this isn't held against it.
+#[derive(Debug)]
+struct PeerAnswered(hyper::StatusCode);
+
+impl std::fmt::Display for PeerAnswered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "peer returned {}", self.0)
+    }
+}
+
+impl std::error::Error for PeerAnswered {}
+
+/// Fail unless the peer at `peer_url` is on the `namespace` network. Its
+/// `/capabilities` says which; peers from before namespaces are on the
+/// default one.
+async fn check_peer_network(
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    namespace: &str,
+) -> anyhow::Result<()> {
+    let url = format!("{}/capabilities", peer_url);
+    let response = client.get(&url).peer_auth().send().await?;
+    check_peer_auth(response.status())?;
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to get peer capabilities: {}", response.status());
+    }
+
+    #[derive(serde::Deserialize)]
+    struct PeerNetwork {
+        #[serde(default = "crate::config::default_network_namespace")]
+        network_namespace: String,
+    }
+
+    let peer: PeerNetwork = response.json().await?;
+    if peer.network_namespace != namespace {
+        anyhow::bail!("peer is on network {:?}, not {:?}", peer.network_namespace, namespace);
+    }
+    Ok(())
+}
+
+/// Objects of a repo that need repair: local copies failing verification,
+/// plus objects the best reachable peer has that are missing here
+pub async fn find_damaged_objects(
+    storage: &Arc<GitStorage>,
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    client: &crate::http_client::HyruleClient,
+    scores: &PeerScores,
+) -> anyhow::Result<Vec<String>> {
+    let local = storage.list_objects_async(repo_hash).await?;
+
+    let mut index = VerifyIndex::load(storage, repo_hash);
+    let mut damaged: Vec<String> = local
+        .iter()
+        .filter(|id| !index.verify(storage, repo_hash, id, true).unwrap_or(false))
+        .cloned()
+        .collect();
+    if let Err(e) = index.save() {
+        tracing::warn!(repo = %repo_hash, error = %e, "Failed to save verification index");
+    }
+
+    let local: HashSet<String> = local.into_iter().collect();
+    for peer in scores.rank(get_repo_nodes(server, namespace, repo_hash, client).await?) {
+        let peer_url = peer.url();
+        if let Err(e) = check_peer_network(client, &peer_url, namespace).await {
+            tracing::debug!("Skipping peer {}: {}", &peer.node_id[..8], e);
+            continue;
+        }
+        match fetch_object_list(client, &peer_url, repo_hash).await {
+            Ok(objects) => {
+                damaged.extend(objects.into_iter().filter(|id| !local.contains(id)));
+                break;
             }
+            Err(e) => tracing::debug!("Peer {} has no object list: {}", &peer.node_id[..8], e),
+        }
+    }
+
+    Ok(damaged)
+}
+
+/// Outcome of [`check_complete`]
+#[derive(Debug)]
+pub struct CompletenessReport {
+    /// Where the list of expected objects came from
+    pub source: String,
+    /// Objects the repo should hold
+    pub expected: usize,
+    /// Expected objects not stored here, sorted
+    pub missing: Vec<String>,
+}
+
+/// Whether this node holds every object of a repo. Unlike verification,
+/// which only checks the objects that are here, this compares against the
+/// coordinator's manifest hash and the object list of the best-scoring
+/// peer whose list matches it (any peer's, if the coordinator has none).
+pub async fn check_complete(
+    storage: &Arc<GitStorage>,
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    client: &crate::http_client::HyruleClient,
+    scores: &PeerScores,
+) -> anyhow::Result<CompletenessReport> {
+    let local = storage.list_objects_async(repo_hash).await?;
+    let expected = get_expected_manifest(server, namespace, repo_hash, client).await?;
+
+    if expected
+        .as_deref()
+        .is_some_and(|hash| storage::manifest_of(local.clone()).eq_ignore_ascii_case(hash))
+    {
+        return Ok(CompletenessReport {
+            source: "coordinator manifest".to_string(),
+            expected: local.len(),
+            missing: Vec::new(),
+        });
+    }
+
+    for peer in scores.rank(get_repo_nodes(server, namespace, repo_hash, client).await?) {
+        let peer_url = peer.url();
+        if let Err(e) = check_peer_network(client, &peer_url, namespace).await {
+            tracing::debug!("Skipping peer {}: {}", &peer.node_id[..8], e);
+            continue;
+        }
+        let objects = match fetch_object_list(client, &peer_url, repo_hash).await {
+            Ok(objects) => objects,
             Err(e) => {
-                tracing::warn!("Error fetching object {}: {}", &object_id[..8], e);
+                tracing::debug!("Peer {} has no object list: {}", &peer.node_id[..8], e);
+                continue;
+            }
+        };
+        if let Some(hash) = &expected {
+            if !storage::manifest_of(objects.clone()).eq_ignore_ascii_case(hash) {
+                tracing::debug!("Peer {} lists a different set of objects than the coordinator", &peer.node_id[..8]);
+                continue;
             }
         }
+
+        return Ok(CompletenessReport {
+            source: format!("peer {}", &peer.node_id[..8]),
+            expected: objects.len(),
+            missing: missing_objects(&objects, &local),
+        });
     }
 
-    tracing::info!("Completed replication from peer {}", &peer.node_id[..8]);
+    match expected {
+        Some(_) => anyhow::bail!("No peer has an object list matching the coordinator's manifest"),
+        None => anyhow::bail!("No peer could supply an object list"),
+    }
+}
+
+/// Ids in `expected` that aren't in `local`, sorted
+fn missing_objects(expected: &[String], local: &[String]) -> Vec<String> {
+    let local: HashSet<&String> = local.iter().collect();
+    let mut missing: Vec<String> = expected.iter().filter(|id| !local.contains(id)).cloned().collect();
+    missing.sort();
+    missing.dedup();
+    missing
+}
+
+/// Re-fetch specific objects (e.g. ones that failed verification or are
+/// missing) from peers hosting the repository, best-scoring first,
+/// replacing the local copies. Peer data is only stored once it hashes to
+/// the object id. Returns the ids that were successfully repaired.
+pub async fn repair_objects(
+    storage: &Arc<GitStorage>,
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    object_ids: &[String],
+    client: &crate::http_client::HyruleClient,
+    scores: &PeerScores,
+) -> anyhow::Result<Vec<String>> {
+    let peers = scores.rank(get_repo_nodes(server, namespace, repo_hash, client).await?);
+
+    if peers.is_empty() {
+        anyhow::bail!("No nodes hosting this repository");
+    }
+
+    let mut repaired = Vec::new();
+    // Peers that timed out or are on another network are not asked for
+    // the remaining objects
+    let mut unresponsive = HashSet::new();
+    let mut checked = HashSet::new();
+
+    for object_id in object_ids {
+        for peer in &peers {
+            if unresponsive.contains(&peer.node_id) {
+                continue;
+            }
+            let peer_url = peer.url();
+            if checked.insert(peer.node_id.clone()) {
+                if let Err(e) = check_peer_network(client, &peer_url, namespace).await {
+                    tracing::debug!("Skipping peer {}: {}", &peer.node_id[..8], e);
+                    unresponsive.insert(peer.node_id.clone());
+                    continue;
+                }
+            }
+
+            let started = Instant::now();
+            let data = match fetch_object(client, &peer_url, repo_hash, object_id).await {
+                Ok(data) if crypto::git_object_id(&data) == *object_id => {
+                    scores.record_success(&peer.node_id, started.elapsed());
+                    data
+                }
+                Ok(_) => {
+                    tracing::warn!("Peer {} sent a bad copy of {}", &peer.node_id[..8], object_id);
+                    scores.record_failure(&peer.node_id);
+                    continue;
+                }
+                // Nothing more will get through to a peer refusing our token
+                Err(e) if is_auth_error(&e) => {
+                    tracing::warn!("Peer {}: {}", &peer.node_id[..8], e);
+                    unresponsive.insert(peer.node_id.clone());
+                    continue;
+                }
+                // Peers may hold only part of a repo
+                Err(e) if e.is::<PeerAnswered>() => {
+                    tracing::debug!("Peer {} doesn't have {}: {}", &peer.node_id[..8], object_id, e);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::debug!("Peer {} couldn't supply {}: {}", &peer.node_id[..8], object_id, e);
+                    scores.record_failure(&peer.node_id);
+                    if is_timeout(&e) {
+                        unresponsive.insert(peer.node_id.clone());
+                    }
+                    continue;
+                }
+            };
+
+            storage.store_object_batched_async(repo_hash, object_id, data.to_vec()).await?;
+
+            if storage.verify_object(repo_hash, object_id).unwrap_or(false) {
+                repaired.push(object_id.clone());
+                break;
+            }
+        }
+    }
+
+    save_scores(scores);
+    storage.sync_pending_async().await?;
+    Ok(repaired)
+}
+
+/// Objects sent per batch request when pushing to a peer
+const PUSH_BATCH_OBJECTS: usize = 128;
+
+/// Objects larger than this are pushed one at a time with a raw PUT. With
+/// [`PUSH_BATCH_OBJECTS`] this keeps a batch, base64 included, well under
+/// the default `max_batch_bytes`.
+const PUSH_RAW_THRESHOLD: usize = 256 * 1024;
+
+/// Batch requests in flight at once while pushing
+const PUSH_CONCURRENCY: usize = 4;
+
+/// Objects and refs sent by [`push_repo_to_peer`]
+#[derive(Debug)]
+pub struct PushReport {
+    pub peer_id: String,
+    /// Objects the peer didn't have
+    pub uploaded: usize,
+    /// Objects the peer already had
+    pub skipped: usize,
+    /// Refs created or moved on the peer
+    pub refs: usize,
+}
+
+/// Upload a repo to a peer: the objects it lacks, in concurrent batches
+/// (large objects one at a time), then every ref that differs. `peer` is a
+/// node id, a unique prefix of one, or `host:port`. Objects are checked
+/// against their ids before they're sent, refs only move once the peer
+/// lists every object, and each ref update is leased on the value the
+/// peer had, so a push racing the peer's own writes fails instead of
+/// overwriting them.
+pub async fn push_repo_to_peer(
+    storage: &Arc<GitStorage>,
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    peer: &str,
+    client: &crate::http_client::HyruleClient,
+) -> anyhow::Result<PushReport> {
+    let peer = resolve_peer(server, namespace, peer, client).await?;
+    let peer_url = peer.url();
+    check_peer_network(client, &peer_url, namespace).await?;
+
+    let local = storage.list_objects_async(repo_hash).await?;
+    if local.is_empty() {
+        anyhow::bail!("Repository {} has no objects here", &repo_hash[..16.min(repo_hash.len())]);
+    }
+
+    let remote = fetch_object_list(client, &peer_url, repo_hash).await?;
+    let missing = missing_objects(&local, &remote);
+    tracing::info!(repo = %repo_hash, peer = %peer.node_id, "Pushing {} of {} objects", missing.len(), local.len());
+
+    let results: Vec<anyhow::Result<()>> = futures::stream::iter(missing.chunks(PUSH_BATCH_OBJECTS))
+        .map(|ids| push_objects(storage, client, &peer_url, repo_hash, ids))
+        .buffer_unordered(PUSH_CONCURRENCY)
+        .collect()
+        .await;
+    results.into_iter().collect::<anyhow::Result<()>>()?;
+
+    // Refs must never point at objects the peer doesn't have
+    let stored = fetch_object_list(client, &peer_url, repo_hash).await?;
+    let absent = missing_objects(&local, &stored);
+    if let Some(first) = absent.first() {
+        anyhow::bail!("Peer is missing {} objects after the push, e.g. {}", absent.len(), first);
+    }
+
+    let refs = storage.list_refs(repo_hash)?;
+    let updates = ref_updates(&refs, &fetch_peer_refs(client, &peer_url, repo_hash).await?);
+    if !updates.is_empty() {
+        push_refs(client, &peer_url, repo_hash, &updates).await?;
+    }
+
+    Ok(PushReport {
+        peer_id: peer.node_id,
+        uploaded: missing.len(),
+        skipped: local.len() - missing.len(),
+        refs: updates.len(),
+    })
+}
+
+/// Find the peer a push names, via the coordinator unless it's an address
+async fn resolve_peer(
+    server: &str,
+    namespace: &str,
+    peer: &str,
+    client: &crate::http_client::HyruleClient,
+) -> anyhow::Result<registration::PeerNode> {
+    let (tls, address) = match peer.strip_prefix("https://") {
+        Some(rest) => (true, rest),
+        None => (false, peer.strip_prefix("http://").unwrap_or(peer)),
+    };
+    if let Some((address, port)) = address.trim_end_matches('/').rsplit_once(':') {
+        let port = port.parse().with_context(|| format!("Invalid port in {}", peer))?;
+        return Ok(registration::PeerNode {
+            node_id: peer.to_string(),
+            address: address.trim_matches(|c| c == '[' || c == ']').to_string(),
+            port,
+            is_anchor: 0,
+            last_seen: chrono::Utc::now().to_rfc3339(),
+            tls,
+        });
+    }
+
+    let url = registration::coordinator_url(server, namespace, "/api/nodes");
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to list peers: {}", response.status());
+    }
+    match_peer(response.json().await?, peer)
+}
+
+/// The one peer whose node id is `name` or starts with it
+fn match_peer(peers: Vec<registration::PeerNode>, name: &str) -> anyhow::Result<registration::PeerNode> {
+    if let Some(peer) = peers.iter().find(|p| p.node_id == name) {
+        return Ok(peer.clone());
+    }
+    let mut matches: Vec<_> = peers.into_iter().filter(|p| p.node_id.starts_with(name)).collect();
+    match matches.len() {
+        0 => anyhow::bail!("No peer {} known to the coordinator", name),
+        1 => Ok(matches.remove(0)),
+        n => anyhow::bail!("{} peers match {}, give more of the node id", n, name),
+    }
+}
+
+/// Send one chunk of objects: small ones in a single batch request, large
+/// ones with a raw PUT each
+async fn push_objects(
+    storage: &Arc<GitStorage>,
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    repo_hash: &str,
+    object_ids: &[String],
+) -> anyhow::Result<()> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    #[derive(serde::Serialize)]
+    struct BatchObject {
+        object_id: String,
+        data: String,
+    }
+
+    #[derive(serde::Serialize)]
+    struct BatchRequest {
+        objects: Vec<BatchObject>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct BatchResponse {
+        failed: Vec<serde_json::Value>,
+    }
+
+    let mut batch = Vec::new();
+    for object_id in object_ids {
+        let data = storage.read_object_async(repo_hash, object_id).await?;
+        if crypto::git_object_id(&data) != *object_id {
+            anyhow::bail!("Local copy of {} is corrupt, run `hyrule-node repair` first", object_id);
+        }
+
+        if data.len() > PUSH_RAW_THRESHOLD {
+            let url = format!("{}/repos/{}/objects/{}", peer_url, repo_hash, object_id);
+            let response = client.put(&url).body(data).peer_auth().send().await?;
+            if !response.status().is_success() {
+                anyhow::bail!("Peer refused object {}: {}", object_id, response.status());
+            }
+        } else {
+            batch.push(BatchObject {
+                object_id: object_id.clone(),
+                data: general_purpose::STANDARD.encode(&data),
+            });
+        }
+    }
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let url = format!("{}/repos/{}/objects/batch", peer_url, repo_hash);
+    let response = client.post(&url).json(&BatchRequest { objects: batch }).peer_auth().send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Peer refused batch: {}", response.status());
+    }
+    let result: BatchResponse = response.json().await?;
+    if let Some(failure) = result.failed.first() {
+        anyhow::bail!("Peer failed to store {} objects, e.g. {}", result.failed.len(), failure);
+    }
+    Ok(())
+}
+
+/// Branches and tags a peer has for a repo; none if it doesn't host it
+async fn fetch_peer_refs(
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    repo_hash: &str,
+) -> anyhow::Result<HashMap<String, String>> {
+    let url = format!("{}/repos/{}/refs", peer_url, repo_hash);
+    let response = client.get(&url).peer_auth().send().await?;
+
+    if response.status() == hyper::StatusCode::NOT_FOUND {
+        return Ok(HashMap::new());
+    }
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to get peer refs: {}", response.status());
+    }
+
+    #[derive(serde::Deserialize)]
+    struct RefEntry {
+        name: String,
+        commit_id: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct RefList {
+        refs: Vec<RefEntry>,
+    }
+
+    let list: RefList = response.json().await?;
+    Ok(list.refs.into_iter().map(|r| (r.name, r.commit_id)).collect())
+}
+
+/// Updates moving the peer's refs to ours, each leased on the peer's
+/// current value. HEAD isn't a ref the peer accepts updates to.
+fn ref_updates(local: &[(String, String)], remote: &HashMap<String, String>) -> Vec<storage::RefUpdate> {
+    local
+        .iter()
+        .filter(|(name, _)| name != "HEAD")
+        .filter(|(name, commit_id)| remote.get(name) != Some(commit_id))
+        .map(|(name, commit_id)| storage::RefUpdate {
+            ref_name: name.clone(),
+            commit_id: commit_id.clone(),
+            expected_old: Some(remote.get(name).cloned().unwrap_or_else(|| storage::ZERO_ID.to_string())),
+        })
+        .collect()
+}
+
+/// Apply ref updates on a peer in one atomic batch
+async fn push_refs(
+    client: &crate::http_client::HyruleClient,
+    peer_url: &str,
+    repo_hash: &str,
+    updates: &[storage::RefUpdate],
+) -> anyhow::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Update<'a> {
+        ref_name: &'a str,
+        commit_id: &'a str,
+        expected_old: Option<&'a str>,
+    }
+
+    #[derive(serde::Serialize)]
+    struct BatchRefRequest<'a> {
+        updates: Vec<Update<'a>>,
+    }
+
+    let request = BatchRefRequest {
+        updates: updates
+            .iter()
+            .map(|u| Update {
+                ref_name: &u.ref_name,
+                commit_id: &u.commit_id,
+                expected_old: u.expected_old.as_deref(),
+            })
+            .collect(),
+    };
+
+    let url = format!("{}/repos/{}/refs/batch", peer_url, repo_hash);
+    let response = client.post(&url).json(&request).peer_auth().send().await?;
+    if response.status() == hyper::StatusCode::CONFLICT {
+        anyhow::bail!("Refs on the peer changed during the push; nothing was updated, push again");
+    }
+    if !response.status().is_success() {
+        anyhow::bail!("Peer refused ref updates: {}", response.status());
+    }
     Ok(())
 }
 
 async fn get_repo_size(
     server: &str,
+    namespace: &str,
     repo_hash: &str,
     client: &crate::http_client::HyruleClient,
 ) -> anyhow::Result<u64> {
-    let url = format!("{}/api/repos/{}", server, repo_hash);
+    let url = registration::coordinator_url(server, namespace, &format!("/api/repos/{}", repo_hash));
     let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
@@ -244,12 +1316,53 @@ async fn get_repo_size(
     Ok(info.size as u64)
 }
 
+/// The coordinator's manifest hash for a repo (see
+/// [`GitStorage::manifest_hash`]), or None if it doesn't have one
+async fn get_expected_manifest(
+    server: &str,
+    namespace: &str,
+    repo_hash: &str,
+    client: &crate::http_client::HyruleClient,
+) -> anyhow::Result<Option<String>> {
+    let url = registration::coordinator_url(server, namespace, &format!("/api/repos/{}/manifest", repo_hash));
+    let response = client.get(&url).send().await?;
+
+    if response.status() == hyper::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to get repo manifest: {}", response.status());
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Manifest {
+        manifest_hash: String,
+    }
+
+    let manifest: Manifest = response.json().await?;
+    Ok(Some(manifest.manifest_hash))
+}
+
+/// Fail a fetched replica whose objects aren't the set the coordinator
+/// expects, e.g. an outdated snapshot
+async fn check_manifest(spool: &Arc<GitStorage>, repo_hash: &str, expected: Option<&str>) -> anyhow::Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual = spool.manifest_hash_async(repo_hash).await?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        anyhow::bail!("manifest {} doesn't match the coordinator's {}", &actual[..16], expected);
+    }
+    Ok(())
+}
+
 async fn get_repo_nodes(
     server: &str,
+    namespace: &str,
     repo_hash: &str,
     client: &crate::http_client::HyruleClient,
 ) -> anyhow::Result<Vec<registration::PeerNode>> {
-    let url = format!("{}/api/repos/{}/nodes", server, repo_hash);
+    let url = registration::coordinator_url(server, namespace, &format!("/api/repos/{}/nodes", repo_hash));
     let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
@@ -262,6 +1375,8 @@ async fn get_repo_nodes(
         address: String,
         port: i32,
         is_anchor: bool,
+        #[serde(default)]
+        tls: bool,
     }
 
     let nodes: Vec<NodeInfo> = response.json().await?;
@@ -274,8 +1389,248 @@ async fn get_repo_nodes(
             port: n.port,
             is_anchor: if n.is_anchor { 1 } else { 0 },
             last_seen: chrono::Utc::now().to_rfc3339(),
+            tls: n.tls,
         })
         .collect();
 
     Ok(peers)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_pull_per_repo_at_a_time() {
+        let in_flight = Arc::new(InFlight::default());
+
+        let claim = in_flight.claim("abc").unwrap();
+        assert!(in_flight.claim("abc").is_none());
+        assert!(in_flight.claim("def").is_some());
+
+        drop(claim);
+        assert!(in_flight.claim("abc").is_some());
+    }
+
+    fn repo(hash: &str, replica_count: u32, priority: i32, size: Option<u64>) -> UnhealthyRepo {
+        UnhealthyRepo {
+            repo_hash: hash.to_string(),
+            replica_count,
+            priority,
+            size,
+            target_replicas: None,
+        }
+    }
+
+    fn hashes(repos: &[&UnhealthyRepo]) -> Vec<String> {
+        repos.iter().map(|r| r.repo_hash.clone()).collect()
+    }
+
+    #[test]
+    fn test_carry_sizes() {
+        let carried = vec![repo("aa", 0, 0, Some(500)), repo("bb", 0, 0, None)];
+        let mut candidates = vec![repo("aa", 1, 0, None), repo("bb", 1, 0, None), repo("cc", 1, 0, Some(7))];
+
+        carry_sizes(&mut candidates, &carried);
+
+        let sizes: Vec<_> = candidates.iter().map(|r| r.size).collect();
+        assert_eq!(sizes, vec![Some(500), None, Some(7)]);
+    }
+
+    #[test]
+    fn test_prioritize_under_replicated_first() {
+        let mut repos = vec![
+            repo("well", 4, 10, Some(10)),
+            repo("critical", 0, 0, Some(500)),
+            repo("low-urgent", 1, 5, Some(50)),
+            repo("low", 1, 1, Some(20)),
+        ];
+
+        prioritize(&mut repos);
+
+        let order: Vec<_> = repos.iter().map(|r| r.repo_hash.as_str()).collect();
+        assert_eq!(order, vec!["critical", "low-urgent", "low", "well"]);
+    }
+
+    #[test]
+    fn test_reservation_skips_repos_that_dont_fit() {
+        let mut repos = vec![
+            repo("big-critical", 0, 0, Some(900)),
+            repo("medium", 1, 0, Some(300)),
+            repo("unknown-size", 1, 0, None),
+            repo("small", 2, 0, Some(100)),
+            repo("too-big", 3, 0, Some(700)),
+        ];
+        prioritize(&mut repos);
+
+        let select = |available: u64| {
+            let mut budget = SpaceReservation::new(available);
+            repos
+                .iter()
+                .filter(|r| r.size.is_some_and(|size| budget.try_reserve(size)))
+                .collect::<Vec<_>>()
+        };
+
+        // big-critical doesn't fit, so we move on instead of stopping
+        assert_eq!(hashes(&select(500)), vec!["medium", "small"]);
+        assert_eq!(hashes(&select(1000)), vec!["big-critical", "small"]);
+    }
+
+    #[test]
+    fn test_reservation_accounts_for_earlier_repos() {
+        let mut budget = SpaceReservation::new(1000);
+
+        assert!(budget.try_reserve(600));
+        // Second repo would fit the original budget but not what's left
+        assert!(!budget.try_reserve(600));
+        assert_eq!(budget.remaining(), 400);
+
+        // A failed replication gives its bytes back
+        budget.release(600);
+        assert!(budget.try_reserve(600));
+    }
+
+    #[test]
+    fn test_is_disk_full() {
+        let full = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::StorageFull))
+            .context("storing object");
+        assert!(is_disk_full(&full));
+        assert!(!is_disk_full(&anyhow::anyhow!("connection reset")));
+    }
+
+    #[test]
+    fn test_parse_legacy_and_detailed_entries() {
+        let json = r#"["abc", {"repo_hash": "def", "replica_count": 2, "size": 42}]"#;
+        let entries: Vec<UnhealthyEntry> = serde_json::from_str(json).unwrap();
+        let repos: Vec<UnhealthyRepo> = entries.into_iter().map(UnhealthyRepo::from).collect();
+
+        assert_eq!(repos[0].repo_hash, "abc");
+        assert_eq!(repos[0].size, None);
+        assert_eq!(repos[1].replica_count, 2);
+        assert_eq!(repos[1].size, Some(42));
+        assert_eq!(repos[1].target_replicas, None);
+    }
+
+    #[test]
+    fn test_below_target() {
+        assert!(repo("aa", 2, 0, None).below_target(3));
+        assert!(!repo("aa", 3, 0, None).below_target(3));
+
+        // The coordinator's per-repo target wins over the config
+        let mut pinned = repo("aa", 3, 0, None);
+        pinned.target_replicas = Some(5);
+        assert!(pinned.below_target(3));
+        pinned.target_replicas = Some(1);
+        assert!(!pinned.below_target(3));
+    }
+
+    #[test]
+    fn test_diff_served() {
+        let list = |repos: &[&str]| repos.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+
+        let diff = diff_served(&list(&["cc", "aa", "bb"]), &list(&["bb", "dd"]));
+        assert_eq!(diff.missing, list(&["aa", "cc"]));
+        assert_eq!(diff.unlisted, list(&["dd"]));
+
+        assert_eq!(diff_served(&list(&["aa", "bb"]), &list(&["bb", "aa"])), ServedDiff::default());
+    }
+
+    #[test]
+    fn test_missing_objects() {
+        let list = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        let missing = missing_objects(&list(&["cc", "aa", "bb", "cc"]), &list(&["bb", "dd"]));
+        assert_eq!(missing, list(&["aa", "cc"]));
+        assert!(missing_objects(&list(&["aa"]), &list(&["aa", "bb"])).is_empty());
+
+        // Peer lists are checked with the same hash as local manifests
+        assert_eq!(storage::manifest_of(list(&["bb", "aa"])), storage::manifest_of(list(&["aa", "bb"])));
+    }
+
+    #[test]
+    fn test_match_peer() {
+        let peer = |id: &str| registration::PeerNode {
+            node_id: id.to_string(),
+            address: "127.0.0.1".to_string(),
+            port: 8080,
+            is_anchor: 0,
+            last_seen: String::new(),
+            tls: false,
+        };
+        let peers = || vec![peer("abc123"), peer("abd456"), peer("ab")];
+
+        assert_eq!(match_peer(peers(), "abc").unwrap().node_id, "abc123");
+        // An exact id wins even when it prefixes others
+        assert_eq!(match_peer(peers(), "ab").unwrap().node_id, "ab");
+        assert!(match_peer(peers(), "a").is_err());
+        assert!(match_peer(peers(), "ff").is_err());
+    }
+
+    #[test]
+    fn test_ref_updates() {
+        let local = vec![
+            ("HEAD".to_string(), "1".repeat(40)),
+            ("refs/heads/main".to_string(), "1".repeat(40)),
+            ("refs/heads/dev".to_string(), "2".repeat(40)),
+            ("refs/tags/v1".to_string(), "3".repeat(40)),
+        ];
+        let remote: HashMap<String, String> = [
+            ("refs/heads/main".to_string(), "1".repeat(40)),
+            ("refs/heads/dev".to_string(), "4".repeat(40)),
+        ]
+        .into_iter()
+        .collect();
+
+        let updates = ref_updates(&local, &remote);
+        let summary: Vec<_> = updates
+            .iter()
+            .map(|u| (u.ref_name.as_str(), u.expected_old.as_deref().unwrap()))
+            .collect();
+        let dev_old = "4".repeat(40);
+        assert_eq!(summary, vec![("refs/heads/dev", dev_old.as_str()), ("refs/tags/v1", storage::ZERO_ID)]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_object_ids_are_rejected() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let body = r#"{"objects":["abc"]}"#;
+                let reply = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        let (proxy, _) = crate::socks::tests::fake_proxy(target).await;
+        let client = crate::http_client::HyruleClient::socks(&proxy).unwrap();
+
+        let err = fetch_object_list(&client, "http://peer.onion", &"ab".repeat(32)).await.unwrap_err();
+        assert!(err.to_string().contains("invalid object id"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn test_check_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Arc::new(GitStorage::new(dir.path()).unwrap());
+        let repo = "ab".repeat(32);
+        let data = b"blob 1\0a";
+        spool.store_object(&repo, &crypto::git_object_id(data), data).unwrap();
+        let expected = spool.manifest_hash(&repo).unwrap();
+
+        check_manifest(&spool, &repo, Some(&expected)).await.unwrap();
+        check_manifest(&spool, &repo, Some(&expected.to_uppercase())).await.unwrap();
+        // Nothing to compare against
+        check_manifest(&spool, &repo, None).await.unwrap();
+
+        // A peer with an older snapshot is missing the newest object
+        let mut newer = spool.list_objects(&repo).unwrap();
+        newer.push("1".repeat(40));
+        newer.sort();
+        let newer = blake3::hash(format!("{}\n", newer.join("\n")).as_bytes()).to_hex().to_string();
+        assert!(check_manifest(&spool, &repo, Some(&newer)).await.is_err());
+    }
+}
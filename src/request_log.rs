@@ -0,0 +1,177 @@
+// hyrule-node/src/request_log.rs
+use crate::NodeState;
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Number of recent requests kept in memory
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestRecord {
+    pub timestamp: String,
+    pub method: String,
+    pub path: String,
+    pub repo_hash: Option<String>,
+    pub object_id: Option<String>,
+    pub status: u16,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub duration_ms: u64,
+}
+
+/// Bounded ring buffer of the most recent requests
+pub struct RequestLog {
+    records: Mutex<VecDeque<RequestRecord>>,
+    capacity: usize,
+}
+
+impl RequestLog {
+    pub fn new() -> Self {
+        Self::with_capacity(CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, record: RequestRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Most recent requests, newest first
+    pub fn recent(&self) -> Vec<RequestRecord> {
+        self.records.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pull the repo hash and object id out of `/repos/{hash}/objects/{id}`-style paths
+fn path_labels(path: &str) -> (Option<String>, Option<String>) {
+    let mut segments = path.trim_start_matches('/').split('/');
+
+    if segments.next() != Some("repos") {
+        return (None, None);
+    }
+
+    let repo_hash = segments.next().filter(|s| !s.is_empty()).map(str::to_string);
+    let object_id = match (segments.next(), segments.next()) {
+        (Some("objects"), Some(id)) if id != "batch" && !id.is_empty() => Some(id.to_string()),
+        _ => None,
+    };
+
+    (repo_hash, object_id)
+}
+
+/// Middleware that records repo/object labels, status, size and latency
+pub async fn record(State(state): State<NodeState>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let bytes_in = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let response = next.run(request).await;
+
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let status = response.status().as_u16();
+    let bytes_out = response.body().size_hint().exact().unwrap_or(0);
+    let (repo_hash, object_id) = path_labels(&path);
+
+    tracing::info!(
+        method = %method,
+        path = %path,
+        repo = repo_hash.as_deref().unwrap_or("-"),
+        object = object_id.as_deref().unwrap_or("-"),
+        status,
+        bytes_in,
+        bytes_out,
+        duration_ms,
+        "request"
+    );
+
+    state.request_log.push(RequestRecord {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        method,
+        path,
+        repo_hash,
+        object_id,
+        status,
+        bytes_in,
+        bytes_out,
+        duration_ms,
+    });
+
+    response
+}
+
+/// `GET /admin/requests` - recent request log
+pub async fn recent_requests(State(state): State<NodeState>) -> Json<Vec<RequestRecord>> {
+    Json(state.request_log.recent())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str) -> RequestRecord {
+        RequestRecord {
+            timestamp: String::new(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            repo_hash: None,
+            object_id: None,
+            status: 200,
+            bytes_in: 0,
+            bytes_out: 0,
+            duration_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_is_bounded() {
+        let log = RequestLog::with_capacity(3);
+        for i in 0..5 {
+            log.push(record(&format!("/{}", i)));
+        }
+
+        let paths: Vec<_> = log.recent().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, vec!["/4", "/3", "/2"]);
+    }
+
+    #[test]
+    fn test_path_labels() {
+        assert_eq!(
+            path_labels("/repos/abc/objects/def"),
+            (Some("abc".to_string()), Some("def".to_string()))
+        );
+        assert_eq!(path_labels("/repos/abc/objects/batch"), (Some("abc".to_string()), None));
+        assert_eq!(path_labels("/repos/abc/pack"), (Some("abc".to_string()), None));
+        assert_eq!(path_labels("/status"), (None, None));
+    }
+}
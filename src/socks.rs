@@ -0,0 +1,120 @@
+// hyrule-node/src/socks.rs
+//
+// Connector for `tor_mode = "socks"`: requests go through the SOCKS5 port
+// of a Tor daemon the operator already runs, instead of embedded Arti.
+
+use hyper::service::Service;
+use hyper::Uri;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+
+/// Dials every connection through a SOCKS5 proxy. Host names are passed
+/// to the proxy unresolved, which is what lets Tor reach `.onion` hosts.
+#[derive(Debug, Clone)]
+pub struct SocksConnector {
+    proxy: String,
+}
+
+impl SocksConnector {
+    pub fn new(proxy: impl Into<String>) -> Self {
+        Self { proxy: proxy.into() }
+    }
+}
+
+/// Open a stream to `host:port` through the SOCKS5 proxy at `proxy`
+pub async fn connect(proxy: &str, host: &str, port: u16) -> Result<TcpStream, tokio_socks::Error> {
+    Ok(Socks5Stream::connect(proxy, (host, port)).await?.into_inner())
+}
+
+impl Service<Uri> for SocksConnector {
+    type Response = TcpStream;
+    type Error = anyhow::Error;
+    type Future = Pin<Box<dyn Future<Output = anyhow::Result<TcpStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let proxy = self.proxy.clone();
+        Box::pin(async move {
+            let host = uri
+                .host()
+                .ok_or_else(|| anyhow::anyhow!("URL has no host: {}", uri))?
+                .trim_matches(|c| c == '[' || c == ']')
+                .to_string();
+            let port = uri
+                .port_u16()
+                .unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+
+            // Kept as the source so callers can tell a failing proxy from
+            // an unreachable host
+            connect(&proxy, &host, port)
+                .await
+                .map_err(|e| anyhow::Error::new(e).context(format!("SOCKS proxy {} couldn't reach {}:{}", proxy, host, port)))
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Minimal SOCKS5 proxy: no auth, CONNECT only. Sends each stream to
+    /// `target` whatever was asked for, and records the requested host.
+    pub(crate) async fn fake_proxy(target: std::net::SocketAddr) -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (requested, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let requested = requested.clone();
+                tokio::spawn(async move {
+                    let mut greeting = [0u8; 3];
+                    client.read_exact(&mut greeting).await.unwrap();
+                    client.write_all(&[5, 0]).await.unwrap();
+
+                    let mut head = [0u8; 5];
+                    client.read_exact(&mut head).await.unwrap();
+                    assert_eq!(head[3], 3, "host names must reach the proxy unresolved");
+                    let mut host = vec![0u8; head[4] as usize + 2];
+                    client.read_exact(&mut host).await.unwrap();
+                    let port = u16::from_be_bytes([host[host.len() - 2], host[host.len() - 1]]);
+                    host.truncate(host.len() - 2);
+                    let _ = requested.send(format!("{}:{}", String::from_utf8(host).unwrap(), port));
+
+                    client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+                    let mut upstream = TcpStream::connect(target).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                });
+            }
+        });
+
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_connects_through_proxy() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = server.accept().await.unwrap();
+            socket.write_all(b"hello").await.unwrap();
+        });
+
+        let (proxy, mut requested) = fake_proxy(target).await;
+        let uri: Uri = "http://example.onion/health".parse().unwrap();
+        let mut stream = SocksConnector::new(proxy).call(uri).await.unwrap();
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "hello");
+        assert_eq!(requested.recv().await.unwrap(), "example.onion:80");
+    }
+}
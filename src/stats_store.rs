@@ -0,0 +1,93 @@
+// hyrule-node/src/stats_store.rs
+//
+// Keeps the `NodeStats` counters across restarts: saved to the storage
+// root periodically and on shutdown, and loaded again at startup. Uptime is
+// per process and never saved.
+
+use crate::jitter::JitteredInterval;
+use crate::storage::{write_atomic, GitStorage};
+use crate::{NodeState, NodeStats};
+use std::fs;
+use std::time::Duration;
+
+/// Sidecar file in the storage root
+const STATS_FILE: &str = "node-stats.json";
+
+/// How often the counters are saved while running. A crash loses at most
+/// this much.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Counters saved by an earlier run; a missing or unreadable file starts
+/// from zero
+pub fn load(storage: &GitStorage) -> NodeStats {
+    fs::read(storage.base_path().join(STATS_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(storage: &GitStorage, stats: &NodeStats) -> anyhow::Result<()> {
+    Ok(write_atomic(&storage.base_path().join(STATS_FILE), &serde_json::to_vec(stats)?)?)
+}
+
+/// Save the counters every [`SNAPSHOT_INTERVAL`]
+pub async fn snapshot_loop(state: NodeState) {
+    let mut interval = JitteredInterval::new(&state.config.node_id, "stats snapshot", SNAPSHOT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        crate::tasks::record_run();
+
+        let stats = state.stats.read().await.clone();
+        let storage = state.storage.clone();
+        let saved = tokio::task::spawn_blocking(move || save(&storage, &stats))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r);
+        if let Err(e) = saved {
+            tracing::warn!(error = %e, "Failed to save node stats");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        assert_eq!(load(&storage).total_requests, 0);
+
+        let stats = NodeStats {
+            total_requests: 10,
+            bytes_served: 2048,
+            replication_count: 3,
+            failed_requests: 1,
+            repos_hosted: 4,
+            last_heartbeat_ok: Some(true),
+            self_reachable: Some(false),
+            repos_missing: Some(2),
+            repos_missing_since: Some(std::time::Instant::now()),
+            last_replication_pass: Some(std::time::Instant::now()),
+        };
+        save(&storage, &stats).unwrap();
+
+        let loaded = load(&storage);
+        assert_eq!(loaded.total_requests, 10);
+        assert_eq!(loaded.bytes_served, 2048);
+        assert_eq!(loaded.replication_count, 3);
+        assert_eq!(loaded.failed_requests, 1);
+
+        // What describes the running process starts over
+        assert_eq!(loaded.repos_hosted, 0);
+        assert_eq!(loaded.last_heartbeat_ok, None);
+        assert_eq!(loaded.self_reachable, None);
+        assert_eq!(loaded.repos_missing, None);
+        assert!(loaded.last_replication_pass.is_none());
+
+        fs::write(dir.path().join(STATS_FILE), b"{not json").unwrap();
+        assert_eq!(load(&storage).total_requests, 0);
+    }
+}
@@ -1,21 +1,361 @@
 // hyrule-node/src/storage.rs
 use std::path::{Path, PathBuf};
 use std::fs;
-use anyhow::Result;
+use crate::config::FsyncPolicy;
+use crate::error::{bail, HyruleError, Result};
 use flate2::write::ZlibEncoder;
 use flate2::read::ZlibDecoder;
 use flate2::Compression;
 use std::io::{Write, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use crate::pack::{self, ObjectType};
+
+/// Name of the advisory lock file held by a running node
+const LOCK_FILE: &str = ".lock";
+
+/// Per-repo directory holding the last generated packfile
+const PACK_CACHE_DIR: &str = "pack-cache";
+
+/// Directory under `base_path` holding objects shared between repos
+const POOL_DIR: &str = "pool";
+
+/// Default directory under `base_path` where replicas are assembled
+/// before they go live
+pub const SPOOL_DIR: &str = "spool";
+
+/// Lock file serializing ref updates within one repo
+const REFS_LOCK_FILE: &str = ".refs.lock";
+
+/// File in a repo directory holding its object count, so the count
+/// doesn't need a full listing
+const OBJECT_COUNT_FILE: &str = "object-count";
+
+/// File in `base_path` recording the on-disk format version
+const STORE_VERSION_FILE: &str = "STORE_VERSION";
+
+/// Store format written by this build. Bump it and append to `MIGRATIONS`
+/// whenever the layout changes.
+const STORE_VERSION: u32 = 1;
+
+/// One step upgrading a store from format `n` (its index) to `n + 1`
+type Migration = fn(&Path) -> anyhow::Result<()>;
+
+/// Upgrade steps, indexed by the version they start from
+const MIGRATIONS: &[Migration] = &[
+    // Stores from before versioning already use the version 1 layout
+    |_| Ok(()),
+];
+
+const _: () = assert!(MIGRATIONS.len() == STORE_VERSION as usize);
+
+/// Object id git uses for "no ref"
+pub const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+
+/// How many `ref:` hops HEAD may take before we call it a loop, as in git
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Locks serializing repo initialization, shared by repos that hash alike
+const INIT_LOCK_STRIPES: usize = 64;
+
+/// Largest object stored or inflated on read, so a small corrupt or
+/// hostile file can't expand to fill memory
+const MAX_OBJECT_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Bytes of a new object looked at before deciding whether to compress it
+const SNIFF_BYTES: usize = 16 * 1024;
+
+/// Blobs smaller than this are always compressed; judging them costs
+/// about as much as compressing them
+const MIN_UNCOMPRESSED_SIZE: usize = 4 * 1024;
+
+/// A sample that compresses to more than this share of its size is
+/// stored as is
+const INCOMPRESSIBLE_RATIO: f64 = 0.95;
+
+/// Leading bytes of file formats that are compressed already
+const COMPRESSED_MAGIC: &[&[u8]] = &[
+    b"\x89PNG",           // PNG
+    b"\xff\xd8\xff",       // JPEG
+    b"GIF8",              // GIF
+    b"PK\x03\x04",         // zip, jar, docx, apk
+    b"\x1f\x8b",           // gzip
+    b"\x28\xb5\x2f\xfd",   // zstd
+    b"\xfd7zXZ\x00",       // xz
+    b"BZh",               // bzip2
+    b"7z\xbc\xaf\x27\x1c",  // 7z
+    b"Rar!\x1a\x07",       // rar
+    b"PACK",              // git packfile
+    b"OggS",              // ogg
+    b"\x1a\x45\xdf\xa3",   // matroska, webm
+    b"wOF2",              // woff2
+];
+
+/// A compare-and-swap ref update found a different value than expected
+#[derive(Debug)]
+pub struct RefConflict {
+    pub ref_name: String,
+    /// Current value, None when the ref doesn't exist
+    pub actual: Option<String>,
+}
+
+impl std::fmt::Display for RefConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(f, "Ref {} is at {}", self.ref_name, actual),
+            None => write!(f, "Ref {} does not exist", self.ref_name),
+        }
+    }
+}
+
+impl std::error::Error for RefConflict {}
+
+/// One entry of a batched ref update
+#[derive(Debug, Clone)]
+pub struct RefUpdate {
+    pub ref_name: String,
+    pub commit_id: String,
+    pub expected_old: Option<String>,
+}
+
+/// Refused to delete the branch HEAD points at
+#[derive(Debug)]
+pub struct RefIsHead(pub String);
+
+impl std::fmt::Display for RefIsHead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ref {} is the target of HEAD", self.0)
+    }
+}
+
+impl std::error::Error for RefIsHead {}
+
+/// Where a repo's HEAD points
+#[derive(Debug, Clone, PartialEq)]
+pub struct Head {
+    /// Branch HEAD ultimately follows, or None when detached
+    pub target: Option<String>,
+    /// Commit HEAD resolves to, or None on a branch with no commits yet
+    pub commit_id: Option<String>,
+}
 
 pub struct GitStorage {
     base_path: PathBuf,
+    /// Object stores, fastest first. The first tier is always `base_path`,
+    /// which also holds refs, HEAD and other repo metadata.
+    tiers: Vec<Tier>,
+    /// Held for the lifetime of a node process; the OS drops the lock on exit
+    lock: Option<fs::File>,
+    /// zlib level used when writing objects
+    compression: Compression,
+    /// Store new objects once in the shared pool and hard-link them into
+    /// each repo
+    dedup: bool,
+    /// Object counts of the repos touched so far, mirrored to
+    /// `OBJECT_COUNT_FILE`
+    object_counts: Mutex<HashMap<String, u64>>,
+    fsync: FsyncPolicy,
+    /// Objects written under `FsyncPolicy::Batch` and not yet synced
+    unsynced: Mutex<PendingSyncs>,
+    /// Generation of the last write a completed sync covers. Held while
+    /// syncing, so callers queue behind a sync already running.
+    synced: Mutex<u64>,
+    /// Repos hash onto a fixed set of locks, so concurrent first stores
+    /// initialize a repo once without a lock per repo ever seen
+    init_locks: [Mutex<()>; INIT_LOCK_STRIPES],
+    /// Bumped whenever pool link counts may have changed
+    pool_generation: AtomicU64,
+    /// Last [`GitStorage::dedup_stats`] result and the pool generation it
+    /// was computed at
+    dedup_cache: Mutex<Option<(u64, DedupStats)>>,
+}
+
+/// Deferred writes waiting for [`GitStorage::sync_pending`]
+#[derive(Debug, Default)]
+struct PendingSyncs {
+    paths: Vec<PathBuf>,
+    /// Generation of the latest write, counting up from 1
+    written: u64,
+}
+
+impl PendingSyncs {
+    fn push(&mut self, path: PathBuf) {
+        self.paths.push(path);
+        self.written += 1;
+    }
+}
+
+/// Space saved by the shared object pool
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DedupStats {
+    pub pooled_objects: u64,
+    /// Bytes that would be used again if every repo had its own copy
+    pub saved_bytes: u64,
+}
+
+/// Disk use of one repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoUsage {
+    pub repo_hash: String,
+    /// Everything under the repo on every tier, cached packs included
+    pub size: u64,
+    pub objects: u64,
+    pub pack_cache: u64,
+}
+
+/// Where the node's disk space goes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageBreakdown {
+    /// Largest first
+    pub repos: Vec<RepoUsage>,
+    /// Bytes in the shared pool. Pooled objects are hard links, so they
+    /// are also counted in the size of each repo that uses them.
+    pub pool: u64,
+    pub dedup: DedupStats,
+    /// Replicas being assembled, not yet part of any repo
+    pub spool: u64,
+}
+
+/// One object store in a tiered setup
+struct Tier {
+    path: PathBuf,
+    capacity: u64,
+    /// Bytes of objects on this tier. Only tracked once tiers are configured.
+    used: AtomicU64,
+}
+
+impl Tier {
+    fn new(path: PathBuf, capacity: u64, used: u64) -> Self {
+        Self { path, capacity, used: AtomicU64::new(used) }
+    }
+    
+    fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+    
+    fn add_used(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+    
+    fn sub_used(&self, bytes: u64) {
+        let _ = self.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+            Some(used.saturating_sub(bytes))
+        });
+    }
+    
+    fn has_room(&self, bytes: u64) -> bool {
+        self.used().saturating_add(bytes) <= self.capacity
+    }
 }
 
 impl GitStorage {
+    /// Open storage without the instance lock, e.g. for CLI commands next
+    /// to a running node. A store that needs migrating is locked while it
+    /// is migrated, so this fails if a node is using it.
     pub fn new(base_path: impl AsRef<Path>) -> Result<Self> {
+        let storage = Self::new_unchecked(base_path)?;
+        let base_path = &storage.base_path;
+        // Reading a current store or stamping an empty one is safe to race
+        if store_is_current(base_path, MIGRATIONS) || store_is_empty(base_path)? {
+            check_store_version(base_path, MIGRATIONS)?;
+        } else {
+            let lock = lock_store(base_path)?;
+            check_store_version(base_path, MIGRATIONS)?;
+            let _ = lock.unlock();
+        }
+        Ok(storage)
+    }
+    
+    /// Storage as found on disk, before its format is checked
+    fn new_unchecked(base_path: impl AsRef<Path>) -> Result<Self> {
         let base_path = PathBuf::from(base_path.as_ref());
         fs::create_dir_all(&base_path)?;
-        Ok(Self { base_path })
+        Ok(Self {
+            tiers: vec![Tier::new(base_path.clone(), u64::MAX, 0)],
+            base_path,
+            lock: None,
+            compression: Compression::default(),
+            dedup: false,
+            object_counts: Mutex::new(HashMap::new()),
+            fsync: FsyncPolicy::Always,
+            unsynced: Mutex::new(PendingSyncs::default()),
+            synced: Mutex::new(0),
+            init_locks: std::array::from_fn(|_| Mutex::new(())),
+            pool_generation: AtomicU64::new(0),
+            dedup_cache: Mutex::new(None),
+        })
+    }
+    
+    /// Spread objects over several stores, fastest first, each with a
+    /// capacity in bytes. The first tier must be the base storage path.
+    pub fn with_tiers(mut self, tiers: &[(PathBuf, u64)]) -> Result<Self> {
+        if tiers.is_empty() {
+            return Ok(self);
+        }
+        
+        if tiers[0].0 != self.base_path {
+            bail!(
+                Config,
+                "First storage tier must be the storage path {}",
+                self.base_path.display()
+            );
+        }
+        
+        self.tiers = tiers
+            .iter()
+            .map(|(path, capacity)| {
+                fs::create_dir_all(path)?;
+                let used = tier_objects_size(path)?;
+                Ok(Tier::new(path.clone(), *capacity, used))
+            })
+            .collect::<Result<_>>()?;
+        
+        Ok(self)
+    }
+    
+    /// Set the zlib compression level (0-9) used for new objects
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.compression = Compression::new(level.min(9));
+        self
+    }
+    
+    /// Share identical objects between repos through hard links to the
+    /// pool. Needs hard link counts, so it is only available on Unix.
+    pub fn with_dedup(mut self, enabled: bool) -> Self {
+        self.dedup = enabled && cfg!(unix);
+        self
+    }
+    
+    /// When object writes are fsynced. With `FsyncPolicy::Batch`, objects
+    /// stored with [`GitStorage::store_object_batched`] are synced together
+    /// by [`GitStorage::sync_pending`]; single stores sync right away.
+    pub fn with_fsync_policy(mut self, policy: FsyncPolicy) -> Self {
+        self.fsync = policy;
+        self
+    }
+    
+    /// Open storage and take an exclusive lock on it, so a second node
+    /// process pointed at the same directory refuses to start. The store
+    /// is only migrated once the lock is held.
+    pub fn open_exclusive(base_path: impl AsRef<Path>) -> Result<Self> {
+        let mut storage = Self::new_unchecked(base_path)?;
+        let lock = lock_store(&storage.base_path)?;
+        check_store_version(&storage.base_path, MIGRATIONS)?;
+        storage.lock = Some(lock);
+        Ok(storage)
+    }
+    
+    /// Release the storage lock early (it is also released on drop)
+    pub fn unlock(&mut self) {
+        if let Some(file) = self.lock.take() {
+            let _ = file.unlock();
+        }
+    }
+    
+    /// Root of the fastest tier, which also holds node-wide sidecar files
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
     }
     
     pub fn repo_path(&self, repo_hash: &str) -> PathBuf {
@@ -30,114 +370,662 @@ impl GitStorage {
         self.repo_path(repo_hash).join("refs")
     }
     
-    /// Initialize repository storage
+    /// Path of an object on whichever tier holds it, or where it would be
+    /// written on the first tier if it isn't stored yet
+    pub fn object_path(&self, repo_hash: &str, object_id: &str) -> PathBuf {
+        self.find_object(repo_hash, object_id)
+            .map(|(_, path)| path)
+            .unwrap_or_else(|| self.tier_object_path(0, repo_hash, object_id))
+    }
+    
+    fn tier_objects_path(&self, tier: usize, repo_hash: &str) -> PathBuf {
+        self.tiers[tier].path.join(repo_hash).join("objects")
+    }
+    
+    fn tier_object_path(&self, tier: usize, repo_hash: &str, object_id: &str) -> PathBuf {
+        self.tier_objects_path(tier, repo_hash)
+            .join(&object_id[..2])
+            .join(&object_id[2..])
+    }
+    
+    /// Search the tiers in order for an object
+    fn find_object(&self, repo_hash: &str, object_id: &str) -> Option<(usize, PathBuf)> {
+        check_object_ref(repo_hash, object_id).ok()?;
+        (0..self.tiers.len())
+            .map(|tier| (tier, self.tier_object_path(tier, repo_hash, object_id)))
+            .find(|(_, path)| path.exists())
+    }
+    
+    /// Whether a repo has an object on any tier
+    pub fn object_exists(&self, repo_hash: &str, object_id: &str) -> bool {
+        self.find_object(repo_hash, object_id).is_some()
+    }
+    
+    /// First tier with room for `size` more bytes
+    fn tier_for_write(&self, size: u64) -> Result<usize> {
+        self.tiers
+            .iter()
+            .position(|tier| tier.has_room(size))
+            .ok_or_else(|| HyruleError::StorageFull("All storage tiers are full".to_string()))
+    }
+    
+    /// Number of object tiers (1 when tiering isn't configured)
+    pub fn tier_count(&self) -> usize {
+        self.tiers.len()
+    }
+    
+    /// Bytes used and capacity of a tier
+    pub fn tier_usage(&self, tier: usize) -> (u64, u64) {
+        (self.tiers[tier].used(), self.tiers[tier].capacity)
+    }
+    
+    /// Bytes of a repository's objects on each tier
+    pub fn repo_tier_bytes(&self, repo_hash: &str) -> Result<Vec<u64>> {
+        (0..self.tiers.len())
+            .map(|tier| dir_size(&self.tier_objects_path(tier, repo_hash)))
+            .collect()
+    }
+    
+    /// Move all of a repository's objects onto one tier. Each object is
+    /// written to the target before the old copy is removed, so readers
+    /// always find it somewhere. Returns the number of bytes moved.
+    pub fn move_repo_to_tier(&self, repo_hash: &str, target: usize) -> Result<u64> {
+        let mut moved = 0u64;
+        
+        for source in (0..self.tiers.len()).filter(|&t| t != target) {
+            let objects_dir = self.tier_objects_path(source, repo_hash);
+            
+            for object_id in list_objects_in(&objects_dir)? {
+                let from = self.tier_object_path(source, repo_hash, &object_id);
+                let to = self.tier_object_path(target, repo_hash, &object_id);
+                let compressed = fs::read(&from)?;
+                let size = compressed.len() as u64;
+                
+                if !to.exists() {
+                    if !self.tiers[target].has_room(size) {
+                        bail!(StorageFull, "Tier {} is full", target);
+                    }
+                    if let Some(parent) = to.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    write_atomic(&to, &compressed)?;
+                    self.tiers[target].add_used(size);
+                }
+                
+                fs::remove_file(&from)?;
+                self.tiers[source].sub_used(size);
+                moved += size;
+            }
+        }
+        
+        // Objects demoted off the first tier may have been the last link
+        // to a pooled copy
+        if target != 0 {
+            self.prune_pool()?;
+        }
+        
+        Ok(moved)
+    }
+    
+    /// Initialize repository storage. Safe to repeat and to race: existing
+    /// directories are left alone and an existing HEAD is kept.
     pub fn init_repo(&self, repo_hash: &str) -> Result<()> {
+        check_repo_name(repo_hash)?;
+        let _guard = self.init_lock(repo_hash).lock().unwrap();
+        
+        // create_dir_all already treats a directory created meanwhile as
+        // success
         let repo_path = self.repo_path(repo_hash);
         fs::create_dir_all(&repo_path)?;
         fs::create_dir_all(self.objects_path(repo_hash))?;
         fs::create_dir_all(self.refs_path(repo_hash).join("heads"))?;
         fs::create_dir_all(self.refs_path(repo_hash).join("tags"))?;
         
+        // Written whole, so readers never see it half-written
         let head_path = repo_path.join("HEAD");
-        fs::write(head_path, "ref: refs/heads/main\n")?;
+        if !head_path.exists() {
+            write_atomic(&head_path, b"ref: refs/heads/main\n")?;
+        }
         
         Ok(())
     }
     
+    /// The init lock a repo shares with the others hashing to its stripe
+    fn init_lock(&self, repo_hash: &str) -> &Mutex<()> {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        repo_hash.hash(&mut hasher);
+        &self.init_locks[hasher.finish() as usize % INIT_LOCK_STRIPES]
+    }
+    
     /// Store a Git object
     pub fn store_object(&self, repo_hash: &str, object_id: &str, data: &[u8]) -> Result<()> {
+        self.store_data(repo_hash, object_id, data, false)
+    }
+    
+    /// Store one of many objects. Under `FsyncPolicy::Batch` the sync is
+    /// left to the caller's [`GitStorage::sync_pending`] once the group is
+    /// written; otherwise the same as [`GitStorage::store_object`].
+    pub fn store_object_batched(&self, repo_hash: &str, object_id: &str, data: &[u8]) -> Result<()> {
+        self.store_data(repo_hash, object_id, data, true)
+    }
+    
+    fn store_data(&self, repo_hash: &str, object_id: &str, data: &[u8], defer_sync: bool) -> Result<()> {
+        check_object_ref(repo_hash, object_id)?;
+        
+        let mut upload = self.begin_object();
+        upload.write(data)?;
+        self.store_upload(repo_hash, object_id, upload, defer_sync).map(drop)
+    }
+    
+    /// Start an object that arrives in pieces. Each piece is compressed as
+    /// it is written, so the uncompressed object is never held whole.
+    /// Blobs that are already compressed (see [`ObjectUpload`]) are stored
+    /// as raw loose objects instead, which [`GitStorage::read_object`]
+    /// tells apart by their header.
+    pub fn begin_object(&self) -> ObjectUpload {
+        ObjectUpload {
+            compression: self.compression,
+            state: UploadState::Sniffing(Vec::new()),
+            size: 0,
+        }
+    }
+    
+    /// Store an object written through [`GitStorage::begin_object`].
+    /// Returns its uncompressed size.
+    pub fn finish_object(&self, repo_hash: &str, object_id: &str, upload: ObjectUpload) -> Result<u64> {
+        self.store_upload(repo_hash, object_id, upload, false)
+    }
+    
+    fn store_upload(&self, repo_hash: &str, object_id: &str, upload: ObjectUpload, defer_sync: bool) -> Result<u64> {
+        check_object_ref(repo_hash, object_id)?;
+        let raw_size = upload.size;
+        let (compressed, skipped) = upload.finish()?;
+        if let Some(reason) = skipped {
+            tracing::debug!(repo = %repo_hash, object = %object_id, size = raw_size, reason, "Storing object uncompressed");
+        }
+        let size = compressed.len() as u64;
+        
         let objects_dir = self.objects_path(repo_hash);
         
         if !objects_dir.exists() {
             self.init_repo(repo_hash)?;
         }
         
-        let subdir = &object_id[..2];
-        let filename = &object_id[2..];
+        // Overwrites stay on the object's current tier
+        let existing = self.find_object(repo_hash, object_id);
+        let is_new = existing.is_none();
+        let (tier, object_path) = match existing {
+            Some(found) => found,
+            None => {
+                // Load the count first so a fresh listing doesn't include this object
+                self.object_count(repo_hash)?;
+                let tier = self.tier_for_write(size)?;
+                (tier, self.tier_object_path(tier, repo_hash, object_id))
+            }
+        };
         
-        let subdir_path = objects_dir.join(subdir);
-        fs::create_dir_all(&subdir_path)?;
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         
-        let object_path = subdir_path.join(filename);
+        let old_size = fs::metadata(&object_path).map(|m| m.len()).unwrap_or(0);
+        let stored = if self.dedup && tier == 0 && is_new {
+            // The pooled copy may have been compressed at another level
+            self.link_from_pool(object_id, &compressed, &object_path, defer_sync)?;
+            fs::metadata(&object_path)?.len()
+        } else {
+            self.write_object_file(&object_path, &compressed, defer_sync)?;
+            size
+        };
         
-        // Compress with zlib
-        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(data)?;
-        let compressed = encoder.finish()?;
+        self.tiers[tier].sub_used(old_size);
+        self.tiers[tier].add_used(stored);
+        if is_new {
+            self.adjust_object_count(repo_hash, 1)?;
+        }
+        self.invalidate_pack_cache(repo_hash)?;
+        Ok(raw_size)
+    }
+    
+    /// Number of objects in a repository. Counted from disk the first time
+    /// and kept up to date by stores and deletes after that.
+    pub fn object_count(&self, repo_hash: &str) -> Result<u64> {
+        let mut counts = self.object_counts.lock().unwrap();
+        self.cached_object_count(&mut counts, repo_hash)
+    }
+    
+    fn cached_object_count(&self, counts: &mut HashMap<String, u64>, repo_hash: &str) -> Result<u64> {
+        if let Some(&count) = counts.get(repo_hash) {
+            return Ok(count);
+        }
         
-        fs::write(object_path, compressed)?;
+        // A missing or unreadable file is rebuilt from a listing
+        let saved = fs::read_to_string(self.repo_path(repo_hash).join(OBJECT_COUNT_FILE))
+            .ok()
+            .and_then(|content| content.trim().parse().ok());
+        let count = match saved {
+            Some(count) => count,
+            None => self.list_objects(repo_hash)?.len() as u64,
+        };
+        counts.insert(repo_hash.to_string(), count);
+        Ok(count)
+    }
+    
+    fn adjust_object_count(&self, repo_hash: &str, delta: i64) -> Result<()> {
+        let mut counts = self.object_counts.lock().unwrap();
+        let count = self.cached_object_count(&mut counts, repo_hash)?.saturating_add_signed(delta);
+        counts.insert(repo_hash.to_string(), count);
+        // Not fsynced: a torn write is unreadable and just gets recounted
+        fs::write(self.repo_path(repo_hash).join(OBJECT_COUNT_FILE), format!("{}\n", count))?;
         Ok(())
     }
     
-    /// Read a Git object
-    pub fn read_object(&self, repo_hash: &str, object_id: &str) -> Result<Vec<u8>> {
-        let subdir = &object_id[..2];
-        let filename = &object_id[2..];
+    fn pool_path(&self, object_id: &str) -> PathBuf {
+        self.base_path
+            .join(POOL_DIR)
+            .join(&object_id[..2])
+            .join(&object_id[2..])
+    }
+    
+    /// Store an object as a hard link to its copy in the pool, adding it to
+    /// the pool first if no other repo has it yet
+    fn link_from_pool(&self, object_id: &str, compressed: &[u8], object_path: &Path, defer_sync: bool) -> Result<()> {
+        let pool_path = self.pool_path(object_id);
+        if !pool_path.exists() {
+            if let Some(parent) = pool_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.write_object_file(&pool_path, compressed, defer_sync)?;
+        }
+        self.pool_changed();
+        
+        match fs::hard_link(&pool_path, object_path) {
+            Ok(()) => {
+                // The new directory entry needs syncing as much as the data
+                if defer_sync && self.fsync == FsyncPolicy::Batch {
+                    self.unsynced.lock().unwrap().push(object_path.to_path_buf());
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+            // Pruned in the meantime; fall back to a private copy
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.write_object_file(object_path, compressed, defer_sync),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    /// Write an object file as `fsync_policy` says. Under
+    /// `FsyncPolicy::Batch` only `defer_sync` writes wait for
+    /// [`GitStorage::sync_pending`].
+    fn write_object_file(&self, path: &Path, data: &[u8], defer_sync: bool) -> Result<()> {
+        match self.fsync {
+            FsyncPolicy::Batch if defer_sync => {
+                write_replace(path, data, false)?;
+                self.unsynced.lock().unwrap().push(path.to_path_buf());
+                Ok(())
+            }
+            FsyncPolicy::Always | FsyncPolicy::Batch => write_atomic(path, data),
+            FsyncPolicy::Never => write_replace(path, data, false),
+        }
+    }
+    
+    /// Flush objects stored with [`GitStorage::store_object_batched`] to
+    /// disk. Returns once a sync covering every write made before the call
+    /// has finished, whoever ran it. A no-op under the other policies.
+    pub fn sync_pending(&self) -> Result<()> {
+        let target = self.unsynced.lock().unwrap().written;
+        let mut synced = self.synced.lock().unwrap();
+        if *synced >= target {
+            return Ok(());
+        }
+        
+        let (paths, covers) = {
+            let mut pending = self.unsynced.lock().unwrap();
+            (std::mem::take(&mut pending.paths), pending.written)
+        };
+        
+        // One syncfs per tier covers every file and rename at once, which
+        // is where batching beats a sync per object
+        #[cfg(target_os = "linux")]
+        let result = self.tiers.iter().try_for_each(|tier| syncfs(&tier.path));
+        
+        #[cfg(not(target_os = "linux"))]
+        let result = sync_each(&paths);
+        
+        match result {
+            Ok(()) => {
+                *synced = covers;
+                Ok(())
+            }
+            Err(e) => {
+                // Still unsynced; the next call tries again
+                self.unsynced.lock().unwrap().paths.extend(paths);
+                Err(e)
+            }
+        }
+    }
+    
+    /// Remove pool objects no repo links to any more. The link count is the
+    /// reference count: one for the pool entry plus one per repo.
+    pub fn prune_pool(&self) -> Result<u64> {
+        let pool_dir = self.base_path.join(POOL_DIR);
+        if !pool_dir.exists() {
+            return Ok(0);
+        }
         
-        let object_path = self.objects_path(repo_hash)
-            .join(subdir)
-            .join(filename);
+        let mut removed = 0u64;
+        for entry in walkdir::WalkDir::new(&pool_dir) {
+            let entry = entry?;
+            if entry.file_type().is_file() && link_count(&entry.metadata()?) <= 1 {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        // Repos unlinking objects leave the pool as is until this runs
+        self.pool_changed();
+        Ok(removed)
+    }
+    
+    /// Note that pool link counts may have changed, so the next
+    /// [`GitStorage::dedup_stats`] walks the pool again
+    fn pool_changed(&self) {
+        self.pool_generation.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// How many objects are pooled and how much space sharing them saves.
+    /// Walking the pool is only needed after it changed; otherwise the last
+    /// result is returned.
+    pub fn dedup_stats(&self) -> Result<DedupStats> {
+        let generation = self.pool_generation.load(Ordering::Relaxed);
+        if let Some((cached_at, stats)) = *self.dedup_cache.lock().unwrap() {
+            if cached_at == generation {
+                return Ok(stats);
+            }
+        }
         
-        if !object_path.exists() {
-            anyhow::bail!("Object not found: {}", object_id);
+        let mut stats = DedupStats::default();
+        let pool_dir = self.base_path.join(POOL_DIR);
+        if pool_dir.exists() {
+            // A file reachable under two pool paths is still one copy
+            let mut seen = std::collections::HashSet::new();
+            for entry in walkdir::WalkDir::new(&pool_dir) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                if file_id(&metadata).is_some_and(|id| !seen.insert(id)) {
+                    continue;
+                }
+                let repos = link_count(&metadata).saturating_sub(1);
+                stats.pooled_objects += 1;
+                stats.saved_bytes += repos.saturating_sub(1) * metadata.len();
+            }
         }
         
-        let compressed = fs::read(object_path)?;
-        let mut decoder = ZlibDecoder::new(&compressed[..]);
-        let mut data = Vec::new();
-        decoder.read_to_end(&mut data)?;
+        // Only keep the result if nothing changed the pool mid-walk
+        if self.pool_generation.load(Ordering::Relaxed) == generation {
+            *self.dedup_cache.lock().unwrap() = Some((generation, stats));
+        }
+        Ok(stats)
+    }
+    
+    /// Read a Git object
+    pub fn read_object(&self, repo_hash: &str, object_id: &str) -> Result<Vec<u8>> {
+        check_object_ref(repo_hash, object_id)?;
+        let Some((_, object_path)) = self.find_object(repo_hash, object_id) else {
+            bail!(NotFound, "Object {}", object_id);
+        };
         
-        Ok(data)
+        let stored = fs::read(object_path)?;
+        decode_object(&stored, MAX_OBJECT_SIZE).map_err(|e| HyruleError::Corrupt {
+            object_id: object_id.to_string(),
+            reason: e.to_string(),
+        })
     }
     
-    /// Update a ref
-    pub fn update_ref(&self, repo_hash: &str, ref_name: &str, commit_id: &str) -> Result<()> {
+    /// Update a ref. With `expected_old` the update only happens if the ref
+    /// currently has that value, where `ZERO_ID` means it must not exist;
+    /// otherwise a `RefConflict` is returned.
+    pub fn update_ref(
+        &self,
+        repo_hash: &str,
+        ref_name: &str,
+        commit_id: &str,
+        expected_old: Option<&str>,
+    ) -> Result<()> {
+        check_ref_path(repo_hash, ref_name, false)?;
         let ref_path = self.repo_path(repo_hash).join(ref_name);
         
         if let Some(parent) = ref_path.parent() {
             fs::create_dir_all(parent)?;
         }
         
-        fs::write(ref_path, format!("{}\n", commit_id))?;
+        let _lock = self.lock_refs(repo_hash)?;
+        
+        if let Some(expected) = expected_old {
+            let actual = read_ref_file(&ref_path)?;
+            if actual.as_deref().unwrap_or(ZERO_ID) != expected {
+                return Err(RefConflict {
+                    ref_name: ref_name.to_string(),
+                    actual,
+                }
+                .into());
+            }
+        }
+        
+        write_atomic(&ref_path, format!("{}\n", commit_id).as_bytes())?;
+        self.invalidate_pack_cache(repo_hash)?;
+        Ok(())
+    }
+    
+    /// Apply several ref updates all-or-nothing. Every `expected_old` is
+    /// checked before anything is written; if any fail, nothing changes and
+    /// the conflicts are returned. A failed write rolls back the updates
+    /// already made.
+    pub fn update_refs(&self, repo_hash: &str, updates: &[RefUpdate]) -> Result<Vec<RefConflict>> {
+        for (i, update) in updates.iter().enumerate() {
+            check_ref_path(repo_hash, &update.ref_name, false)?;
+            if updates[..i].iter().any(|u| u.ref_name == update.ref_name) {
+                bail!(Invalid, "Ref {} updated twice in one batch", update.ref_name);
+            }
+        }
+        
+        let repo_path = self.repo_path(repo_hash);
+        fs::create_dir_all(&repo_path)?;
+        let _lock = self.lock_refs(repo_hash)?;
+        
+        let mut previous = Vec::with_capacity(updates.len());
+        let mut conflicts = Vec::new();
+        for update in updates {
+            let actual = read_ref_file(&repo_path.join(&update.ref_name))?;
+            if let Some(expected) = &update.expected_old {
+                if actual.as_deref().unwrap_or(ZERO_ID) != expected {
+                    conflicts.push(RefConflict {
+                        ref_name: update.ref_name.clone(),
+                        actual: actual.clone(),
+                    });
+                }
+            }
+            previous.push(actual);
+        }
+        
+        if !conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+        
+        for (i, update) in updates.iter().enumerate() {
+            let ref_path = repo_path.join(&update.ref_name);
+            let written = ref_path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .map_err(HyruleError::from)
+                .and_then(|_| write_atomic(&ref_path, format!("{}\n", update.commit_id).as_bytes()));
+            
+            if let Err(e) = written {
+                for (done, old) in updates[..i].iter().zip(&previous) {
+                    let path = repo_path.join(&done.ref_name);
+                    let restored = match old {
+                        Some(old) => write_atomic(&path, format!("{}\n", old).as_bytes()),
+                        None => fs::remove_file(&path).map_err(Into::into),
+                    };
+                    if let Err(restore_err) = restored {
+                        tracing::error!("Failed to roll back ref {}: {}", done.ref_name, restore_err);
+                    }
+                }
+                return Err(anyhow::Error::from(e).context(format!("Failed to update {}", update.ref_name)).into());
+            }
+        }
+        
+        self.invalidate_pack_cache(repo_hash)?;
+        Ok(Vec::new())
+    }
+    
+    /// Delete a ref. Deleting the branch HEAD points at needs `force`,
+    /// otherwise a `RefIsHead` is returned.
+    pub fn delete_ref(&self, repo_hash: &str, ref_name: &str, force: bool) -> Result<()> {
+        check_ref_path(repo_hash, ref_name, false)?;
+        
+        let _lock = self.lock_refs(repo_hash)?;
+        
+        if !force {
+            let head_target = self.read_head(repo_hash).ok().and_then(|head| head.target);
+            if head_target.as_deref() == Some(ref_name) {
+                return Err(RefIsHead(ref_name.to_string()).into());
+            }
+        }
+        
+        match fs::remove_file(self.repo_path(repo_hash).join(ref_name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(NotFound, "Ref {}", ref_name),
+            Err(e) => return Err(e.into()),
+        }
+        self.invalidate_pack_cache(repo_hash)?;
         Ok(())
     }
     
-    /// Read a ref
+    /// Take the repo's ref lock; it is released when the file is dropped
+    fn lock_refs(&self, repo_hash: &str) -> Result<fs::File> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.repo_path(repo_hash).join(REFS_LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+    
+    /// Read a ref, or `HEAD`
     pub fn read_ref(&self, repo_hash: &str, ref_name: &str) -> Result<String> {
+        check_ref_path(repo_hash, ref_name, true)?;
         let ref_path = self.repo_path(repo_hash).join(ref_name);
         
         if !ref_path.exists() {
-            anyhow::bail!("Ref not found: {}", ref_name);
+            bail!(NotFound, "Ref {}", ref_name);
         }
         
         let content = fs::read_to_string(ref_path)?;
         Ok(content.trim().to_string())
     }
     
-    /// List all objects in a repository
-    pub fn list_objects(&self, repo_hash: &str) -> Result<Vec<String>> {
-        let objects_dir = self.objects_path(repo_hash);
-        let mut objects = Vec::new();
+    /// Resolve HEAD, following `ref:` links to the commit
+    pub fn read_head(&self, repo_hash: &str) -> Result<Head> {
+        let mut name = "HEAD".to_string();
+        let mut target = None;
         
-        if !objects_dir.exists() {
-            return Ok(objects);
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            if target.is_some() && !self.repo_path(repo_hash).join(&name).exists() {
+                // Unborn branch, e.g. a freshly initialised repo
+                return Ok(Head { target, commit_id: None });
+            }
+            
+            let value = self.read_ref(repo_hash, &name)?;
+            match value.strip_prefix("ref: ") {
+                Some(next) => {
+                    name = next.trim().to_string();
+                    if !is_valid_ref_name(&name) {
+                        bail!(Invalid, "Invalid symbolic ref: {}", name);
+                    }
+                    target = Some(name.clone());
+                }
+                None => return Ok(Head { target, commit_id: Some(value) }),
+            }
+        }
+        
+        bail!(Invalid, "Symbolic ref loop at HEAD of {}", repo_hash)
+    }
+    
+    /// Point HEAD at a branch (`refs/...`) or detach it at a commit id
+    pub fn set_head(&self, repo_hash: &str, target: &str) -> Result<()> {
+        let content = if is_object_id(target) {
+            format!("{}\n", target)
+        } else if is_valid_ref_name(target) {
+            format!("ref: {}\n", target)
+        } else {
+            bail!(Invalid, "Invalid HEAD target: {}", target);
+        };
+        check_repo_name(repo_hash)?;
+        
+        let repo_path = self.repo_path(repo_hash);
+        if !repo_path.exists() {
+            bail!(NotFound, "Repository {}", repo_hash);
+        }
+        
+        write_atomic(&repo_path.join("HEAD"), content.as_bytes())
+    }
+    
+    /// List all refs under `refs/` as (name, commit id) pairs, sorted by name
+    pub fn list_refs(&self, repo_hash: &str) -> Result<Vec<(String, String)>> {
+        check_repo_name(repo_hash)?;
+        let refs_dir = self.refs_path(repo_hash);
+        let mut refs = Vec::new();
+        
+        if !refs_dir.exists() {
+            return Ok(refs);
         }
         
-        for entry in fs::read_dir(objects_dir)? {
+        for entry in walkdir::WalkDir::new(&refs_dir) {
             let entry = entry?;
-            let subdir_name = entry.file_name();
-            let subdir_path = entry.path();
+            if !entry.file_type().is_file() || is_temp_file(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
             
-            if subdir_path.is_dir() {
-                for obj_entry in fs::read_dir(subdir_path)? {
-                    let obj_entry = obj_entry?;
-                    let obj_name = obj_entry.file_name();
-                    let object_id = format!(
-                        "{}{}",
-                        subdir_name.to_string_lossy(),
-                        obj_name.to_string_lossy()
-                    );
-                    objects.push(object_id);
+            let relative = entry.path().strip_prefix(self.repo_path(repo_hash)).map_err(anyhow::Error::from)?;
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            
+            let commit_id = fs::read_to_string(entry.path())?.trim().to_string();
+            if !commit_id.is_empty() {
+                refs.push((name, commit_id));
+            }
+        }
+        
+        refs.sort();
+        Ok(refs)
+    }
+    
+    /// BLAKE3 of the repo's sorted object ids, one per line. Two copies
+    /// with the same manifest hold the same objects.
+    pub fn manifest_hash(&self, repo_hash: &str) -> Result<String> {
+        Ok(manifest_of(self.list_objects(repo_hash)?))
+    }
+    
+    /// List all objects in a repository, across all tiers
+    pub fn list_objects(&self, repo_hash: &str) -> Result<Vec<String>> {
+        check_repo_name(repo_hash)?;
+        let mut objects = list_objects_in(&self.objects_path(repo_hash))?;
+        
+        if self.tiers.len() > 1 {
+            let mut seen: std::collections::HashSet<String> = objects.iter().cloned().collect();
+            for tier in 1..self.tiers.len() {
+                for object_id in list_objects_in(&self.tier_objects_path(tier, repo_hash))? {
+                    if seen.insert(object_id.clone()) {
+                        objects.push(object_id);
+                    }
                 }
             }
         }
@@ -155,33 +1043,117 @@ impl GitStorage {
         
         for entry in fs::read_dir(&self.base_path)? {
             let entry = entry?;
-            if entry.path().is_dir() {
-                if let Some(name) = entry.file_name().to_str() {
-                    repos.push(name.to_string());
-                }
+            if !entry.path().is_dir() || entry.file_name() == POOL_DIR || entry.file_name() == SPOOL_DIR {
+                continue;
+            }
+            // Skips promote's staging copies and other dot entries
+            if let Some(name) = entry.file_name().to_str().filter(|name| is_repo_name(name)) {
+                repos.push(name.to_string());
             }
         }
         
         Ok(repos)
     }
     
-    /// Get repository size
+    /// Get repository size, including objects on slower tiers
     pub fn get_repo_size(&self, repo_hash: &str) -> Result<u64> {
-        let repo_path = self.repo_path(repo_hash);
+        let mut total_size = dir_size(&self.repo_path(repo_hash))?;
         
-        if !repo_path.exists() {
-            return Ok(0);
+        for tier in 1..self.tiers.len() {
+            total_size += dir_size(&self.tier_objects_path(tier, repo_hash))?;
         }
         
-        let mut total_size = 0u64;
-        for entry in walkdir::WalkDir::new(&repo_path) {
-            let entry = entry?;
-            if entry.file_type().is_file() {
-                total_size += entry.metadata()?.len();
+        Ok(total_size)
+    }
+    
+    /// Per-repo sizes and object counts, plus the pool and the spool at
+    /// `spool_path`
+    pub fn usage_breakdown(&self, spool_path: &Path) -> Result<UsageBreakdown> {
+        let mut repos = self
+            .list_hosted_repos()?
+            .into_iter()
+            .map(|repo_hash| {
+                Ok(RepoUsage {
+                    size: self.get_repo_size(&repo_hash)?,
+                    objects: self.object_count(&repo_hash)?,
+                    pack_cache: dir_size(&self.repo_path(&repo_hash).join(PACK_CACHE_DIR))?,
+                    repo_hash,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        repos.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.repo_hash.cmp(&b.repo_hash)));
+        
+        Ok(UsageBreakdown {
+            repos,
+            pool: dir_size(&self.base_path.join(POOL_DIR))?,
+            dedup: self.dedup_stats()?,
+            spool: dir_size(spool_path)?,
+        })
+    }
+    
+    /// Whether every object a stored commit, tree or tag points at is
+    /// stored too. A partial replica has dangling references.
+    pub fn is_complete(&self, repo_hash: &str) -> Result<bool> {
+        let objects: std::collections::HashSet<String> =
+            self.list_objects(repo_hash)?.into_iter().collect();
+        
+        for object_id in &objects {
+            let raw = self.read_object(repo_hash, object_id)?;
+            let (object_type, body) = pack::parse_loose_object(&raw)?;
+            if object_type == ObjectType::Blob {
+                continue;
+            }
+            
+            if pack::referenced_ids(object_type, body)?.iter().any(|id| !objects.contains(id)) {
+                return Ok(false);
             }
         }
         
-        Ok(total_size)
+        Ok(true)
+    }
+    
+    /// Objects reachable from `tips` but not from `haves`, following the
+    /// links in commits, trees and tags. Everything a have points at is
+    /// taken to be present, as in git's fetch negotiation. Objects missing
+    /// here are skipped, so a partial replica sends what it has.
+    pub fn objects_since(&self, repo_hash: &str, tips: &[String], haves: &[String]) -> Result<Vec<String>> {
+        let known = self.reachable(repo_hash, haves, &std::collections::HashSet::new())?;
+        let mut wanted: Vec<String> = self.reachable(repo_hash, tips, &known)?.into_iter().collect();
+        wanted.sort();
+        Ok(wanted)
+    }
+    
+    /// Stored objects reachable from `start`, not walking into `stop`
+    fn reachable(
+        &self,
+        repo_hash: &str,
+        start: &[String],
+        stop: &std::collections::HashSet<String>,
+    ) -> Result<std::collections::HashSet<String>> {
+        let mut seen = std::collections::HashSet::new();
+        let mut pending = start.to_vec();
+        
+        while let Some(object_id) = pending.pop() {
+            if stop.contains(&object_id) || seen.contains(&object_id) || !self.object_exists(repo_hash, &object_id) {
+                continue;
+            }
+            
+            let raw = self.read_object(repo_hash, &object_id)?;
+            let (object_type, body) = pack::parse_loose_object(&raw)?;
+            pending.extend(pack::referenced_ids(object_type, body)?);
+            seen.insert(object_id);
+        }
+        
+        Ok(seen)
+    }
+    
+    /// Packfile holding the given objects
+    pub fn write_pack(&self, repo_hash: &str, object_ids: &[String]) -> Result<Vec<u8>> {
+        let mut writer = pack::PackWriter::new();
+        for object_id in object_ids {
+            writer.add_loose_object(&self.read_object(repo_hash, object_id)?)?;
+        }
+        Ok(writer.finish())
     }
     
     /// Get total storage usage
@@ -195,6 +1167,32 @@ impl GitStorage {
         Ok(total)
     }
     
+    /// Check that the storage directory accepts writes. Each call probes
+    /// with a file of its own, so concurrent checks (e.g. `/ready` and a
+    /// `doctor` run) can't remove each other's.
+    pub fn check_writable(&self) -> Result<()> {
+        static NEXT_PROBE: AtomicU64 = AtomicU64::new(0);
+        let probe = self.base_path.join(format!(
+            ".write-probe.{}.{}",
+            std::process::id(),
+            NEXT_PROBE.fetch_add(1, Ordering::Relaxed)
+        ));
+        write_atomic(&probe, b"ok")?;
+        fs::remove_file(probe)?;
+        Ok(())
+    }
+    
+    /// Space left for objects: free disk on each tier, capped by the
+    /// tier's configured capacity
+    pub fn available_disk_space(&self) -> Result<u64> {
+        let mut total = 0u64;
+        for tier in &self.tiers {
+            let free = disk_free(&tier.path)?.min(tier.capacity.saturating_sub(tier.used()));
+            total = total.saturating_add(free);
+        }
+        Ok(total)
+    }
+    
     /// Verify object integrity
     pub fn verify_object(&self, repo_hash: &str, object_id: &str) -> Result<bool> {
         let data = self.read_object(repo_hash, object_id)?;
@@ -204,25 +1202,1938 @@ impl GitStorage {
         Ok(!data.is_empty())
     }
     
-    /// Delete a repository
+    /// Whether a repo's directory is on disk, rather than the repo only
+    /// being remembered as hosted. A freshly initialized repo with nothing
+    /// in it yet counts.
+    pub fn repo_on_disk(&self, repo_hash: &str) -> Result<bool> {
+        Ok(self.repo_path(repo_hash).is_dir())
+    }
+    
+    /// Delete a repository from every tier
     pub fn delete_repo(&self, repo_hash: &str) -> Result<()> {
-        let repo_path = self.repo_path(repo_hash);
-        if repo_path.exists() {
-            fs::remove_dir_all(repo_path)?;
+        for tier in (0..self.tiers.len()).rev() {
+            let repo_path = self.tiers[tier].path.join(repo_hash);
+            if repo_path.exists() {
+                let size = dir_size(&self.tier_objects_path(tier, repo_hash))?;
+                fs::remove_dir_all(repo_path)?;
+                self.tiers[tier].sub_used(size);
+            }
+        }
+        self.object_counts.lock().unwrap().remove(repo_hash);
+        self.prune_pool()?;
+        Ok(())
+    }
+    
+    /// Remove one object from a repository. Unlinking is atomic: readers
+    /// that already opened the file finish reading it, later ones get not
+    /// found. Returns false if the repo didn't have the object.
+    pub fn delete_object(&self, repo_hash: &str, object_id: &str) -> Result<bool> {
+        check_object_ref(repo_hash, object_id)?;
+        self.object_count(repo_hash)?;
+        
+        let mut found = false;
+        for tier in 0..self.tiers.len() {
+            let object_path = self.tier_object_path(tier, repo_hash, object_id);
+            let size = match fs::metadata(&object_path) {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            match fs::remove_file(&object_path) {
+                Ok(()) => {}
+                // Deleted concurrently
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+            self.tiers[tier].sub_used(size);
+            found = true;
+        }
+        
+        if !found {
+            return Ok(false);
+        }
+        self.adjust_object_count(repo_hash, -1)?;
+        
+        // Drop the pooled copy once no repo links to it
+        let pool_path = self.pool_path(object_id);
+        if fs::metadata(&pool_path).is_ok_and(|m| link_count(&m) <= 1) {
+            let _ = fs::remove_file(&pool_path);
+        }
+        self.pool_changed();
+        
+        self.invalidate_pack_cache(repo_hash)?;
+        Ok(true)
+    }
+    
+    /// Objects that no ref or HEAD leads to and that were last written
+    /// before `cutoff`. Recent ones are kept because a push uploads its
+    /// objects before moving the ref.
+    pub fn unreachable_objects(&self, repo_hash: &str, cutoff: std::time::SystemTime) -> Result<Vec<String>> {
+        let mut tips: Vec<String> = self.list_refs(repo_hash)?
+            .into_iter()
+            .map(|(_, object_id)| object_id)
+            .collect();
+        if let Ok(Head { commit_id: Some(commit_id), .. }) = self.read_head(repo_hash) {
+            tips.push(commit_id);
+        }
+        
+        let live = self.reachable(repo_hash, &tips, &std::collections::HashSet::new())?;
+        let mut garbage = Vec::new();
+        for object_id in self.list_objects(repo_hash)? {
+            if live.contains(&object_id) {
+                continue;
+            }
+            let modified = fs::metadata(self.object_path(repo_hash, &object_id))?.modified()?;
+            if modified < cutoff {
+                garbage.push(object_id);
+            }
+        }
+        
+        garbage.sort();
+        Ok(garbage)
+    }
+    
+    /// Separate store for assembling a replica out of sight of readers,
+    /// written with the same compression. Move the result live with
+    /// [`GitStorage::promote`].
+    pub fn spool(&self, spool_path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            compression: self.compression,
+            fsync: self.fsync,
+            ..Self::new(spool_path)?
+        })
+    }
+    
+    /// Move a repo assembled in `spool` into this store in one step, so it
+    /// is either absent or complete. Refuses to replace a live repo.
+    pub fn promote(&self, spool: &GitStorage, repo_hash: &str) -> Result<()> {
+        if !is_repo_name(repo_hash) {
+            bail!(Invalid, "Invalid repository hash: {}", repo_hash);
+        }
+        let source = spool.repo_path(repo_hash);
+        let target = self.repo_path(repo_hash);
+        
+        if target.exists() {
+            bail!(Conflict, "{} is already stored here", repo_hash);
+        }
+        let size = dir_size(&spool.objects_path(repo_hash))?;
+        
+        match fs::rename(&source, &target) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                // Copy next to the target so the final step is still a rename
+                let staging = self.base_path.join(format!(".{}{}", repo_hash, TEMP_SUFFIX));
+                let _ = fs::remove_dir_all(&staging);
+                let result = copy_dir(&source, &staging).and_then(|()| Ok(fs::rename(&staging, &target)?));
+                if result.is_err() {
+                    let _ = fs::remove_dir_all(&staging);
+                }
+                result?;
+                fs::remove_dir_all(&source)?;
+            }
+            Err(e) => return Err(e.into()),
         }
+        
+        self.tiers[0].add_used(size);
+        // The spool's count file moved in with the repo
+        self.object_counts.lock().unwrap().remove(repo_hash);
+        
+        // The repo is live either way; unpooled objects only cost space
+        if self.dedup {
+            if let Err(e) = self.pool_repo_objects(repo_hash) {
+                tracing::warn!("Failed to deduplicate promoted repo {}: {}", repo_hash, e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Share a repo's objects through the pool. Spooled objects are written
+    /// without it, so each is either added to the pool or swapped for a
+    /// link to the copy already there.
+    fn pool_repo_objects(&self, repo_hash: &str) -> Result<()> {
+        for object_id in list_objects_in(&self.objects_path(repo_hash))? {
+            let object_path = self.tier_object_path(0, repo_hash, &object_id);
+            let pool_path = self.pool_path(&object_id);
+            if let Some(parent) = pool_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            
+            match fs::hard_link(&object_path, &pool_path) {
+                Ok(()) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            
+            // Swap the private copy for the pooled one with a rename, so
+            // readers always find the object
+            let own_size = fs::metadata(&object_path)?.len();
+            let tmp_path = object_path.with_file_name(format!(
+                ".{}.{}{}",
+                &object_id[2..],
+                rand::random::<u32>(),
+                TEMP_SUFFIX
+            ));
+            match fs::hard_link(&pool_path, &tmp_path) {
+                Ok(()) => {}
+                // Pruned in the meantime; keep the private copy
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+            if let Err(e) = fs::rename(&tmp_path, &object_path) {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(e.into());
+            }
+            let pooled_size = fs::metadata(&object_path)?.len();
+            self.tiers[0].sub_used(own_size);
+            self.tiers[0].add_used(pooled_size);
+        }
+        self.pool_changed();
         Ok(())
     }
     
-    /// Create a packfile from objects
+    /// Create a packfile from objects, reusing the cached copy while the
+    /// repository's objects and refs are unchanged
     pub fn create_pack(&self, repo_hash: &str) -> Result<Vec<u8>> {
-        let objects = self.list_objects(repo_hash)?;
-        let mut pack_data = Vec::new();
+        check_repo_name(repo_hash)?;
+        // The cache lives in the repo; never create one for a missing repo
+        if !self.repo_path(repo_hash).is_dir() {
+            bail!(NotFound, "Repository {}", repo_hash);
+        }
         
-        for object_id in objects {
-            let data = self.read_object(repo_hash, &object_id)?;
+        let mut objects = self.list_objects(repo_hash)?;
+        objects.sort();
+        
+        let cache_path = self.pack_cache_path(repo_hash, &objects)?;
+        if let Ok(cached) = fs::read(&cache_path) {
+            return Ok(cached);
+        }
+        
+        let mut pack_data = Vec::new();
+        for object_id in &objects {
+            let data = self.read_object(repo_hash, object_id)?;
             pack_data.extend_from_slice(&data);
         }
         
+        // Replace any pack built for an older state of the repo. Only the
+        // stale files go, so a concurrent build keeps its directory.
+        self.remove_cached_packs(repo_hash, cache_path.file_name())?;
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&cache_path, &pack_data)?;
+        
         Ok(pack_data)
     }
+    
+    /// Cache file for a pack, keyed by a hash of the object ids and refs
+    fn pack_cache_path(&self, repo_hash: &str, sorted_objects: &[String]) -> Result<PathBuf> {
+        let mut hasher = blake3::Hasher::new();
+        for object_id in sorted_objects {
+            hasher.update(object_id.as_bytes());
+            hasher.update(b"\n");
+        }
+        for (name, commit_id) in self.list_refs(repo_hash)? {
+            hasher.update(format!("{} {}\n", name, commit_id).as_bytes());
+        }
+        
+        let key = hasher.finalize().to_hex();
+        Ok(self.repo_path(repo_hash)
+            .join(PACK_CACHE_DIR)
+            .join(format!("{}.pack", &key[..32])))
+    }
+    
+    /// Drop the cached pack after a write
+    fn invalidate_pack_cache(&self, repo_hash: &str) -> Result<()> {
+        self.remove_cached_packs(repo_hash, None)
+    }
+    
+    /// Delete the repo's cached `*.pack` files other than `keep`
+    fn remove_cached_packs(&self, repo_hash: &str, keep: Option<&std::ffi::OsStr>) -> Result<()> {
+        let entries = match fs::read_dir(self.repo_path(repo_hash).join(PACK_CACHE_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            if Some(name.as_os_str()) == keep || !name.to_string_lossy().ends_with(".pack") {
+                continue;
+            }
+            match fs::remove_file(entry.path()) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Async wrappers that move blocking disk I/O onto Tokio's blocking pool,
+/// so handlers don't stall the reactor on large or slow reads/writes
+impl GitStorage {
+    async fn blocking<T, F>(self: &Arc<Self>, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&GitStorage) -> Result<T> + Send + 'static,
+    {
+        let storage = Arc::clone(self);
+        tokio::task::spawn_blocking(move || f(&storage)).await.map_err(anyhow::Error::from)?
+    }
+    
+    pub async fn read_object_async(self: &Arc<Self>, repo_hash: &str, object_id: &str) -> Result<Vec<u8>> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| s.read_object(&repo_hash, &object_id)).await
+    }
+    
+    pub async fn store_object_async(self: &Arc<Self>, repo_hash: &str, object_id: &str, data: Vec<u8>) -> Result<()> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| s.store_object(&repo_hash, &object_id, &data)).await
+    }
+    
+    pub async fn store_object_batched_async(self: &Arc<Self>, repo_hash: &str, object_id: &str, data: Vec<u8>) -> Result<()> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| s.store_object_batched(&repo_hash, &object_id, &data)).await
+    }
+    
+    pub async fn finish_object_async(self: &Arc<Self>, repo_hash: &str, object_id: &str, upload: ObjectUpload) -> Result<u64> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| s.finish_object(&repo_hash, &object_id, upload)).await
+    }
+    
+    pub async fn sync_pending_async(self: &Arc<Self>) -> Result<()> {
+        self.blocking(|s| s.sync_pending()).await
+    }
+    
+    pub async fn delete_object_async(self: &Arc<Self>, repo_hash: &str, object_id: &str) -> Result<bool> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| s.delete_object(&repo_hash, &object_id)).await
+    }
+    
+    pub async fn update_ref_async(self: &Arc<Self>, repo_hash: &str, ref_name: &str, commit_id: &str, expected_old: Option<&str>) -> Result<()> {
+        let (repo_hash, ref_name, commit_id) = (repo_hash.to_string(), ref_name.to_string(), commit_id.to_string());
+        let expected_old = expected_old.map(str::to_string);
+        self.blocking(move |s| s.update_ref(&repo_hash, &ref_name, &commit_id, expected_old.as_deref())).await
+    }
+    
+    pub async fn update_refs_async(self: &Arc<Self>, repo_hash: &str, updates: Vec<RefUpdate>) -> Result<Vec<RefConflict>> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.update_refs(&repo_hash, &updates)).await
+    }
+    
+    pub async fn delete_ref_async(self: &Arc<Self>, repo_hash: &str, ref_name: &str, force: bool) -> Result<()> {
+        let (repo_hash, ref_name) = (repo_hash.to_string(), ref_name.to_string());
+        self.blocking(move |s| s.delete_ref(&repo_hash, &ref_name, force)).await
+    }
+    
+    pub async fn read_ref_async(self: &Arc<Self>, repo_hash: &str, ref_name: &str) -> Result<String> {
+        let (repo_hash, ref_name) = (repo_hash.to_string(), ref_name.to_string());
+        self.blocking(move |s| s.read_ref(&repo_hash, &ref_name)).await
+    }
+    
+    pub async fn read_head_async(self: &Arc<Self>, repo_hash: &str) -> Result<Head> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.read_head(&repo_hash)).await
+    }
+    
+    pub async fn set_head_async(self: &Arc<Self>, repo_hash: &str, target: &str) -> Result<()> {
+        let (repo_hash, target) = (repo_hash.to_string(), target.to_string());
+        self.blocking(move |s| s.set_head(&repo_hash, &target)).await
+    }
+    
+    pub async fn list_refs_async(self: &Arc<Self>, repo_hash: &str) -> Result<Vec<(String, String)>> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.list_refs(&repo_hash)).await
+    }
+    
+    pub async fn object_exists_async(self: &Arc<Self>, repo_hash: &str, object_id: &str) -> Result<bool> {
+        let (repo_hash, object_id) = (repo_hash.to_string(), object_id.to_string());
+        self.blocking(move |s| Ok(s.object_exists(&repo_hash, &object_id))).await
+    }
+    
+    pub async fn object_count_async(self: &Arc<Self>, repo_hash: &str) -> Result<u64> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.object_count(&repo_hash)).await
+    }
+    
+    pub async fn list_objects_async(self: &Arc<Self>, repo_hash: &str) -> Result<Vec<String>> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.list_objects(&repo_hash)).await
+    }
+    
+    pub async fn create_pack_async(self: &Arc<Self>, repo_hash: &str) -> Result<Vec<u8>> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.create_pack(&repo_hash)).await
+    }
+    
+    /// Pack of the objects [`GitStorage::objects_since`] selects
+    pub async fn pack_since_async(self: &Arc<Self>, repo_hash: &str, tips: Vec<String>, haves: Vec<String>) -> Result<Vec<u8>> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.write_pack(&repo_hash, &s.objects_since(&repo_hash, &tips, &haves)?)).await
+    }
+    
+    pub async fn manifest_hash_async(self: &Arc<Self>, repo_hash: &str) -> Result<String> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.manifest_hash(&repo_hash)).await
+    }
+    
+    pub async fn repo_on_disk_async(self: &Arc<Self>, repo_hash: &str) -> Result<bool> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.repo_on_disk(&repo_hash)).await
+    }
+    
+    pub async fn get_repo_size_async(self: &Arc<Self>, repo_hash: &str) -> Result<u64> {
+        let repo_hash = repo_hash.to_string();
+        self.blocking(move |s| s.get_repo_size(&repo_hash)).await
+    }
+    
+    pub async fn get_storage_usage_async(self: &Arc<Self>) -> Result<u64> {
+        self.blocking(|s| s.get_storage_usage()).await
+    }
+    
+    pub async fn usage_breakdown_async(self: &Arc<Self>, spool_path: PathBuf) -> Result<UsageBreakdown> {
+        self.blocking(move |s| s.usage_breakdown(&spool_path)).await
+    }
+    
+    pub async fn dedup_stats_async(self: &Arc<Self>) -> Result<DedupStats> {
+        self.blocking(|s| s.dedup_stats()).await
+    }
+    
+    pub async fn check_writable_async(self: &Arc<Self>) -> Result<()> {
+        self.blocking(|s| s.check_writable()).await
+    }
+    
+    pub async fn promote_async(self: &Arc<Self>, spool: &Arc<GitStorage>, repo_hash: &str) -> Result<()> {
+        let (spool, repo_hash) = (Arc::clone(spool), repo_hash.to_string());
+        self.blocking(move |s| s.promote(&spool, &repo_hash)).await
+    }
+}
+
+/// An object being compressed as it arrives, see [`GitStorage::begin_object`].
+/// The first [`SNIFF_BYTES`] are held back to decide how to store it: a
+/// blob starting with the magic bytes of a compressed format, or whose
+/// start barely compresses, is kept uncompressed.
+pub struct ObjectUpload {
+    compression: Compression,
+    state: UploadState,
+    /// Uncompressed bytes written so far
+    size: u64,
+}
+
+enum UploadState {
+    /// Start of the object, until there's enough of it to judge
+    Sniffing(Vec<u8>),
+    Compressed(ZlibEncoder<Vec<u8>>),
+    /// Stored as is, and why
+    Raw(Vec<u8>, &'static str),
+}
+
+impl ObjectUpload {
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.size += data.len() as u64;
+        if self.size > MAX_OBJECT_SIZE {
+            bail!(TooLarge, "Object is larger than {} bytes", MAX_OBJECT_SIZE);
+        }
+        match &mut self.state {
+            UploadState::Sniffing(head) => {
+                head.extend_from_slice(data);
+                if head.len() >= SNIFF_BYTES {
+                    self.decide()?;
+                }
+            }
+            UploadState::Compressed(encoder) => encoder.write_all(data)?,
+            UploadState::Raw(raw, _) => raw.extend_from_slice(data),
+        }
+        Ok(())
+    }
+    
+    /// Pick compressed or raw storage from what has been written so far
+    fn decide(&mut self) -> Result<()> {
+        let UploadState::Sniffing(head) = &mut self.state else {
+            return Ok(());
+        };
+        let head = std::mem::take(head);
+        self.state = match incompressible(&head) {
+            Some(reason) => UploadState::Raw(head, reason),
+            None => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
+                encoder.write_all(&head)?;
+                UploadState::Compressed(encoder)
+            }
+        };
+        Ok(())
+    }
+    
+    /// The bytes to store, and why they weren't compressed if they weren't
+    fn finish(mut self) -> Result<(Vec<u8>, Option<&'static str>)> {
+        self.decide()?;
+        match self.state {
+            UploadState::Compressed(encoder) => Ok((encoder.finish()?, None)),
+            // Only a complete loose object reads back without inflating
+            UploadState::Raw(raw, reason) if pack::parse_loose_object(&raw).is_ok() => Ok((raw, Some(reason))),
+            UploadState::Raw(raw, _) => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
+                encoder.write_all(&raw)?;
+                Ok((encoder.finish()?, None))
+            }
+            UploadState::Sniffing(_) => unreachable!("decided above"),
+        }
+    }
+}
+
+/// Why the start of a loose object shows it isn't worth compressing, or
+/// None if it should be compressed. Only blobs are judged: trees, commits
+/// and tags are text and ids, and compress well.
+fn incompressible(head: &[u8]) -> Option<&'static str> {
+    let nul = head.iter().take(32).position(|&b| b == 0)?;
+    if !head.starts_with(b"blob ") {
+        return None;
+    }
+    let body = &head[nul + 1..];
+    if body.len() < MIN_UNCOMPRESSED_SIZE {
+        return None;
+    }
+    
+    if COMPRESSED_MAGIC.iter().any(|magic| body.starts_with(magic)) {
+        return Some("compressed file format");
+    }
+    
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(body).ok()?;
+    let compressed = encoder.finish().ok()?;
+    if compressed.len() as f64 > body.len() as f64 * INCOMPRESSIBLE_RATIO {
+        return Some("sample doesn't compress");
+    }
+    None
+}
+
+/// Manifest hash (see [`GitStorage::manifest_hash`]) of a list of object ids
+pub fn manifest_of(mut object_ids: Vec<String>) -> String {
+    object_ids.sort();
+    
+    let mut hasher = blake3::Hasher::new();
+    for object_id in &object_ids {
+        hasher.update(object_id.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Object ids under one `objects/` directory, skipping in-progress writes
+fn list_objects_in(objects_dir: &Path) -> Result<Vec<String>> {
+    let mut objects = Vec::new();
+    
+    if !objects_dir.exists() {
+        return Ok(objects);
+    }
+    
+    for entry in fs::read_dir(objects_dir)? {
+        let entry = entry?;
+        let subdir_name = entry.file_name();
+        let subdir_path = entry.path();
+        
+        if subdir_path.is_dir() {
+            for obj_entry in fs::read_dir(subdir_path)? {
+                let obj_entry = obj_entry?;
+                let obj_name = obj_entry.file_name();
+                let object_id = format!(
+                    "{}{}",
+                    subdir_name.to_string_lossy(),
+                    obj_name.to_string_lossy()
+                );
+                // Skips temp files and anything else that isn't an object
+                if is_object_id(&object_id) {
+                    objects.push(object_id);
+                }
+            }
+        }
+    }
+    
+    Ok(objects)
+}
+
+/// Take the instance lock on a store and record our pid in it
+fn lock_store(base_path: &Path) -> Result<fs::File> {
+    let lock_path = base_path.join(LOCK_FILE);
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)?;
+    
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(fs::TryLockError::WouldBlock) => {
+            let holder = fs::read_to_string(&lock_path).unwrap_or_default();
+            bail!(
+                Config,
+                "Storage at {} is already in use by another hyrule-node instance (pid {})",
+                base_path.display(),
+                holder.trim()
+            );
+        }
+        Err(fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+    
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    file.sync_all()?;
+    Ok(file)
+}
+
+/// Whether a store is stamped with the current format, so opening it
+/// needs neither a migration nor a stamp
+fn store_is_current(base_path: &Path, migrations: &[Migration]) -> bool {
+    fs::read_to_string(base_path.join(STORE_VERSION_FILE))
+        .is_ok_and(|content| content.trim().parse::<u32>().ok() == Some(migrations.len() as u32))
+}
+
+/// Whether a store holds nothing yet besides its lock file
+fn store_is_empty(base_path: &Path) -> Result<bool> {
+    for entry in fs::read_dir(base_path)? {
+        if entry?.file_name() != LOCK_FILE {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Stamp a new store with the current format, migrate an older one in
+/// place and refuse one written by a newer build. A store with data but
+/// no version file predates versioning and counts as version 0. Only
+/// called with the instance lock held.
+fn check_store_version(base_path: &Path, migrations: &[Migration]) -> Result<()> {
+    let current = migrations.len() as u32;
+    let version_path = base_path.join(STORE_VERSION_FILE);
+    
+    let mut version = match fs::read_to_string(&version_path) {
+        Ok(content) => content.trim().parse::<u32>().map_err(|_| {
+            HyruleError::Config(format!("Unreadable store version {:?} in {}", content.trim(), version_path.display()))
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if store_is_empty(base_path)? {
+                return write_atomic(&version_path, format!("{}\n", current).as_bytes());
+            }
+            0
+        }
+        Err(e) => return Err(e.into()),
+    };
+    
+    if version > current {
+        bail!(
+            Config,
+            "Storage at {} uses store format {}, but this hyrule-node only supports up to {}. \
+             Upgrade hyrule-node, or point storage_path at a different directory.",
+            base_path.display(),
+            version,
+            current
+        );
+    }
+    
+    while version < current {
+        tracing::info!("Migrating storage at {} from format {} to {}", base_path.display(), version, version + 1);
+        migrations[version as usize](base_path).map_err(|e| {
+            HyruleError::Other(anyhow::anyhow!("Storage migration from format {} failed: {}", version, e))
+        })?;
+        version += 1;
+        // Record each step so an interrupted upgrade resumes where it stopped
+        write_atomic(&version_path, format!("{}\n", version).as_bytes())?;
+    }
+    
+    Ok(())
+}
+
+/// Recursively copy a directory tree
+fn copy_dir(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &path)?;
+        } else {
+            fs::copy(entry.path(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Total size of the files under a directory; 0 if it doesn't exist
+fn dir_size(path: &Path) -> Result<u64> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    
+    let mut total = 0u64;
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+/// Number of hard links to a file
+#[cfg(unix)]
+fn link_count(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Device and inode of a file, the same for all of its hard links
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Bytes of objects stored on a tier, summed over its repositories
+fn tier_objects_size(tier_path: &Path) -> Result<u64> {
+    let mut total = 0u64;
+    for entry in fs::read_dir(tier_path)? {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            total += dir_size(&entry.path().join("objects"))?;
+        }
+    }
+    Ok(total)
+}
+
+/// Size of the volume holding a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiskSpace {
+    pub total: u64,
+    /// Free space available to this process
+    pub available: u64,
+}
+
+/// Flush everything written to the filesystem holding `path`
+#[cfg(target_os = "linux")]
+fn syncfs(path: &Path) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    
+    let dir = fs::File::open(path)?;
+    // SAFETY: `dir` stays open for the duration of the call
+    if unsafe { libc::syncfs(dir.as_raw_fd()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Sync each file, then each directory they were renamed into once
+#[cfg(not(target_os = "linux"))]
+fn sync_each(paths: &[PathBuf]) -> Result<()> {
+    let mut dirs = std::collections::BTreeSet::new();
+    for path in paths {
+        match fs::File::open(path) {
+            Ok(file) => file.sync_all()?,
+            // Deleted or moved since; nothing left to sync
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(dir) = path.parent() {
+            dirs.insert(dir);
+        }
+    }
+    
+    #[cfg(unix)]
+    for dir in dirs {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Size and free space of the volume holding `path`
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Result<DiskSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(anyhow::Error::from)?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` is a
+    // properly sized, writable statvfs struct
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    
+    Ok(DiskSpace {
+        total: stat.f_blocks as u64 * stat.f_frsize as u64,
+        available: stat.f_bavail as u64 * stat.f_frsize as u64,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> Result<DiskSpace> {
+    Err(anyhow::anyhow!("Disk size detection is only supported on Unix").into())
+}
+
+/// Free space available to this process on the volume holding `path`
+#[cfg(unix)]
+fn disk_free(path: &Path) -> Result<u64> {
+    Ok(disk_space(path)?.available)
+}
+
+/// Free space is not queried on non-unix platforms
+#[cfg(not(unix))]
+fn disk_free(_path: &Path) -> Result<u64> {
+    Ok(u64::MAX)
+}
+
+/// Current value of a ref file, None when it doesn't exist
+fn read_ref_file(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(value) => Ok(Some(value.trim().to_string())),
+        Err(e) if matches!(
+            e.kind(),
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+        ) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A full SHA-1 object id
+pub fn is_object_id(value: &str) -> bool {
+    value.len() == 40 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A repo directory name that can't step outside the storage root
+pub fn is_repo_name(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+pub fn check_repo_name(repo_hash: &str) -> Result<()> {
+    if !is_repo_name(repo_hash) {
+        bail!(Invalid, "Invalid repository hash: {}", repo_hash);
+    }
+    Ok(())
+}
+
+/// Refuse repo and ref names that could step outside the repo before they
+/// become paths. `HEAD` passes only with `allow_head`.
+pub fn check_ref_path(repo_hash: &str, ref_name: &str, allow_head: bool) -> Result<()> {
+    check_repo_name(repo_hash)?;
+    if !(is_valid_ref_name(ref_name) || allow_head && ref_name == "HEAD") {
+        bail!(Invalid, "Invalid ref name: {}", ref_name);
+    }
+    Ok(())
+}
+
+/// Refuse names that don't identify an object before they become paths
+pub fn check_object_ref(repo_hash: &str, object_id: &str) -> Result<()> {
+    check_repo_name(repo_hash)?;
+    if !is_object_id(object_id) {
+        bail!(Invalid, "Invalid object id: {}", object_id);
+    }
+    Ok(())
+}
+
+/// Contents of an object file: zlib data, or a raw loose object
+/// (`<type> <size>\0<body>`) for blobs that don't compress and objects
+/// left uncompressed by older versions or imports
+fn decode_object(stored: &[u8], limit: u64) -> Result<Vec<u8>> {
+    if is_zlib(stored) {
+        return inflate(stored, limit);
+    }
+    if stored.len() as u64 > limit {
+        bail!(TooLarge, "Object is larger than {} bytes", limit);
+    }
+    pack::parse_loose_object(stored)?;
+    Ok(stored.to_vec())
+}
+
+/// Whether data starts with a zlib header: deflate with a window of at
+/// most 32K (so `0x78` or lower) and a valid check value. Raw loose
+/// objects start with a type name, which never passes.
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Decompress zlib data, failing once the output passes `limit` bytes
+fn inflate(compressed: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(limit + 1)
+        .read_to_end(&mut data)?;
+    
+    if data.len() as u64 > limit {
+        bail!(TooLarge, "Object inflates to more than {} bytes", limit);
+    }
+    Ok(data)
+}
+
+/// A ref under `refs/` that stays inside the repo directory
+pub fn is_valid_ref_name(name: &str) -> bool {
+    name.starts_with("refs/")
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != ".." && !part.starts_with('.'))
+}
+
+/// Suffix used for in-progress writes; these are never treated as objects
+const TEMP_SUFFIX: &str = ".tmp";
+
+fn is_temp_file(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(TEMP_SUFFIX)
+}
+
+/// Write a file atomically: write to a temp file in the same directory,
+/// fsync it, then rename over the final path. A crash mid-write leaves
+/// only a stray temp file and the previous contents stay intact.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    write_replace(path, data, true)
+}
+
+/// Write through a temp file and rename, fsyncing the file and its
+/// directory only if `sync` is set
+fn write_replace(path: &Path, data: &[u8], sync: bool) -> Result<()> {
+    let dir = path.parent()
+        .ok_or_else(|| HyruleError::Invalid(format!("Invalid path: {}", path.display())))?;
+    let file_name = path.file_name()
+        .ok_or_else(|| HyruleError::Invalid(format!("Invalid path: {}", path.display())))?
+        .to_string_lossy();
+    
+    let tmp_path = dir.join(format!(
+        ".{}.{}{}",
+        file_name,
+        rand::random::<u32>(),
+        TEMP_SUFFIX
+    ));
+    
+    let result = (|| -> Result<()> {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        if sync {
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+    
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+    
+    // Persist the rename itself
+    #[cfg(unix)]
+    if let Some(dir_handle) = sync.then(|| fs::File::open(dir).ok()).flatten() {
+        let _ = dir_handle.sync_all();
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const REPO: &str = "a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+    const OBJECT: &str = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+    
+    #[test]
+    fn test_store_and_read_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        storage.store_object(REPO, OBJECT, b"blob 5\0hello").unwrap();
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"blob 5\0hello");
+        assert_eq!(storage.list_objects(REPO).unwrap(), vec![OBJECT.to_string()]);
+        assert!(storage.object_exists(REPO, OBJECT));
+        assert!(!storage.object_exists(REPO, &"0".repeat(40)));
+    }
+    
+    #[test]
+    fn test_arbitrary_payloads_round_trip() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let mut rng = StdRng::seed_from_u64(42);
+        
+        let mut sizes = vec![0, 1, 2, 4096, 3 * 1024 * 1024];
+        sizes.extend((0..40).map(|_| rng.gen_range(0..20_000)));
+        
+        let mut ids = Vec::new();
+        for size in sizes {
+            let mut data = vec![0u8; size];
+            rng.fill(&mut data[..]);
+            let id = hex::encode(rng.gen::<[u8; 20]>());
+            
+            storage.store_object(REPO, &id, &data).unwrap();
+            assert_eq!(storage.read_object(REPO, &id).unwrap(), data);
+            ids.push(id);
+        }
+        
+        // Stray files in the object store aren't reported as objects
+        fs::write(storage.objects_path(REPO).join(&ids[0][..2]).join("notes.txt"), b"x").unwrap();
+        fs::create_dir_all(storage.objects_path(REPO).join("pack")).unwrap();
+        fs::write(storage.objects_path(REPO).join("pack").join("pack-1.idx"), b"x").unwrap();
+        
+        let mut listed = storage.list_objects(REPO).unwrap();
+        listed.sort();
+        ids.sort();
+        assert_eq!(listed, ids);
+    }
+    
+    #[test]
+    fn test_rejects_hostile_names_and_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path().join("store")).unwrap();
+        
+        for object_id in ["", "a", "é", "../../../../outside", &format!("{}/x", &OBJECT[..38])] {
+            assert!(storage.store_object(REPO, object_id, b"data").is_err());
+            assert!(storage.read_object(REPO, object_id).is_err());
+            assert!(!storage.object_exists(REPO, object_id));
+        }
+        for repo_hash in ["", "..", "../escape", "a/b"] {
+            assert!(storage.store_object(repo_hash, OBJECT, b"data").is_err());
+            assert!(storage.read_object(repo_hash, OBJECT).is_err());
+        }
+        assert!(!dir.path().join("outside").exists());
+        
+        // A small file that inflates past the limit is refused
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0u8; 64 * 1024]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(inflate(&bomb, 1024).is_err());
+        assert_eq!(inflate(&bomb, 64 * 1024).unwrap().len(), 64 * 1024);
+        assert!(inflate(b"not zlib", 1024).is_err());
+    }
+    
+    #[test]
+    fn test_read_uncompressed_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let raw = b"blob 5\0hello";
+        let object_id = crate::crypto::git_object_id(raw);
+        
+        // Written compressed, as every small object is
+        storage.store_object(REPO, &object_id, raw).unwrap();
+        let path = storage.object_path(REPO, &object_id);
+        assert!(is_zlib(&fs::read(&path).unwrap()));
+        assert_eq!(storage.read_object(REPO, &object_id).unwrap(), raw);
+        
+        // Left uncompressed by an old version or an import
+        fs::write(&path, raw).unwrap();
+        assert_eq!(storage.read_object(REPO, &object_id).unwrap(), raw);
+        assert!(storage.verify_object(REPO, &object_id).unwrap());
+        for header in [&b"tree 0\0"[..], b"commit 0\0", b"tag 0\0"] {
+            assert!(!is_zlib(header));
+        }
+        
+        // Anything else is still corrupt
+        fs::write(&path, b"garbage").unwrap();
+        assert!(matches!(storage.read_object(REPO, &object_id), Err(HyruleError::Corrupt { .. })));
+        fs::write(&path, b"blob 9\0hello").unwrap();
+        assert!(matches!(storage.read_object(REPO, &object_id), Err(HyruleError::Corrupt { .. })));
+        assert!(decode_object(raw, 4).is_err());
+    }
+    
+    #[test]
+    fn test_is_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        let blob = b"blob 5\0hello";
+        let blob_id = crate::crypto::git_object_id(blob);
+        
+        let mut tree_body = b"100644 hello.txt\0".to_vec();
+        tree_body.extend(hex::decode(&blob_id).unwrap());
+        let mut tree = format!("tree {}\0", tree_body.len()).into_bytes();
+        tree.extend(&tree_body);
+        let tree_id = crate::crypto::git_object_id(&tree);
+        
+        let commit_body = format!("tree {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nmsg\n", tree_id);
+        let commit = format!("commit {}\0{}", commit_body.len(), commit_body).into_bytes();
+        
+        storage.store_object(REPO, &crate::crypto::git_object_id(&commit), &commit).unwrap();
+        storage.store_object(REPO, &tree_id, &tree).unwrap();
+        assert!(!storage.is_complete(REPO).unwrap());
+        
+        storage.store_object(REPO, &blob_id, blob).unwrap();
+        assert!(storage.is_complete(REPO).unwrap());
+    }
+    
+    /// Store a loose object, returning its id
+    fn put(storage: &GitStorage, raw: &[u8]) -> String {
+        let id = crate::crypto::git_object_id(raw);
+        storage.store_object(REPO, &id, raw).unwrap();
+        id
+    }
+    
+    fn tree(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, id) in entries {
+            body.extend(format!("100644 {}\0", name).as_bytes());
+            body.extend(hex::decode(id).unwrap());
+        }
+        let mut tree = format!("tree {}\0", body.len()).into_bytes();
+        tree.extend(body);
+        tree
+    }
+    
+    fn commit(tree_id: &str, parent: Option<&str>) -> Vec<u8> {
+        let parent = parent.map(|p| format!("parent {}\n", p)).unwrap_or_default();
+        let body = format!("tree {}\n{}author A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nmsg\n", tree_id, parent);
+        format!("commit {}\0{}", body.len(), body).into_bytes()
+    }
+    
+    #[test]
+    fn test_objects_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        let old_blob = put(&storage, b"blob 3\0old");
+        let old_tree = put(&storage, &tree(&[("a", &old_blob)]));
+        let first = put(&storage, &commit(&old_tree, None));
+        
+        let new_blob = put(&storage, b"blob 3\0new");
+        let new_tree = put(&storage, &tree(&[("a", &old_blob), ("b", &new_blob)]));
+        let second = put(&storage, &commit(&new_tree, Some(&first)));
+        
+        let mut expected = vec![second.clone(), new_tree, new_blob];
+        expected.sort();
+        let since = storage.objects_since(REPO, std::slice::from_ref(&second), std::slice::from_ref(&first)).unwrap();
+        assert_eq!(since, expected);
+        
+        // Without haves the whole history is sent
+        assert_eq!(storage.objects_since(REPO, std::slice::from_ref(&second), &[]).unwrap().len(), 6);
+        // Nothing new, or a have we don't know
+        assert!(storage.objects_since(REPO, std::slice::from_ref(&second), std::slice::from_ref(&second)).unwrap().is_empty());
+        assert_eq!(storage.objects_since(REPO, std::slice::from_ref(&first), &[ZERO_ID.to_string()]).unwrap().len(), 3);
+        
+        let pack = storage.write_pack(REPO, &since).unwrap();
+        assert_eq!(&pack[8..12], &3u32.to_be_bytes());
+    }
+    
+    #[test]
+    fn test_symbolic_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.init_repo(REPO).unwrap();
+        
+        // Fresh repo: HEAD names main, which has no commits yet
+        let head = storage.read_head(REPO).unwrap();
+        assert_eq!(head.target.as_deref(), Some("refs/heads/main"));
+        assert_eq!(head.commit_id, None);
+        
+        storage.update_ref(REPO, "refs/heads/dev", OBJECT, None).unwrap();
+        storage.set_head(REPO, "refs/heads/dev").unwrap();
+        let head = storage.read_head(REPO).unwrap();
+        assert_eq!(head.target.as_deref(), Some("refs/heads/dev"));
+        assert_eq!(head.commit_id.as_deref(), Some(OBJECT));
+        
+        assert!(storage.set_head(REPO, "refs/../../etc").is_err());
+        assert!(storage.set_head(REPO, "main").is_err());
+    }
+    
+    #[test]
+    fn test_concurrent_first_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let objects: Vec<(String, Vec<u8>)> = (0..32u32)
+            .map(|i| {
+                let data = format!("blob {}\0{}", i.to_string().len(), i).into_bytes();
+                (crate::crypto::git_object_id(&data), data)
+            })
+            .collect();
+        
+        std::thread::scope(|scope| {
+            for (object_id, data) in &objects {
+                let storage = &storage;
+                scope.spawn(move || storage.store_object(REPO, object_id, data).unwrap());
+            }
+        });
+        
+        assert_eq!(storage.object_count(REPO).unwrap(), 32);
+        assert_eq!(storage.list_objects(REPO).unwrap().len(), 32);
+        assert_eq!(storage.read_head(REPO).unwrap().target.as_deref(), Some("refs/heads/main"));
+        
+        // Initializing again keeps a HEAD that was moved
+        storage.update_ref(REPO, "refs/heads/dev", OBJECT, None).unwrap();
+        storage.set_head(REPO, "refs/heads/dev").unwrap();
+        storage.init_repo(REPO).unwrap();
+        assert_eq!(storage.read_head(REPO).unwrap().target.as_deref(), Some("refs/heads/dev"));
+    }
+    
+    #[test]
+    fn test_detached_head_and_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.init_repo(REPO).unwrap();
+        
+        storage.set_head(REPO, OBJECT).unwrap();
+        let head = storage.read_head(REPO).unwrap();
+        assert_eq!(head.target, None);
+        assert_eq!(head.commit_id.as_deref(), Some(OBJECT));
+        
+        // Two refs pointing at each other must not hang
+        let refs = storage.refs_path(REPO).join("heads");
+        fs::write(refs.join("a"), "ref: refs/heads/b\n").unwrap();
+        fs::write(refs.join("b"), "ref: refs/heads/a\n").unwrap();
+        storage.set_head(REPO, "refs/heads/a").unwrap();
+        assert!(storage.read_head(REPO).is_err());
+    }
+    
+    #[test]
+    fn test_compare_and_swap_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let other = "1111111111111111111111111111111111111111";
+        
+        // Creating requires the ref to be absent
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, Some(ZERO_ID)).unwrap();
+        let err = storage.update_ref(REPO, "refs/heads/main", other, Some(ZERO_ID)).unwrap_err();
+        let HyruleError::RefConflict(conflict) = err else { panic!("expected a conflict, got {}", err) };
+        assert_eq!(conflict.actual.as_deref(), Some(OBJECT));
+        
+        storage.update_ref(REPO, "refs/heads/main", other, Some(OBJECT)).unwrap();
+        assert_eq!(storage.read_ref(REPO, "refs/heads/main").unwrap(), other);
+    }
+    
+    #[test]
+    fn test_racing_compare_and_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(GitStorage::new(dir.path()).unwrap());
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        
+        for round in 0..20 {
+            let current = storage.read_ref(REPO, "refs/heads/main").unwrap();
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            
+            let handles: Vec<_> = (0..2)
+                .map(|i| {
+                    let storage = storage.clone();
+                    let barrier = barrier.clone();
+                    let current = current.clone();
+                    let new = format!("{:038x}{:x}{:x}", round, i, i);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        storage.update_ref(REPO, "refs/heads/main", &new, Some(&current))
+                    })
+                })
+                .collect();
+            
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            let won = results.iter().filter(|r| r.is_ok()).count();
+            assert_eq!(won, 1, "exactly one racing update must win");
+            assert!(results
+                .iter()
+                .filter_map(|r| r.as_ref().err())
+                .all(|e| matches!(e, HyruleError::RefConflict(_))));
+        }
+    }
+    
+    #[test]
+    fn test_batch_ref_update_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let other = "1111111111111111111111111111111111111111";
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        
+        let update = |ref_name: &str, expected_old: Option<&str>| RefUpdate {
+            ref_name: ref_name.to_string(),
+            commit_id: other.to_string(),
+            expected_old: expected_old.map(str::to_string),
+        };
+        
+        // One stale lease blocks the whole batch
+        let conflicts = storage
+            .update_refs(REPO, &[update("refs/tags/v1", Some(ZERO_ID)), update("refs/heads/main", Some(other))])
+            .unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].ref_name, "refs/heads/main");
+        assert!(storage.read_ref(REPO, "refs/tags/v1").is_err());
+        
+        // A write failure rolls back earlier updates: refs/heads/main is a
+        // file, so refs/heads/main/x can't be created
+        let err = storage.update_refs(REPO, &[update("refs/tags/v1", None), update("refs/heads/main/x", None)]);
+        assert!(err.is_err());
+        assert!(storage.read_ref(REPO, "refs/tags/v1").is_err());
+        
+        let conflicts = storage
+            .update_refs(REPO, &[update("refs/tags/v1", Some(ZERO_ID)), update("refs/heads/main", Some(OBJECT))])
+            .unwrap();
+        assert!(conflicts.is_empty());
+        assert_eq!(storage.read_ref(REPO, "refs/tags/v1").unwrap(), other);
+        assert_eq!(storage.read_ref(REPO, "refs/heads/main").unwrap(), other);
+    }
+    
+    #[test]
+    fn test_delete_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.init_repo(REPO).unwrap();
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        storage.update_ref(REPO, "refs/heads/stale", OBJECT, None).unwrap();
+        
+        storage.delete_ref(REPO, "refs/heads/stale", false).unwrap();
+        let err = storage.delete_ref(REPO, "refs/heads/stale", false).unwrap_err();
+        assert!(matches!(err, HyruleError::NotFound(_)));
+        
+        // HEAD's branch needs force
+        let err = storage.delete_ref(REPO, "refs/heads/main", false).unwrap_err();
+        assert!(matches!(err, HyruleError::RefIsHead(_)));
+        storage.delete_ref(REPO, "refs/heads/main", true).unwrap();
+        assert!(storage.list_refs(REPO).unwrap().is_empty());
+        
+        assert!(storage.delete_ref(REPO, "HEAD", true).is_err());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_dedup_pool_shares_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_dedup(true);
+        let fork = "f".repeat(64);
+        let data = format!("blob 100\0{}", "x".repeat(100));
+        
+        storage.store_object(REPO, OBJECT, data.as_bytes()).unwrap();
+        storage.store_object(&fork, OBJECT, data.as_bytes()).unwrap();
+        assert_eq!(storage.read_object(&fork, OBJECT).unwrap(), data.as_bytes());
+        
+        let size = fs::metadata(storage.object_path(REPO, OBJECT)).unwrap().len();
+        let stats = storage.dedup_stats().unwrap();
+        assert_eq!(stats, DedupStats { pooled_objects: 1, saved_bytes: size });
+        
+        // The pool is not a repo
+        let mut repos = storage.list_hosted_repos().unwrap();
+        repos.sort();
+        assert_eq!(repos, vec![REPO.to_string(), fork.clone()]);
+        
+        // The pooled copy survives until the last repo using it is gone
+        storage.delete_repo(REPO).unwrap();
+        assert_eq!(storage.dedup_stats().unwrap().pooled_objects, 1);
+        assert_eq!(storage.read_object(&fork, OBJECT).unwrap(), data.as_bytes());
+        
+        storage.delete_repo(&fork).unwrap();
+        assert_eq!(storage.dedup_stats().unwrap(), DedupStats::default());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_dedup_stats_count_each_inode_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_dedup(true);
+        let data = b"blob 5\0hello";
+        storage.store_object(REPO, OBJECT, data).unwrap();
+        let stats = storage.dedup_stats().unwrap();
+        
+        // A second pool path for the same file
+        let alias = dir.path().join(POOL_DIR).join("ff").join("f".repeat(38));
+        fs::create_dir_all(alias.parent().unwrap()).unwrap();
+        fs::hard_link(storage.pool_path(OBJECT), &alias).unwrap();
+        
+        // Changes made behind the store's back aren't seen until the pool
+        // changes through it
+        assert_eq!(storage.dedup_stats().unwrap(), stats);
+        let reopened = GitStorage::new(dir.path()).unwrap();
+        assert_eq!(reopened.dedup_stats().unwrap().pooled_objects, 1);
+    }
+    
+    #[test]
+    fn test_delete_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_dedup(true);
+        let fork = "f".repeat(64);
+        let data = b"blob 5\0hello";
+        
+        storage.store_object(REPO, OBJECT, data).unwrap();
+        storage.store_object(&fork, OBJECT, data).unwrap();
+        let used = storage.tier_usage(0).0;
+        
+        assert!(storage.delete_object(REPO, OBJECT).unwrap());
+        assert!(!storage.object_exists(REPO, OBJECT));
+        assert!(storage.tier_usage(0).0 < used);
+        assert!(!storage.delete_object(REPO, OBJECT).unwrap());
+        
+        // The other repo's copy and the pool entry it links to remain
+        assert_eq!(storage.read_object(&fork, OBJECT).unwrap(), data);
+        assert_eq!(storage.dedup_stats().unwrap().pooled_objects, 1);
+        
+        assert!(storage.delete_object(&fork, OBJECT).unwrap());
+        assert_eq!(storage.dedup_stats().unwrap(), DedupStats::default());
+        assert_eq!(storage.tier_usage(0).0, 0);
+    }
+    
+    #[test]
+    fn test_object_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let other = "1111111111111111111111111111111111111111";
+        
+        assert_eq!(storage.object_count(REPO).unwrap(), 0);
+        storage.store_object(REPO, OBJECT, b"blob 1\0a").unwrap();
+        storage.store_object(REPO, other, b"blob 1\0b").unwrap();
+        // Overwrites don't count twice
+        storage.store_object(REPO, OBJECT, b"blob 1\0a").unwrap();
+        assert_eq!(storage.object_count(REPO).unwrap(), 2);
+        
+        storage.delete_object(REPO, other).unwrap();
+        assert_eq!(storage.object_count(REPO).unwrap(), 1);
+        
+        // Persisted across restarts, and recounted if the file goes missing
+        let reopened = GitStorage::new(dir.path()).unwrap();
+        assert_eq!(reopened.object_count(REPO).unwrap(), 1);
+        fs::remove_file(reopened.repo_path(REPO).join(OBJECT_COUNT_FILE)).unwrap();
+        let reopened = GitStorage::new(dir.path()).unwrap();
+        reopened.store_object(REPO, other, b"blob 1\0b").unwrap();
+        assert_eq!(reopened.object_count(REPO).unwrap(), 2);
+        
+        reopened.delete_repo(REPO).unwrap();
+        assert_eq!(reopened.object_count(REPO).unwrap(), 0);
+    }
+    
+    #[test]
+    fn test_fsync_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_fsync_policy(FsyncPolicy::Batch);
+        let other = "1111111111111111111111111111111111111111";
+        
+        storage.store_object_batched(REPO, OBJECT, b"blob 1\0a").unwrap();
+        storage.store_object_batched(REPO, other, b"blob 1\0b").unwrap();
+        assert_eq!(storage.unsynced.lock().unwrap().paths.len(), 2);
+        
+        // Objects deleted before the sync don't trip it up
+        storage.delete_object(REPO, other).unwrap();
+        storage.sync_pending().unwrap();
+        assert!(storage.unsynced.lock().unwrap().paths.is_empty());
+        assert_eq!(*storage.synced.lock().unwrap(), 2);
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"blob 1\0a");
+        
+        // A single store is synced on its own
+        storage.store_object(REPO, other, b"blob 1\0c").unwrap();
+        assert!(storage.unsynced.lock().unwrap().paths.is_empty());
+        
+        // The spool inherits the policy
+        let spool = storage.spool(dir.path().join(SPOOL_DIR)).unwrap();
+        spool.store_object_batched(REPO, OBJECT, b"blob 1\0a").unwrap();
+        assert_eq!(spool.unsynced.lock().unwrap().paths.len(), 1);
+        
+        let never = GitStorage::new(dir.path()).unwrap().with_fsync_policy(FsyncPolicy::Never);
+        never.store_object_batched(REPO, other, b"blob 1\0b").unwrap();
+        assert!(never.unsynced.lock().unwrap().paths.is_empty());
+        assert_eq!(never.read_object(REPO, other).unwrap(), b"blob 1\0b");
+    }
+    
+    #[test]
+    fn test_sync_pending_covers_each_callers_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(GitStorage::new(dir.path()).unwrap().with_fsync_policy(FsyncPolicy::Batch));
+        
+        // However the syncs interleave, nobody returns before their own
+        // writes are covered
+        let handles: Vec<_> = (0..8u8)
+            .map(|i| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    let data = format!("blob 1\0{}", i).into_bytes();
+                    storage.store_object_batched(REPO, &crate::crypto::git_object_id(&data), &data).unwrap();
+                    let written = storage.unsynced.lock().unwrap().written;
+                    storage.sync_pending().unwrap();
+                    assert!(*storage.synced.lock().unwrap() >= written);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*storage.synced.lock().unwrap(), 8);
+    }
+    
+    #[test]
+    fn test_manifest_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let other = "1111111111111111111111111111111111111111";
+        
+        storage.store_object(REPO, OBJECT, b"blob 1\0a").unwrap();
+        storage.store_object(REPO, other, b"blob 1\0b").unwrap();
+        let expected = blake3::hash(format!("{}\n{}\n", other, OBJECT).as_bytes()).to_hex().to_string();
+        assert_eq!(storage.manifest_hash(REPO).unwrap(), expected);
+        
+        // Same objects in another store, written in another order
+        let copy = GitStorage::new(dir.path().join("copy")).unwrap();
+        copy.store_object(REPO, other, b"blob 1\0b").unwrap();
+        copy.store_object(REPO, OBJECT, b"blob 1\0a").unwrap();
+        assert_eq!(copy.manifest_hash(REPO).unwrap(), expected);
+        
+        copy.delete_object(REPO, other).unwrap();
+        assert_ne!(copy.manifest_hash(REPO).unwrap(), expected);
+    }
+    
+    #[test]
+    fn test_repo_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        assert!(!storage.repo_on_disk(REPO).unwrap());
+        storage.store_object(REPO, OBJECT, b"blob 1\0a").unwrap();
+        assert!(storage.repo_on_disk(REPO).unwrap());
+        
+        // Deleted behind the node's back
+        fs::remove_dir_all(storage.repo_path(REPO)).unwrap();
+        assert!(!storage.repo_on_disk(REPO).unwrap());
+        
+        // Initialized but still empty
+        storage.init_repo(REPO).unwrap();
+        assert!(storage.repo_on_disk(REPO).unwrap());
+    }
+    
+    #[test]
+    fn test_usage_breakdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_dedup(true);
+        let small = "f".repeat(64);
+        storage.store_object(REPO, OBJECT, b"blob 5\0hello").unwrap();
+        storage.store_object(REPO, "1111111111111111111111111111111111111111", b"blob 3\0big").unwrap();
+        storage.store_object(&small, OBJECT, b"blob 5\0hello").unwrap();
+        
+        let spool = storage.spool(dir.path().join(SPOOL_DIR)).unwrap();
+        spool.store_object(REPO, OBJECT, b"blob 5\0hello").unwrap();
+        
+        let usage = storage.usage_breakdown(&dir.path().join(SPOOL_DIR)).unwrap();
+        let repos: Vec<(&str, u64)> = usage.repos.iter().map(|r| (r.repo_hash.as_str(), r.objects)).collect();
+        assert_eq!(repos, vec![(REPO, 2), (small.as_str(), 1)]);
+        assert!(usage.repos[0].size > usage.repos[1].size);
+        assert_eq!(usage.repos[0].size, storage.get_repo_size(REPO).unwrap());
+        assert_eq!(usage.repos[0].pack_cache, 0);
+        
+        assert!(usage.pool > 0);
+        assert_eq!(usage.dedup.pooled_objects, 2);
+        assert!(usage.spool > 0);
+        
+        storage.create_pack(REPO).unwrap();
+        let usage = storage.usage_breakdown(&dir.path().join(SPOOL_DIR)).unwrap();
+        assert!(usage.repos[0].pack_cache > 0);
+    }
+    
+    #[test]
+    fn test_unreachable_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        let blob = put(&storage, b"blob 4\0kept");
+        let kept_tree = put(&storage, &tree(&[("a", &blob)]));
+        let head = put(&storage, &commit(&kept_tree, None));
+        let orphan = put(&storage, b"blob 6\0orphan");
+        storage.update_ref(REPO, "refs/heads/main", &head, None).unwrap();
+        storage.set_head(REPO, "refs/heads/main").unwrap();
+        
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        assert_eq!(storage.unreachable_objects(REPO, later).unwrap(), vec![orphan.clone()]);
+        
+        // Objects newer than the cutoff may belong to a push in progress
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        assert!(storage.unreachable_objects(REPO, earlier).unwrap().is_empty());
+        
+        // A detached HEAD keeps its commit alive
+        let detached = put(&storage, &commit(&kept_tree, Some(&head)));
+        storage.set_head(REPO, &detached).unwrap();
+        assert_eq!(storage.unreachable_objects(REPO, later).unwrap(), vec![orphan]);
+    }
+    
+    #[test]
+    fn test_list_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.init_repo(REPO).unwrap();
+        
+        assert!(storage.list_refs(REPO).unwrap().is_empty());
+        
+        storage.update_ref(REPO, "refs/tags/v1.0", OBJECT, None).unwrap();
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        storage.update_ref(REPO, "refs/heads/feature/x", OBJECT, None).unwrap();
+        
+        let names: Vec<_> = storage.list_refs(REPO).unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, vec!["refs/heads/feature/x", "refs/heads/main", "refs/tags/v1.0"]);
+    }
+    
+    #[test]
+    fn test_streamed_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let data = [b"blob 3000\0".as_slice(), &[b'x'; 3000]].concat();
+        let object_id = crate::crypto::git_object_id(&data);
+        
+        let mut upload = storage.begin_object();
+        for chunk in data.chunks(1000) {
+            upload.write(chunk).unwrap();
+        }
+        assert_eq!(storage.finish_object(REPO, &object_id, upload).unwrap(), data.len() as u64);
+        assert_eq!(storage.read_object(REPO, &object_id).unwrap(), data);
+        assert_eq!(storage.object_count(REPO).unwrap(), 1);
+        
+        // Checked before anything is written
+        let upload = storage.begin_object();
+        assert!(matches!(storage.finish_object(REPO, "../escape", upload), Err(HyruleError::Invalid(_))));
+    }
+    
+    #[test]
+    fn test_compression_level() {
+        let fast_dir = tempfile::tempdir().unwrap();
+        let best_dir = tempfile::tempdir().unwrap();
+        let fast = GitStorage::new(fast_dir.path()).unwrap().with_compression_level(1);
+        let best = GitStorage::new(best_dir.path()).unwrap().with_compression_level(9);
+        
+        // Repetitive but not trivially so, like typical source files
+        let data: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| format!("fn item_{}() {{ {} }}\n", i % 997, i % 13).into_bytes())
+            .collect();
+        
+        fast.store_object(REPO, OBJECT, &data).unwrap();
+        best.store_object(REPO, OBJECT, &data).unwrap();
+        
+        let fast_size = fast.get_repo_size(REPO).unwrap();
+        let best_size = best.get_repo_size(REPO).unwrap();
+        assert!(best_size < fast_size, "level 9 ({}) should beat level 1 ({})", best_size, fast_size);
+        
+        assert_eq!(fast.read_object(REPO, OBJECT).unwrap(), data);
+        assert_eq!(best.read_object(REPO, OBJECT).unwrap(), data);
+    }
+    
+    #[test]
+    fn test_incompressible_blobs_stored_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let blob = |body: &[u8]| [format!("blob {}\0", body.len()).as_bytes(), body].concat();
+        let store = |data: &[u8]| {
+            let object_id = crate::crypto::git_object_id(data);
+            // Sent in pieces, as a streamed upload is
+            let mut upload = storage.begin_object();
+            for chunk in data.chunks(5000) {
+                upload.write(chunk).unwrap();
+            }
+            storage.finish_object(REPO, &object_id, upload).unwrap();
+            assert_eq!(storage.read_object(REPO, &object_id).unwrap(), data);
+            assert!(storage.verify_object(REPO, &object_id).unwrap());
+            is_zlib(&fs::read(storage.object_path(REPO, &object_id)).unwrap())
+        };
+        
+        let text: Vec<u8> = (0..4_000u32).flat_map(|i| format!("line {}\n", i % 97).into_bytes()).collect();
+        let mut noise = vec![0u8; 64 * 1024];
+        blake3::Hasher::new().update(b"seed").finalize_xof().fill(&mut noise);
+        
+        assert!(store(&blob(&text)));
+        assert!(!store(&blob(&noise)));
+        
+        // Known formats are recognized without trying
+        let png = [&b"\x89PNG\r\n\x1a\n"[..], &vec![0u8; 8192]].concat();
+        assert!(!store(&blob(&png)));
+        
+        // Small blobs and other object types are always compressed
+        assert!(store(&blob(&noise[..1000])));
+        assert!(store(&[format!("tree {}\0", noise.len()).as_bytes(), &noise].concat()));
+        
+        // A raw copy that wouldn't read back is compressed after all
+        let wrong_size = [&b"blob 10\0"[..], &noise].concat();
+        assert!(store(&wrong_size));
+    }
+    
+    #[test]
+    fn test_partial_write_leaves_old_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        storage.store_object(REPO, OBJECT, b"original").unwrap();
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        
+        // Simulate a crash mid-write: a truncated temp file left next to the object
+        let object_dir = storage.objects_path(REPO).join(&OBJECT[..2]);
+        let stray = object_dir.join(format!(".{}.1234{}", &OBJECT[2..], TEMP_SUFFIX));
+        fs::write(&stray, b"trunc").unwrap();
+        
+        let ref_dir = storage.refs_path(REPO).join("heads");
+        fs::write(ref_dir.join(format!(".main.1234{}", TEMP_SUFFIX)), b"dead").unwrap();
+        
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"original");
+        assert_eq!(storage.list_objects(REPO).unwrap(), vec![OBJECT.to_string()]);
+        assert_eq!(storage.read_ref(REPO, "refs/heads/main").unwrap(), OBJECT);
+        assert!(storage.verify_object(REPO, OBJECT).unwrap());
+    }
+    
+    #[test]
+    fn test_check_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        storage.check_writable().unwrap();
+        // The probe doesn't show up as a repository, or at all
+        assert!(storage.list_hosted_repos().unwrap().is_empty());
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![STORE_VERSION_FILE]);
+    }
+    
+    #[test]
+    fn test_available_disk_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        assert!(storage.available_disk_space().unwrap() > 0);
+    }
+    
+    #[test]
+    fn test_tiers_spill_and_move() {
+        let hot = tempfile::tempdir().unwrap();
+        let cold = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(hot.path()).unwrap()
+            .with_tiers(&[
+                (hot.path().to_path_buf(), 128),
+                (cold.path().to_path_buf(), 1024 * 1024),
+            ])
+            .unwrap();
+        
+        let ids: Vec<String> = (0..4).map(|i| format!("{:02x}{}", i, &OBJECT[2..])).collect();
+        for id in &ids {
+            let data = format!("blob 64\0{}", crate::crypto::hash_data(id.as_bytes()));
+            storage.store_object(REPO, id, data.as_bytes()).unwrap();
+        }
+        
+        // The small hot tier fills up and later objects land on the cold tier
+        let per_tier = storage.repo_tier_bytes(REPO).unwrap();
+        assert!(per_tier[0] > 0 && per_tier[0] <= 128);
+        assert!(per_tier[1] > 0);
+        assert_eq!(storage.tier_usage(0).0, per_tier[0]);
+        
+        let mut listed = storage.list_objects(REPO).unwrap();
+        listed.sort();
+        assert_eq!(listed, ids);
+        for id in &ids {
+            assert!(storage.read_object(REPO, id).is_ok());
+        }
+        assert!(storage.get_repo_size(REPO).unwrap() >= per_tier[0] + per_tier[1]);
+        
+        // Demote everything to the cold tier
+        storage.move_repo_to_tier(REPO, 1).unwrap();
+        assert_eq!(storage.repo_tier_bytes(REPO).unwrap()[0], 0);
+        assert_eq!(storage.tier_usage(0).0, 0);
+        assert_eq!(storage.list_objects(REPO).unwrap().len(), 4);
+        
+        // Promoting back stops when the hot tier is full
+        assert!(storage.move_repo_to_tier(REPO, 0).is_err());
+        assert_eq!(storage.list_objects(REPO).unwrap().len(), 4);
+        
+        storage.delete_repo(REPO).unwrap();
+        assert!(storage.list_objects(REPO).unwrap().is_empty());
+        assert_eq!(storage.tier_usage(1).0, 0);
+    }
+    
+    #[test]
+    fn test_first_tier_must_be_base_path() {
+        let base = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        
+        let result = GitStorage::new(base.path()).unwrap()
+            .with_tiers(&[(other.path().to_path_buf(), 1024)]);
+        assert!(result.is_err());
+    }
+    
+    #[test]
+    fn test_pack_cache_invalidated_by_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let cache_dir = storage.repo_path(REPO).join(PACK_CACHE_DIR);
+        let cached = || fs::read_dir(&cache_dir).unwrap().count();
+        
+        // Asking for a repo that isn't here leaves no trace
+        assert!(matches!(storage.create_pack(&"cd".repeat(32)), Err(HyruleError::NotFound(_))));
+        assert!(storage.create_pack("../escape").is_err());
+        assert!(storage.list_hosted_repos().unwrap().is_empty());
+        
+        storage.store_object(REPO, OBJECT, b"blob 5\0hello").unwrap();
+        let first = storage.create_pack(REPO).unwrap();
+        assert_eq!(cached(), 1);
+        
+        // Served from cache, and counted in the repo's size
+        assert_eq!(storage.create_pack(REPO).unwrap(), first);
+        assert!(storage.get_repo_size(REPO).unwrap() >= first.len() as u64);
+        
+        // Overwriting an object with new content drops the cache
+        storage.store_object(REPO, OBJECT, b"blob 5\0world").unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(storage.create_pack(REPO).unwrap(), b"blob 5\0world");
+        assert_eq!(cached(), 1);
+        
+        storage.update_ref(REPO, "refs/heads/main", OBJECT, None).unwrap();
+        assert_eq!(cached(), 0);
+    }
+    
+    #[test]
+    fn test_second_instance_cannot_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        
+        let mut first = GitStorage::open_exclusive(dir.path()).unwrap();
+        let second = GitStorage::open_exclusive(dir.path());
+        assert!(second.is_err());
+        
+        // Read-only handles are still allowed alongside a running node
+        assert!(GitStorage::new(dir.path()).is_ok());
+        
+        // but never migrate the store under it
+        fs::write(dir.path().join(STORE_VERSION_FILE), "0\n").unwrap();
+        assert!(GitStorage::new(dir.path()).is_err());
+        assert_eq!(fs::read_to_string(dir.path().join(STORE_VERSION_FILE)).unwrap(), "0\n");
+        
+        first.unlock();
+        assert!(GitStorage::open_exclusive(dir.path()).is_ok());
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_async_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(GitStorage::new(dir.path()).unwrap());
+        
+        let payload = vec![7u8; 256 * 1024];
+        storage.store_object_async(REPO, OBJECT, payload.clone()).await.unwrap();
+        
+        let reads = (0..64).map(|_| {
+            let storage = storage.clone();
+            tokio::spawn(async move { storage.read_object_async(REPO, OBJECT).await })
+        });
+        
+        for read in futures::future::join_all(reads).await {
+            assert_eq!(read.unwrap().unwrap(), payload);
+        }
+        
+        assert_eq!(storage.list_objects_async(REPO).await.unwrap().len(), 1);
+        assert!(storage.get_storage_usage_async().await.unwrap() > 0);
+    }
+    
+    #[test]
+    fn test_overwrite_is_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        
+        storage.store_object(REPO, OBJECT, b"first").unwrap();
+        storage.store_object(REPO, OBJECT, b"second").unwrap();
+        
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"second");
+        
+        // No temp files remain after successful writes
+        let object_dir = storage.objects_path(REPO).join(&OBJECT[..2]);
+        let entries: Vec<_> = fs::read_dir(object_dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+    
+    #[test]
+    fn test_spooled_repo_is_hidden_until_promoted() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        let spool = storage.spool(dir.path().join(SPOOL_DIR)).unwrap();
+        
+        spool.init_repo(REPO).unwrap();
+        spool.store_object(REPO, OBJECT, b"data").unwrap();
+        assert!(storage.list_hosted_repos().unwrap().is_empty());
+        assert!(!storage.object_exists(REPO, OBJECT));
+        
+        storage.promote(&spool, REPO).unwrap();
+        assert_eq!(storage.list_hosted_repos().unwrap(), vec![REPO.to_string()]);
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"data");
+        assert!(!spool.repo_path(REPO).exists());
+        
+        // A live repo is never replaced
+        spool.init_repo(REPO).unwrap();
+        assert!(matches!(storage.promote(&spool, REPO), Err(HyruleError::Conflict(_))));
+        assert_eq!(storage.list_objects(REPO).unwrap(), vec![OBJECT.to_string()]);
+    }
+    
+    #[test]
+    fn test_staging_dirs_are_not_repos() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.init_repo(REPO).unwrap();
+        fs::create_dir_all(dir.path().join(format!(".{}{}", REPO, TEMP_SUFFIX))).unwrap();
+        fs::create_dir_all(dir.path().join(".write-probe.1.0")).unwrap();
+        
+        assert_eq!(storage.list_hosted_repos().unwrap(), vec![REPO.to_string()]);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_promoted_objects_are_pooled() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap().with_dedup(true);
+        let spool = storage.spool(dir.path().join(SPOOL_DIR)).unwrap();
+        let fork = "f".repeat(64);
+        let data = b"blob 5\0hello";
+        
+        storage.store_object(&fork, OBJECT, data).unwrap();
+        spool.store_object(REPO, OBJECT, data).unwrap();
+        spool.store_object(REPO, &"b".repeat(40), b"blob 3\0abc").unwrap();
+        storage.promote(&spool, REPO).unwrap();
+        
+        // One pooled copy per object, shared with the fork where it can be
+        assert_eq!(storage.dedup_stats().unwrap().pooled_objects, 2);
+        let linked = fs::metadata(storage.object_path(REPO, OBJECT)).unwrap();
+        assert_eq!(link_count(&linked), 3);
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), data);
+    }
+    
+    #[test]
+    fn test_older_store_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let version_path = dir.path().join(STORE_VERSION_FILE);
+        let version = || fs::read_to_string(&version_path).unwrap().trim().parse::<u32>().unwrap();
+        
+        let storage = GitStorage::new(dir.path()).unwrap();
+        assert_eq!(version(), STORE_VERSION);
+        storage.store_object(REPO, OBJECT, b"data").unwrap();
+        
+        // This store as seen by a build two formats ahead
+        let migrations: &[Migration] = &[
+            |_| Ok(()),
+            |base| Ok(fs::write(base.join("format-2"), b"")?),
+            |base| {
+                anyhow::ensure!(base.join("format-2").exists(), "ran out of order");
+                Ok(fs::write(base.join("format-3"), b"")?)
+            },
+        ];
+        check_store_version(dir.path(), migrations).unwrap();
+        assert_eq!(version(), 3);
+        assert!(dir.path().join("format-3").exists());
+        
+        // A failed step keeps the steps before it
+        fs::write(&version_path, "1").unwrap();
+        let failing: &[Migration] = &[|_| Ok(()), |_| Ok(()), |_| anyhow::bail!("disk full")];
+        assert!(check_store_version(dir.path(), failing).is_err());
+        assert_eq!(version(), 2);
+        
+        // Data without a version file predates versioning
+        fs::remove_file(&version_path).unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        assert_eq!(version(), STORE_VERSION);
+        assert_eq!(storage.read_object(REPO, OBJECT).unwrap(), b"data");
+    }
+    
+    #[test]
+    fn test_refuses_newer_store() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(STORE_VERSION_FILE), format!("{}\n", STORE_VERSION + 1)).unwrap();
+        
+        let err = GitStorage::new(dir.path()).err().unwrap().to_string();
+        assert!(err.contains("Upgrade hyrule-node"), "{}", err);
+        
+        fs::write(dir.path().join(STORE_VERSION_FILE), "garbage").unwrap();
+        assert!(GitStorage::new(dir.path()).is_err());
+    }
 }
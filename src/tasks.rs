@@ -0,0 +1,269 @@
+// hyrule-node/src/tasks.rs
+use crate::NodeState;
+use axum::{extract::State, Json};
+use futures::FutureExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// Wait before restarting a task that panicked, doubled on each panic
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+
+/// Longest wait between restarts. A task that ran at least this long
+/// before panicking starts over from the minimum.
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+tokio::task_local! {
+    /// Registry and name of the background task being polled
+    static CURRENT_TASK: (Arc<TaskHealth>, &'static str);
+}
+
+/// What the watchdog knows about one background task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    /// Running now; false once stopped and while waiting to restart
+    pub alive: bool,
+    /// When the task was last (re)started
+    pub started_at: String,
+    /// When the task last began a round of work
+    pub last_run: Option<String>,
+    pub restarts: u32,
+    pub last_panic: Option<String>,
+}
+
+/// Status of every background task, for `/admin/tasks`
+#[derive(Debug, Default)]
+pub struct TaskHealth {
+    tasks: Mutex<BTreeMap<&'static str, TaskStatus>>,
+}
+
+impl TaskHealth {
+    pub fn snapshot(&self) -> BTreeMap<&'static str, TaskStatus> {
+        self.tasks.lock().unwrap().clone()
+    }
+
+    fn started(&self, name: &'static str) {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tasks = self.tasks.lock().unwrap();
+        match tasks.get_mut(name) {
+            Some(status) => {
+                status.alive = true;
+                status.started_at = now;
+                status.restarts += 1;
+            }
+            None => {
+                tasks.insert(name, TaskStatus {
+                    alive: true,
+                    started_at: now,
+                    last_run: None,
+                    restarts: 0,
+                    last_panic: None,
+                });
+            }
+        }
+    }
+
+    fn update(&self, name: &'static str, f: impl FnOnce(&mut TaskStatus)) {
+        if let Some(status) = self.tasks.lock().unwrap().get_mut(name) {
+            f(status);
+        }
+    }
+}
+
+/// Note that the current background task is starting a round of work.
+/// Loops call this once per tick; outside a background task it does nothing.
+pub fn record_run() {
+    let _ = CURRENT_TASK.try_with(|(health, name)| {
+        health.update(name, |status| status.last_run = Some(chrono::Utc::now().to_rfc3339()));
+    });
+}
+
+/// Long-running background loops owned by the node, so they can be stopped
+/// and awaited on shutdown instead of being orphaned
+#[derive(Default)]
+pub struct BackgroundTasks {
+    token: CancellationToken,
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+    health: Arc<TaskHealth>,
+}
+
+impl BackgroundTasks {
+    /// Report task status to `health`
+    pub fn with_health(health: Arc<TaskHealth>) -> Self {
+        Self {
+            health,
+            ..Self::default()
+        }
+    }
+
+    /// Spawn a task built by `make_task`, which is dropped at its next
+    /// await point once shutdown starts. If it panics, the panic is logged
+    /// and a fresh task is built after a backoff. It runs in the caller's
+    /// current tracing span.
+    pub fn spawn<M, F>(&mut self, name: &'static str, make_task: M)
+    where
+        M: Fn() -> F + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.token.clone();
+        let health = self.health.clone();
+        let span = tracing::Span::current();
+        health.started(name);
+
+        let handle = tokio::spawn(async move {
+            let mut backoff = RESTART_BACKOFF_MIN;
+
+            loop {
+                let started = Instant::now();
+                let task = AssertUnwindSafe(make_task()).catch_unwind().instrument(span.clone());
+                let result = tokio::select! {
+                    _ = token.cancelled() => break,
+                    result = CURRENT_TASK.scope((health.clone(), name), task) => result,
+                };
+
+                let Err(panic) = result else { break };
+                let message = panic_message(panic.as_ref());
+                if started.elapsed() >= RESTART_BACKOFF_MAX {
+                    backoff = RESTART_BACKOFF_MIN;
+                }
+
+                tracing::error!(parent: &span, "Background task {} panicked: {}, restarting in {:?}", name, message, backoff);
+                health.update(name, |status| {
+                    status.alive = false;
+                    status.last_panic = Some(message);
+                });
+
+                tokio::select! {
+                    _ = token.cancelled() => break,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+                health.started(name);
+            }
+
+            health.update(name, |status| status.alive = false);
+        });
+        self.handles.push((name, handle));
+    }
+
+    /// Cancel every task and wait up to `timeout` in total for them to
+    /// exit. Tasks still running after that are aborted and logged.
+    pub async fn shutdown(self, timeout: Duration) -> Vec<&'static str> {
+        self.token.cancel();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut stuck = Vec::new();
+
+        for (name, mut handle) in self.handles {
+            match tokio::time::timeout_at(deadline, &mut handle).await {
+                Ok(Ok(())) => tracing::debug!("Background task {} stopped", name),
+                Ok(Err(e)) => tracing::warn!("Background task {} failed: {}", name, e),
+                Err(_) => {
+                    tracing::warn!("Background task {} did not stop in time, aborting", name);
+                    handle.abort();
+                    stuck.push(name);
+                }
+            }
+        }
+
+        stuck
+    }
+}
+
+/// Text of a panic payload, which is usually a `&str` or `String`
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// `GET /admin/tasks` - background task status
+pub async fn task_status(State(state): State<NodeState>) -> Json<BTreeMap<&'static str, TaskStatus>> {
+    Json(state.task_health.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_loops() {
+        let mut tasks = BackgroundTasks::default();
+        let ticked = Arc::new(AtomicBool::new(false));
+
+        let flag = ticked.clone();
+        tasks.spawn("looping", move || {
+            let flag = flag.clone();
+            async move {
+                loop {
+                    flag.store(true, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        });
+        tasks.spawn("finished", || async {});
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(ticked.load(Ordering::SeqCst));
+
+        let stuck = tasks.shutdown(Duration::from_secs(1)).await;
+        assert!(stuck.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown_reports_stuck_tasks() {
+        let mut tasks = BackgroundTasks::default();
+        // Blocks the worker thread, so cancellation can't interrupt it
+        tasks.spawn("blocking", || async {
+            tokio::task::block_in_place(|| std::thread::sleep(Duration::from_millis(500)));
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let stuck = tasks.shutdown(Duration::from_millis(50)).await;
+        assert_eq!(stuck, vec!["blocking"]);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_is_restarted() {
+        let health = Arc::new(TaskHealth::default());
+        let mut tasks = BackgroundTasks::with_health(health.clone());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counter = runs.clone();
+        tasks.spawn("flaky", move || {
+            let counter = counter.clone();
+            async move {
+                record_run();
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first run fails");
+                }
+                std::future::pending::<()>().await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let status = &health.snapshot()["flaky"];
+        assert!(!status.alive);
+        assert_eq!(status.last_panic.as_deref(), Some("first run fails"));
+
+        tokio::time::sleep(RESTART_BACKOFF_MIN + Duration::from_millis(200)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let status = &health.snapshot()["flaky"];
+        assert!(status.alive);
+        assert_eq!(status.restarts, 1);
+        assert!(status.last_run.is_some());
+
+        assert!(tasks.shutdown(Duration::from_secs(1)).await.is_empty());
+        assert!(!health.snapshot()["flaky"].alive);
+    }
+}
@@ -0,0 +1,177 @@
+// hyrule-node/src/tiering.rs
+use crate::NodeState;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time;
+
+/// How often repos are moved between tiers
+const REBALANCE_INTERVAL_SECS: u64 = 600;
+
+/// Fraction of the fastest tier we aim to stay under
+const HIGH_WATER: f64 = 0.9;
+
+/// Access and placement of one repository over the last window
+#[derive(Debug)]
+struct RepoHeat {
+    repo_hash: String,
+    recent_requests: u64,
+    /// Bytes on the fastest tier
+    hot_bytes: u64,
+    /// Bytes on any slower tier
+    cold_bytes: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Move {
+    Promote(String),
+    Demote(String),
+}
+
+/// Decide which repos to move. Idle repos are demoted, largest first, only
+/// while the fast tier is above the high-water mark; then repos that were
+/// accessed are promoted, busiest first, as long as they fit under it.
+fn plan(repos: &[RepoHeat], hot_used: u64, hot_capacity: u64) -> Vec<Move> {
+    let high_water = (hot_capacity as f64 * HIGH_WATER) as u64;
+    let mut used = hot_used;
+    let mut moves = Vec::new();
+
+    let mut idle: Vec<&RepoHeat> = repos
+        .iter()
+        .filter(|r| r.recent_requests == 0 && r.hot_bytes > 0)
+        .collect();
+    idle.sort_by_key(|r| std::cmp::Reverse(r.hot_bytes));
+
+    for repo in idle {
+        if used <= high_water {
+            break;
+        }
+        used = used.saturating_sub(repo.hot_bytes);
+        moves.push(Move::Demote(repo.repo_hash.clone()));
+    }
+
+    let mut busy: Vec<&RepoHeat> = repos
+        .iter()
+        .filter(|r| r.recent_requests > 0 && r.cold_bytes > 0)
+        .collect();
+    busy.sort_by_key(|r| std::cmp::Reverse(r.recent_requests));
+
+    for repo in busy {
+        if used + repo.cold_bytes <= high_water {
+            used += repo.cold_bytes;
+            moves.push(Move::Promote(repo.repo_hash.clone()));
+        }
+    }
+
+    moves
+}
+
+/// Periodically promote busy repos to the fastest tier and demote idle
+/// ones to the slowest, using the per-repo access stats
+pub async fn rebalance_loop(state: NodeState) {
+    let mut interval = time::interval(Duration::from_secs(REBALANCE_INTERVAL_SECS));
+    // Request totals at the previous pass, to measure activity per window
+    let mut last_requests: HashMap<String, u64> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+        crate::tasks::record_run();
+
+        if state.maintenance.is_enabled() {
+            continue;
+        }
+
+        if let Err(e) = rebalance(&state, &mut last_requests).await {
+            tracing::warn!("Tier rebalance failed: {}", e);
+        }
+    }
+}
+
+async fn rebalance(state: &NodeState, last_requests: &mut HashMap<String, u64>) -> Result<()> {
+    let repos = state.hosted_repos.read().await.clone();
+    let totals: HashMap<String, u64> = state
+        .repo_stats
+        .read()
+        .await
+        .iter()
+        .map(|(repo, stats)| (repo.clone(), stats.requests))
+        .collect();
+
+    let mut heat = Vec::new();
+    for repo_hash in repos {
+        let total = totals.get(&repo_hash).copied().unwrap_or(0);
+        let previous = last_requests.insert(repo_hash.clone(), total).unwrap_or(0);
+
+        let storage = state.storage.clone();
+        let hash = repo_hash.clone();
+        let bytes = tokio::task::spawn_blocking(move || storage.repo_tier_bytes(&hash)).await??;
+
+        heat.push(RepoHeat {
+            repo_hash,
+            recent_requests: total.saturating_sub(previous),
+            hot_bytes: bytes[0],
+            cold_bytes: bytes[1..].iter().sum(),
+        });
+    }
+
+    let (hot_used, hot_capacity) = state.storage.tier_usage(0);
+    let coldest = state.storage.tier_count() - 1;
+
+    for action in plan(&heat, hot_used, hot_capacity) {
+        let (repo_hash, target) = match action {
+            Move::Promote(repo_hash) => (repo_hash, 0),
+            Move::Demote(repo_hash) => (repo_hash, coldest),
+        };
+
+        let storage = state.storage.clone();
+        let hash = repo_hash.clone();
+        match tokio::task::spawn_blocking(move || storage.move_repo_to_tier(&hash, target)).await? {
+            Ok(bytes) => tracing::info!(repo = %repo_hash, tier = target, bytes, "Moved repo between tiers"),
+            Err(e) => tracing::warn!(repo = %repo_hash, tier = target, error = %e, "Failed to move repo between tiers"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heat(repo_hash: &str, recent_requests: u64, hot_bytes: u64, cold_bytes: u64) -> RepoHeat {
+        RepoHeat {
+            repo_hash: repo_hash.to_string(),
+            recent_requests,
+            hot_bytes,
+            cold_bytes,
+        }
+    }
+
+    #[test]
+    fn test_promotes_busiest_that_fit() {
+        let repos = vec![
+            heat("quiet", 1, 0, 300),
+            heat("busy", 50, 0, 400),
+            heat("huge", 10, 0, 5000),
+        ];
+
+        // 1000 capacity, high water 900, 100 used
+        let moves = plan(&repos, 100, 1000);
+        assert_eq!(moves, vec![Move::Promote("busy".into()), Move::Promote("quiet".into())]);
+    }
+
+    #[test]
+    fn test_demotes_idle_only_above_high_water() {
+        let repos = vec![
+            heat("idle-small", 0, 50, 0),
+            heat("idle-large", 0, 200, 0),
+            heat("active", 5, 700, 0),
+        ];
+
+        assert!(plan(&repos, 800, 1000).is_empty());
+
+        // 950 used: dropping the largest idle repo gets under 900
+        let moves = plan(&repos, 950, 1000);
+        assert_eq!(moves, vec![Move::Demote("idle-large".into())]);
+    }
+}
@@ -0,0 +1,151 @@
+// hyrule-node/src/verify_index.rs
+use crate::storage::{write_atomic, GitStorage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// Sidecar file inside each repo directory
+const INDEX_FILE: &str = "verify-index.json";
+
+/// On-disk identity of an object file; any write changes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    fingerprint: Fingerprint,
+    verified_at: String,
+    ok: bool,
+}
+
+/// Cached verification results for one repository, so repeated scans
+/// only decompress objects that changed since they were last checked
+pub struct VerifyIndex {
+    path: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    dirty: bool,
+}
+
+impl VerifyIndex {
+    /// Load the index for a repo; a missing or unreadable index starts empty
+    pub fn load(storage: &GitStorage, repo_hash: &str) -> Self {
+        let path = storage.repo_path(repo_hash).join(INDEX_FILE);
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Persist the index if anything changed
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        write_atomic(&self.path, &serde_json::to_vec(&self.entries)?)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Verify an object, reusing the cached result when the file is
+    /// unchanged since the last check. `force` always re-reads.
+    pub fn verify(
+        &mut self,
+        storage: &GitStorage,
+        repo_hash: &str,
+        object_id: &str,
+        force: bool,
+    ) -> Result<bool> {
+        let fingerprint = fingerprint(storage, repo_hash, object_id)?;
+
+        if !force {
+            if let Some(entry) = self.entries.get(object_id) {
+                if entry.fingerprint == fingerprint {
+                    return Ok(entry.ok);
+                }
+            }
+        }
+
+        let ok = storage.verify_object(repo_hash, object_id)?;
+
+        self.entries.insert(
+            object_id.to_string(),
+            IndexEntry {
+                fingerprint,
+                verified_at: chrono::Utc::now().to_rfc3339(),
+                ok,
+            },
+        );
+        self.dirty = true;
+
+        Ok(ok)
+    }
+}
+
+fn fingerprint(storage: &GitStorage, repo_hash: &str, object_id: &str) -> Result<Fingerprint> {
+    let metadata = fs::metadata(storage.object_path(repo_hash, object_id))?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+
+    Ok(Fingerprint {
+        size: metadata.len(),
+        mtime_secs: mtime.as_secs(),
+        mtime_nanos: mtime.subsec_nanos(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPO: &str = "a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+    const OBJECT: &str = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+
+    #[test]
+    fn test_unchanged_objects_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.store_object(REPO, OBJECT, b"hello").unwrap();
+
+        let mut index = VerifyIndex::load(&storage, REPO);
+        assert!(index.verify(&storage, REPO, OBJECT, false).unwrap());
+        index.save().unwrap();
+
+        // Corrupt the file but keep its size and mtime, so only a forced
+        // check can notice - proving the cached result was used
+        let path = storage.object_path(REPO, OBJECT);
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        let len = fs::metadata(&path).unwrap().len() as usize;
+        fs::write(&path, vec![0u8; len]).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+
+        let mut index = VerifyIndex::load(&storage, REPO);
+        assert!(index.verify(&storage, REPO, OBJECT, false).unwrap());
+        assert!(index.verify(&storage, REPO, OBJECT, true).is_err());
+    }
+
+    #[test]
+    fn test_changed_objects_are_reverified() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorage::new(dir.path()).unwrap();
+        storage.store_object(REPO, OBJECT, b"hello").unwrap();
+
+        let mut index = VerifyIndex::load(&storage, REPO);
+        assert!(index.verify(&storage, REPO, OBJECT, false).unwrap());
+
+        // A rewrite changes the size, invalidating the cached entry
+        fs::write(storage.object_path(REPO, OBJECT), b"garbage").unwrap();
+        assert!(index.verify(&storage, REPO, OBJECT, false).is_err());
+    }
+}